@@ -0,0 +1,125 @@
+/* C interface to the raytracer. Build with `cargo build --release --features capi`. */
+
+#ifndef RAYTRACING_H
+#define RAYTRACING_H
+
+/* Generated by cbindgen from src/capi.rs, do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef enum RtStatus {
+  RT_STATUS_OK = 0,
+  RT_STATUS_NULL_ARGUMENT = 1,
+  RT_STATUS_INVALID_ARGUMENT = 2,
+  RT_STATUS_CANCELLED = 3,
+  RT_STATUS_FAILED = 4,
+  RT_STATUS_IO_ERROR = 5,
+  RT_STATUS_INVALID_FORMAT = 6,
+} RtStatus;
+
+typedef enum RtMaterialKind {
+  RT_MATERIAL_KIND_LAMBERTIAN = 0,
+  RT_MATERIAL_KIND_METAL = 1,
+  RT_MATERIAL_KIND_DIELECTRIC = 2,
+  RT_MATERIAL_KIND_MIRROR = 3,
+} RtMaterialKind;
+
+typedef struct RtCancel RtCancel;
+
+typedef struct RtScene RtScene;
+
+typedef struct RtVec3 {
+  double x;
+  double y;
+  double z;
+} RtVec3;
+
+typedef struct RtMaterial {
+  enum RtMaterialKind kind;
+  struct RtVec3 color;
+  double fuzz;
+  double ior;
+} RtMaterial;
+
+typedef void (*RtProgressFn)(double fraction, void *user_data);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+const char *rt_last_error(void);
+
+struct RtScene *rt_scene_new(int32_t width,
+                             int32_t height,
+                             uint32_t samples_per_pixel,
+                             int32_t max_depth,
+                             bool multithreading);
+
+void rt_scene_free(struct RtScene *scene);
+
+enum RtStatus rt_scene_set_camera(struct RtScene *scene,
+                                  struct RtVec3 look_from,
+                                  struct RtVec3 look_at,
+                                  struct RtVec3 v_up,
+                                  double v_fov,
+                                  double aperture,
+                                  double focus_distance);
+
+enum RtStatus rt_scene_add_sphere(struct RtScene *scene,
+                                  struct RtVec3 center,
+                                  double radius,
+                                  const struct RtMaterial *material);
+
+enum RtStatus rt_scene_add_plane(struct RtScene *scene,
+                                 struct RtVec3 point,
+                                 struct RtVec3 normal,
+                                 const struct RtMaterial *material);
+
+enum RtStatus rt_scene_add_triangle(struct RtScene *scene,
+                                    struct RtVec3 a,
+                                    struct RtVec3 b,
+                                    struct RtVec3 c,
+                                    const struct RtMaterial *material);
+
+enum RtStatus rt_scene_add_cube(struct RtScene *scene,
+                                struct RtVec3 corner1,
+                                struct RtVec3 corner2,
+                                const struct RtMaterial *material);
+
+enum RtStatus rt_scene_add_mesh(struct RtScene *scene,
+                                const char *path,
+                                const struct RtMaterial *material);
+
+enum RtStatus rt_scene_add_light(struct RtScene *scene, struct RtVec3 position);
+
+enum RtStatus rt_scene_render(const struct RtScene *scene,
+                              uint8_t *buffer,
+                              size_t buffer_len,
+                              RtProgressFn progress,
+                              void *user_data,
+                              const struct RtCancel *cancel);
+
+enum RtStatus rt_ron_image_size(const char *ron, int32_t *width, int32_t *height);
+
+enum RtStatus rt_render_ron(const char *ron,
+                            uint8_t *buffer,
+                            size_t buffer_len,
+                            RtProgressFn progress,
+                            void *user_data,
+                            const struct RtCancel *cancel);
+
+struct RtCancel *rt_cancel_new(void);
+
+void rt_cancel_request(const struct RtCancel *cancel);
+
+void rt_cancel_free(struct RtCancel *cancel);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* RAYTRACING_H */
@@ -0,0 +1,99 @@
+use crate::matrix::Mat4;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+
+/// Axis aligned bounding box used by the BVH.
+#[derive(Debug, Copy, Clone)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    /// Box around two opposite corners, flat boxes are padded so rays cannot slip past them.
+    pub fn new(corner1: Vec3, corner2: Vec3) -> Aabb {
+        let pad = |a: f64, b: f64| {
+            let (lo, hi) = (a.min(b), a.max(b));
+            if hi - lo < 1e-4 {
+                (lo - 5e-5, hi + 5e-5)
+            } else {
+                (lo, hi)
+            }
+        };
+        let (x0, x1) = pad(corner1.x(), corner2.x());
+        let (y0, y1) = pad(corner1.y(), corner2.y());
+        let (z0, z1) = pad(corner1.z(), corner2.z());
+        Aabb {
+            min: Vec3::new(x0, y0, z0),
+            max: Vec3::new(x1, y1, z1),
+        }
+    }
+
+    pub fn from_points(points: &[Vec3]) -> Aabb {
+        let mut min = points[0];
+        let mut max = points[0];
+        for p in &points[1..] {
+            min = Vec3::new(min.x().min(p.x()), min.y().min(p.y()), min.z().min(p.z()));
+            max = Vec3::new(max.x().max(p.x()), max.y().max(p.y()), max.z().max(p.z()));
+        }
+        Aabb::new(min, max)
+    }
+
+    pub fn surrounding(&self, other: &Aabb) -> Aabb {
+        Aabb::from_points(&[self.min, self.max, other.min, other.max])
+    }
+
+    pub fn corners(&self) -> [Vec3; 8] {
+        let (a, b) = (self.min, self.max);
+        [
+            Vec3::new(a.x(), a.y(), a.z()),
+            Vec3::new(b.x(), a.y(), a.z()),
+            Vec3::new(a.x(), b.y(), a.z()),
+            Vec3::new(b.x(), b.y(), a.z()),
+            Vec3::new(a.x(), a.y(), b.z()),
+            Vec3::new(b.x(), a.y(), b.z()),
+            Vec3::new(a.x(), b.y(), b.z()),
+            Vec3::new(b.x(), b.y(), b.z()),
+        ]
+    }
+
+    /// Box around this box after an affine transform.
+    pub fn transformed(&self, matrix: &Mat4) -> Aabb {
+        let corners = self.corners().map(|corner| matrix.transform_point(corner));
+        Aabb::from_points(&corners)
+    }
+
+    pub fn centroid(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn hit(&self, ray: Ray, t_min: f64, t_max: f64) -> bool {
+        let (o, d) = (ray.origin(), ray.direction());
+        let slabs = [
+            (o.x(), d.x(), self.min.x(), self.max.x()),
+            (o.y(), d.y(), self.min.y(), self.max.y()),
+            (o.z(), d.z(), self.min.z(), self.max.z()),
+        ];
+        let mut t_near = t_min;
+        let mut t_far = t_max;
+        for (origin, direction, lo, hi) in slabs {
+            let inv = 1.0 / direction;
+            let mut t0 = (lo - origin) * inv;
+            let mut t1 = (hi - origin) * inv;
+            if inv < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            // NaN from a zero direction on the slab boundary keeps the current range.
+            if t0 > t_near {
+                t_near = t0;
+            }
+            if t1 < t_far {
+                t_far = t1;
+            }
+            if t_far < t_near {
+                return false;
+            }
+        }
+        true
+    }
+}
@@ -0,0 +1,481 @@
+use crate::configuration::{RaytracerScene, RonGeometry, RonObject, RonTransform};
+use crate::vec3::Vec3;
+use std::collections::HashSet;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Interpolation {
+    Linear,
+    Bezier,
+}
+
+impl Interpolation {
+    pub fn from_name(name: &str) -> Interpolation {
+        match name {
+            "Linear" => Interpolation::Linear,
+            "Bezier" => Interpolation::Bezier,
+            _ => panic!("Unknown interpolation {}", name),
+        }
+    }
+}
+
+// Value of a track at a frame. Bezier handles are control values on either side of the key.
+#[derive(Debug, Clone)]
+pub struct Keyframe {
+    pub frame: f64,
+    pub value: Vec<f64>,
+    pub handle_in: Option<Vec<f64>>,
+    pub handle_out: Option<Vec<f64>>,
+}
+
+// Scene parameter a track drives.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target {
+    CameraPos,
+    CameraDir,
+    CameraUp,
+    VFov,
+    Aperture,
+    FocalDistance,
+    Light(usize),
+    Object(String, Channel),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Channel {
+    Translate,
+    Rotate,
+    Scale,
+    Vector(usize),
+    Scalar(usize),
+}
+
+impl Target {
+    // `camera_pos`, `v_fov`, `lights.0`, or `<object name>.translate`, `.rotate`, `.scale`,
+    // `.vectors.<i>` and `.scalars.<i>`.
+    pub fn from_name(name: &str) -> Target {
+        let index = |i: &str| {
+            i.parse::<usize>()
+                .unwrap_or_else(|_| panic!("Bad index in animation target {}", name))
+        };
+        match name {
+            "camera_pos" => return Target::CameraPos,
+            "camera_dir" => return Target::CameraDir,
+            "camera_up" => return Target::CameraUp,
+            "v_fov" => return Target::VFov,
+            "aperture" => return Target::Aperture,
+            "focal_distance" => return Target::FocalDistance,
+            _ => {}
+        }
+        let parts: Vec<&str> = name.split('.').collect();
+        match parts[..] {
+            ["lights", i] => Target::Light(index(i)),
+            [object, "translate"] => Target::Object(object.to_string(), Channel::Translate),
+            [object, "rotate"] => Target::Object(object.to_string(), Channel::Rotate),
+            [object, "scale"] => Target::Object(object.to_string(), Channel::Scale),
+            [object, "vectors", i] => Target::Object(object.to_string(), Channel::Vector(index(i))),
+            [object, "scalars", i] => Target::Object(object.to_string(), Channel::Scalar(index(i))),
+            _ => panic!("Unknown animation target {}", name),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Track {
+    pub target: Target,
+    interpolation: Interpolation,
+    keys: Vec<Keyframe>,
+}
+
+impl Track {
+    // Keys without explicit handles get smooth Catmull-Rom style handles, flat at the ends.
+    pub fn new(target: Target, interpolation: Interpolation, mut keys: Vec<Keyframe>) -> Track {
+        assert!(!keys.is_empty(), "animation track needs at least one key");
+        keys.sort_by(|a, b| a.frame.partial_cmp(&b.frame).unwrap());
+
+        let count = keys.len();
+        for i in 0..count {
+            let slope: Vec<f64> = if i == 0 || i + 1 == count {
+                vec![0.0; keys[i].value.len()]
+            } else {
+                let span = keys[i + 1].frame - keys[i - 1].frame;
+                keys[i + 1]
+                    .value
+                    .iter()
+                    .zip(&keys[i - 1].value)
+                    .map(|(next, prev)| (next - prev) / span)
+                    .collect()
+            };
+            let handle = |distance: f64| -> Vec<f64> {
+                keys[i]
+                    .value
+                    .iter()
+                    .zip(&slope)
+                    .map(|(value, slope)| value + slope * distance / 3.0)
+                    .collect()
+            };
+            let handle_in = match (&keys[i].handle_in, i) {
+                (Some(handle), _) => handle.clone(),
+                (None, 0) => keys[i].value.clone(),
+                (None, _) => handle(keys[i - 1].frame - keys[i].frame),
+            };
+            let handle_out = match &keys[i].handle_out {
+                Some(handle) => handle.clone(),
+                None if i + 1 == count => keys[i].value.clone(),
+                None => handle(keys[i + 1].frame - keys[i].frame),
+            };
+            keys[i].handle_in = Some(handle_in);
+            keys[i].handle_out = Some(handle_out);
+        }
+
+        Track {
+            target,
+            interpolation,
+            keys,
+        }
+    }
+
+    // Values hold before the first and after the last key.
+    pub fn sample(&self, frame: f64) -> Vec<f64> {
+        let first = &self.keys[0];
+        let last = &self.keys[self.keys.len() - 1];
+        if frame <= first.frame {
+            return first.value.clone();
+        }
+        if frame >= last.frame {
+            return last.value.clone();
+        }
+
+        let k = self
+            .keys
+            .windows(2)
+            .position(|pair| frame <= pair[1].frame)
+            .unwrap();
+        let (a, b) = (&self.keys[k], &self.keys[k + 1]);
+        let s = (frame - a.frame) / (b.frame - a.frame);
+        match self.interpolation {
+            Interpolation::Linear => a
+                .value
+                .iter()
+                .zip(&b.value)
+                .map(|(p0, p3)| p0 * (1.0 - s) + p3 * s)
+                .collect(),
+            Interpolation::Bezier => {
+                let out = a.handle_out.as_ref().unwrap();
+                let into = b.handle_in.as_ref().unwrap();
+                let r = 1.0 - s;
+                (0..a.value.len())
+                    .map(|i| {
+                        r * r * r * a.value[i]
+                            + 3.0 * r * r * s * out[i]
+                            + 3.0 * r * s * s * into[i]
+                            + s * s * s * b.value[i]
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+// Frame range and tracks of a scene. `turntable` orbits the camera around its look-at point
+// about the up vector by that many degrees over the sequence, so 360 loops seamlessly.
+#[derive(Debug, Clone)]
+pub struct Animation {
+    pub frame_start: i32,
+    pub frame_end: i32,
+    pub turntable: f64,
+    pub tracks: Vec<Track>,
+}
+
+impl Animation {
+    // A single still frame.
+    pub fn still() -> Animation {
+        Animation {
+            frame_start: 0,
+            frame_end: 0,
+            turntable: 0.0,
+            tracks: vec![],
+        }
+    }
+
+    // Names of the objects moved by a track.
+    pub fn animated_objects(&self) -> HashSet<String> {
+        self.tracks
+            .iter()
+            .filter_map(|track| match &track.target {
+                Target::Object(name, _) => Some(name.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn scene_at(&self, scene: &RaytracerScene, frame: i32) -> RaytracerScene {
+        let mut scene = scene.clone();
+        for track in &self.tracks {
+            let value = track.sample(frame as f64);
+            match &track.target {
+                Target::CameraPos => scene.camera_pos = value,
+                Target::CameraDir => scene.camera_dir = value,
+                Target::CameraUp => scene.camera_up = value,
+                Target::VFov => scene.v_fov = value[0],
+                Target::Aperture => scene.aperture = value[0],
+                Target::FocalDistance => scene.focal_distance = value[0],
+                Target::Light(i) => scene.lights[*i] = value,
+                Target::Object(name, channel) => {
+                    let object = find_object(&mut scene, name)
+                        .unwrap_or_else(|| panic!("Unknown animated object {}", name));
+                    set_channel(object, channel, value);
+                }
+            }
+        }
+
+        if self.turntable != 0.0 {
+            let count = (self.frame_end - self.frame_start + 1).max(1) as f64;
+            let angle = (self.turntable * (frame - self.frame_start) as f64 / count).to_radians();
+            let pivot = to_vec3(&scene.camera_dir);
+            let axis = to_vec3(&scene.camera_up).unit_vector();
+            let offset = to_vec3(&scene.camera_pos) - pivot;
+            let (sin, cos) = angle.sin_cos();
+            let rotated =
+                offset * cos + axis.cross(offset) * sin + axis * (axis.dot(offset) * (1.0 - cos));
+            let position = pivot + rotated;
+            scene.camera_pos = vec![position.x(), position.y(), position.z()];
+        }
+        scene
+    }
+}
+
+fn to_vec3(v: &[f64]) -> Vec3 {
+    Vec3::new(v[0], v[1], v[2])
+}
+
+// Whether the object or one of its children is named in `names`.
+pub fn contains_named(obj: &RonObject, names: &HashSet<String>) -> bool {
+    obj.name.as_ref().is_some_and(|name| names.contains(name))
+        || obj
+            .children
+            .iter()
+            .any(|child| contains_named(child, names))
+}
+
+// Whether the object or one of its children places a geometry named in `geometries`, as an
+// instance, as scattered copies or as the surface copies are scattered over.
+pub fn references_geometry(obj: &RonObject, geometries: &HashSet<String>) -> bool {
+    obj.geometry
+        .as_ref()
+        .is_some_and(|name| geometries.contains(name))
+        || obj
+            .scatter
+            .as_ref()
+            .is_some_and(|scatter| geometries.contains(&scatter.surface))
+        || obj
+            .children
+            .iter()
+            .any(|child| references_geometry(child, geometries))
+}
+
+// Geometries holding an animated object, directly or through another such geometry they place.
+pub fn animated_geometries(
+    geometries: &[RonGeometry],
+    animated: &HashSet<String>,
+) -> HashSet<String> {
+    let mut names = HashSet::new();
+    loop {
+        let found: Vec<String> = geometries
+            .iter()
+            .filter(|geometry| !names.contains(&geometry.name))
+            .filter(|geometry| {
+                geometry
+                    .objects
+                    .iter()
+                    .any(|obj| contains_named(obj, animated) || references_geometry(obj, &names))
+            })
+            .map(|geometry| geometry.name.clone())
+            .collect();
+        if found.is_empty() {
+            return names;
+        }
+        names.extend(found);
+    }
+}
+
+fn find_in<'a>(objects: &'a mut [RonObject], name: &str) -> Option<&'a mut RonObject> {
+    for obj in objects {
+        if obj.name.as_deref() == Some(name) {
+            return Some(obj);
+        }
+        if let Some(found) = find_in(&mut obj.children, name) {
+            return Some(found);
+        }
+    }
+    None
+}
+
+fn find_object<'a>(scene: &'a mut RaytracerScene, name: &str) -> Option<&'a mut RonObject> {
+    if let Some(found) = find_in(&mut scene.objects, name) {
+        return Some(found);
+    }
+    scene
+        .geometries
+        .iter_mut()
+        .find_map(|geometry| find_in(&mut geometry.objects, name))
+}
+
+fn set_channel(obj: &mut RonObject, channel: &Channel, value: Vec<f64>) {
+    match channel {
+        Channel::Translate => {
+            obj.transform
+                .get_or_insert_with(RonTransform::default)
+                .translate = value
+        }
+        Channel::Rotate => {
+            obj.transform
+                .get_or_insert_with(RonTransform::default)
+                .rotate = value
+        }
+        Channel::Scale => {
+            obj.transform
+                .get_or_insert_with(RonTransform::default)
+                .scale = value
+        }
+        Channel::Vector(i) => obj.vectors[*i] = value,
+        Channel::Scalar(i) => obj.scalars[*i] = value[0],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(frame: f64, value: f64) -> Keyframe {
+        Keyframe {
+            frame,
+            value: vec![value],
+            handle_in: None,
+            handle_out: None,
+        }
+    }
+
+    fn close(a: Vec<f64>, b: f64) -> bool {
+        (a[0] - b).abs() < 1e-9
+    }
+
+    fn scene() -> RaytracerScene {
+        RaytracerScene::from_ron(
+            r#"(multithreading: false, aspect_ratio: 1.0, image_width: 4, image_height: 4,
+                samples_per_pixel: 1, max_depth: 1, v_fov: 90.0, aperture: 0.0,
+                focal_distance: 1.0, camera_pos: [0.0, 0.0, 5.0], camera_dir: [0.0, 0.0, 0.0],
+                camera_up: [0.0, 1.0, 0.0], lights: [],
+                objects: [(objtype: "Node", children: [(objtype: "Sphere", name: Some("ball"),
+                    vectors: [[0.0, 0.0, 0.0]], scalars: [1.0], material: ["Mirror"])])])"#
+                .to_string(),
+        )
+    }
+
+    #[test]
+    fn linear_tracks_interpolate_between_keys_and_hold_outside_them() {
+        let track = Track::new(
+            Target::VFov,
+            Interpolation::Linear,
+            vec![key(10.0, 4.0), key(0.0, 0.0), key(20.0, 0.0)],
+        );
+        assert!(close(track.sample(0.0), 0.0));
+        assert!(close(track.sample(2.5), 1.0));
+        assert!(close(track.sample(10.0), 4.0));
+        assert!(close(track.sample(15.0), 2.0));
+        assert!(close(track.sample(-5.0), 0.0));
+        assert!(close(track.sample(25.0), 0.0));
+    }
+
+    #[test]
+    fn bezier_tracks_ease_through_smooth_handles() {
+        let ends = Track::new(
+            Target::VFov,
+            Interpolation::Bezier,
+            vec![key(0.0, 0.0), key(10.0, 10.0)],
+        );
+        // Flat handles at both ends ease in and out, symmetric about the middle.
+        assert!(close(ends.sample(2.5), 1.5625));
+        assert!(close(ends.sample(5.0), 5.0));
+        assert!(close(ends.sample(7.5), 8.4375));
+        assert!(close(ends.sample(-1.0), 0.0));
+        assert!(close(ends.sample(11.0), 10.0));
+
+        // An inner key on a straight line gets handles along it, so the curve stays straight
+        // through the key.
+        let line = Track::new(
+            Target::VFov,
+            Interpolation::Bezier,
+            vec![key(0.0, 0.0), key(10.0, 10.0), key(20.0, 20.0)],
+        );
+        assert!(close(line.sample(10.0), 10.0));
+        let before = line.sample(9.999)[0];
+        let after = line.sample(10.001)[0];
+        assert!(((after - before) / 0.002 - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn explicit_bezier_handles_are_kept() {
+        let mut start = key(0.0, 0.0);
+        start.handle_out = Some(vec![10.0]);
+        let mut end = key(1.0, 0.0);
+        end.handle_in = Some(vec![10.0]);
+        let track = Track::new(Target::VFov, Interpolation::Bezier, vec![start, end]);
+        assert!(close(track.sample(0.5), 7.5));
+    }
+
+    #[test]
+    fn tracks_move_nested_objects() {
+        let settings = scene();
+        let animation = Animation {
+            frame_start: 0,
+            frame_end: 2,
+            turntable: 0.0,
+            tracks: vec![Track::new(
+                Target::from_name("ball.translate"),
+                Interpolation::Linear,
+                vec![
+                    Keyframe {
+                        frame: 0.0,
+                        value: vec![0.0, 0.0, 0.0],
+                        handle_in: None,
+                        handle_out: None,
+                    },
+                    Keyframe {
+                        frame: 2.0,
+                        value: vec![4.0, 0.0, 0.0],
+                        handle_in: None,
+                        handle_out: None,
+                    },
+                ],
+            )],
+        };
+        assert_eq!(
+            animation.animated_objects(),
+            HashSet::from(["ball".to_string()])
+        );
+        let frame = animation.scene_at(&settings, 1);
+        let ball = &frame.objects[0].children[0];
+        assert_eq!(
+            ball.transform.as_ref().unwrap().translate,
+            vec![2.0, 0.0, 0.0]
+        );
+        // The source scene is left as it was.
+        assert!(settings.objects[0].children[0].transform.is_none());
+    }
+
+    #[test]
+    fn turntable_orbits_the_camera_around_its_look_at_point() {
+        let settings = scene();
+        let animation = Animation {
+            frame_start: 0,
+            frame_end: 3,
+            turntable: 360.0,
+            tracks: vec![],
+        };
+        let position = |frame: i32| to_vec3(&animation.scene_at(&settings, frame).camera_pos);
+        assert!((position(0) - Vec3::new(0.0, 0.0, 5.0)).length() < 1e-9);
+        assert!((position(1) - Vec3::new(5.0, 0.0, 0.0)).length() < 1e-9);
+        assert!((position(2) - Vec3::new(0.0, 0.0, -5.0)).length() < 1e-9);
+        assert!((position(3) - Vec3::new(-5.0, 0.0, 0.0)).length() < 1e-9);
+    }
+}
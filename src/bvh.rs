@@ -0,0 +1,260 @@
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::ray::Ray;
+use crate::scene_export::SceneWriter;
+use crate::stats;
+use std::collections::HashSet;
+use std::mem::size_of;
+
+// Only overwrites `rec` on an accepted hit. Intersections cut out by the material's opacity
+// mask are walked past here, and only here: a hit coming out of a nested BVH already had its
+// mask resolved, so stochastic masks are sampled once however deep the object sits.
+fn hit_object(
+    object: &(dyn Hittable + Send + Sync),
+    ray: Ray,
+    t_min: f64,
+    t_max: f64,
+    rec: &mut HitRecord,
+) -> bool {
+    let mut temp_rec = HitRecord::new();
+    let mut start = t_min;
+    stats::count(|counters| counters.intersection_tests += 1);
+    while object.hit(ray, start, t_max, &mut temp_rec) {
+        let opaque = temp_rec.opacity_resolved
+            || match &temp_rec.material {
+                Some(material) => material.is_opaque_at(&temp_rec),
+                None => true,
+            };
+        if opaque {
+            temp_rec.opacity_resolved = true;
+            rec.set_rec(&temp_rec);
+            return true;
+        }
+        // Step just past the rejected hit so inclusive range checks cannot return it again.
+        let t = temp_rec.get_t().unwrap();
+        start = t + 1e-9 * t.abs().max(1.0);
+        stats::count(|counters| counters.intersection_tests += 1);
+    }
+    false
+}
+
+// Leaves keep the position of their object in the list the BVH was built from.
+enum BvhNode {
+    Leaf(Aabb, usize, Box<dyn Hittable + Send + Sync>),
+    Branch(Aabb, Box<BvhNode>, Box<BvhNode>),
+}
+
+impl BvhNode {
+    // Median split along the axis where the object centroids are spread the most.
+    fn build(mut objects: Vec<(Aabb, usize, Box<dyn Hittable + Send + Sync>)>) -> BvhNode {
+        if objects.len() == 1 {
+            let (bounds, index, object) = objects.pop().unwrap();
+            return BvhNode::Leaf(bounds, index, object);
+        }
+
+        let centroids: Vec<_> = objects.iter().map(|(b, _, _)| b.centroid()).collect();
+        let spread = Aabb::from_points(&centroids);
+        let extent = spread.max - spread.min;
+        let key: fn(&Aabb) -> f64 = if extent.x() >= extent.y() && extent.x() >= extent.z() {
+            |b| b.centroid().x()
+        } else if extent.y() >= extent.z() {
+            |b| b.centroid().y()
+        } else {
+            |b| b.centroid().z()
+        };
+        objects.sort_by(|a, b| key(&a.0).partial_cmp(&key(&b.0)).unwrap());
+
+        let right = objects.split_off(objects.len() / 2);
+        let left = BvhNode::build(objects);
+        let right = BvhNode::build(right);
+        let bounds = left.bounds().surrounding(&right.bounds());
+        BvhNode::Branch(bounds, Box::new(left), Box::new(right))
+    }
+
+    fn bounds(&self) -> Aabb {
+        match self {
+            BvhNode::Leaf(bounds, _, _) => *bounds,
+            BvhNode::Branch(bounds, _, _) => *bounds,
+        }
+    }
+
+    fn acceleration_bytes(&self, seen: &mut HashSet<usize>) -> usize {
+        size_of::<BvhNode>()
+            + match self {
+                BvhNode::Leaf(_, _, object) => object.acceleration_bytes(seen),
+                BvhNode::Branch(_, left, right) => {
+                    left.acceleration_bytes(seen) + right.acceleration_bytes(seen)
+                }
+            }
+    }
+
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        stats::count(|counters| counters.bvh_node_visits += 1);
+        if !self.bounds().hit(ray, t_min, t_max) {
+            return false;
+        }
+        match self {
+            BvhNode::Leaf(_, _, object) => hit_object(object.as_ref(), ray, t_min, t_max, rec),
+            BvhNode::Branch(_, left, right) => {
+                let hit_left = left.hit(ray, t_min, t_max, rec);
+                let closest = if hit_left {
+                    rec.get_t().unwrap()
+                } else {
+                    t_max
+                };
+                let hit_right = right.hit(ray, t_min, closest, rec);
+                hit_left || hit_right
+            }
+        }
+    }
+
+    fn leaves<'a>(&'a self, found: &mut Vec<(usize, &'a (dyn Hittable + Send + Sync))>) {
+        match self {
+            BvhNode::Leaf(_, index, object) => found.push((*index, object.as_ref())),
+            BvhNode::Branch(_, left, right) => {
+                left.leaves(found);
+                right.leaves(found);
+            }
+        }
+    }
+}
+
+/// Bounding volume hierarchy over the bounded objects, unbounded ones such as planes are
+/// tested against every ray.
+pub struct Bvh {
+    root: Option<BvhNode>,
+    unbounded: Vec<(usize, Box<dyn Hittable + Send + Sync>)>,
+}
+
+impl Bvh {
+    pub fn new(objects: Vec<Box<dyn Hittable + Send + Sync>>) -> Bvh {
+        let mut bounded = vec![];
+        let mut unbounded = vec![];
+        for (index, object) in objects.into_iter().enumerate() {
+            match object.bounding_box() {
+                Some(bounds) => bounded.push((bounds, index, object)),
+                None => unbounded.push((index, object)),
+            }
+        }
+        Bvh {
+            root: if bounded.is_empty() {
+                None
+            } else {
+                Some(BvhNode::build(bounded))
+            },
+            unbounded,
+        }
+    }
+}
+
+impl Hittable for Bvh {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let mut closest = t_max;
+        let mut hit_anything = false;
+        if let Some(root) = &self.root {
+            if root.hit(ray, t_min, closest, rec) {
+                closest = rec.get_t().unwrap();
+                hit_anything = true;
+            }
+        }
+        for (_, object) in &self.unbounded {
+            if hit_object(object.as_ref(), ray, t_min, closest, rec) {
+                closest = rec.get_t().unwrap();
+                hit_anything = true;
+            }
+        }
+        hit_anything
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        if !self.unbounded.is_empty() {
+            return None;
+        }
+        self.root.as_ref().map(|root| root.bounds())
+    }
+
+    fn acceleration_bytes(&self, seen: &mut HashSet<usize>) -> usize {
+        let nodes = self
+            .root
+            .as_ref()
+            .map_or(0, |root| root.acceleration_bytes(seen));
+        let unbounded: usize = self
+            .unbounded
+            .iter()
+            .map(|(_, object)| object.acceleration_bytes(seen))
+            .sum();
+        nodes + unbounded
+    }
+
+    // Leaves are written into the enclosing node in the order the objects were given, so the
+    // hierarchy rebuilt when loading is the same one.
+    fn export(&self, writer: &mut SceneWriter) {
+        let mut objects = vec![];
+        if let Some(root) = &self.root {
+            root.leaves(&mut objects);
+        }
+        objects.extend(
+            self.unbounded
+                .iter()
+                .map(|(index, object)| (*index, object.as_ref())),
+        );
+        objects.sort_by_key(|(index, _)| *index);
+        for (_, object) in objects {
+            object.export(writer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittables::Hittables;
+    use crate::material::Material;
+    use crate::matrix::Mat4;
+    use crate::rectangle::Rectangle;
+    use crate::texture::SolidColor;
+    use crate::transform::Transform;
+    use crate::vec3::Vec3;
+    use std::sync::Arc;
+
+    #[test]
+    fn stochastic_mask_is_sampled_once_through_nested_bvhs() {
+        let half = Arc::new(SolidColor::new(Vec3::new(0.5, 0.5, 0.5)));
+        let masked = Material::Masked(
+            Box::new(Material::Lambertian(half.clone())),
+            half,
+            0.5,
+            true,
+        );
+        let quad = Rectangle::new(
+            Vec3::new(-1.0, -1.0, 0.0),
+            Vec3::new(1.0, -1.0, 0.0),
+            Vec3::new(-1.0, 1.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            masked,
+            false,
+        );
+        // Scattered geometry: an instanced BVH inside the world BVH, inside the world.
+        let instance = Transform::new(Arc::new(Bvh::new(vec![Box::new(quad)])), Mat4::identity());
+        let world = Hittables {
+            lights: vec![],
+            hittables: vec![Box::new(Bvh::new(vec![Box::new(instance)]))],
+        };
+
+        let rays = 10_000;
+        let hits = (0..rays)
+            .filter(|i| {
+                let x = (i % 100) as f64 / 50.0 - 0.99;
+                let y = (i / 100) as f64 / 50.0 - 0.99;
+                let ray = Ray::new(Vec3::new(x, y, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+                world.hit(ray, 0.001, f64::INFINITY, &mut HitRecord::new())
+            })
+            .count();
+        let coverage = hits as f64 / rays as f64;
+        assert!(
+            (coverage - 0.5).abs() < 0.03,
+            "coverage {} instead of 0.5",
+            coverage
+        );
+    }
+}
@@ -1,6 +1,10 @@
+use crate::configuration::RonBokeh;
 use crate::ray::Ray;
+use crate::scene_export::{ron_vector, SceneWriter};
+use crate::texture::TextureRef;
 use crate::vec3::Vec3;
-use crate::random_f64;
+use crate::{random, random_f64};
+use std::f64::consts::PI;
 
 fn random_in_unit_disk() -> Vec3{
     loop {
@@ -11,8 +15,127 @@ fn random_in_unit_disk() -> Vec3{
     }
 }
 
+/// Shape of the lens opening, which out of focus highlights take on.
+#[derive(Debug, Clone)]
+pub enum Aperture {
+    Circle,
+    /// Regular polygon with this many blades, rotated by the angle in degrees.
+    Polygon(usize, f64),
+    /// Mask over the unit square around the disk, weighted by its alpha or red channel.
+    Image(TextureRef),
+}
+
+impl Aperture {
+    // Point on the lens in the unit disk around the origin.
+    fn sample(&self) -> Vec3 {
+        match self {
+            Aperture::Circle => random_in_unit_disk(),
+            Aperture::Polygon(blades, rotation) => {
+                let step = 2.0 * PI / *blades as f64;
+                let i = (random() * *blades as f64) as usize % blades;
+                let angle = rotation.to_radians() + step * i as f64;
+                let a = Vec3::new(angle.cos(), angle.sin(), 0.0);
+                let b = Vec3::new((angle + step).cos(), (angle + step).sin(), 0.0);
+                // Uniform point in the triangle between the centre and one edge.
+                let (mut x, mut y) = (random(), random());
+                if x + y > 1.0 {
+                    x = 1.0 - x;
+                    y = 1.0 - y;
+                }
+                a * x + b * y
+            }
+            Aperture::Image(mask) => {
+                for _ in 0..64 {
+                    let p = Vec3::new(random_f64(-1.0, 1.0), random_f64(-1.0, 1.0), 0.0);
+                    let coverage = mask.alpha(0.5 + 0.5 * p.x(), 0.5 + 0.5 * p.y(), p);
+                    if random() < coverage {
+                        return p;
+                    }
+                }
+                Vec3::new(0.0, 0.0, 0.0)
+            }
+        }
+    }
+}
+
+/// Maps normalised image coordinates, (0, 0) bottom left and (1, 1) top right, to primary rays.
+pub trait Camera: Send + Sync {
+    /// Primary ray through the image point `(s, t)`, at a random time within the shutter.
+    fn get_ray(&self, s: f64, t: f64) -> Ray;
+
+    /// Scale applied to the radiance reaching the image.
+    fn exposure(&self) -> f64 {
+        1.0
+    }
+
+    /// Writes the projection, placement and lens into the settings of a scene being written out.
+    fn export(&self, writer: &mut SceneWriter) {
+        writer.skip("custom camera");
+    }
+}
+
+// Lets a camera kept by the Python bindings be rendered repeatedly.
+impl<T: Camera + ?Sized> Camera for std::sync::Arc<T> {
+    fn get_ray(&self, s: f64, t: f64) -> Ray {
+        self.as_ref().get_ray(s, t)
+    }
+    fn exposure(&self) -> f64 {
+        self.as_ref().exposure()
+    }
+    fn export(&self, writer: &mut SceneWriter) {
+        self.as_ref().export(writer)
+    }
+}
+
+// Placement a camera was created with, kept for writing the scene back out.
+#[derive(Debug, Copy, Clone)]
+struct View {
+    look_from: Vec3,
+    look_at: Vec3,
+    v_up: Vec3,
+}
+
+impl View {
+    fn export(&self, camera_type: &str, shutter: Shutter, exposure: f64, writer: &mut SceneWriter) {
+        let scene = &mut writer.scene;
+        scene.camera_type = camera_type.to_string();
+        scene.camera_pos = ron_vector(self.look_from);
+        scene.camera_dir = ron_vector(self.look_at);
+        scene.camera_up = ron_vector(self.v_up);
+        scene.shutter_open = shutter.open;
+        scene.shutter_close = shutter.close;
+        scene.exposure = exposure;
+    }
+}
+
+// Right-handed view basis: `u` to the right, `v` up and `w` pointing back from the view direction.
+fn view_basis(look_from: Vec3, look_at: Vec3, v_up: Vec3) -> (Vec3, Vec3, Vec3) {
+    let w = (look_from - look_at).unit_vector();
+    let u = (v_up.cross(w)).unit_vector();
+    let v = w.cross(u);
+    (u, v, w)
+}
+
+/// Rays are spread uniformly over [open, close], objects move between times 0 and 1.
+#[derive(Debug, Copy, Clone)]
+pub struct Shutter {
+    pub open: f64,
+    pub close: f64,
+}
+
+impl Shutter {
+    pub fn closed() -> Shutter {
+        Shutter { open: 0.0, close: 0.0 }
+    }
+
+    fn sample(&self) -> f64 {
+        random_f64(self.open, self.close)
+    }
+}
+
+/// Thin lens perspective projection.
 #[allow(dead_code)]
-pub struct Camera {
+pub struct PerspectiveCamera {
     origin: Vec3,
     horizontal: Vec3,
     vertical: Vec3,
@@ -21,11 +144,18 @@ pub struct Camera {
     u: Vec3,
     v: Vec3,
     lens_radius: f64,
+    aperture: Aperture,
+    exposure: f64,
+    shutter: Shutter,
+    view: View,
+    v_fov: f64,
+    aspect_ratio: f64,
+    focus_dist: f64,
 }
 
-impl Camera {
-    pub fn new(look_from: Vec3, look_at: Vec3, v_up: Vec3, v_fov: f64, aspect_ratio: f64, aperature: f64, focus_dist: f64) -> Camera {
-        let theta = (v_fov * 3.14159) / 180.0;
+impl PerspectiveCamera {
+    pub fn new(look_from: Vec3, look_at: Vec3, v_up: Vec3, v_fov: f64, aspect_ratio: f64, aperature: f64, focus_dist: f64) -> PerspectiveCamera {
+        let theta = v_fov.to_radians();
         let h = (theta / 2.0).tan();
 
         let _viewport_height = 2.0 * h;
@@ -33,9 +163,7 @@ impl Camera {
         let _focal_length = 1.0;
 
 
-        let w = (look_from - look_at).unit_vector();
-        let u = (v_up.cross(w)).unit_vector();
-        let v = w.cross(u);
+        let (u, v, w) = view_basis(look_from, look_at, v_up);
 
         let _origin = look_from;
         let _horizontal = u * focus_dist * _viewport_width;
@@ -45,7 +173,7 @@ impl Camera {
 
         let lens_radius = aperature / 2.0;
 
-        Camera {
+        PerspectiveCamera {
             origin: _origin,
             horizontal: _horizontal,
             vertical: _vertical,
@@ -53,16 +181,420 @@ impl Camera {
             w,
             u,
             v,
-            lens_radius
+            lens_radius,
+            aperture: Aperture::Circle,
+            exposure: 1.0,
+            shutter: Shutter::closed(),
+            view: View {
+                look_from,
+                look_at,
+                v_up,
+            },
+            v_fov,
+            aspect_ratio,
+            focus_dist,
         }
     }
 
-    pub fn get_ray(&self, s: f64, t: f64) -> Ray {
-        let rd = random_in_unit_disk() * self.lens_radius;
+    pub fn with_aperture(mut self, aperture: Aperture) -> PerspectiveCamera {
+        self.aperture = aperture;
+        self
+    }
+
+    pub fn with_exposure(mut self, exposure: f64) -> PerspectiveCamera {
+        self.exposure = exposure;
+        self
+    }
+
+    pub fn with_shutter(mut self, shutter: Shutter) -> PerspectiveCamera {
+        self.shutter = shutter;
+        self
+    }
+}
+
+impl Camera for PerspectiveCamera {
+    fn get_ray(&self, s: f64, t: f64) -> Ray {
+        let rd = self.aperture.sample() * self.lens_radius;
         let offset = (self.u * rd.x()) + (self.v * rd.y());
         Ray::new(
-            self.origin.clone() + offset,
+            self.origin + offset,
             self.lower_left_corner + self.horizontal * s + self.vertical * t - self.origin - offset,
+            self.shutter.sample(),
+        )
+    }
+
+    fn exposure(&self) -> f64 {
+        self.exposure
+    }
+
+    fn export(&self, writer: &mut SceneWriter) {
+        self.view
+            .export("Perspective", self.shutter, self.exposure, writer);
+        writer.scene.v_fov = self.v_fov;
+        writer.scene.aspect_ratio = self.aspect_ratio;
+        writer.scene.aperture = self.lens_radius * 2.0;
+        writer.scene.focal_distance = self.focus_dist;
+        writer.scene.bokeh = match &self.aperture {
+            Aperture::Circle => None,
+            Aperture::Polygon(blades, rotation) => Some(RonBokeh {
+                blades: *blades,
+                rotation: *rotation,
+                texture: None,
+            }),
+            Aperture::Image(mask) => Some(RonBokeh {
+                blades: 0,
+                rotation: 0.0,
+                texture: Some(writer.texture(mask)),
+            }),
+        };
+    }
+}
+
+/// Physical lens and film settings in millimetres, seconds and ISO, scene units are metres.
+#[derive(Debug, Copy, Clone)]
+pub struct PhysicalLens {
+    pub focal_length: f64,
+    pub sensor_width: f64,
+    pub sensor_height: f64,
+    pub f_stop: f64,
+    pub iso: f64,
+    pub shutter_speed: f64,
+}
+
+impl PhysicalLens {
+    /// Vertical field of view in degrees of the image fitted inside the sensor.
+    pub fn v_fov(&self, aspect_ratio: f64) -> f64 {
+        let height = if aspect_ratio >= self.sensor_width / self.sensor_height {
+            self.sensor_width / aspect_ratio
+        } else {
+            self.sensor_height
+        };
+        (2.0 * (height / (2.0 * self.focal_length)).atan()).to_degrees()
+    }
+
+    /// Diameter of the entrance pupil in metres.
+    pub fn aperture(&self) -> f64 {
+        self.focal_length / self.f_stop / 1000.0
+    }
+
+    /// Calibrated with the sunny 16 rule: ISO 100, 1/100 s at f/16 gives an exposure of 1.
+    pub fn exposure(&self) -> f64 {
+        (self.iso / 100.0) * (self.shutter_speed * 100.0) * (16.0 / self.f_stop).powi(2)
+    }
+}
+
+/// Parallel rays through a `view_height` tall window centred on `look_from`, e.g. terrain map
+/// views from above.
+pub struct OrthographicCamera {
+    lower_left_corner: Vec3,
+    horizontal: Vec3,
+    vertical: Vec3,
+    direction: Vec3,
+    exposure: f64,
+    shutter: Shutter,
+    view: View,
+    view_height: f64,
+    aspect_ratio: f64,
+}
+
+impl OrthographicCamera {
+    pub fn new(look_from: Vec3, look_at: Vec3, v_up: Vec3, view_height: f64, aspect_ratio: f64) -> OrthographicCamera {
+        let (u, v, w) = view_basis(look_from, look_at, v_up);
+        let horizontal = u * view_height * aspect_ratio;
+        let vertical = v * view_height;
+        OrthographicCamera {
+            lower_left_corner: look_from - horizontal * 0.5 - vertical * 0.5,
+            horizontal,
+            vertical,
+            direction: -w,
+            exposure: 1.0,
+            shutter: Shutter::closed(),
+            view: View {
+                look_from,
+                look_at,
+                v_up,
+            },
+            view_height,
+            aspect_ratio,
+        }
+    }
+
+    pub fn with_exposure(mut self, exposure: f64) -> OrthographicCamera {
+        self.exposure = exposure;
+        self
+    }
+
+    pub fn with_shutter(mut self, shutter: Shutter) -> OrthographicCamera {
+        self.shutter = shutter;
+        self
+    }
+}
+
+impl Camera for OrthographicCamera {
+    fn get_ray(&self, s: f64, t: f64) -> Ray {
+        Ray::new(
+            self.lower_left_corner + self.horizontal * s + self.vertical * t,
+            self.direction,
+            self.shutter.sample(),
+        )
+    }
+
+    fn exposure(&self) -> f64 {
+        self.exposure
+    }
+
+    fn export(&self, writer: &mut SceneWriter) {
+        self.view
+            .export("Orthographic", self.shutter, self.exposure, writer);
+        writer.scene.ortho_height = self.view_height;
+        writer.scene.aspect_ratio = self.aspect_ratio;
+    }
+}
+
+/// Equidistant fisheye, the angle from the view direction grows linearly with the distance from
+/// the image centre and reaches `fov / 2` at the top and bottom edges.
+pub struct FisheyeCamera {
+    origin: Vec3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    // Degrees, as given.
+    fov: f64,
+    aspect_ratio: f64,
+    exposure: f64,
+    shutter: Shutter,
+    view: View,
+}
+
+impl FisheyeCamera {
+    pub fn new(look_from: Vec3, look_at: Vec3, v_up: Vec3, fov: f64, aspect_ratio: f64) -> FisheyeCamera {
+        let (u, v, w) = view_basis(look_from, look_at, v_up);
+        FisheyeCamera {
+            origin: look_from,
+            u,
+            v,
+            w,
+            fov,
+            aspect_ratio,
+            exposure: 1.0,
+            shutter: Shutter::closed(),
+            view: View {
+                look_from,
+                look_at,
+                v_up,
+            },
+        }
+    }
+
+    pub fn with_exposure(mut self, exposure: f64) -> FisheyeCamera {
+        self.exposure = exposure;
+        self
+    }
+
+    pub fn with_shutter(mut self, shutter: Shutter) -> FisheyeCamera {
+        self.shutter = shutter;
+        self
+    }
+}
+
+impl Camera for FisheyeCamera {
+    fn get_ray(&self, s: f64, t: f64) -> Ray {
+        let x = (s - 0.5) * self.aspect_ratio;
+        let y = t - 0.5;
+        let theta = (x * x + y * y).sqrt() * self.fov.to_radians();
+        let phi = y.atan2(x);
+        let direction = -self.w * theta.cos()
+            + (self.u * phi.cos() + self.v * phi.sin()) * theta.sin();
+        Ray::new(self.origin, direction, self.shutter.sample())
+    }
+
+    fn exposure(&self) -> f64 {
+        self.exposure
+    }
+
+    fn export(&self, writer: &mut SceneWriter) {
+        self.view
+            .export("Fisheye", self.shutter, self.exposure, writer);
+        writer.scene.v_fov = self.fov;
+        writer.scene.aspect_ratio = self.aspect_ratio;
+    }
+}
+
+/// Full 360 by 180 degree panorama, longitude across the image and latitude up it, centred on the
+/// view direction. Meant for 2:1 images.
+pub struct EquirectangularCamera {
+    origin: Vec3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    exposure: f64,
+    shutter: Shutter,
+    view: View,
+}
+
+impl EquirectangularCamera {
+    pub fn new(look_from: Vec3, look_at: Vec3, v_up: Vec3) -> EquirectangularCamera {
+        let (u, v, w) = view_basis(look_from, look_at, v_up);
+        EquirectangularCamera {
+            origin: look_from,
+            u,
+            v,
+            w,
+            exposure: 1.0,
+            shutter: Shutter::closed(),
+            view: View {
+                look_from,
+                look_at,
+                v_up,
+            },
+        }
+    }
+
+    pub fn with_exposure(mut self, exposure: f64) -> EquirectangularCamera {
+        self.exposure = exposure;
+        self
+    }
+
+    pub fn with_shutter(mut self, shutter: Shutter) -> EquirectangularCamera {
+        self.shutter = shutter;
+        self
+    }
+}
+
+impl Camera for EquirectangularCamera {
+    fn get_ray(&self, s: f64, t: f64) -> Ray {
+        let longitude = (s - 0.5) * 2.0 * PI;
+        let latitude = (t - 0.5) * PI;
+        let direction = -self.w * (latitude.cos() * longitude.cos())
+            + self.u * (latitude.cos() * longitude.sin())
+            + self.v * latitude.sin();
+        Ray::new(self.origin, direction, self.shutter.sample())
+    }
+
+    fn exposure(&self) -> f64 {
+        self.exposure
+    }
+
+    fn export(&self, writer: &mut SceneWriter) {
+        self.view
+            .export("Equirectangular", self.shutter, self.exposure, writer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn looking_down_z() -> (Vec3, Vec3, Vec3) {
+        (
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
         )
     }
+
+    fn assert_direction(camera: &dyn Camera, s: f64, t: f64, expected: Vec3) {
+        let direction = camera.get_ray(s, t).direction().unit_vector();
+        let expected = expected.unit_vector();
+        assert!(
+            (direction - expected).length() < 1e-9,
+            "({}, {}) looks along {:?}, not {:?}",
+            s,
+            t,
+            direction,
+            expected
+        );
+    }
+
+    #[test]
+    fn perspective_rays_spread_over_the_field_of_view() {
+        let (from, at, up) = looking_down_z();
+        let camera = PerspectiveCamera::new(from, at, up, 90.0, 2.0, 0.0, 1.0);
+        assert_direction(&camera, 0.5, 0.5, Vec3::new(0.0, 0.0, -1.0));
+        assert_direction(&camera, 0.0, 0.0, Vec3::new(-2.0, -1.0, -1.0));
+        assert_direction(&camera, 1.0, 1.0, Vec3::new(2.0, 1.0, -1.0));
+    }
+
+    #[test]
+    fn lens_rays_converge_on_the_focus_plane() {
+        let (from, at, up) = looking_down_z();
+        for aperture in [Aperture::Circle, Aperture::Polygon(6, 30.0)] {
+            let camera =
+                PerspectiveCamera::new(from, at, up, 90.0, 1.0, 2.0, 4.0).with_aperture(aperture);
+            for _ in 0..100 {
+                let ray = camera.get_ray(1.0, 0.5);
+                assert!(ray.origin().length() < 1.0 + 1e-9);
+                assert!((ray.at(1.0) - Vec3::new(4.0, 0.0, -4.0)).length() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn physical_lens_matches_photographic_rules() {
+        let lens = PhysicalLens {
+            focal_length: 18.0,
+            sensor_width: 36.0,
+            sensor_height: 24.0,
+            f_stop: 16.0,
+            iso: 100.0,
+            shutter_speed: 0.01,
+        };
+        // A 3:2 image fills the sensor, a square one is limited by its height.
+        assert!((lens.v_fov(1.5) - 2.0 * (24.0_f64 / 36.0).atan().to_degrees()).abs() < 1e-9);
+        assert!((lens.v_fov(1.0) - 2.0 * (24.0_f64 / 36.0).atan().to_degrees()).abs() < 1e-9);
+        assert!((lens.v_fov(3.0) - 2.0 * (12.0_f64 / 36.0).atan().to_degrees()).abs() < 1e-9);
+        assert!((lens.aperture() - 0.001125).abs() < 1e-12);
+        assert!((lens.exposure() - 1.0).abs() < 1e-12);
+        let brighter = PhysicalLens {
+            f_stop: 8.0,
+            iso: 200.0,
+            ..lens
+        };
+        assert!((brighter.exposure() - 8.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn orthographic_rays_are_parallel_and_start_across_the_window() {
+        let (from, at, up) = looking_down_z();
+        let camera = OrthographicCamera::new(from, at, up, 2.0, 2.0);
+        for (s, t, origin) in [
+            (0.5, 0.5, Vec3::new(0.0, 0.0, 0.0)),
+            (0.0, 0.0, Vec3::new(-2.0, -1.0, 0.0)),
+            (1.0, 0.0, Vec3::new(2.0, -1.0, 0.0)),
+            (1.0, 1.0, Vec3::new(2.0, 1.0, 0.0)),
+        ] {
+            assert_direction(&camera, s, t, Vec3::new(0.0, 0.0, -1.0));
+            assert!((camera.get_ray(s, t).origin() - origin).length() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn fisheye_angle_grows_linearly_from_the_centre() {
+        let (from, at, up) = looking_down_z();
+        let camera = FisheyeCamera::new(from, at, up, 180.0, 1.0);
+        assert_direction(&camera, 0.5, 0.5, Vec3::new(0.0, 0.0, -1.0));
+        // The edges are a quarter turn from the view direction.
+        assert_direction(&camera, 1.0, 0.5, Vec3::new(1.0, 0.0, 0.0));
+        assert_direction(&camera, 0.5, 1.0, Vec3::new(0.0, 1.0, 0.0));
+        assert_direction(&camera, 0.5, 0.0, Vec3::new(0.0, -1.0, 0.0));
+        // The corners are further out and look backwards.
+        let theta = 0.5_f64.sqrt() * PI;
+        let side = theta.sin() * 0.5_f64.sqrt();
+        assert_direction(&camera, 1.0, 1.0, Vec3::new(side, side, -theta.cos()));
+        assert_direction(&camera, 0.0, 0.0, Vec3::new(-side, -side, -theta.cos()));
+    }
+
+    #[test]
+    fn equirectangular_rays_cover_the_whole_sphere() {
+        let (from, at, up) = looking_down_z();
+        let camera = EquirectangularCamera::new(from, at, up);
+        assert_direction(&camera, 0.5, 0.5, Vec3::new(0.0, 0.0, -1.0));
+        assert_direction(&camera, 0.75, 0.5, Vec3::new(1.0, 0.0, 0.0));
+        assert_direction(&camera, 0.25, 0.5, Vec3::new(-1.0, 0.0, 0.0));
+        // Both side edges meet behind the camera, the top and bottom edges at the poles.
+        assert_direction(&camera, 0.0, 0.5, Vec3::new(0.0, 0.0, 1.0));
+        assert_direction(&camera, 1.0, 0.5, Vec3::new(0.0, 0.0, 1.0));
+        assert_direction(&camera, 0.0, 0.0, Vec3::new(0.0, -1.0, 0.0));
+        assert_direction(&camera, 1.0, 1.0, Vec3::new(0.0, 1.0, 0.0));
+    }
 }
@@ -0,0 +1,683 @@
+// C interface to the renderer, declared in include/raytracing.h. Pointers passed in must be
+// null or valid for the call, strings NUL terminated UTF-8, and scenes are not used from two
+// threads at once apart from rendering. Panics are caught at the boundary and reported through
+// `rt_last_error` on the calling thread.
+#![allow(clippy::missing_safety_doc)]
+
+use crate::bvh::Bvh;
+use crate::camera::{Camera, PerspectiveCamera};
+use crate::configuration::RaytracerScene;
+use crate::cube::Cube;
+use crate::hittable::Hittable;
+use crate::material::Material;
+use crate::mesh_file::{MeshData, MeshError};
+use crate::plane::Plane;
+use crate::scene::{RenderControl, RenderSettings, Scene};
+use crate::sphere::Sphere;
+use crate::texture::SolidColor;
+use crate::triangle::Triangle;
+use crate::vec3::Vec3;
+use std::cell::RefCell;
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::Arc;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RtStatus {
+    Ok = 0,
+    NullArgument = 1,
+    InvalidArgument = 2,
+    // The render stopped early, pixels it did not reach are black.
+    Cancelled = 3,
+    Failed = 4,
+    // A file argument could not be read.
+    IoError = 5,
+    // A file argument was read but is not in a format it should be in.
+    InvalidFormat = 6,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct RtVec3 {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+// Only constructed on the C side.
+#[allow(dead_code)]
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RtMaterialKind {
+    Lambertian = 0,
+    Metal = 1,
+    Dielectric = 2,
+    Mirror = 3,
+}
+
+// `color` is used by Lambertian and Metal, `fuzz` by Metal and `ior` by Dielectric.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct RtMaterial {
+    pub kind: RtMaterialKind,
+    pub color: RtVec3,
+    pub fuzz: f64,
+    pub ior: f64,
+}
+
+// Called with the finished fraction of rows, from the rendering threads one call at a time.
+pub type RtProgressFn = Option<extern "C" fn(fraction: f64, user_data: *mut c_void)>;
+
+// Scene built up through the `rt_scene_*` calls, it can be rendered any number of times.
+pub struct RtScene {
+    settings: RenderSettings,
+    camera: Option<Arc<dyn Camera>>,
+    objects: Vec<Arc<dyn Hittable + Send + Sync>>,
+    lights: Vec<Vec3>,
+}
+
+// Cancels the renders it is passed to when requested, from any thread.
+pub struct RtCancel {
+    control: RenderControl,
+}
+
+// The progress callback's user data, handed back untouched on the rendering threads.
+struct UserData(*mut c_void);
+unsafe impl Send for UserData {}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: &str) {
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|error| *error.borrow_mut() = Some(message));
+}
+
+fn fail(status: RtStatus, message: &str) -> RtStatus {
+    set_last_error(message);
+    status
+}
+
+// Runs `f`, turning a panic into `RtStatus::Failed` with its message as the last error.
+fn guard(f: impl FnOnce() -> RtStatus) -> RtStatus {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(status) => status,
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<String>()
+                .map(String::as_str)
+                .or_else(|| payload.downcast_ref::<&str>().copied())
+                .unwrap_or("render failed");
+            fail(RtStatus::Failed, message)
+        }
+    }
+}
+
+fn vec3(v: RtVec3) -> Vec3 {
+    Vec3::new(v.x, v.y, v.z)
+}
+
+fn material(material: &RtMaterial) -> Material {
+    let color = Arc::new(SolidColor::new(vec3(material.color)));
+    match material.kind {
+        RtMaterialKind::Lambertian => Material::Lambertian(color),
+        RtMaterialKind::Metal => {
+            let fuzz = material.fuzz;
+            Material::Metal(
+                color,
+                Arc::new(SolidColor::new(Vec3::new(fuzz, fuzz, fuzz))),
+            )
+        }
+        RtMaterialKind::Dielectric => Material::Dielectric(material.ior),
+        RtMaterialKind::Mirror => Material::Mirror,
+    }
+}
+
+unsafe fn add_object(
+    scene: *mut RtScene,
+    mat: *const RtMaterial,
+    object: impl FnOnce(Material) -> Arc<dyn Hittable + Send + Sync>,
+) -> RtStatus {
+    let (scene, mat) = match (scene.as_mut(), mat.as_ref()) {
+        (Some(scene), Some(mat)) => (scene, mat),
+        _ => {
+            return fail(
+                RtStatus::NullArgument,
+                "scene and material must not be null",
+            )
+        }
+    };
+    guard(|| {
+        scene.objects.push(object(material(mat)));
+        RtStatus::Ok
+    })
+}
+
+unsafe fn string_argument<'a>(string: *const c_char) -> Result<&'a str, RtStatus> {
+    if string.is_null() {
+        return Err(fail(RtStatus::NullArgument, "string must not be null"));
+    }
+    CStr::from_ptr(string)
+        .to_str()
+        .map_err(|_| fail(RtStatus::InvalidArgument, "string is not UTF-8"))
+}
+
+// Progress and cancellation of one render, sharing the cancellation state of `cancel`.
+unsafe fn render_control(
+    progress: RtProgressFn,
+    user_data: *mut c_void,
+    cancel: *const RtCancel,
+) -> RenderControl {
+    let control = match cancel.as_ref() {
+        Some(cancel) => cancel.control.clone(),
+        None => RenderControl::new(),
+    };
+    match progress {
+        Some(progress) => {
+            let user_data = UserData(user_data);
+            control.on_progress(move |fraction| {
+                let user_data = &user_data;
+                progress(fraction, user_data.0)
+            })
+        }
+        None => control,
+    }
+}
+
+// The caller's buffer for the image, checked to hold at least width * height * 3 bytes before
+// anything is rendered.
+unsafe fn image_buffer<'a>(
+    settings: &RenderSettings,
+    buffer: *mut u8,
+    buffer_len: usize,
+) -> Result<&'a mut [u8], RtStatus> {
+    let needed = settings.image_width as usize * settings.image_height as usize * 3;
+    if buffer.is_null() {
+        return Err(fail(RtStatus::NullArgument, "buffer must not be null"));
+    }
+    if buffer_len < needed {
+        return Err(fail(
+            RtStatus::InvalidArgument,
+            &format!(
+                "buffer holds {} bytes, the image needs {}",
+                buffer_len, needed
+            ),
+        ));
+    }
+    Ok(std::slice::from_raw_parts_mut(buffer, needed))
+}
+
+// Renders `scene` into `buffer`, which is the size of its image.
+fn render_into(scene: Scene, control: &RenderControl, buffer: &mut [u8]) -> RtStatus {
+    buffer.copy_from_slice(&scene.render_rgb(control));
+    if control.is_cancelled() {
+        RtStatus::Cancelled
+    } else {
+        RtStatus::Ok
+    }
+}
+
+// Message of the last failed call on this thread, valid until the next failure. Null if none.
+#[no_mangle]
+pub extern "C" fn rt_last_error() -> *const c_char {
+    LAST_ERROR.with(|error| {
+        error
+            .borrow()
+            .as_ref()
+            .map_or(std::ptr::null(), |message| message.as_ptr())
+    })
+}
+
+// Null when the image size is not positive.
+#[no_mangle]
+pub extern "C" fn rt_scene_new(
+    width: i32,
+    height: i32,
+    samples_per_pixel: u32,
+    max_depth: i32,
+    multithreading: bool,
+) -> *mut RtScene {
+    if width <= 0 || height <= 0 {
+        set_last_error("image size must be positive");
+        return std::ptr::null_mut();
+    }
+    Box::into_raw(Box::new(RtScene {
+        settings: RenderSettings {
+            image_width: width,
+            image_height: height,
+            samples_per_pixel: samples_per_pixel as usize,
+            max_depth,
+            multithreading,
+        },
+        camera: None,
+        objects: vec![],
+        lights: vec![],
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_free(scene: *mut RtScene) {
+    if !scene.is_null() {
+        drop(Box::from_raw(scene));
+    }
+}
+
+// Perspective camera, the aspect ratio follows the image size.
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_set_camera(
+    scene: *mut RtScene,
+    look_from: RtVec3,
+    look_at: RtVec3,
+    v_up: RtVec3,
+    v_fov: f64,
+    aperture: f64,
+    focus_distance: f64,
+) -> RtStatus {
+    let scene = match scene.as_mut() {
+        Some(scene) => scene,
+        None => return fail(RtStatus::NullArgument, "scene must not be null"),
+    };
+    scene.camera = Some(Arc::new(PerspectiveCamera::new(
+        vec3(look_from),
+        vec3(look_at),
+        vec3(v_up),
+        v_fov,
+        scene.settings.aspect_ratio(),
+        aperture,
+        focus_distance,
+    )));
+    RtStatus::Ok
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_add_sphere(
+    scene: *mut RtScene,
+    center: RtVec3,
+    radius: f64,
+    material: *const RtMaterial,
+) -> RtStatus {
+    add_object(scene, material, |mat| {
+        Arc::new(Sphere::new(vec3(center), radius, mat))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_add_plane(
+    scene: *mut RtScene,
+    point: RtVec3,
+    normal: RtVec3,
+    material: *const RtMaterial,
+) -> RtStatus {
+    add_object(scene, material, |mat| {
+        Arc::new(Plane::new(vec3(point), vec3(normal), mat))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_add_triangle(
+    scene: *mut RtScene,
+    a: RtVec3,
+    b: RtVec3,
+    c: RtVec3,
+    material: *const RtMaterial,
+) -> RtStatus {
+    add_object(scene, material, |mat| {
+        Arc::new(Triangle::new(vec3(a), vec3(b), vec3(c), mat, false))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_add_cube(
+    scene: *mut RtScene,
+    corner1: RtVec3,
+    corner2: RtVec3,
+    material: *const RtMaterial,
+) -> RtStatus {
+    add_object(scene, material, |mat| {
+        Arc::new(Cube::new(vec3(corner1), vec3(corner2), mat))
+    })
+}
+
+// PLY or STL file, vertex colors replace the material color. Files that cannot be read give
+// `IoError` and files that are not meshes `InvalidFormat`.
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_add_mesh(
+    scene: *mut RtScene,
+    path: *const c_char,
+    material: *const RtMaterial,
+) -> RtStatus {
+    let path = match string_argument(path) {
+        Ok(path) => path,
+        Err(status) => return status,
+    };
+    let mesh = match MeshData::read(path) {
+        Ok(mesh) => mesh,
+        Err(e) => {
+            let status = match e {
+                MeshError::Io(_) => RtStatus::IoError,
+                MeshError::Format(_) => RtStatus::InvalidFormat,
+            };
+            return fail(status, &format!("failed to load mesh {}: {}", path, e));
+        }
+    };
+    add_object(scene, material, |mat| {
+        Arc::new(Bvh::new(mesh.triangles(&mat)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_add_light(scene: *mut RtScene, position: RtVec3) -> RtStatus {
+    match scene.as_mut() {
+        Some(scene) => {
+            scene.lights.push(vec3(position));
+            RtStatus::Ok
+        }
+        None => fail(RtStatus::NullArgument, "scene must not be null"),
+    }
+}
+
+// Renders RGB rows top to bottom into `buffer`. `progress`, `user_data` and `cancel` may be null.
+#[no_mangle]
+pub unsafe extern "C" fn rt_scene_render(
+    scene: *const RtScene,
+    buffer: *mut u8,
+    buffer_len: usize,
+    progress: RtProgressFn,
+    user_data: *mut c_void,
+    cancel: *const RtCancel,
+) -> RtStatus {
+    let scene = match scene.as_ref() {
+        Some(scene) => scene,
+        None => return fail(RtStatus::NullArgument, "scene must not be null"),
+    };
+    let buffer = match image_buffer(&scene.settings, buffer, buffer_len) {
+        Ok(buffer) => buffer,
+        Err(status) => return status,
+    };
+    let control = render_control(progress, user_data, cancel);
+    guard(|| {
+        let mut builder = Scene::builder().settings(scene.settings);
+        if let Some(camera) = &scene.camera {
+            builder = builder.camera(camera.clone());
+        }
+        for object in &scene.objects {
+            builder = builder.add(object.clone());
+        }
+        for light in &scene.lights {
+            builder = builder.light(*light);
+        }
+        render_into(builder.build(), &control, buffer)
+    })
+}
+
+// Image size of a RON scene, to allocate the buffer for `rt_render_ron`.
+#[no_mangle]
+pub unsafe extern "C" fn rt_ron_image_size(
+    ron: *const c_char,
+    width: *mut i32,
+    height: *mut i32,
+) -> RtStatus {
+    let ron = match string_argument(ron) {
+        Ok(ron) => ron,
+        Err(status) => return status,
+    };
+    if width.is_null() || height.is_null() {
+        return fail(RtStatus::NullArgument, "width and height must not be null");
+    }
+    guard(|| {
+        let settings = RaytracerScene::from_ron(ron.to_string());
+        *width = settings.image_width;
+        *height = settings.image_height;
+        RtStatus::Ok
+    })
+}
+
+// Renders a RON scene like `create_image` into `buffer`, see `rt_scene_render`.
+#[no_mangle]
+pub unsafe extern "C" fn rt_render_ron(
+    ron: *const c_char,
+    buffer: *mut u8,
+    buffer_len: usize,
+    progress: RtProgressFn,
+    user_data: *mut c_void,
+    cancel: *const RtCancel,
+) -> RtStatus {
+    let ron = match string_argument(ron) {
+        Ok(ron) => ron,
+        Err(status) => return status,
+    };
+    let control = render_control(progress, user_data, cancel);
+    guard(|| {
+        let scene = Scene::from_ron(ron.to_string());
+        match image_buffer(&scene.settings(), buffer, buffer_len) {
+            Ok(buffer) => render_into(scene, &control, buffer),
+            Err(status) => status,
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn rt_cancel_new() -> *mut RtCancel {
+    Box::into_raw(Box::new(RtCancel {
+        control: RenderControl::new(),
+    }))
+}
+
+// Safe to call while a render using `cancel` runs on another thread.
+#[no_mangle]
+pub unsafe extern "C" fn rt_cancel_request(cancel: *const RtCancel) {
+    if let Some(cancel) = cancel.as_ref() {
+        cancel.control.cancel();
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rt_cancel_free(cancel: *mut RtCancel) {
+    if !cancel.is_null() {
+        drop(Box::from_raw(cancel));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aabb::Aabb;
+    use crate::hittable::HitRecord;
+    use crate::ray::Ray;
+
+    // Sphere in front of the sky on an 8x6 image.
+    fn scene(multithreading: bool) -> *mut RtScene {
+        let at = |x, y, z| RtVec3 { x, y, z };
+        let red = RtMaterial {
+            kind: RtMaterialKind::Lambertian,
+            color: at(0.8, 0.1, 0.1),
+            fuzz: 0.0,
+            ior: 1.0,
+        };
+        let scene = rt_scene_new(8, 6, 1, 4, multithreading);
+        unsafe {
+            rt_scene_set_camera(
+                scene,
+                at(0.0, 0.0, 3.0),
+                at(0.0, 0.0, 0.0),
+                at(0.0, 1.0, 0.0),
+                40.0,
+                0.0,
+                3.0,
+            );
+            assert_eq!(
+                rt_scene_add_sphere(scene, at(0.0, 0.0, 0.0), 0.5, &red),
+                RtStatus::Ok
+            );
+        }
+        scene
+    }
+
+    fn last_error() -> String {
+        unsafe { CStr::from_ptr(rt_last_error()) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    extern "C" fn record(fraction: f64, user_data: *mut c_void) {
+        unsafe { &mut *(user_data as *mut Vec<f64>) }.push(fraction);
+    }
+
+    extern "C" fn cancel_after_first_row(_fraction: f64, user_data: *mut c_void) {
+        unsafe { rt_cancel_request(user_data as *const RtCancel) };
+    }
+
+    #[derive(Debug)]
+    struct Broken;
+
+    impl Hittable for Broken {
+        fn hit(&self, _r: Ray, _t_min: f64, _t_max: f64, _rec: &mut HitRecord) -> bool {
+            panic!("broken primitive")
+        }
+
+        fn bounding_box(&self) -> Option<Aabb> {
+            None
+        }
+    }
+
+    #[test]
+    fn committed_header_is_up_to_date() {
+        let generated = concat!(env!("OUT_DIR"), "/raytracing.h");
+        assert!(
+            include_str!(concat!(env!("OUT_DIR"), "/raytracing.h"))
+                == include_str!("../include/raytracing.h"),
+            "include/raytracing.h is out of date, copy {} over it",
+            generated
+        );
+    }
+
+    #[test]
+    fn render_reports_progress() {
+        let scene = scene(false);
+        let mut buffer = vec![0_u8; 8 * 6 * 3];
+        let mut progress = vec![];
+        let status = unsafe {
+            rt_scene_render(
+                scene,
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                Some(record),
+                &mut progress as *mut Vec<f64> as *mut c_void,
+                std::ptr::null(),
+            )
+        };
+        unsafe { rt_scene_free(scene) };
+        assert_eq!(status, RtStatus::Ok);
+        assert_eq!(progress.len(), 6);
+        assert!(progress.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(progress.last(), Some(&1.0));
+        assert!(buffer[buffer.len() - 24..].iter().any(|&value| value > 0));
+    }
+
+    #[test]
+    fn cancelled_render_leaves_the_rest_black() {
+        let scene = scene(false);
+        let cancel = rt_cancel_new();
+        let mut buffer = vec![0_u8; 8 * 6 * 3];
+        let status = unsafe {
+            rt_scene_render(
+                scene,
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                Some(cancel_after_first_row),
+                cancel as *mut c_void,
+                cancel,
+            )
+        };
+        unsafe {
+            rt_scene_free(scene);
+            rt_cancel_free(cancel);
+        }
+        assert_eq!(status, RtStatus::Cancelled);
+        assert!(buffer[..24].iter().any(|&value| value > 0));
+        assert!(buffer[24..].iter().all(|&value| value == 0));
+    }
+
+    #[test]
+    fn small_buffer_is_rejected() {
+        let scene = scene(false);
+        let mut buffer = vec![0_u8; 8 * 6 * 3 - 1];
+        let status = unsafe {
+            rt_scene_render(
+                scene,
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                None,
+                std::ptr::null_mut(),
+                std::ptr::null(),
+            )
+        };
+        unsafe { rt_scene_free(scene) };
+        assert_eq!(status, RtStatus::InvalidArgument);
+        assert_eq!(last_error(), "buffer holds 143 bytes, the image needs 144");
+    }
+
+    #[test]
+    fn bad_mesh_files_report_why_they_failed() {
+        let scene = scene(false);
+        let mirror = RtMaterial {
+            kind: RtMaterialKind::Mirror,
+            color: RtVec3 {
+                x: 1.0,
+                y: 1.0,
+                z: 1.0,
+            },
+            fuzz: 0.0,
+            ior: 1.0,
+        };
+        let dir = std::env::temp_dir();
+        let missing = dir.join("capi_missing.stl").display().to_string();
+        let status = unsafe {
+            rt_scene_add_mesh(
+                scene,
+                CString::new(missing.clone()).unwrap().as_ptr(),
+                &mirror,
+            )
+        };
+        assert_eq!(status, RtStatus::IoError);
+        assert!(last_error().starts_with(&format!("failed to load mesh {}: ", missing)));
+
+        let garbage = dir.join("capi_garbage.ply");
+        std::fs::write(&garbage, "not a mesh").unwrap();
+        let garbage = garbage.display().to_string();
+        let status = unsafe {
+            rt_scene_add_mesh(
+                scene,
+                CString::new(garbage.clone()).unwrap().as_ptr(),
+                &mirror,
+            )
+        };
+        assert_eq!(status, RtStatus::InvalidFormat);
+        assert!(last_error().starts_with(&format!("failed to load mesh {}: ", garbage)));
+
+        assert_eq!(unsafe { &*scene }.objects.len(), 1);
+        unsafe { rt_scene_free(scene) };
+    }
+
+    #[test]
+    fn panic_on_a_rendering_thread_fails_the_render() {
+        let scene = scene(true);
+        unsafe { &mut *scene }.objects.push(Arc::new(Broken));
+        let mut buffer = vec![0_u8; 8 * 6 * 3];
+        let status = unsafe {
+            rt_scene_render(
+                scene,
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                None,
+                std::ptr::null_mut(),
+                std::ptr::null(),
+            )
+        };
+        unsafe { rt_scene_free(scene) };
+        assert_eq!(status, RtStatus::Failed);
+        assert_eq!(last_error(), "broken primitive");
+    }
+}
@@ -0,0 +1,195 @@
+use crate::aabb::Aabb;
+use crate::frame::{azimuth_u, azimuthal_frame, local_intervals, Frame, LocalHit};
+use crate::hittable;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::scene_export::SceneWriter;
+use crate::vec3::Vec3;
+
+/// Cone with its capped base on `base` and apex `height` along `axis`.
+#[derive(Debug, Clone)]
+pub struct Cone {
+    frame: Frame,
+    radius: f64,
+    height: f64,
+    material: Material,
+}
+
+impl Cone {
+    pub fn new(base: Vec3, axis: Vec3, radius: f64, height: f64, mat: Material) -> Cone {
+        Cone {
+            frame: Frame::new(base, axis),
+            radius,
+            height,
+            material: mat,
+        }
+    }
+
+    /// Every intersection of the local ray with the side and base, sorted by distance.
+    pub fn local_hits(&self, ray: Ray) -> Vec<LocalHit> {
+        let o = ray.origin();
+        let d = ray.direction();
+        let k = self.radius / self.height;
+        let k2 = k * k;
+        let mut hits = vec![];
+
+        // x^2 + z^2 = k^2 (h - y)^2
+        let oy = self.height - o.y();
+        let a = d.x() * d.x() + d.z() * d.z() - k2 * d.y() * d.y();
+        let half_b = o.x() * d.x() + o.z() * d.z() + k2 * oy * d.y();
+        let c = o.x() * o.x() + o.z() * o.z() - k2 * oy * oy;
+        let mut roots = vec![];
+        if a.abs() > 1e-12 {
+            let discriminant = half_b * half_b - a * c;
+            if discriminant > 0.0 {
+                let root = discriminant.sqrt();
+                roots.push((-half_b - root) / a);
+                roots.push((-half_b + root) / a);
+            }
+        } else if half_b.abs() > 1e-12 {
+            roots.push(-c / (2.0 * half_b));
+        }
+        for t in roots {
+            let p = o + d * t;
+            if p.y() >= 0.0 && p.y() <= self.height {
+                let normal = Vec3::new(p.x(), k2 * (self.height - p.y()), p.z());
+                if normal.length_squared() > 1e-18 {
+                    hits.push(LocalHit {
+                        t,
+                        normal: normal.unit_vector(),
+                        uv: (azimuth_u(p), p.y() / self.height),
+                    });
+                }
+            }
+        }
+
+        if d.y().abs() > 1e-12 {
+            let t = -o.y() / d.y();
+            let p = o + d * t;
+            if p.x() * p.x() + p.z() * p.z() <= self.radius * self.radius {
+                hits.push(LocalHit {
+                    t,
+                    normal: Vec3::new(0.0, -1.0, 0.0),
+                    uv: (
+                        0.5 + p.x() / (2.0 * self.radius),
+                        0.5 + p.z() / (2.0 * self.radius),
+                    ),
+                });
+            }
+        }
+
+        hits.sort_by(|a, b| a.t.partial_cmp(&b.t).unwrap());
+        hits
+    }
+}
+
+impl hittable::Hittable for Cone {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, rec: &mut hittable::HitRecord) -> bool {
+        let local_ray = self.frame.ray_to_local(ray);
+        let hit = match self
+            .local_hits(local_ray)
+            .into_iter()
+            .find(|hit| hit.t > t_min && hit.t < t_max)
+        {
+            Some(hit) => hit,
+            None => return false,
+        };
+
+        let outward_normal = self.frame.dir_to_world(hit.normal);
+        let (tangent, bitangent) = if hit.normal.y() < -0.999 {
+            let tangent = self.frame.tangent;
+            (tangent, outward_normal.cross(tangent))
+        } else {
+            azimuthal_frame(self.frame.axis, outward_normal)
+        };
+        rec.t = Some(hit.t);
+        rec.p = Some(ray.at(hit.t));
+        rec.set_face_normal(ray, outward_normal);
+        rec.material = Some(self.material.clone());
+        rec.uv = Some(hit.uv);
+        rec.tangent = Some(tangent);
+        rec.bitangent = Some(bitangent);
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.frame.bounds(
+            Vec3::new(-self.radius, 0.0, -self.radius),
+            Vec3::new(self.radius, self.height, self.radius),
+        ))
+    }
+
+    fn intervals(&self, ray: Ray) -> Option<Vec<hittable::Interval>> {
+        Some(local_intervals(
+            &self.local_hits(self.frame.ray_to_local(ray)),
+        ))
+    }
+
+    fn export(&self, writer: &mut SceneWriter) {
+        let vectors = [self.frame.origin, self.frame.axis];
+        writer.primitive(
+            "Cone",
+            &vectors,
+            &[self.radius, self.height],
+            &self.material,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::{HitRecord, Hittable};
+
+    // Radius 1 and height 1, so the side is at 45 degrees.
+    fn cast(origin: Vec3, direction: Vec3) -> Option<HitRecord> {
+        let cone = Cone::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            1.0,
+            1.0,
+            Material::Mirror,
+        );
+        let mut rec = HitRecord::new();
+        let ray = Ray::new(origin, direction, 0.0);
+        cone.hit(ray, 0.001, f64::INFINITY, &mut rec).then_some(rec)
+    }
+
+    #[test]
+    fn hits_the_side_and_the_base() {
+        let rec = cast(Vec3::new(5.0, 0.5, 0.0), Vec3::new(-1.0, 0.0, 0.0)).unwrap();
+        assert_eq!(rec.t, Some(4.5));
+        let slope = Vec3::new(1.0, 1.0, 0.0).unit_vector();
+        assert!((rec.normal.unwrap() - slope).length() < 1e-9);
+        assert_eq!(rec.uv.unwrap().1, 0.5);
+
+        let rec = cast(Vec3::new(0.2, -3.0, 0.0), Vec3::new(0.0, 1.0, 0.0)).unwrap();
+        assert_eq!(rec.t, Some(3.0));
+        assert_eq!(rec.normal, Some(Vec3::new(0.0, -1.0, 0.0)));
+
+        assert!(cast(Vec3::new(5.0, 0.5, 0.6), Vec3::new(-1.0, 0.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn grazing_the_apex() {
+        let right = Vec3::new(1.0, 0.0, 0.0);
+        // Touching only the apex is not a hit.
+        assert!(cast(Vec3::new(-2.0, 1.0, 0.0), right).is_none());
+        assert!(cast(Vec3::new(-2.0, 1.001, 0.0), right).is_none());
+
+        // Just below it the side is hit with a well defined normal.
+        let rec = cast(Vec3::new(-2.0, 0.99, 0.0), right).unwrap();
+        assert!((rec.t.unwrap() - 1.99).abs() < 1e-9);
+        let normal = rec.normal.unwrap();
+        assert!(
+            (normal.length() - 1.0).abs() < 1e-9 && normal.x() < 0.0,
+            "{:?}",
+            normal
+        );
+
+        // Along the axis the ray passes the apex and leaves through the base.
+        let rec = cast(Vec3::new(0.0, 3.0, 0.0), Vec3::new(0.0, -1.0, 0.0)).unwrap();
+        assert_eq!(rec.t, Some(3.0));
+        assert_eq!(rec.get_front_face(), Some(false));
+    }
+}
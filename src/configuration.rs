@@ -1,3 +1,4 @@
+use crate::scene_file;
 use crate::vec3::Vec3;
 use ron::from_str;
 use ron::ser::{to_string_pretty, PrettyConfig};
@@ -45,7 +46,7 @@ impl RaytracerSettings {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
 pub struct RaytracerScene {
     pub multithreading: bool,
     pub aspect_ratio: f64,
@@ -59,27 +60,362 @@ pub struct RaytracerScene {
     pub camera_pos: Vec<f64>,
     pub camera_dir: Vec<f64>,
     pub camera_up: Vec<f64>,
+    // "Perspective", "Orthographic", "Fisheye", "Equirectangular", or "Gltf" for the first camera
+    // of the first imported glTF file.
+    #[serde(default = "RaytracerScene::perspective")]
+    pub camera_type: String,
+    // World space height of the orthographic view, 0 matches the perspective framing at the
+    // focal distance.
+    #[serde(default)]
+    pub ortho_height: f64,
+    // Sets the exposure of any camera, and replaces `v_fov` and `aperture` of a perspective one.
+    #[serde(default)]
+    pub physical_camera: Option<RonPhysicalCamera>,
+    // Focus on the surface under the image centre instead of at `focal_distance`.
+    #[serde(default)]
+    pub autofocus: bool,
+    #[serde(default)]
+    pub bokeh: Option<RonBokeh>,
+    #[serde(default)]
+    pub shutter_open: f64,
+    #[serde(default)]
+    pub shutter_close: f64,
+    // Scale applied to the radiance reaching the image, on top of the physical camera's.
+    #[serde(default = "RaytracerScene::unit_exposure")]
+    pub exposure: f64,
     pub objects: Vec<RonObject>,
     pub lights: Vec<Vec<f64>>,
+    #[serde(default)]
+    pub textures: Vec<RonTexture>,
+    #[serde(default)]
+    pub geometries: Vec<RonGeometry>,
+    // Named materials that objects can use in place of a material type.
+    #[serde(default)]
+    pub materials: Vec<RonMaterial>,
+    // Keyframes for sequence rendering, a single image ignores it.
+    #[serde(default)]
+    pub animation: Option<RonAnimation>,
 }
 
-#[derive(Clone, Serialize, Deserialize, Debug)]
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
 pub struct RonObject {
     pub objtype: String,
+    // Lets animation tracks refer to the object.
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub vectors: Vec<Vec<f64>>,
+    #[serde(default)]
+    pub scalars: Vec<f64>,
+    // Empty on nodes and instances that keep the materials of their geometry.
+    #[serde(default)]
+    pub material: Vec<String>,
+    #[serde(default)]
+    pub transform: Option<RonTransform>,
+    // Placement at time 1, the object moves from `transform` during the shutter interval.
+    #[serde(default)]
+    pub transform_end: Option<RonTransform>,
+    // Child objects of a `Node` or the operands of a CSG operation, placed relative to its
+    // transform.
+    #[serde(default)]
+    pub children: Vec<RonObject>,
+    // Name of the shared geometry placed by an `Instance`.
+    #[serde(default)]
+    pub geometry: Option<String>,
+    // Distance function traced by an `Sdf` object.
+    #[serde(default)]
+    pub sdf: Option<RonSdf>,
+    // File an object is loaded from, e.g. the heightmap of a `Heightfield`.
+    #[serde(default)]
+    pub path: Option<String>,
+    // Placement of the `geometry` copies spread by a `Scatter`.
+    #[serde(default)]
+    pub scatter: Option<RonScatter>,
+    // Triangles of a `Mesh` given inline instead of by `path`.
+    #[serde(default)]
+    pub mesh: Option<RonMesh>,
+    // Rows of a `Heightfield` given inline, in place of a heightmap file or noise.
+    #[serde(default)]
+    pub heights: Vec<Vec<f64>>,
+}
+
+impl RonObject {
+    // Object of the given type with everything else left at its default.
+    pub fn new(objtype: &str) -> RonObject {
+        RonObject {
+            objtype: objtype.to_string(),
+            name: None,
+            vectors: vec![],
+            scalars: vec![],
+            material: vec![],
+            transform: None,
+            transform_end: None,
+            children: vec![],
+            geometry: None,
+            sdf: None,
+            path: None,
+            scatter: None,
+            mesh: None,
+            heights: vec![],
+        }
+    }
+}
+
+// Unindexed triangles, three corners each. Normals, uvs and colors are per corner and either
+// empty or given for every corner, colors replace the color slot of the material. Colors are
+// blended by the default uvs, so a mesh has either colors or uvs.
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
+pub struct RonMesh {
+    pub positions: Vec<Vec<f64>>,
+    #[serde(default)]
+    pub normals: Vec<Vec<f64>>,
+    #[serde(default)]
+    pub uvs: Vec<Vec<f64>>,
+    #[serde(default)]
+    pub colors: Vec<Vec<f64>>,
+    #[serde(default)]
+    pub cull_back: bool,
+}
+
+// Spreads instances over the triangles and heightfields of the `surface` geometry. Slopes are in
+// degrees from straight up, and a zero spacing keeps every sampled point.
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
+pub struct RonScatter {
+    pub surface: String,
+    pub density: f64,
+    #[serde(default)]
+    pub seed: u32,
+    #[serde(default)]
+    pub spacing: f64,
+    #[serde(default = "RonScatter::any_slope")]
+    pub slope: (f64, f64),
+    #[serde(default = "RonScatter::any_altitude")]
+    pub altitude: (f64, f64),
+    #[serde(default = "RonScatter::full_turn")]
+    pub rotation_jitter: f64,
+    #[serde(default = "RonScatter::unit_scale")]
+    pub scale: (f64, f64),
+    #[serde(default)]
+    pub align_to_normal: bool,
+}
+
+impl RonScatter {
+    fn any_slope() -> (f64, f64) {
+        (0.0, 180.0)
+    }
+    fn any_altitude() -> (f64, f64) {
+        (f64::NEG_INFINITY, f64::INFINITY)
+    }
+    fn full_turn() -> f64 {
+        180.0
+    }
+    fn unit_scale() -> (f64, f64) {
+        (1.0, 1.0)
+    }
+}
+
+// Node of a signed distance function: a primitive, a combinator over its children or a
+// transform of its single child.
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
+pub struct RonSdf {
+    pub shape: String,
+    #[serde(default)]
     pub vectors: Vec<Vec<f64>>,
+    #[serde(default)]
     pub scalars: Vec<f64>,
+    #[serde(default)]
+    pub children: Vec<RonSdf>,
+}
+
+// Scale, then rotation (Euler degrees about x, y, z), then translation. An explicit `matrix`,
+// given as its four rows, is applied before all three.
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
+pub struct RonTransform {
+    #[serde(default = "RonTransform::zero")]
+    pub translate: Vec<f64>,
+    #[serde(default = "RonTransform::zero")]
+    pub rotate: Vec<f64>,
+    #[serde(default = "RonTransform::one")]
+    pub scale: Vec<f64>,
+    #[serde(default)]
+    pub matrix: Option<Vec<Vec<f64>>>,
+}
+
+impl RonTransform {
+    fn zero() -> Vec<f64> {
+        vec![0.0, 0.0, 0.0]
+    }
+    fn one() -> Vec<f64> {
+        vec![1.0, 1.0, 1.0]
+    }
+}
+
+impl Default for RonTransform {
+    fn default() -> RonTransform {
+        RonTransform {
+            translate: RonTransform::zero(),
+            rotate: RonTransform::zero(),
+            scale: RonTransform::one(),
+            matrix: None,
+        }
+    }
+}
+
+// Named objects built once and shared by every `Instance` that references them.
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
+pub struct RonGeometry {
+    pub name: String,
+    pub objects: Vec<RonObject>,
+}
+
+// Material definition such as `["Metal", "0.8", "0.8", "0.8", "0.1"]`, used by objects as
+// `["steel"]`. Further entries on the object, e.g. modifiers, are appended to the definition.
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
+pub struct RonMaterial {
+    pub name: String,
     pub material: Vec<String>,
 }
 
+// Named texture that materials can reference in place of a color or scalar value.
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
+pub struct RonTexture {
+    pub name: String,
+    pub textype: String,
+    #[serde(default)]
+    pub vectors: Vec<Vec<f64>>,
+    #[serde(default)]
+    pub scalars: Vec<f64>,
+    #[serde(default)]
+    pub children: Vec<String>,
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(default)]
+    pub wrap: Option<String>,
+    #[serde(default)]
+    pub linear: bool,
+}
+
+impl RonTexture {
+    // Unnamed texture of the given type with everything else left at its default.
+    pub fn new(textype: &str) -> RonTexture {
+        RonTexture {
+            name: String::new(),
+            textype: textype.to_string(),
+            vectors: vec![],
+            scalars: vec![],
+            children: vec![],
+            path: None,
+            wrap: None,
+            linear: false,
+        }
+    }
+}
+
+// Lens and film settings in millimetres, seconds and ISO, with scene units in metres. Defaults
+// to a full frame sensor at ISO 100 and 1/100 s.
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
+pub struct RonPhysicalCamera {
+    pub focal_length: f64,
+    #[serde(default = "RonPhysicalCamera::full_frame_width")]
+    pub sensor_width: f64,
+    #[serde(default = "RonPhysicalCamera::full_frame_height")]
+    pub sensor_height: f64,
+    pub f_stop: f64,
+    #[serde(default = "RonPhysicalCamera::base_iso")]
+    pub iso: f64,
+    #[serde(default = "RonPhysicalCamera::base_shutter_speed")]
+    pub shutter_speed: f64,
+}
+
+impl RonPhysicalCamera {
+    fn full_frame_width() -> f64 {
+        36.0
+    }
+    fn full_frame_height() -> f64 {
+        24.0
+    }
+    fn base_iso() -> f64 {
+        100.0
+    }
+    fn base_shutter_speed() -> f64 {
+        0.01
+    }
+}
+
+// Aperture shape: a polygon with `blades` sides (round when below 3), or the named texture
+// used as a mask.
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
+pub struct RonBokeh {
+    #[serde(default)]
+    pub blades: usize,
+    #[serde(default)]
+    pub rotation: f64,
+    #[serde(default)]
+    pub texture: Option<String>,
+}
+
+// Frames `frame_start..=frame_end` rendered by the sequence renderer.
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
+pub struct RonAnimation {
+    #[serde(default)]
+    pub frame_start: i32,
+    pub frame_end: i32,
+    // Degrees the camera orbits its look-at point over the sequence.
+    #[serde(default)]
+    pub turntable: f64,
+    #[serde(default)]
+    pub tracks: Vec<RonTrack>,
+}
+
+// Keyframed parameter, e.g. `camera_pos`, `v_fov`, `lights.0` or `<object name>.rotate`.
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
+pub struct RonTrack {
+    pub target: String,
+    // "Linear" or "Bezier".
+    #[serde(default = "RonTrack::linear")]
+    pub interpolation: String,
+    pub keys: Vec<RonKey>,
+}
+
+impl RonTrack {
+    fn linear() -> String {
+        "Linear".to_string()
+    }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
+pub struct RonKey {
+    pub frame: f64,
+    pub value: Vec<f64>,
+    // Bezier control values, smooth handles are generated when omitted.
+    #[serde(default)]
+    pub handle_in: Option<Vec<f64>>,
+    #[serde(default)]
+    pub handle_out: Option<Vec<f64>>,
+}
+
 #[allow(dead_code)]
 impl RaytracerScene {
+    fn perspective() -> String {
+        "Perspective".to_string()
+    }
+
+    fn unit_exposure() -> f64 {
+        1.0
+    }
+
     pub fn to_ron(&self) -> String {
         let pretty = PrettyConfig::new();
 
         to_string_pretty(&self, pretty).expect("serialization failed")
     }
 
+    // Includes are relative to the working directory, see `scene_file::parse_scene`.
     pub fn from_ron(ron_string: String) -> RaytracerScene {
-        from_str(&ron_string).expect("deserialization failed")
+        scene_file::parse_scene(&ron_string, None).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn from_file(path: &str) -> RaytracerScene {
+        scene_file::load_scene(path).unwrap_or_else(|e| panic!("{}", e))
     }
 }
@@ -0,0 +1,298 @@
+use crate::aabb::Aabb;
+use crate::configuration::RonObject;
+use crate::hittable::{HitRecord, Hittable, Interval};
+use crate::ray::Ray;
+use crate::scene_export::SceneWriter;
+use crate::vec3::Vec3;
+use std::sync::Arc;
+
+/// How a `Csg` combines the insides of its operands.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CsgOp {
+    Union,
+    Intersection,
+    Difference,
+}
+
+impl CsgOp {
+    pub fn from_name(name: &str) -> CsgOp {
+        match name {
+            "Union" => CsgOp::Union,
+            "Intersection" => CsgOp::Intersection,
+            "Difference" => CsgOp::Difference,
+            _ => panic!("Unknown CSG operation {}", name),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CsgOp::Union => "Union",
+            CsgOp::Intersection => "Intersection",
+            CsgOp::Difference => "Difference",
+        }
+    }
+
+    fn inside(&self, in_left: bool, in_right: bool) -> bool {
+        match self {
+            CsgOp::Union => in_left || in_right,
+            CsgOp::Intersection => in_left && in_right,
+            CsgOp::Difference => in_left && !in_right,
+        }
+    }
+}
+
+/// Sorts spans and joins the ones that overlap.
+pub fn merge_intervals(mut spans: Vec<Interval>) -> Vec<Interval> {
+    spans.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+    let mut merged: Vec<Interval> = vec![];
+    for span in spans {
+        match merged.last_mut() {
+            Some(last) if span.0 <= last.1 => last.1 = last.1.max(span.1),
+            _ => merged.push(span),
+        }
+    }
+    merged
+}
+
+/// Closed objects give their spans along any ray, open surfaces along none, so one probe tells
+/// which an object is.
+pub fn is_closed(object: &(dyn Hittable + Send + Sync)) -> bool {
+    let probe = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+    object.intervals(probe).is_some()
+}
+
+// Boundary of the combined solid and the operand surface it lies on.
+#[derive(Debug, Copy, Clone)]
+struct Boundary {
+    t: f64,
+    from_right: bool,
+}
+
+/// Boolean combination of two closed objects. Surfaces of the right operand that bound a
+/// difference face inwards, so their normals are flipped.
+pub struct Csg {
+    op: CsgOp,
+    left: Arc<dyn Hittable + Send + Sync>,
+    right: Arc<dyn Hittable + Send + Sync>,
+}
+
+impl Csg {
+    pub fn new(
+        op: CsgOp,
+        left: Arc<dyn Hittable + Send + Sync>,
+        right: Arc<dyn Hittable + Send + Sync>,
+    ) -> Csg {
+        assert!(
+            is_closed(left.as_ref()) && is_closed(right.as_ref()),
+            "CSG operands must be closed objects"
+        );
+        Csg { op, left, right }
+    }
+
+    // Sweeps the entry and exit events of both operands in order, keeping the ones where the
+    // inside state of the result changes.
+    fn boundaries(&self, ray: Ray) -> Vec<Boundary> {
+        let spans = |object: &Arc<dyn Hittable + Send + Sync>| {
+            object
+                .intervals(ray)
+                .expect("CSG operands must be closed objects")
+        };
+        let mut events = vec![];
+        for (from_right, object) in [(false, &self.left), (true, &self.right)] {
+            for (enter, exit) in spans(object) {
+                events.push((enter, from_right));
+                events.push((exit, from_right));
+            }
+        }
+        events.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+        let (mut in_left, mut in_right) = (false, false);
+        let mut inside = false;
+        let mut boundaries = vec![];
+        for (i, &(t, from_right)) in events.iter().enumerate() {
+            if from_right {
+                in_right = !in_right;
+            } else {
+                in_left = !in_left;
+            }
+            // Coincident surfaces switch together, so a solid minus itself leaves nothing.
+            if events.get(i + 1).is_some_and(|next| next.0 == t) {
+                continue;
+            }
+            let now_inside = self.op.inside(in_left, in_right);
+            if now_inside != inside {
+                inside = now_inside;
+                boundaries.push(Boundary { t, from_right });
+            }
+        }
+        boundaries
+    }
+}
+
+impl Hittable for Csg {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let boundary = match self
+            .boundaries(ray)
+            .into_iter()
+            .find(|b| b.t > t_min && b.t < t_max)
+        {
+            Some(boundary) => boundary,
+            None => return false,
+        };
+
+        // Re-intersect the operand in a small window around the boundary for the full record.
+        let operand = if boundary.from_right {
+            &self.right
+        } else {
+            &self.left
+        };
+        let eps = 1e-7 * boundary.t.abs().max(1.0);
+        if !operand.hit(ray, boundary.t - eps, boundary.t + eps, rec) {
+            return false;
+        }
+        if boundary.from_right && self.op == CsgOp::Difference {
+            let normal = rec.normal.unwrap();
+            let outward = if rec.get_front_face().unwrap() {
+                normal
+            } else {
+                -normal
+            };
+            rec.set_face_normal(ray, -outward);
+            rec.bitangent = rec.bitangent.map(|bitangent| -bitangent);
+        }
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let left = self.left.bounding_box()?;
+        match self.op {
+            CsgOp::Union => Some(left.surrounding(&self.right.bounding_box()?)),
+            CsgOp::Intersection | CsgOp::Difference => Some(left),
+        }
+    }
+
+    fn intervals(&self, ray: Ray) -> Option<Vec<Interval>> {
+        Some(
+            self.boundaries(ray)
+                .chunks_exact(2)
+                .map(|pair| (pair[0].t, pair[1].t))
+                .collect(),
+        )
+    }
+
+    fn export(&self, writer: &mut SceneWriter) {
+        let operands = [&self.left, &self.right].map(|operand| {
+            let children = writer.export_children(|writer| operand.export(writer));
+            writer.single(children)
+        });
+        writer.object(RonObject {
+            children: operands.to_vec(),
+            ..RonObject::new(self.op.name())
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Material;
+    use crate::plane::Plane;
+    use crate::sphere::Sphere;
+    use crate::texture::SolidColor;
+
+    fn ball(z: f64) -> Arc<dyn Hittable + Send + Sync> {
+        let white = Arc::new(SolidColor::new(Vec3::new(1.0, 1.0, 1.0)));
+        Arc::new(Sphere::new(
+            Vec3::new(0.0, 0.0, z),
+            1.0,
+            Material::Lambertian(white),
+        ))
+    }
+
+    // Spans along the z axis of unit balls centred at z = left and z = right, seen from z = -10.
+    fn spans(op: CsgOp, left: f64, right: f64) -> Vec<Interval> {
+        let ray = Ray::new(Vec3::new(0.0, 0.0, -10.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        Csg::new(op, ball(left), ball(right))
+            .intervals(ray)
+            .unwrap()
+    }
+
+    fn assert_spans(actual: Vec<Interval>, expected: &[Interval]) {
+        assert_eq!(actual.len(), expected.len(), "{:?}", actual);
+        for (a, e) in actual.iter().zip(expected) {
+            assert!(
+                (a.0 - e.0).abs() < 1e-9 && (a.1 - e.1).abs() < 1e-9,
+                "{:?}",
+                actual
+            );
+        }
+    }
+
+    #[test]
+    fn union_joins_overlapping_operands() {
+        assert_spans(spans(CsgOp::Union, 0.0, 1.0), &[(9.0, 12.0)]);
+        assert_spans(spans(CsgOp::Union, 0.0, 5.0), &[(9.0, 11.0), (14.0, 16.0)]);
+    }
+
+    #[test]
+    fn intersection_keeps_the_overlap() {
+        assert_spans(spans(CsgOp::Intersection, 0.0, 1.0), &[(10.0, 11.0)]);
+        assert_spans(spans(CsgOp::Intersection, 0.0, 5.0), &[]);
+    }
+
+    #[test]
+    fn difference_removes_the_right_operand() {
+        assert_spans(spans(CsgOp::Difference, 0.0, 1.0), &[(9.0, 10.0)]);
+        assert_spans(spans(CsgOp::Difference, 1.0, 0.0), &[(11.0, 12.0)]);
+        assert_spans(spans(CsgOp::Difference, 0.0, 0.0), &[]);
+    }
+
+    #[test]
+    fn difference_faces_point_out_of_the_hollow() {
+        let ray = Ray::new(Vec3::new(0.0, 0.0, -10.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        let mut rec = HitRecord::new();
+        let csg = Csg::new(CsgOp::Difference, ball(1.0), ball(0.0));
+        assert!(csg.hit(ray, 0.0, f64::INFINITY, &mut rec));
+        assert!((rec.t.unwrap() - 11.0).abs() < 1e-9);
+        assert!(rec.normal.unwrap().z() < 0.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "CSG operands must be closed objects")]
+    fn open_operands_are_rejected() {
+        let white = Arc::new(SolidColor::new(Vec3::new(1.0, 1.0, 1.0)));
+        let plane = Plane::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Material::Lambertian(white),
+        );
+        Csg::new(CsgOp::Union, ball(0.0), Arc::new(plane));
+    }
+
+    #[test]
+    #[should_panic(expected = "Difference operand Triangle \"cut\" is not a closed object")]
+    fn open_operands_in_scenes_are_named_while_loading() {
+        crate::create_image(
+            r#"(
+                multithreading: false,
+                aspect_ratio: 1.0,
+                image_width: 4,
+                image_height: 4,
+                samples_per_pixel: 1,
+                max_depth: 2,
+                v_fov: 60.0,
+                aperture: 0.0,
+                focal_distance: 5.0,
+                camera_pos: [0.0, 0.0, 5.0],
+                camera_dir: [0.0, 0.0, 0.0],
+                camera_up: [0.0, 1.0, 0.0],
+                objects: [(objtype: "Difference", children: [
+                    (objtype: "Sphere", vectors: [[0.0, 0.0, 0.0]], scalars: [1.0], material: ["Mirror"]),
+                    (objtype: "Triangle", name: Some("cut"), vectors: [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]], scalars: [0.0], material: ["Mirror"]),
+                ])],
+                lights: [],
+            )"#
+            .to_string(),
+        );
+    }
+}
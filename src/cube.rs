@@ -0,0 +1,201 @@
+use crate::aabb::Aabb;
+use crate::hittable;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::scene_export::SceneWriter;
+use crate::vec3::Vec3;
+
+/// Axis aligned box between two opposite corners.
+#[derive(Debug, Clone)]
+pub struct Cube {
+    min: Vec3,
+    max: Vec3,
+    material: Material,
+}
+
+impl Cube {
+    pub fn new(corner1: Vec3, corner2: Vec3, mat: Material) -> Cube {
+        Cube {
+            min: Vec3::new(
+                corner1.x().min(corner2.x()),
+                corner1.y().min(corner2.y()),
+                corner1.z().min(corner2.z()),
+            ),
+            max: Vec3::new(
+                corner1.x().max(corner2.x()),
+                corner1.y().max(corner2.y()),
+                corner1.z().max(corner2.z()),
+            ),
+            material: mat,
+        }
+    }
+}
+
+fn component(v: Vec3, axis: usize) -> f64 {
+    match axis {
+        0 => v.x(),
+        1 => v.y(),
+        _ => v.z(),
+    }
+}
+
+fn unit_axis(axis: usize, sign: f64) -> Vec3 {
+    match axis {
+        0 => Vec3::new(sign, 0.0, 0.0),
+        1 => Vec3::new(0.0, sign, 0.0),
+        _ => Vec3::new(0.0, 0.0, sign),
+    }
+}
+
+/// Entry and exit distances of a ray through a box, with the outward normal of each face.
+pub fn slab_intersect(min: Vec3, max: Vec3, ray: Ray) -> Option<((f64, Vec3), (f64, Vec3))> {
+    let mut t_near = f64::NEG_INFINITY;
+    let mut t_far = f64::INFINITY;
+    let mut near_normal = Vec3::new(0.0, 0.0, 0.0);
+    let mut far_normal = Vec3::new(0.0, 0.0, 0.0);
+
+    for axis in 0..3 {
+        let origin = component(ray.origin(), axis);
+        let direction = component(ray.direction(), axis);
+        let (lo, hi) = (component(min, axis), component(max, axis));
+        if direction.abs() < 1e-12 {
+            if origin < lo || origin > hi {
+                return None;
+            }
+            continue;
+        }
+
+        let mut t0 = (lo - origin) / direction;
+        let mut t1 = (hi - origin) / direction;
+        let mut n0 = unit_axis(axis, -1.0);
+        let mut n1 = unit_axis(axis, 1.0);
+        if t0 > t1 {
+            std::mem::swap(&mut t0, &mut t1);
+            std::mem::swap(&mut n0, &mut n1);
+        }
+        if t0 > t_near {
+            t_near = t0;
+            near_normal = n0;
+        }
+        if t1 < t_far {
+            t_far = t1;
+            far_normal = n1;
+        }
+        if t_near > t_far {
+            return None;
+        }
+    }
+    Some(((t_near, near_normal), (t_far, far_normal)))
+}
+
+/// Face uvs span [0, 1] on every face, with v pointing up on the side faces.
+pub fn face_uv(local: Vec3, outward_normal: Vec3) -> ((f64, f64), Vec3, Vec3) {
+    let tangent = if outward_normal.y().abs() > 0.5 {
+        Vec3::new(1.0, 0.0, 0.0)
+    } else {
+        Vec3::new(0.0, 1.0, 0.0).cross(outward_normal)
+    };
+    let bitangent = outward_normal.cross(tangent);
+    let coord = |dir: Vec3| {
+        let c = local.dot(dir);
+        if c < 0.0 {
+            1.0 + c
+        } else {
+            c
+        }
+    };
+    ((coord(tangent), coord(bitangent)), tangent, bitangent)
+}
+
+impl hittable::Hittable for Cube {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, rec: &mut hittable::HitRecord) -> bool {
+        let ((t_near, near_normal), (t_far, far_normal)) =
+            match slab_intersect(self.min, self.max, ray) {
+                Some(hits) => hits,
+                None => return false,
+            };
+        let (t, outward_normal) = if t_near > t_min && t_near < t_max {
+            (t_near, near_normal)
+        } else if t_far > t_min && t_far < t_max {
+            (t_far, far_normal)
+        } else {
+            return false;
+        };
+
+        let p = ray.at(t);
+        let local = (p - self.min) / (self.max - self.min);
+        let (uv, tangent, bitangent) = face_uv(local, outward_normal);
+        rec.t = Some(t);
+        rec.p = Some(p);
+        rec.set_face_normal(ray, outward_normal);
+        rec.material = Some(self.material.clone());
+        rec.uv = Some(uv);
+        rec.tangent = Some(tangent);
+        rec.bitangent = Some(bitangent);
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::new(self.min, self.max))
+    }
+
+    fn intervals(&self, ray: Ray) -> Option<Vec<hittable::Interval>> {
+        Some(match slab_intersect(self.min, self.max, ray) {
+            Some(((t_near, _), (t_far, _))) => vec![(t_near, t_far)],
+            None => vec![],
+        })
+    }
+
+    fn export(&self, writer: &mut SceneWriter) {
+        writer.primitive("Box", &[self.min, self.max], &[], &self.material);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::{HitRecord, Hittable};
+
+    fn cast(origin: Vec3, direction: Vec3, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let cube = Cube::new(
+            Vec3::new(1.0, 1.0, 1.0),
+            Vec3::new(-1.0, -1.0, -1.0),
+            Material::Mirror,
+        );
+        let mut rec = HitRecord::new();
+        cube.hit(Ray::new(origin, direction, 0.0), t_min, t_max, &mut rec)
+            .then_some(rec)
+    }
+
+    #[test]
+    fn hits_the_near_face_from_outside() {
+        let rec = cast(
+            Vec3::new(0.5, 0.5, 5.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            0.001,
+            f64::INFINITY,
+        )
+        .unwrap();
+        assert_eq!(rec.t, Some(4.0));
+        assert_eq!(rec.normal, Some(Vec3::new(0.0, 0.0, 1.0)));
+        assert_eq!(rec.uv, Some((0.75, 0.75)));
+        assert_eq!(rec.tangent, Some(Vec3::new(1.0, 0.0, 0.0)));
+
+        let down = Vec3::new(0.0, -1.0, 0.0);
+        assert!(cast(Vec3::new(0.0, 5.0, 0.0), down, 0.001, 3.5).is_none());
+        assert!(cast(Vec3::new(1.5, 5.0, 0.0), down, 0.001, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn hits_the_far_face_from_inside() {
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let rec = cast(Vec3::new(0.0, 0.0, 0.0), up, 0.001, f64::INFINITY).unwrap();
+        assert_eq!(rec.t, Some(1.0));
+        assert_eq!(rec.normal, Some(Vec3::new(0.0, -1.0, 0.0)));
+        assert_eq!(rec.get_front_face(), Some(false));
+
+        // Past the near face, the ray leaves through the far one.
+        let rec = cast(Vec3::new(0.0, -5.0, 0.0), up, 5.0, f64::INFINITY).unwrap();
+        assert_eq!(rec.t, Some(6.0));
+    }
+}
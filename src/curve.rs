@@ -0,0 +1,334 @@
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::scene_export::SceneWriter;
+use crate::vec3::Vec3;
+
+// Straight pieces a curve is flattened into before intersection.
+const SEGMENTS: usize = 8;
+
+/// Cross section a `Curve` is intersected with.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CurveShape {
+    /// Flat strip that always faces the incoming ray, cheap for dense grass and fur.
+    Ribbon,
+    /// Round tube, for strands seen up close.
+    Cylinder,
+}
+
+// Closest accepted intersection along the flattened curve.
+struct CurveHit {
+    t: f64,
+    segment: usize,
+    // Position along the segment and signed offset across the width, both in [0, 1] and [-1, 1].
+    along: f64,
+    across: f64,
+    normal: Vec3,
+}
+
+/// Cubic Bézier curve whose width is interpolated from the root to the tip. u runs along the
+/// curve and v across it, and the tangent follows the curve so hair shading can use it.
+#[derive(Debug, Clone)]
+pub struct Curve {
+    control: [Vec3; 4],
+    points: Vec<Vec3>,
+    widths: Vec<f64>,
+    shape: CurveShape,
+    material: Material,
+    bounds: Aabb,
+}
+
+impl Curve {
+    pub fn new(
+        control: [Vec3; 4],
+        root_width: f64,
+        tip_width: f64,
+        shape: CurveShape,
+        mat: Material,
+    ) -> Curve {
+        let points: Vec<Vec3> = (0..=SEGMENTS)
+            .map(|i| bezier(&control, i as f64 / SEGMENTS as f64))
+            .collect();
+        let widths: Vec<f64> = (0..=SEGMENTS)
+            .map(|i| {
+                let u = i as f64 / SEGMENTS as f64;
+                root_width * (1.0 - u) + tip_width * u
+            })
+            .collect();
+        let half_width = root_width.max(tip_width) / 2.0;
+        let hull = Aabb::from_points(&control);
+        let pad = Vec3::new(half_width, half_width, half_width);
+
+        Curve {
+            control,
+            points,
+            widths,
+            shape,
+            material: mat,
+            bounds: Aabb::new(hull.min - pad, hull.max + pad),
+        }
+    }
+
+    fn width_at(&self, segment: usize, along: f64) -> f64 {
+        self.widths[segment] * (1.0 - along) + self.widths[segment + 1] * along
+    }
+
+    // Point on the segment closest to the ray, accepted when it is within half the width.
+    fn hit_ribbon(&self, segment: usize, origin: Vec3, dir: Vec3) -> Option<CurveHit> {
+        let a = self.points[segment];
+        let axis = self.points[segment + 1] - a;
+        let w0 = origin - a;
+        let (aa, ad, dd) = (axis.dot(axis), axis.dot(dir), dir.dot(dir));
+        let (aw, dw) = (axis.dot(w0), dir.dot(w0));
+        let denom = aa * dd - ad * ad;
+        if denom.abs() < 1e-12 {
+            return None;
+        }
+        let along = ((aw * dd - ad * dw) / denom).clamp(0.0, 1.0);
+        let t = (axis * along - w0).dot(dir) / dd;
+        let offset = origin + dir * t - (a + axis * along);
+        let half_width = self.width_at(segment, along) / 2.0;
+        if offset.length() > half_width {
+            return None;
+        }
+
+        let tangent = axis.unit_vector();
+        let facing = -(dir - tangent * dir.dot(tangent)).unit_vector();
+        let side = tangent.cross(facing);
+        Some(CurveHit {
+            t,
+            segment,
+            along,
+            across: offset.dot(side) / half_width,
+            normal: facing,
+        })
+    }
+
+    // Capsule around the segment with the width at its middle, so neighbouring pieces overlap
+    // without gaps. The nearest crossing past `t_min` is taken, so rays leaving the surface or
+    // starting inside the tube find its far side.
+    fn hit_cylinder(
+        &self,
+        segment: usize,
+        origin: Vec3,
+        dir: Vec3,
+        t_min: f64,
+    ) -> Option<CurveHit> {
+        let a = self.points[segment];
+        let b = self.points[segment + 1];
+        let radius = self.width_at(segment, 0.5) / 2.0;
+        let (ba, oa) = (b - a, origin - a);
+        let (baba, bard, baoa) = (ba.dot(ba), ba.dot(dir), ba.dot(oa));
+        let (rdoa, oaoa) = (dir.dot(oa), oa.dot(oa));
+        // Crossings of the side between the ends, then of the spherical caps joining the
+        // neighbouring segments beyond them.
+        let mut crossings = Vec::with_capacity(6);
+        let qa = baba - bard * bard;
+        // A ray along the axis never crosses the side and only meets the caps.
+        if qa > 1e-9 * baba {
+            let qb = baba * rdoa - baoa * bard;
+            let qc = baba * oaoa - baoa * baoa - radius * radius * baba;
+            let h = qb * qb - qa * qc;
+            if h >= 0.0 {
+                for t in [(-qb - h.sqrt()) / qa, (-qb + h.sqrt()) / qa] {
+                    let y = baoa + t * bard;
+                    if y > 0.0 && y < baba {
+                        crossings.push(t);
+                    }
+                }
+            }
+        }
+        for (centre, outside) in [(a, -1.0), (b, 1.0)] {
+            let oc = origin - centre;
+            let cb = dir.dot(oc);
+            let ch = cb * cb - (oc.dot(oc) - radius * radius);
+            if ch < 0.0 {
+                continue;
+            }
+            for t in [-cb - ch.sqrt(), -cb + ch.sqrt()] {
+                if (oc + dir * t).dot(ba) * outside >= 0.0 {
+                    crossings.push(t);
+                }
+            }
+        }
+        let t = crossings
+            .into_iter()
+            .filter(|t| *t > t_min)
+            .min_by(|x, y| x.partial_cmp(y).unwrap())?;
+
+        let p = origin + dir * t;
+        let along = ((p - a).dot(ba) / baba).clamp(0.0, 1.0);
+        let normal = (p - (a + ba * along)).unit_vector();
+        let tangent = ba.unit_vector();
+        let facing = -(dir - tangent * dir.dot(tangent)).unit_vector();
+        Some(CurveHit {
+            t,
+            segment,
+            along,
+            across: normal.dot(tangent.cross(facing)),
+            normal,
+        })
+    }
+}
+
+impl Hittable for Curve {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        // Intersect with a unit direction so widths and distances share units.
+        let scale = ray.direction().length();
+        let dir = ray.direction() * (1.0 / scale);
+        let (t_min, t_max) = (t_min * scale, t_max * scale);
+
+        let mut best: Option<CurveHit> = None;
+        for segment in 0..SEGMENTS {
+            let hit = match self.shape {
+                CurveShape::Ribbon => self.hit_ribbon(segment, ray.origin(), dir),
+                CurveShape::Cylinder => self.hit_cylinder(segment, ray.origin(), dir, t_min),
+            };
+            if let Some(hit) = hit {
+                let closest = best.as_ref().map_or(t_max, |b| b.t);
+                if hit.t > t_min && hit.t < closest {
+                    best = Some(hit);
+                }
+            }
+        }
+        let hit = match best {
+            Some(hit) => hit,
+            None => return false,
+        };
+
+        let tangent = (self.points[hit.segment + 1] - self.points[hit.segment]).unit_vector();
+        rec.t = Some(hit.t / scale);
+        rec.p = Some(ray.at(hit.t / scale));
+        rec.set_face_normal(ray, hit.normal);
+        rec.material = Some(self.material.clone());
+        rec.uv = Some((
+            (hit.segment as f64 + hit.along) / SEGMENTS as f64,
+            0.5 * (hit.across + 1.0),
+        ));
+        rec.tangent = Some(tangent);
+        rec.bitangent = Some(hit.normal.cross(tangent));
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+
+    fn export(&self, writer: &mut SceneWriter) {
+        let cylindrical = match self.shape {
+            CurveShape::Ribbon => 0.0,
+            CurveShape::Cylinder => 1.0,
+        };
+        let scalars = [self.widths[0], self.widths[SEGMENTS], cylindrical];
+        writer.primitive("Curve", &self.control, &scalars, &self.material);
+    }
+}
+
+pub(crate) fn bezier(control: &[Vec3; 4], u: f64) -> Vec3 {
+    let s = 1.0 - u;
+    control[0] * (s * s * s)
+        + control[1] * (3.0 * s * s * u)
+        + control[2] * (3.0 * s * u * u)
+        + control[3] * (u * u * u)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Straight curve from the origin to (3, 0, 0), so segment ends fall on every 3/8 of x.
+    fn straight(root_width: f64, tip_width: f64, shape: CurveShape) -> Curve {
+        Curve::new(
+            [
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(2.0, 0.0, 0.0),
+                Vec3::new(3.0, 0.0, 0.0),
+            ],
+            root_width,
+            tip_width,
+            shape,
+            Material::Mirror,
+        )
+    }
+
+    fn cast(curve: &Curve, origin: Vec3, direction: Vec3) -> Option<HitRecord> {
+        let mut rec = HitRecord::new();
+        let ray = Ray::new(origin, direction, 0.0);
+        curve
+            .hit(ray, 0.001, f64::INFINITY, &mut rec)
+            .then_some(rec)
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn ribbons_face_the_ray_and_taper_to_the_tip() {
+        let ribbon = straight(0.2, 0.0, CurveShape::Ribbon);
+        let down = Vec3::new(0.0, -1.0, 0.0);
+        // Halfway along the ribbon is 0.1 wide, v runs across it.
+        let rec = cast(&ribbon, Vec3::new(1.5, 5.0, 0.025), down).unwrap();
+        assert!(close(rec.t.unwrap(), 5.0));
+        assert!((rec.normal.unwrap() - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-9);
+        let (u, v) = rec.uv.unwrap();
+        assert!(close(u, 0.5) && close(v, 0.75));
+
+        // The same ribbon seen from the side turns to face that ray too.
+        let rec = cast(
+            &ribbon,
+            Vec3::new(1.5, 0.02, 5.0),
+            Vec3::new(0.0, 0.0, -2.0),
+        )
+        .unwrap();
+        assert!(close(rec.t.unwrap(), 2.5));
+        assert!((rec.normal.unwrap() - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-9);
+
+        // Near the tip it is only 0.02 wide.
+        assert!(cast(&ribbon, Vec3::new(1.5, 5.0, 0.06), down).is_none());
+        assert!(cast(&ribbon, Vec3::new(2.7, 5.0, 0.05), down).is_none());
+        assert!(cast(&ribbon, Vec3::new(2.7, 5.0, 0.005), down).is_some());
+    }
+
+    #[test]
+    fn cylinders_are_hit_on_the_near_side() {
+        let tube = straight(0.5, 0.5, CurveShape::Cylinder);
+        let rec = cast(&tube, Vec3::new(1.5, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0)).unwrap();
+        assert!(close(rec.t.unwrap(), 4.75));
+        assert!((rec.normal.unwrap() - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-9);
+        assert!(close(rec.uv.unwrap().0, 0.5));
+
+        let rec = cast(&tube, Vec3::new(1.5, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0)).unwrap();
+        assert!(close(rec.t.unwrap(), 4.75));
+        assert!(cast(&tube, Vec3::new(1.5, 0.3, 5.0), Vec3::new(0.0, 0.0, -1.0)).is_none());
+    }
+
+    #[test]
+    fn cylinders_are_left_through_the_far_side() {
+        let tube = straight(0.5, 0.5, CurveShape::Cylinder);
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let rec = cast(&tube, Vec3::new(1.5, 0.0, 0.0), up).unwrap();
+        assert!(close(rec.t.unwrap(), 0.25));
+        assert_eq!(rec.get_front_face(), Some(false));
+
+        // A ray leaving the surface, like a refracted one, finds the far side.
+        let rec = cast(&tube, Vec3::new(1.5, 0.25, 0.0), -up).unwrap();
+        assert!(close(rec.t.unwrap(), 0.5));
+    }
+
+    #[test]
+    fn rays_along_the_axis_meet_the_caps() {
+        let tube = straight(0.5, 0.5, CurveShape::Cylinder);
+        let along = Vec3::new(1.0, 0.0, 0.0);
+        let rec = cast(&tube, Vec3::new(-5.0, 0.0, 0.0), along).unwrap();
+        assert!(close(rec.t.unwrap(), 4.75));
+        assert!((rec.normal.unwrap() - Vec3::new(-1.0, 0.0, 0.0)).length() < 1e-9);
+
+        // Inside the last piece the ray leaves through the cap at the tip.
+        let rec = cast(&tube, Vec3::new(2.9, 0.0, 0.0), along).unwrap();
+        assert!(close(rec.t.unwrap(), 0.35));
+        assert!(cast(&tube, Vec3::new(-5.0, 0.3, 0.0), along).is_none());
+    }
+}
@@ -0,0 +1,184 @@
+use crate::aabb::Aabb;
+use crate::frame::{azimuth_u, azimuthal_frame, local_intervals, Frame, LocalHit};
+use crate::hittable;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::scene_export::SceneWriter;
+use crate::vec3::Vec3;
+
+/// Capped cylinder standing on `base` and extending `height` along `axis`.
+#[derive(Debug, Clone)]
+pub struct Cylinder {
+    frame: Frame,
+    radius: f64,
+    height: f64,
+    material: Material,
+}
+
+impl Cylinder {
+    pub fn new(base: Vec3, axis: Vec3, radius: f64, height: f64, mat: Material) -> Cylinder {
+        Cylinder {
+            frame: Frame::new(base, axis),
+            radius,
+            height,
+            material: mat,
+        }
+    }
+
+    /// Every intersection of the local ray with the side and caps, sorted by distance.
+    pub fn local_hits(&self, ray: Ray) -> Vec<LocalHit> {
+        let o = ray.origin();
+        let d = ray.direction();
+        let mut hits = vec![];
+
+        let a = d.x() * d.x() + d.z() * d.z();
+        let half_b = o.x() * d.x() + o.z() * d.z();
+        let c = o.x() * o.x() + o.z() * o.z() - self.radius * self.radius;
+        let discriminant = half_b * half_b - a * c;
+        if a > 1e-12 && discriminant > 0.0 {
+            let root = discriminant.sqrt();
+            for t in [(-half_b - root) / a, (-half_b + root) / a] {
+                let p = o + d * t;
+                if p.y() >= 0.0 && p.y() <= self.height {
+                    hits.push(LocalHit {
+                        t,
+                        normal: Vec3::new(p.x(), 0.0, p.z()) * (1.0 / self.radius),
+                        uv: (azimuth_u(p), p.y() / self.height),
+                    });
+                }
+            }
+        }
+
+        if d.y().abs() > 1e-12 {
+            for (cap_y, normal_y) in [(0.0, -1.0), (self.height, 1.0)] {
+                let t = (cap_y - o.y()) / d.y();
+                let p = o + d * t;
+                if p.x() * p.x() + p.z() * p.z() <= self.radius * self.radius {
+                    hits.push(LocalHit {
+                        t,
+                        normal: Vec3::new(0.0, normal_y, 0.0),
+                        uv: (
+                            0.5 + p.x() / (2.0 * self.radius),
+                            0.5 - normal_y * p.z() / (2.0 * self.radius),
+                        ),
+                    });
+                }
+            }
+        }
+
+        hits.sort_by(|a, b| a.t.partial_cmp(&b.t).unwrap());
+        hits
+    }
+}
+
+impl hittable::Hittable for Cylinder {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, rec: &mut hittable::HitRecord) -> bool {
+        let local_ray = self.frame.ray_to_local(ray);
+        let hit = match self
+            .local_hits(local_ray)
+            .into_iter()
+            .find(|hit| hit.t > t_min && hit.t < t_max)
+        {
+            Some(hit) => hit,
+            None => return false,
+        };
+
+        let outward_normal = self.frame.dir_to_world(hit.normal);
+        let (tangent, bitangent) = if hit.normal.y().abs() > 0.5 {
+            let tangent = self.frame.tangent;
+            (tangent, outward_normal.cross(tangent))
+        } else {
+            azimuthal_frame(self.frame.axis, outward_normal)
+        };
+        rec.t = Some(hit.t);
+        rec.p = Some(ray.at(hit.t));
+        rec.set_face_normal(ray, outward_normal);
+        rec.material = Some(self.material.clone());
+        rec.uv = Some(hit.uv);
+        rec.tangent = Some(tangent);
+        rec.bitangent = Some(bitangent);
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.frame.bounds(
+            Vec3::new(-self.radius, 0.0, -self.radius),
+            Vec3::new(self.radius, self.height, self.radius),
+        ))
+    }
+
+    fn intervals(&self, ray: Ray) -> Option<Vec<hittable::Interval>> {
+        Some(local_intervals(
+            &self.local_hits(self.frame.ray_to_local(ray)),
+        ))
+    }
+
+    fn export(&self, writer: &mut SceneWriter) {
+        let vectors = [self.frame.origin, self.frame.axis];
+        writer.primitive(
+            "Cylinder",
+            &vectors,
+            &[self.radius, self.height],
+            &self.material,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::{HitRecord, Hittable};
+
+    // Radius 1, standing on the origin and 2 high.
+    fn cast(origin: Vec3, direction: Vec3, t_min: f64) -> Option<HitRecord> {
+        let cylinder = Cylinder::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            1.0,
+            2.0,
+            Material::Mirror,
+        );
+        let mut rec = HitRecord::new();
+        let ray = Ray::new(origin, direction, 0.0);
+        cylinder
+            .hit(ray, t_min, f64::INFINITY, &mut rec)
+            .then_some(rec)
+    }
+
+    #[test]
+    fn hits_the_side_and_leaves_through_the_far_side() {
+        let left = Vec3::new(-1.0, 0.0, 0.0);
+        let rec = cast(Vec3::new(5.0, 1.0, 0.0), left, 0.001).unwrap();
+        assert_eq!(rec.t, Some(4.0));
+        assert_eq!(rec.normal, Some(Vec3::new(1.0, 0.0, 0.0)));
+        // World +x is local +z for an upright cylinder, a quarter turn from the uv seam.
+        assert_eq!(rec.uv, Some((0.25, 0.5)));
+
+        let rec = cast(Vec3::new(5.0, 1.0, 0.0), left, 5.0).unwrap();
+        assert_eq!(rec.t, Some(6.0));
+        assert_eq!(rec.get_front_face(), Some(false));
+
+        assert!(cast(Vec3::new(5.0, 2.5, 0.0), left, 0.001).is_none());
+        assert!(cast(Vec3::new(5.0, 1.0, 1.5), left, 0.001).is_none());
+    }
+
+    #[test]
+    fn hits_the_caps_from_outside_and_inside() {
+        let down = Vec3::new(0.0, -1.0, 0.0);
+        let rec = cast(Vec3::new(0.5, 5.0, 0.0), down, 0.001).unwrap();
+        assert_eq!(rec.t, Some(3.0));
+        assert_eq!(rec.normal, Some(Vec3::new(0.0, 1.0, 0.0)));
+        assert_eq!(rec.uv, Some((0.5, 0.25)));
+
+        let rec = cast(Vec3::new(0.0, 1.0, 0.0), -down, 0.001).unwrap();
+        assert_eq!(rec.t, Some(1.0));
+        assert_eq!(rec.normal, Some(Vec3::new(0.0, -1.0, 0.0)));
+        assert_eq!(rec.get_front_face(), Some(false));
+        let rec = cast(Vec3::new(0.0, 1.0, 0.0), down, 0.001).unwrap();
+        assert_eq!(rec.normal, Some(Vec3::new(0.0, 1.0, 0.0)));
+        assert_eq!(rec.get_front_face(), Some(false));
+
+        // Parallel to the axis but outside the radius.
+        assert!(cast(Vec3::new(1.5, 5.0, 0.0), down, 0.001).is_none());
+    }
+}
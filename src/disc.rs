@@ -0,0 +1,111 @@
+use crate::aabb::Aabb;
+use crate::frame::Frame;
+use crate::hittable;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::scene_export::SceneWriter;
+use crate::vec3::Vec3;
+
+/// Flat disc around `center`, facing along `normal`.
+#[derive(Debug, Clone)]
+pub struct Disc {
+    frame: Frame,
+    radius: f64,
+    material: Material,
+}
+
+impl Disc {
+    pub fn new(center: Vec3, normal: Vec3, radius: f64, mat: Material) -> Disc {
+        Disc {
+            frame: Frame::new(center, normal),
+            radius,
+            material: mat,
+        }
+    }
+}
+
+impl hittable::Hittable for Disc {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, rec: &mut hittable::HitRecord) -> bool {
+        let normal = self.frame.axis;
+        let denom = ray.direction().dot(normal);
+        if denom.abs() < 1e-12 {
+            return false;
+        }
+        let t = (self.frame.origin - ray.origin()).dot(normal) / denom;
+        if t < t_min || t > t_max {
+            return false;
+        }
+
+        let p = ray.at(t);
+        let local = self.frame.point_to_local(p);
+        if local.x() * local.x() + local.z() * local.z() > self.radius * self.radius {
+            return false;
+        }
+
+        rec.t = Some(t);
+        rec.p = Some(p);
+        rec.set_face_normal(ray, normal);
+        rec.material = Some(self.material.clone());
+        // The disc maps onto the unit square it is inscribed in.
+        rec.uv = Some((
+            0.5 + local.x() / (2.0 * self.radius),
+            0.5 - local.z() / (2.0 * self.radius),
+        ));
+        rec.tangent = Some(self.frame.tangent);
+        rec.bitangent = Some(normal.cross(self.frame.tangent));
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.frame.bounds(
+            Vec3::new(-self.radius, 0.0, -self.radius),
+            Vec3::new(self.radius, 0.0, self.radius),
+        ))
+    }
+
+    fn export(&self, writer: &mut SceneWriter) {
+        let vectors = [self.frame.origin, self.frame.axis];
+        writer.primitive("Disc", &vectors, &[self.radius], &self.material);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::{HitRecord, Hittable};
+
+    // Straight down onto a unit disc lying in the y = 0 plane.
+    fn cast_down(x: f64, z: f64) -> Option<HitRecord> {
+        let disc = Disc::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            1.0,
+            Material::Mirror,
+        );
+        let mut rec = HitRecord::new();
+        let ray = Ray::new(Vec3::new(x, 1.0, z), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        disc.hit(ray, 0.001, f64::INFINITY, &mut rec).then_some(rec)
+    }
+
+    #[test]
+    fn hits_inside_the_radius_only() {
+        let rec = cast_down(0.5, 0.5).unwrap();
+        assert_eq!(rec.t, Some(1.0));
+        assert_eq!(rec.normal, Some(Vec3::new(0.0, 1.0, 0.0)));
+        assert!(cast_down(0.8, 0.8).is_none());
+        assert!(cast_down(1.5, 0.0).is_none());
+    }
+
+    #[test]
+    fn uvs_span_the_enclosing_square() {
+        assert_eq!(cast_down(0.0, 0.0).unwrap().uv, Some((0.5, 0.5)));
+        // The tangent of a disc facing +y runs along -z.
+        let (u, v) = cast_down(0.0, -1.0 + 1e-9).unwrap().uv.unwrap();
+        assert!(
+            (u - 1.0).abs() < 1e-6 && (v - 0.5).abs() < 1e-6,
+            "{} {}",
+            u,
+            v
+        );
+    }
+}
@@ -0,0 +1,107 @@
+use crate::aabb::Aabb;
+use crate::hittable::Interval;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+use std::f64::consts::PI;
+
+// Right-handed local coordinate frame, x along `tangent`, y along `axis`, z along `bitangent`.
+#[derive(Debug, Copy, Clone)]
+pub struct Frame {
+    pub origin: Vec3,
+    pub tangent: Vec3,
+    pub axis: Vec3,
+    pub bitangent: Vec3,
+}
+
+impl Frame {
+    pub fn new(origin: Vec3, axis: Vec3) -> Frame {
+        // Axes already of unit length are kept as given, normalising them again can move them by
+        // a rounding step and written scenes would not load back to the same frame.
+        let axis = if (axis.length() - 1.0).abs() < 1e-12 {
+            axis
+        } else {
+            axis.unit_vector()
+        };
+        let helper = if axis.x().abs() > 0.9 {
+            Vec3::new(0.0, 0.0, 1.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let tangent = axis.cross(helper).unit_vector();
+        let bitangent = tangent.cross(axis);
+        Frame {
+            origin,
+            tangent,
+            axis,
+            bitangent,
+        }
+    }
+
+    pub fn from_axes(origin: Vec3, tangent: Vec3, axis: Vec3, bitangent: Vec3) -> Frame {
+        Frame {
+            origin,
+            tangent,
+            axis,
+            bitangent,
+        }
+    }
+
+    pub fn point_to_local(&self, p: Vec3) -> Vec3 {
+        self.dir_to_local(p - self.origin)
+    }
+
+    pub fn dir_to_local(&self, d: Vec3) -> Vec3 {
+        Vec3::new(d.dot(self.tangent), d.dot(self.axis), d.dot(self.bitangent))
+    }
+
+    pub fn dir_to_world(&self, d: Vec3) -> Vec3 {
+        self.tangent * d.x() + self.axis * d.y() + self.bitangent * d.z()
+    }
+
+    // World bounds of a box given in local coordinates.
+    pub fn bounds(&self, local_min: Vec3, local_max: Vec3) -> Aabb {
+        let corners = Aabb::new(local_min, local_max)
+            .corners()
+            .map(|corner| self.origin + self.dir_to_world(corner));
+        Aabb::from_points(&corners)
+    }
+
+    pub fn ray_to_local(&self, ray: Ray) -> Ray {
+        Ray::new(
+            self.point_to_local(ray.origin()),
+            self.dir_to_local(ray.direction()),
+            ray.time(),
+        )
+    }
+}
+
+// Tangent along increasing azimuth around `axis` and the matching bitangent, so that
+// (tangent, bitangent, normal) has the same handedness as the sphere's surface frame.
+pub fn azimuthal_frame(axis: Vec3, normal: Vec3) -> (Vec3, Vec3) {
+    let around = axis.cross(normal);
+    let tangent = if around.length_squared() > 1e-12 {
+        around.unit_vector()
+    } else {
+        Frame::new(Vec3::new(0.0, 0.0, 0.0), normal).tangent
+    };
+    (tangent, normal.cross(tangent))
+}
+
+// Angle around the local y axis mapped to [0, 1], matching the sphere's u coordinate.
+pub fn azimuth_u(local: Vec3) -> f64 {
+    ((-local.z()).atan2(local.x()) + PI) / (2.0 * PI)
+}
+
+// Intersection in a primitive's local frame with the outward normal and uv there.
+pub struct LocalHit {
+    pub t: f64,
+    pub normal: Vec3,
+    pub uv: (f64, f64),
+}
+
+// Sorted hits on a closed surface alternate between entering and exiting it.
+pub fn local_intervals(hits: &[LocalHit]) -> Vec<Interval> {
+    hits.chunks_exact(2)
+        .map(|pair| (pair[0].t, pair[1].t))
+        .collect()
+}
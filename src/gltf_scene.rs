@@ -0,0 +1,547 @@
+use crate::bvh::Bvh;
+use crate::hittable::Hittable;
+use crate::material::Material;
+use crate::matrix::Mat4;
+use crate::texture::{ImageTexture, SolidColor, TextureRef, Tinted, WrapMode};
+use crate::transform::Transform;
+use crate::triangle::Triangle;
+use crate::vec3::Vec3;
+use gltf::khr_lights_punctual::Kind;
+use gltf::material::AlphaMode;
+use gltf::texture::WrappingMode;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+// Distance directional lights are placed at, against their direction, as the renderer only has
+// point lights.
+const SUN_DISTANCE: f64 = 1.0e4;
+
+#[derive(Debug, Copy, Clone)]
+pub enum GltfProjection {
+    // Vertical field of view in degrees.
+    Perspective(f64),
+    // World space height of the view.
+    Orthographic(f64),
+}
+
+// Camera node placed in the world, looking down its local -z with +y up.
+#[derive(Debug, Copy, Clone)]
+pub struct GltfCamera {
+    pub look_from: Vec3,
+    pub look_at: Vec3,
+    pub v_up: Vec3,
+    pub projection: GltfProjection,
+}
+
+// Meshes of the default scene placed by their nodes, plus its cameras and punctual lights. Each
+// mesh is built once and shared by every node that uses it.
+pub struct GltfScene {
+    pub objects: Arc<dyn Hittable + Send + Sync>,
+    pub lights: Vec<Vec3>,
+    pub cameras: Vec<GltfCamera>,
+}
+
+impl GltfScene {
+    pub fn load(path: &str) -> GltfScene {
+        let (document, buffers, images) =
+            gltf::import(path).unwrap_or_else(|e| panic!("failed to load glTF {}: {}", path, e));
+        let mut loader = Loader {
+            buffers,
+            images,
+            textures: HashMap::new(),
+            materials: HashMap::new(),
+            meshes: HashMap::new(),
+            objects: vec![],
+            lights: vec![],
+            cameras: vec![],
+            ignored: vec![],
+        };
+        let scene = document
+            .default_scene()
+            .or_else(|| document.scenes().next())
+            .unwrap_or_else(|| panic!("glTF {} has no scene", path));
+        for node in scene.nodes() {
+            loader.add_node(&node, Mat4::identity());
+        }
+        if !loader.ignored.is_empty() {
+            eprintln!("glTF import ignored {}", loader.ignored.join(", "));
+        }
+
+        GltfScene {
+            objects: Arc::new(Bvh::new(loader.objects)),
+            lights: loader.lights,
+            cameras: loader.cameras,
+        }
+    }
+}
+
+struct Loader {
+    buffers: Vec<gltf::buffer::Data>,
+    images: Vec<gltf::image::Data>,
+    // Keyed by image index and whether the image holds color, so sRGB images are linearised once.
+    textures: HashMap<(usize, bool), TextureRef>,
+    materials: HashMap<Option<usize>, Material>,
+    meshes: HashMap<usize, Arc<dyn Hittable + Send + Sync>>,
+    objects: Vec<Box<dyn Hittable + Send + Sync>>,
+    lights: Vec<Vec3>,
+    cameras: Vec<GltfCamera>,
+    // Inputs the renderer has no equivalent for, reported once loading is done.
+    ignored: Vec<String>,
+}
+
+impl Loader {
+    fn add_node(&mut self, node: &gltf::Node, parent: Mat4) {
+        let matrix = parent * column_major(node.transform().matrix());
+
+        if let Some(mesh) = node.mesh() {
+            let shared = self.mesh(&mesh);
+            self.objects.push(Box::new(Transform::new(shared, matrix)));
+        }
+        if let Some(camera) = node.camera() {
+            let projection = match camera.projection() {
+                gltf::camera::Projection::Perspective(p) => {
+                    GltfProjection::Perspective((p.yfov() as f64).to_degrees())
+                }
+                gltf::camera::Projection::Orthographic(o) => {
+                    GltfProjection::Orthographic(2.0 * o.ymag() as f64)
+                }
+            };
+            let look_from = matrix.transform_point(Vec3::new(0.0, 0.0, 0.0));
+            self.cameras.push(GltfCamera {
+                look_from,
+                look_at: look_from + matrix.transform_vector(Vec3::new(0.0, 0.0, -1.0)),
+                v_up: matrix.transform_vector(Vec3::new(0.0, 1.0, 0.0)),
+                projection,
+            });
+        }
+        if let Some(light) = node.light() {
+            let position = match light.kind() {
+                Kind::Directional => {
+                    let direction = matrix.transform_vector(Vec3::new(0.0, 0.0, -1.0));
+                    -direction.unit_vector() * SUN_DISTANCE
+                }
+                Kind::Point | Kind::Spot { .. } => matrix.transform_point(Vec3::new(0.0, 0.0, 0.0)),
+            };
+            self.lights.push(position);
+        }
+
+        for child in node.children() {
+            self.add_node(&child, matrix);
+        }
+    }
+
+    fn mesh(&mut self, mesh: &gltf::Mesh) -> Arc<dyn Hittable + Send + Sync> {
+        if let Some(shared) = self.meshes.get(&mesh.index()) {
+            return shared.clone();
+        }
+
+        let mut triangles: Vec<Box<dyn Hittable + Send + Sync>> = vec![];
+        for primitive in mesh.primitives() {
+            if primitive.mode() != gltf::mesh::Mode::Triangles {
+                continue;
+            }
+            let material = self.material(&primitive.material());
+            let uv_set = self.uv_set(&primitive.material());
+            let reader = primitive.reader(|buffer| Some(&self.buffers[buffer.index()]));
+            let positions: Vec<Vec3> = match reader.read_positions() {
+                Some(positions) => positions
+                    .map(|p| Vec3::new(p[0] as f64, p[1] as f64, p[2] as f64))
+                    .collect(),
+                None => continue,
+            };
+            let normals: Option<Vec<Vec3>> = reader.read_normals().map(|normals| {
+                normals
+                    .map(|n| Vec3::new(n[0] as f64, n[1] as f64, n[2] as f64))
+                    .collect()
+            });
+            // glTF puts v = 0 at the top of the image, textures here sample it at the bottom.
+            let uvs: Option<Vec<(f64, f64)>> = reader.read_tex_coords(uv_set).map(|uvs| {
+                uvs.into_f32()
+                    .map(|uv| (uv[0] as f64, 1.0 - uv[1] as f64))
+                    .collect()
+            });
+            let indices: Vec<usize> = match reader.read_indices() {
+                Some(indices) => indices.into_u32().map(|i| i as usize).collect(),
+                None => (0..positions.len()).collect(),
+            };
+            if reader.read_colors(0).is_some() {
+                self.ignore(format!(
+                    "COLOR_0 of mesh {}",
+                    name(mesh.name(), Some(mesh.index()))
+                ));
+            }
+
+            for corners in indices.chunks_exact(3) {
+                let triangle = Triangle::new(
+                    positions[corners[0]],
+                    positions[corners[1]],
+                    positions[corners[2]],
+                    material.clone(),
+                    false,
+                );
+                let triangle = match &uvs {
+                    Some(uvs) => {
+                        triangle.with_uvs([uvs[corners[0]], uvs[corners[1]], uvs[corners[2]]])
+                    }
+                    None => triangle,
+                };
+                // Vertex normals give smooth shading across the faces of exported meshes.
+                let triangle = match &normals {
+                    Some(normals) => triangle.with_normals([
+                        normals[corners[0]],
+                        normals[corners[1]],
+                        normals[corners[2]],
+                    ]),
+                    None => triangle,
+                };
+                triangles.push(Box::new(triangle));
+            }
+        }
+
+        let shared: Arc<dyn Hittable + Send + Sync> = Arc::new(Bvh::new(triangles));
+        self.meshes.insert(mesh.index(), shared.clone());
+        shared
+    }
+
+    // Metallic-roughness maps to `Metal` when mostly metallic and `Lambertian` otherwise, with
+    // normal maps and alpha masks wrapped around it. Blended materials become stochastic masks.
+    fn material(&mut self, material: &gltf::Material) -> Material {
+        if let Some(converted) = self.materials.get(&material.index()) {
+            return converted.clone();
+        }
+
+        let pbr = material.pbr_metallic_roughness();
+        let factor = pbr.base_color_factor();
+        let tint = Vec3::new(factor[0] as f64, factor[1] as f64, factor[2] as f64);
+        let base_color: TextureRef = match pbr.base_color_texture() {
+            Some(info) => Arc::new(Tinted::new(self.texture(&info.texture(), true), tint)),
+            None => Arc::new(SolidColor::new(tint)),
+        };
+
+        let mut converted = if pbr.metallic_factor() >= 0.5 {
+            let roughness = pbr.roughness_factor() as f64;
+            let roughness_scale = Vec3::new(roughness, roughness, roughness);
+            let roughness: TextureRef = match pbr.metallic_roughness_texture() {
+                Some(info) => Arc::new(Tinted::new(
+                    self.roughness_texture(&info.texture()),
+                    roughness_scale,
+                )),
+                None => Arc::new(SolidColor::new(roughness_scale)),
+            };
+            Material::Metal(base_color.clone(), roughness)
+        } else {
+            Material::Lambertian(base_color.clone())
+        };
+
+        let alpha_mode = material.alpha_mode();
+        if alpha_mode != AlphaMode::Opaque {
+            let opacity: TextureRef = match pbr.base_color_texture() {
+                Some(info) => self.opacity_texture(&info.texture(), factor[3]),
+                None => {
+                    let alpha = factor[3] as f64;
+                    Arc::new(SolidColor::new(Vec3::new(alpha, alpha, alpha)))
+                }
+            };
+            let cutoff = material.alpha_cutoff().unwrap_or(0.5) as f64;
+            let stochastic = alpha_mode == AlphaMode::Blend;
+            converted = Material::Masked(Box::new(converted), opacity, cutoff, stochastic);
+        }
+        if let Some(normal) = material.normal_texture() {
+            converted = Material::NormalMapped(
+                Box::new(converted),
+                self.texture(&normal.texture(), false),
+                normal.scale() as f64,
+            );
+        }
+
+        self.materials.insert(material.index(), converted.clone());
+        converted
+    }
+
+    // Triangles carry a single set of texture coordinates, the one of the first texture the
+    // material samples. Textures reading another set are sampled with it anyway.
+    fn uv_set(&mut self, material: &gltf::Material) -> u32 {
+        let pbr = material.pbr_metallic_roughness();
+        let sets = [
+            pbr.base_color_texture().map(|info| info.tex_coord()),
+            pbr.metallic_roughness_texture()
+                .map(|info| info.tex_coord()),
+            material.normal_texture().map(|normal| normal.tex_coord()),
+        ];
+        let mut sets = sets.iter().flatten();
+        let uv_set = sets.next().copied().unwrap_or(0);
+        if let Some(other) = sets.find(|&&set| set != uv_set) {
+            let material = name(material.name(), material.index());
+            self.ignore(format!("TEXCOORD_{} of material {}", other, material));
+        }
+        uv_set
+    }
+
+    fn ignore(&mut self, input: String) {
+        if !self.ignored.contains(&input) {
+            self.ignored.push(input);
+        }
+    }
+
+    // Color images are linearised with gamma 2 like `ImageTexture::load`.
+    fn texture(&mut self, texture: &gltf::Texture, color: bool) -> TextureRef {
+        let index = texture.source().index();
+        if let Some(converted) = self.textures.get(&(index, color)) {
+            return converted.clone();
+        }
+        let texels = rgba_texels(&self.images[index])
+            .into_iter()
+            .map(|[r, g, b, a]| {
+                if color {
+                    [r * r, g * g, b * b, a]
+                } else {
+                    [r, g, b, a]
+                }
+            })
+            .collect();
+        let converted = self.image_texture(texture, texels);
+        self.textures.insert((index, color), converted.clone());
+        converted
+    }
+
+    // Roughness lives in the green channel, spread to every channel for the scalar slot.
+    fn roughness_texture(&mut self, texture: &gltf::Texture) -> TextureRef {
+        let texels = rgba_texels(&self.images[texture.source().index()])
+            .into_iter()
+            .map(|[_, g, _, _]| [g, g, g, 1.0])
+            .collect();
+        self.image_texture(texture, texels)
+    }
+
+    // Alpha channel scaled by the base color factor's alpha, spread to every channel.
+    fn opacity_texture(&mut self, texture: &gltf::Texture, factor: f32) -> TextureRef {
+        let texels = rgba_texels(&self.images[texture.source().index()])
+            .into_iter()
+            .map(|[_, _, _, a]| [a * factor; 4])
+            .collect();
+        self.image_texture(texture, texels)
+    }
+
+    fn image_texture(&self, texture: &gltf::Texture, texels: Vec<[f32; 4]>) -> TextureRef {
+        let image = &self.images[texture.source().index()];
+        let wrap = match texture.sampler().wrap_s() {
+            WrappingMode::ClampToEdge => WrapMode::Clamp,
+            WrappingMode::MirroredRepeat => WrapMode::Mirror,
+            WrappingMode::Repeat => WrapMode::Repeat,
+        };
+        Arc::new(ImageTexture::new(
+            image.width as usize,
+            image.height as usize,
+            texels,
+            wrap,
+        ))
+    }
+}
+
+// Name of a glTF object for messages, its index when unnamed.
+fn name(name: Option<&str>, index: Option<usize>) -> String {
+    match (name, index) {
+        (Some(name), _) => name.to_string(),
+        (None, Some(index)) => format!("#{}", index),
+        (None, None) => "default".to_string(),
+    }
+}
+
+fn column_major(m: [[f32; 4]; 4]) -> Mat4 {
+    let mut out = [[0.0; 4]; 4];
+    for (row, values) in out.iter_mut().enumerate() {
+        for (column, value) in values.iter_mut().enumerate() {
+            *value = m[column][row] as f64;
+        }
+    }
+    Mat4 { m: out }
+}
+
+// Decoded image data as RGBA in [0, 1], whatever its stored layout.
+fn rgba_texels(image: &gltf::image::Data) -> Vec<[f32; 4]> {
+    use gltf::image::Format;
+    let (channels, bytes) = match image.format {
+        Format::R8 => (1, 1),
+        Format::R8G8 => (2, 1),
+        Format::R8G8B8 => (3, 1),
+        Format::R8G8B8A8 => (4, 1),
+        Format::R16 => (1, 2),
+        Format::R16G16 => (2, 2),
+        Format::R16G16B16 => (3, 2),
+        Format::R16G16B16A16 => (4, 2),
+        Format::R32G32B32FLOAT => (3, 4),
+        Format::R32G32B32A32FLOAT => (4, 4),
+    };
+    let channel = |b: &[u8]| match bytes {
+        1 => b[0] as f32 / u8::MAX as f32,
+        2 => u16::from_le_bytes([b[0], b[1]]) as f32 / u16::MAX as f32,
+        _ => f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+    };
+    image
+        .pixels
+        .chunks_exact(channels * bytes)
+        .map(|pixel| {
+            let value = |i: usize| channel(&pixel[i * bytes..]);
+            match channels {
+                1 => [value(0), value(0), value(0), 1.0],
+                2 => [value(0), value(0), value(0), value(1)],
+                3 => [value(0), value(1), value(2), 1.0],
+                _ => [value(0), value(1), value(2), value(3)],
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::HitRecord;
+    use crate::ray::Ray;
+
+    // A triangle covering [-1, 1]^2 twice, once with an alpha tested 2x1 texture (opaque red
+    // on the left, half transparent blue on the right) and once fully transparent and blended.
+    // The textured one has its vertex normals tilted towards +x. The camera and light hang
+    // under the textured mesh, which hangs under a root node.
+    const FIXTURE: &str = r#"{
+        "asset": { "version": "2.0" },
+        "extensionsUsed": ["KHR_lights_punctual"],
+        "extensions": { "KHR_lights_punctual": { "lights": [{ "type": "point" }] } },
+        "scene": 0,
+        "scenes": [{ "nodes": [0] }],
+        "nodes": [
+            { "translation": [0, 0, -5], "children": [1, 2] },
+            { "mesh": 0, "children": [3, 4] },
+            { "mesh": 1, "translation": [10, 0, 0] },
+            { "camera": 0, "translation": [0, 0, 5] },
+            { "translation": [0, 2, 0], "extensions": { "KHR_lights_punctual": { "light": 0 } } }
+        ],
+        "cameras": [{ "type": "perspective", "perspective": { "yfov": 0.8, "znear": 0.1 } }],
+        "meshes": [
+            { "primitives": [{ "attributes": { "POSITION": 0, "TEXCOORD_1": 1, "NORMAL": 2 }, "material": 0 }] },
+            { "primitives": [{ "attributes": { "POSITION": 0 }, "material": 1 }] }
+        ],
+        "materials": [
+            {
+                "pbrMetallicRoughness": {
+                    "baseColorTexture": { "index": 0, "texCoord": 1 },
+                    "baseColorFactor": [1, 1, 1, 0.5],
+                    "metallicFactor": 0
+                },
+                "alphaMode": "MASK",
+                "alphaCutoff": 0.4
+            },
+            {
+                "pbrMetallicRoughness": { "baseColorFactor": [1, 1, 1, 0], "metallicFactor": 0 },
+                "alphaMode": "BLEND"
+            }
+        ],
+        "textures": [{ "source": 0 }],
+        "images": [{ "uri": "fixture.png" }],
+        "buffers": [{ "uri": "fixture.bin", "byteLength": 96 }],
+        "bufferViews": [
+            { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+            { "buffer": 0, "byteOffset": 36, "byteLength": 24 },
+            { "buffer": 0, "byteOffset": 60, "byteLength": 36 }
+        ],
+        "accessors": [
+            {
+                "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                "min": [-1, -1, 0], "max": [3, 3, 0]
+            },
+            { "bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC2" },
+            { "bufferView": 2, "componentType": 5126, "count": 3, "type": "VEC3" }
+        ]
+    }"#;
+
+    // Each test writes its own copy, as tests run in parallel.
+    fn load_fixture(test: &str) -> GltfScene {
+        let dir = std::env::temp_dir()
+            .join("raytracing-gltf-tests")
+            .join(test);
+        std::fs::create_dir_all(&dir).unwrap();
+        let positions = [-1.0, -1.0, 0.0, 3.0, -1.0, 0.0, -1.0, 3.0, 0.0];
+        let uvs = [0.0, 0.0, 2.0, 0.0, 0.0, 0.0];
+        let normals = [0.6, 0.0, 0.8, 0.6, 0.0, 0.8, 0.6, 0.0, 0.8];
+        let buffer: Vec<u8> = positions
+            .iter()
+            .chain(uvs.iter())
+            .chain(normals.iter())
+            .flat_map(|value: &f32| value.to_le_bytes())
+            .collect();
+        std::fs::write(dir.join("fixture.bin"), buffer).unwrap();
+        image::RgbaImage::from_raw(2, 1, vec![255, 0, 0, 255, 0, 0, 255, 128])
+            .unwrap()
+            .save(dir.join("fixture.png"))
+            .unwrap();
+        let path = dir.join("fixture.gltf");
+        std::fs::write(&path, FIXTURE).unwrap();
+        GltfScene::load(path.to_str().unwrap())
+    }
+
+    fn cast(scene: &GltfScene, x: f64) -> Option<HitRecord> {
+        let ray = Ray::new(Vec3::new(x, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let mut rec = HitRecord::new();
+        scene
+            .objects
+            .hit(ray, 0.001, f64::INFINITY, &mut rec)
+            .then_some(rec)
+    }
+
+    #[test]
+    fn nested_cameras_and_lights_are_placed_in_the_world() {
+        let scene = load_fixture("nodes");
+
+        assert_eq!(scene.cameras.len(), 1);
+        let camera = scene.cameras[0];
+        assert!((camera.look_from - Vec3::new(0.0, 0.0, 0.0)).length() < 1e-9);
+        assert!((camera.look_at - Vec3::new(0.0, 0.0, -1.0)).length() < 1e-9);
+        assert!((camera.v_up - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-9);
+        match camera.projection {
+            GltfProjection::Perspective(fov) => {
+                assert!((fov - 0.8_f64.to_degrees()).abs() < 1e-4)
+            }
+            other => panic!("expected a perspective camera, got {:?}", other),
+        }
+
+        assert_eq!(scene.lights.len(), 1);
+        assert!((scene.lights[0] - Vec3::new(0.0, 2.0, -5.0)).length() < 1e-9);
+    }
+
+    #[test]
+    fn textured_materials_read_their_uv_set_and_alpha_factor() {
+        let scene = load_fixture("materials");
+
+        // Opaque red texel, its alpha of 1 scaled by the factor to 0.5 passes the 0.4 cutoff.
+        let rec = cast(&scene, -0.5).expect("the opaque half should be hit");
+        assert!((rec.t.unwrap() - 5.0).abs() < 1e-9);
+        let (u, v) = rec.uv.unwrap();
+        assert!((u - 0.25).abs() < 1e-6);
+        match rec.material.unwrap() {
+            Material::Masked(inner, _, cutoff, stochastic) => {
+                assert!((cutoff - 0.4).abs() < 1e-6);
+                assert!(!stochastic);
+                match *inner {
+                    Material::Lambertian(albedo) => {
+                        let color = albedo.value(u, v, rec.p.unwrap());
+                        assert!((color - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-6);
+                    }
+                    other => panic!("expected a Lambertian base, got {:?}", other),
+                }
+            }
+            other => panic!("expected an alpha tested material, got {:?}", other),
+        }
+
+        // Half transparent texel scaled to about 0.25, under the cutoff.
+        assert!(cast(&scene, 0.5).is_none());
+        // Blended with an alpha of 0, never kept by the stochastic test.
+        assert!(cast(&scene, 10.0).is_none());
+    }
+
+    #[test]
+    fn vertex_normals_shade_the_triangles() {
+        let scene = load_fixture("normals");
+
+        let rec = cast(&scene, -0.5).unwrap();
+        assert!((rec.normal.unwrap() - Vec3::new(0.6, 0.0, 0.8)).length() < 1e-6);
+    }
+}
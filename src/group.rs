@@ -0,0 +1,67 @@
+use crate::aabb::Aabb;
+use crate::csg::merge_intervals;
+use crate::hittable::{HitRecord, Hittable, Interval};
+use crate::ray::Ray;
+use crate::scene_export::SceneWriter;
+use std::collections::HashSet;
+
+/// Several hittables treated as one object, e.g. a mesh shared between instances.
+pub struct Group {
+    objects: Vec<Box<dyn Hittable + Send + Sync>>,
+}
+
+impl Group {
+    pub fn new(objects: Vec<Box<dyn Hittable + Send + Sync>>) -> Group {
+        Group { objects }
+    }
+}
+
+impl Hittable for Group {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let mut closest = t_max;
+        let mut hit_anything = false;
+        for object in &self.objects {
+            if object.hit(ray, t_min, closest, rec) {
+                closest = rec.get_t().unwrap();
+                hit_anything = true;
+            }
+        }
+        hit_anything
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let mut bounds: Option<Aabb> = None;
+        for object in &self.objects {
+            let object_bounds = object.bounding_box()?;
+            bounds = Some(match bounds {
+                Some(b) => b.surrounding(&object_bounds),
+                None => object_bounds,
+            });
+        }
+        bounds
+    }
+
+    fn intervals(&self, ray: Ray) -> Option<Vec<Interval>> {
+        let mut spans = vec![];
+        for object in &self.objects {
+            spans.extend(object.intervals(ray)?);
+        }
+        Some(merge_intervals(spans))
+    }
+
+    fn acceleration_bytes(&self, seen: &mut HashSet<usize>) -> usize {
+        self.objects
+            .iter()
+            .map(|object| object.acceleration_bytes(seen))
+            .sum()
+    }
+
+    fn export(&self, writer: &mut SceneWriter) {
+        let children = writer.export_children(|writer| {
+            for object in &self.objects {
+                object.export(writer);
+            }
+        });
+        writer.group(children);
+    }
+}
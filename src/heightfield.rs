@@ -0,0 +1,539 @@
+use crate::aabb::Aabb;
+use crate::cube::slab_intersect;
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::perlin::Perlin;
+use crate::ray::Ray;
+use crate::scene_export::SceneWriter;
+use crate::vec3::Vec3;
+use std::collections::HashSet;
+
+// Lowest and highest height of every block of cells, halving the resolution per level.
+struct MinMaxLevel {
+    cols: usize,
+    rows: usize,
+    ranges: Vec<(f32, f32)>,
+}
+
+// Intersected cell, triangle and barycentric coordinates of the closest hit so far.
+#[derive(Copy, Clone)]
+struct CellHit {
+    t: f64,
+    vertices: [(usize, usize); 3],
+    bary: (f64, f64),
+}
+
+/// Terrain over a regular grid of heights in [0, 1], spanning `size` from the `origin` corner
+/// with `size.y` as the height scale. Each cell is split into two triangles and rays descend the
+/// min/max mip pyramid front to back, skipping blocks whose height range they pass over.
+pub struct Heightfield {
+    origin: Vec3,
+    size: Vec3,
+    width: usize,
+    depth: usize,
+    heights: Vec<f32>,
+    levels: Vec<MinMaxLevel>,
+    material: Material,
+}
+
+impl Heightfield {
+    /// `heights` holds `depth` rows of `width` samples, row 0 at the origin's z.
+    pub fn new(
+        origin: Vec3,
+        size: Vec3,
+        width: usize,
+        depth: usize,
+        heights: Vec<f32>,
+        mat: Material,
+    ) -> Heightfield {
+        assert!(
+            width >= 2 && depth >= 2,
+            "heightfield needs at least 2x2 samples"
+        );
+        assert_eq!(
+            width * depth,
+            heights.len(),
+            "height count does not match grid size"
+        );
+
+        let mut cells = MinMaxLevel {
+            cols: width - 1,
+            rows: depth - 1,
+            ranges: Vec::with_capacity((width - 1) * (depth - 1)),
+        };
+        for j in 0..depth - 1 {
+            for i in 0..width - 1 {
+                let corners = [
+                    heights[j * width + i],
+                    heights[j * width + i + 1],
+                    heights[(j + 1) * width + i],
+                    heights[(j + 1) * width + i + 1],
+                ];
+                let lo = corners.iter().cloned().fold(f32::INFINITY, f32::min);
+                let hi = corners.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+                cells.ranges.push((lo, hi));
+            }
+        }
+
+        let mut levels = vec![cells];
+        while levels.last().is_some_and(|l| l.cols > 1 || l.rows > 1) {
+            let below = levels.last().unwrap();
+            let cols = below.cols.div_ceil(2);
+            let rows = below.rows.div_ceil(2);
+            let mut ranges = Vec::with_capacity(cols * rows);
+            for j in 0..rows {
+                for i in 0..cols {
+                    let mut range = (f32::INFINITY, f32::NEG_INFINITY);
+                    for (ci, cj) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                        let (x, y) = (2 * i + ci, 2 * j + cj);
+                        if x < below.cols && y < below.rows {
+                            let (lo, hi) = below.ranges[y * below.cols + x];
+                            range = (range.0.min(lo), range.1.max(hi));
+                        }
+                    }
+                    ranges.push(range);
+                }
+            }
+            levels.push(MinMaxLevel { cols, rows, ranges });
+        }
+
+        Heightfield {
+            origin,
+            size,
+            width,
+            depth,
+            heights,
+            levels,
+            material: mat,
+        }
+    }
+
+    fn height(&self, i: usize, j: usize) -> f64 {
+        self.heights[j * self.width + i] as f64
+    }
+
+    fn vertex(&self, i: usize, j: usize) -> Vec3 {
+        self.origin
+            + Vec3::new(
+                self.size.x() * i as f64 / (self.width - 1) as f64,
+                self.size.y() * self.height(i, j),
+                self.size.z() * j as f64 / (self.depth - 1) as f64,
+            )
+    }
+
+    // Smooth vertex normal from central differences of the neighbouring heights.
+    fn vertex_normal(&self, i: usize, j: usize) -> Vec3 {
+        let (i0, i1) = (i.saturating_sub(1), (i + 1).min(self.width - 1));
+        let (j0, j1) = (j.saturating_sub(1), (j + 1).min(self.depth - 1));
+        let dx = self.size.x() * (i1 - i0) as f64 / (self.width - 1) as f64;
+        let dz = self.size.z() * (j1 - j0) as f64 / (self.depth - 1) as f64;
+        let slope_x = self.size.y() * (self.height(i1, j) - self.height(i0, j)) / dx;
+        let slope_z = self.size.y() * (self.height(i, j1) - self.height(i, j0)) / dz;
+        Vec3::new(-slope_x, 1.0, -slope_z).unit_vector()
+    }
+
+    fn node_bounds(&self, level: usize, ci: usize, cj: usize) -> Aabb {
+        let l = &self.levels[level];
+        let (lo, hi) = l.ranges[cj * l.cols + ci];
+        let cells = self.levels[0].cols.max(1);
+        let rows = self.levels[0].rows.max(1);
+        let i0 = (ci << level).min(cells);
+        let i1 = ((ci + 1) << level).min(cells);
+        let j0 = (cj << level).min(rows);
+        let j1 = ((cj + 1) << level).min(rows);
+        let corner = |i: usize, j: usize, h: f32| {
+            self.origin
+                + Vec3::new(
+                    self.size.x() * i as f64 / cells as f64,
+                    self.size.y() * h as f64,
+                    self.size.z() * j as f64 / rows as f64,
+                )
+        };
+        Aabb::new(corner(i0, j0, lo), corner(i1, j1, hi))
+    }
+
+    // Möller-Trumbore against one triangle of a cell.
+    fn hit_triangle(
+        &self,
+        ray: Ray,
+        vertices: [(usize, usize); 3],
+        t_min: f64,
+        best: &mut Option<CellHit>,
+    ) {
+        let t_max = best.map_or(f64::INFINITY, |b| b.t);
+        let v0 = self.vertex(vertices[0].0, vertices[0].1);
+        let edge1 = self.vertex(vertices[1].0, vertices[1].1) - v0;
+        let edge2 = self.vertex(vertices[2].0, vertices[2].1) - v0;
+        let h = ray.direction().cross(edge2);
+        let a = edge1.dot(h);
+        if a.abs() < 1e-12 {
+            return;
+        }
+        let f = 1.0 / a;
+        let s = ray.origin() - v0;
+        let u = f * s.dot(h);
+        if !(0.0..=1.0).contains(&u) {
+            return;
+        }
+        let q = s.cross(edge1);
+        let v = f * ray.direction().dot(q);
+        if v < 0.0 || u + v > 1.0 {
+            return;
+        }
+        let t = f * edge2.dot(q);
+        if t > t_min && t < t_max {
+            *best = Some(CellHit {
+                t,
+                vertices,
+                bary: (u, v),
+            });
+        }
+    }
+
+    fn hit_node(
+        &self,
+        level: usize,
+        ci: usize,
+        cj: usize,
+        ray: Ray,
+        t_min: f64,
+        best: &mut Option<CellHit>,
+    ) {
+        if level == 0 {
+            let (i, j) = (ci, cj);
+            self.hit_triangle(ray, [(i, j), (i + 1, j + 1), (i + 1, j)], t_min, best);
+            self.hit_triangle(ray, [(i, j), (i, j + 1), (i + 1, j + 1)], t_min, best);
+            return;
+        }
+
+        let below = &self.levels[level - 1];
+        let mut children = vec![];
+        for (di, dj) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            let (x, y) = (2 * ci + di, 2 * cj + dj);
+            if x >= below.cols || y >= below.rows {
+                continue;
+            }
+            let bounds = self.node_bounds(level - 1, x, y);
+            if let Some(((t_near, _), (t_far, _))) = slab_intersect(bounds.min, bounds.max, ray) {
+                if t_far > t_min {
+                    children.push((t_near, x, y));
+                }
+            }
+        }
+        children.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        for (t_near, x, y) in children {
+            if best.is_some_and(|b| t_near > b.t) {
+                break;
+            }
+            self.hit_node(level - 1, x, y, ray, t_min, best);
+        }
+    }
+}
+
+impl Hittable for Heightfield {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let top = self.levels.len() - 1;
+        let bounds = self.node_bounds(top, 0, 0);
+        match slab_intersect(bounds.min, bounds.max, ray) {
+            Some(((t_near, _), (t_far, _))) if t_far > t_min && t_near < t_max => {}
+            _ => return false,
+        }
+
+        let mut best = None;
+        self.hit_node(top, 0, 0, ray, t_min, &mut best);
+        let hit = match best {
+            Some(hit) if hit.t < t_max => hit,
+            _ => return false,
+        };
+
+        let (u, v) = hit.bary;
+        let w = 1.0 - u - v;
+        let normal = |k: usize| self.vertex_normal(hit.vertices[k].0, hit.vertices[k].1);
+        let outward_normal = (normal(0) * w + normal(1) * u + normal(2) * v).unit_vector();
+        let p = ray.at(hit.t);
+        // Tangent along +x to match u, v runs towards -z like an image seen from above.
+        let along_x = Vec3::new(1.0, 0.0, 0.0);
+        let tangent = (along_x - outward_normal * outward_normal.x()).unit_vector();
+        rec.t = Some(hit.t);
+        rec.p = Some(p);
+        rec.set_face_normal(ray, outward_normal);
+        rec.material = Some(self.material.clone());
+        rec.uv = Some((
+            (p.x() - self.origin.x()) / self.size.x(),
+            1.0 - (p.z() - self.origin.z()) / self.size.z(),
+        ));
+        rec.tangent = Some(tangent);
+        rec.bitangent = Some(outward_normal.cross(tangent));
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.node_bounds(self.levels.len() - 1, 0, 0))
+    }
+
+    fn acceleration_bytes(&self, _seen: &mut HashSet<usize>) -> usize {
+        self.levels
+            .iter()
+            .map(|level| level.ranges.len() * std::mem::size_of::<(f32, f32)>())
+            .sum()
+    }
+
+    // Heights are written inline, whichever file or noise they came from.
+    fn export(&self, writer: &mut SceneWriter) {
+        let object = writer.primitive(
+            "Heightfield",
+            &[self.origin, self.size],
+            &[],
+            &self.material,
+        );
+        object.heights = self
+            .heights
+            .chunks(self.width)
+            .map(|row| row.iter().map(|h| *h as f64).collect())
+            .collect();
+    }
+}
+
+/// The two triangles of every cell of a height grid laid out like a `Heightfield`, used as a
+/// surface to scatter objects over.
+pub fn grid_triangles(
+    origin: Vec3,
+    size: Vec3,
+    width: usize,
+    depth: usize,
+    heights: &[f32],
+) -> Vec<[Vec3; 3]> {
+    let vertex = |i: usize, j: usize| {
+        origin
+            + Vec3::new(
+                size.x() * i as f64 / (width - 1) as f64,
+                size.y() * heights[j * width + i] as f64,
+                size.z() * j as f64 / (depth - 1) as f64,
+            )
+    };
+    let mut triangles = Vec::with_capacity(2 * (width - 1) * (depth - 1));
+    for j in 0..depth - 1 {
+        for i in 0..width - 1 {
+            triangles.push([vertex(i, j), vertex(i, j + 1), vertex(i + 1, j + 1)]);
+            triangles.push([vertex(i, j), vertex(i + 1, j + 1), vertex(i + 1, j)]);
+        }
+    }
+    triangles
+}
+
+/// Heights from a 16-bit (or 8-bit) grayscale image, the top row of the image is the far edge at
+/// the maximum z so the terrain reads like a map seen from above.
+pub fn load_image_heights(path: &str) -> (usize, usize, Vec<f32>) {
+    let image = image::open(path)
+        .unwrap_or_else(|e| panic!("failed to load heightmap {}: {}", path, e))
+        .to_luma16();
+    let (width, depth) = image.dimensions();
+    let (width, depth) = (width as usize, depth as usize);
+    let mut heights = Vec::with_capacity(width * depth);
+    for j in 0..depth {
+        for i in 0..width {
+            let value = image.get_pixel(i as u32, (depth - 1 - j) as u32)[0];
+            heights.push(value as f32 / u16::MAX as f32);
+        }
+    }
+    (width, depth, heights)
+}
+
+/// Heights from a headerless little endian 16-bit DEM, rows ordered like the image loader. The
+/// grid is assumed square unless `width` is given.
+pub fn load_raw_heights(path: &str, width: Option<usize>) -> (usize, usize, Vec<f32>) {
+    let bytes =
+        std::fs::read(path).unwrap_or_else(|e| panic!("failed to load heightmap {}: {}", path, e));
+    let samples: Vec<f32> = bytes
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]) as f32 / u16::MAX as f32)
+        .collect();
+    let width = width.unwrap_or_else(|| (samples.len() as f64).sqrt().round() as usize);
+    let depth = samples.len() / width;
+    let mut heights = Vec::with_capacity(width * depth);
+    for j in 0..depth {
+        let row = depth - 1 - j;
+        heights.extend_from_slice(&samples[row * width..(row + 1) * width]);
+    }
+    (width, depth, heights)
+}
+
+/// Procedural terrain from fBm noise, matching the terrain settings of `RaytracerSettings`. Noise
+/// is sampled at the world position of each grid point, so `frequency` is per world unit and the
+/// terrain keeps its features when the grid is resized or refined.
+pub fn noise_heights(
+    corner: Vec3,
+    extent: Vec3,
+    resolution: usize,
+    seed: u32,
+    octaves: usize,
+    frequency: f64,
+    lacunarity: f64,
+) -> Vec<f32> {
+    if resolution < 2 {
+        panic!(
+            "Heightfield noise resolution must be at least 2, got {}",
+            resolution
+        );
+    }
+    let perlin = Perlin::new(seed);
+    let step = 1.0 / (resolution - 1) as f64;
+    let mut heights = Vec::with_capacity(resolution * resolution);
+    for j in 0..resolution {
+        for i in 0..resolution {
+            let x = corner.x() + extent.x() * i as f64 * step;
+            let z = corner.z() + extent.z() * j as f64 * step;
+            let p = Vec3::new(x, 0.5, z) * frequency;
+            let n = perlin.fbm(p, octaves, lacunarity, 0.5);
+            heights.push((0.5 * (1.0 + n)).clamp(0.0, 1.0) as f32);
+        }
+    }
+    heights
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::triangle::Triangle;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    const WIDTH: usize = 9;
+    const DEPTH: usize = 7;
+
+    fn terrain() -> Heightfield {
+        let mut heights = vec![];
+        for j in 0..DEPTH {
+            for i in 0..WIDTH {
+                let (x, z) = (i as f32, j as f32);
+                heights.push(0.5 + 0.4 * (1.3 * x + 0.7 * z).sin() * (0.9 * z - 0.4 * x).cos());
+            }
+        }
+        Heightfield::new(
+            Vec3::new(-4.0, 0.0, -3.0),
+            Vec3::new(8.0, 2.0, 6.0),
+            WIDTH,
+            DEPTH,
+            heights,
+            Material::Mirror,
+        )
+    }
+
+    // The same grid split into the same two triangles per cell.
+    fn tessellated(field: &Heightfield) -> Vec<Triangle> {
+        let mut triangles = vec![];
+        for j in 0..DEPTH - 1 {
+            for i in 0..WIDTH - 1 {
+                let corner = |di: usize, dj: usize| field.vertex(i + di, j + dj);
+                let (a, b, c, d) = (corner(0, 0), corner(1, 0), corner(0, 1), corner(1, 1));
+                triangles.push(Triangle::new(a, d, b, Material::Mirror, false));
+                triangles.push(Triangle::new(a, c, d, Material::Mirror, false));
+            }
+        }
+        triangles
+    }
+
+    fn closest(triangles: &[Triangle], ray: Ray) -> Option<f64> {
+        let mut best = None;
+        for triangle in triangles {
+            let mut rec = HitRecord::new();
+            if triangle.hit(ray, 0.001, best.unwrap_or(f64::INFINITY), &mut rec) {
+                best = rec.t;
+            }
+        }
+        best
+    }
+
+    // Rays from `origin` along `direction`, both drawn per ray, hit both surfaces at the same t.
+    fn assert_matches_triangles(
+        mut origin: impl FnMut(&mut StdRng) -> Vec3,
+        mut direction: impl FnMut(&mut StdRng) -> Vec3,
+    ) {
+        let field = terrain();
+        let triangles = tessellated(&field);
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut hits = 0;
+        for _ in 0..2000 {
+            let ray = Ray::new(origin(&mut rng), direction(&mut rng), 0.0);
+            let mut rec = HitRecord::new();
+            let found = field
+                .hit(ray, 0.001, f64::INFINITY, &mut rec)
+                .then(|| rec.t.unwrap());
+            match (found, closest(&triangles, ray)) {
+                (Some(a), Some(b)) => {
+                    assert!((a - b).abs() < 1e-9, "{:?}: {} vs {}", ray, a, b);
+                    hits += 1;
+                }
+                (None, None) => {}
+                (a, b) => panic!("{:?}: {:?} vs {:?}", ray, a, b),
+            }
+        }
+        assert!(hits > 200, "only {} hits", hits);
+    }
+
+    #[test]
+    fn rays_from_above_match_the_tessellated_grid() {
+        assert_matches_triangles(
+            |rng| Vec3::new(rng.gen_range(-6.0..6.0), 5.0, rng.gen_range(-5.0..5.0)),
+            |rng| Vec3::new(rng.gen_range(-1.0..1.0), -1.0, rng.gen_range(-1.0..1.0)),
+        );
+    }
+
+    #[test]
+    fn rays_through_the_sides_match_the_tessellated_grid() {
+        assert_matches_triangles(
+            |rng| Vec3::new(-6.0, rng.gen_range(0.0..2.0), rng.gen_range(-3.0..3.0)),
+            |rng| Vec3::new(1.0, rng.gen_range(-0.2..0.2), rng.gen_range(-0.5..0.5)),
+        );
+        assert_matches_triangles(
+            |rng| Vec3::new(rng.gen_range(-4.0..4.0), rng.gen_range(0.0..2.0), 5.0),
+            |rng| Vec3::new(rng.gen_range(-0.5..0.5), rng.gen_range(-0.2..0.2), -1.0),
+        );
+    }
+
+    #[test]
+    fn grazing_rays_match_the_tessellated_grid() {
+        assert_matches_triangles(
+            |rng| Vec3::new(-6.0, rng.gen_range(1.0..1.9), rng.gen_range(-3.0..3.0)),
+            |rng| Vec3::new(1.0, rng.gen_range(-0.01..0.01), rng.gen_range(-0.1..0.1)),
+        );
+    }
+
+    fn fixture(name: &str) -> String {
+        let dir = std::env::temp_dir().join("raytracing-heightfield-tests");
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join(name).to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn sixteen_bit_images_load_with_the_top_row_at_the_far_edge() {
+        let path = fixture("heights.png");
+        let pixels: Vec<u16> = vec![0, 1000, 2000, 30000, 40000, u16::MAX];
+        image::ImageBuffer::<image::Luma<u16>, _>::from_raw(3, 2, pixels)
+            .unwrap()
+            .save(&path)
+            .unwrap();
+        let (width, depth, heights) = load_image_heights(&path);
+        assert_eq!((width, depth), (3, 2));
+        let expected = [30000, 40000, u16::MAX, 0, 1000, 2000].map(|v| v as f32 / 65535.0);
+        assert_eq!(heights, expected);
+    }
+
+    #[test]
+    fn raw_dems_are_little_endian_rows() {
+        let path = fixture("heights.raw");
+        let samples: [u16; 6] = [0, 256, 1, 65535, 32768, 7];
+        let bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        std::fs::write(&path, bytes).unwrap();
+
+        let (width, depth, heights) = load_raw_heights(&path, Some(3));
+        assert_eq!((width, depth), (3, 2));
+        let expected = [65535, 32768, 7, 0, 256, 1].map(|v| v as f32 / 65535.0);
+        assert_eq!(heights, expected);
+
+        // Without a width the grid is square, trailing samples are dropped.
+        let (width, depth, heights) = load_raw_heights(&path, None);
+        assert_eq!((width, depth), (2, 3));
+        assert_eq!(heights[..2], [32768.0 / 65535.0, 7.0 / 65535.0]);
+    }
+}
@@ -1,14 +1,36 @@
+use crate::aabb::Aabb;
 use crate::material::Material;
 use crate::ray::Ray;
+use crate::scene_export::SceneWriter;
 use crate::vec3::Vec3;
+use std::collections::HashSet;
+use std::sync::Arc;
 
-#[derive(Debug, Copy, Clone)]
+/// Where a ray hit an object, filled in by [`Hittable::hit`].
+///
+/// A hit must set `t`, `p`, `material` and the normal, the latter through
+/// [`HitRecord::set_face_normal`] so the normal faces against the ray and the side that was hit
+/// is known. `uv` falls back to `(0, 0)` when textures are looked up, and without `tangent` and
+/// `bitangent` normal maps, bumps and fibers leave the normal as it is.
+#[derive(Debug, Clone)]
 pub struct HitRecord {
+    /// Hit point in world space, `ray.at(t)`.
     pub p: Option<Vec3>,
+    /// Unit normal at the hit, facing against the ray.
     pub normal: Option<Vec3>,
+    /// Distance along the ray, in units of its direction.
     pub t: Option<f64>,
+    /// Material of the surface that was hit.
     pub material: Option<Material>,
+    /// Texture coordinates of the hit point.
+    pub uv: Option<(f64, f64)>,
+    /// Surface frame along increasing u and v, in the direction of the outward normal.
+    pub tangent: Option<Vec3>,
+    /// Second axis of the surface frame, see `tangent`.
+    pub bitangent: Option<Vec3>,
     front_face: Option<bool>,
+    // Set once a BVH leaf has tested the opacity mask of the hit material.
+    pub(crate) opacity_resolved: bool,
 }
 
 #[allow(dead_code)]
@@ -20,6 +42,10 @@ impl HitRecord {
             t: None,
             front_face: None,
             material: None,
+            uv: None,
+            tangent: None,
+            bitangent: None,
+            opacity_resolved: false,
         }
     }
     pub fn get_p(&self) -> Option<Vec3> {
@@ -34,9 +60,19 @@ impl HitRecord {
     pub fn get_front_face(&self) -> Option<bool> {
         self.front_face
     }
+    pub fn get_uv(&self) -> Option<(f64, f64)> {
+        self.uv
+    }
+    pub fn get_tangent(&self) -> Option<Vec3> {
+        self.tangent
+    }
+    pub fn get_bitangent(&self) -> Option<Vec3> {
+        self.bitangent
+    }
 
+    /// Orients the normal against the ray and remembers whether the outside was hit.
     pub fn set_face_normal(&mut self, ray: Ray, outward_normal: Vec3) {
-        let r = ray.clone();
+        let r = ray;
         self.front_face = Some(r.direction().dot(outward_normal) < 0.0);
         if self.front_face.unwrap() {
             self.normal = Some(outward_normal);
@@ -45,15 +81,108 @@ impl HitRecord {
         }
     }
 
+    /// Bends the shading normal to one given in the tangent frame (x along the tangent, y along
+    /// the bitangent, z along the outward normal), keeping the orientation chosen by
+    /// `set_face_normal` so back faces are perturbed consistently.
+    pub fn set_shading_normal(&mut self, ray: Ray, local_normal: Vec3) {
+        let (tangent, bitangent) = match (self.tangent, self.bitangent) {
+            (Some(tangent), Some(bitangent)) => (tangent, bitangent),
+            _ => return,
+        };
+        let front_face = self.front_face.unwrap();
+        let outward_normal = if front_face {
+            self.normal.unwrap()
+        } else {
+            -self.normal.unwrap()
+        };
+        let perturbed = (tangent * local_normal.x()
+            + bitangent * local_normal.y()
+            + outward_normal * local_normal.z())
+        .unit_vector();
+        let shading_normal = if front_face { perturbed } else { -perturbed };
+
+        // A normal bent past the horizon would scatter rays through the surface.
+        if shading_normal.dot(ray.direction()) < 0.0 {
+            self.normal = Some(shading_normal);
+        }
+    }
+
+    /// Copies every field of another record, e.g. the closest hit found so far.
     pub fn set_rec(&mut self, r: &HitRecord) {
-        self.p = r.p.clone();
-        self.t = r.t.clone();
-        self.normal = r.normal.clone();
-        self.front_face = r.front_face.clone();
+        self.p = r.p;
+        self.t = r.t;
+        self.normal = r.normal;
+        self.front_face = r.front_face;
         self.material = r.material.clone();
+        self.uv = r.uv;
+        self.tangent = r.tangent;
+        self.bitangent = r.bitangent;
+        self.opacity_resolved = r.opacity_resolved;
+    }
+}
+
+impl Default for HitRecord {
+    fn default() -> HitRecord {
+        HitRecord::new()
     }
 }
 
+/// Stretch of a ray inside a closed object, from the entering to the exiting distance.
+pub type Interval = (f64, f64);
+
+/// Anything a ray can hit, from a single primitive to a whole BVH.
 pub trait Hittable {
+    /// Finds the closest hit with `t_min < t < t_max`, both bounds excluded, and fills `rec` as
+    /// described on [`HitRecord`]. Returns whether there was a hit, `rec` only holds one if so.
+    /// Renderers pass a small `t_min` so rays leaving a surface do not hit it again.
     fn hit(&self, r: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool;
+    /// Bounds over the whole shutter interval. `None` means the object is unbounded, like a
+    /// plane: it is kept out of the BVH and tested against every ray.
+    fn bounding_box(&self) -> Option<Aabb>;
+    /// Every span of the whole ray line inside the object, sorted by distance. Only closed
+    /// objects can take part in CSG; the default, `None`, marks an open surface.
+    fn intervals(&self, _r: Ray) -> Option<Vec<Interval>> {
+        None
+    }
+    /// Heap bytes of acceleration structures inside the object, for render stats. `seen` holds the
+    /// addresses of shared geometry already counted. Defaults to nothing.
+    fn acceleration_bytes(&self, _seen: &mut HashSet<usize>) -> usize {
+        0
+    }
+    /// Describes the object to a scene being written out, see `Scene::to_ron`. The default has
+    /// no description, so the object is reported as left out and the rest of the scene is
+    /// still written.
+    fn export(&self, writer: &mut SceneWriter) {
+        writer.skip(std::any::type_name::<Self>());
+    }
+}
+
+// Lets geometry shared between frames or instances be placed in a scene directly.
+impl<T: Hittable + ?Sized> Hittable for Arc<T> {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        self.as_ref().hit(r, t_min, t_max, rec)
+    }
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.as_ref().bounding_box()
+    }
+    fn intervals(&self, r: Ray) -> Option<Vec<Interval>> {
+        self.as_ref().intervals(r)
+    }
+    fn acceleration_bytes(&self, seen: &mut HashSet<usize>) -> usize {
+        if seen.insert(Arc::as_ptr(self) as *const () as usize) {
+            self.as_ref().acceleration_bytes(seen)
+        } else {
+            0
+        }
+    }
+    // Geometry with several owners, e.g. placed by more than one instance, is written once.
+    fn export(&self, writer: &mut SceneWriter) {
+        if Arc::strong_count(self) > 1 {
+            writer.shared(Arc::as_ptr(self) as *const () as usize, |writer| {
+                self.as_ref().export(writer)
+            });
+        } else {
+            self.as_ref().export(writer);
+        }
+    }
 }
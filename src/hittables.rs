@@ -1,18 +1,21 @@
 use crate::hittable::{HitRecord, Hittable};
 use crate::ray::Ray;
 use crate::vec3::Vec3;
+use std::collections::HashSet;
 
 pub struct Hittables {
-    pub lights: Vec<Box<Vec3>>,
+    pub lights: Vec<Vec3>,
     pub hittables: Vec<Box<dyn Hittable + Send + Sync>>,
 }
 
 #[allow(dead_code)]
 impl Hittables {
     pub fn push(&mut self, hittable_: Box<dyn Hittable + Send + Sync>) {
-        &self.hittables.push(hittable_);
+        self.hittables.push(hittable_);
+    }
+    pub fn push_light(&mut self, light_position: Vec3) {
+        self.lights.push(light_position);
     }
-    pub fn push_light(&mut self, light_position: Vec3) { &self.lights.push(Box::new(light_position.clone())); }
 
     pub fn hit(&self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
         let mut hit_anything = false;
@@ -28,6 +31,14 @@ impl Hittables {
         }
         hit_anything
     }
+
+    pub fn acceleration_bytes(&self) -> usize {
+        let mut seen = HashSet::new();
+        self.hittables
+            .iter()
+            .map(|hittable| hittable.acceleration_bytes(&mut seen))
+            .sum()
+    }
 }
 
 
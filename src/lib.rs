@@ -1,32 +1,136 @@
-use crate::camera::Camera;
+//! Ray tracer behind the `pyrays` Python library.
+//!
+//! Everything needed to build scenes in Rust and to write custom primitives, materials and
+//! textures is re-exported here, starting from [`Scene::builder`]. RON scenes go through
+//! [`create_image`], [`load_scene`] and the other free functions instead.
+
+use crate::animation::{
+    animated_geometries, contains_named, references_geometry, Animation, Interpolation, Keyframe,
+    Target, Track,
+};
 use crate::configuration::RaytracerScene;
-use crate::configuration::RaytracerSettings;
+use crate::configuration::RonAnimation;
+use crate::configuration::RonBokeh;
+use crate::configuration::RonGeometry;
+use crate::configuration::RonMesh;
 use crate::configuration::RonObject;
-use crate::hittable::Hittable;
+use crate::configuration::RonSdf;
+use crate::configuration::RonTexture;
+use crate::configuration::RonTransform;
+use crate::csg::is_closed;
+use crate::gltf_scene::{GltfProjection, GltfScene};
+use crate::heightfield::{grid_triangles, load_image_heights, load_raw_heights, noise_heights};
 use crate::hittables::Hittables;
-use crate::sphere::Sphere;
-use crate::triangle::Triangle;
-use crate::vec3::Vec3;
-use material::Material;
-use num_cpus;
-use rand::Rng;
+use crate::mesh_file::MeshData;
+use crate::perlin::Perlin;
+use crate::scatter::{CurveScatter, InstanceScatter};
+use crate::stats::{Counters, Stopwatch};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
-use std::time::Instant;
-use std::{env, thread};
+use std::thread;
 
+mod aabb;
+mod animation;
+mod bvh;
 mod camera;
+#[cfg(feature = "capi")]
+mod capi;
+mod cone;
 mod configuration;
+mod csg;
+mod curve;
+mod cube;
+mod cylinder;
+mod disc;
+mod frame;
+mod gltf_scene;
+mod group;
+mod heightfield;
 mod hittable;
 mod hittables;
 mod material;
+mod matrix;
+mod mesh_file;
+mod oriented_box;
+mod perlin;
+mod plane;
+#[cfg(feature = "python")]
+mod python;
 mod ray;
+mod rectangle;
+mod scatter;
+mod scene;
+mod scene_export;
+mod scene_file;
+mod sdf;
 mod sphere;
+mod stats;
+mod texture;
+mod torus;
+mod transform;
 mod triangle;
 mod vec3;
+mod water;
+#[cfg(target_arch = "wasm32")]
+mod wasm;
+
+pub use crate::aabb::Aabb;
+pub use crate::bvh::Bvh;
+pub use crate::camera::{
+    Aperture, Camera, EquirectangularCamera, FisheyeCamera, OrthographicCamera, PerspectiveCamera,
+    PhysicalLens, Shutter,
+};
+pub use crate::cone::Cone;
+pub use crate::csg::{Csg, CsgOp};
+pub use crate::cube::Cube;
+pub use crate::curve::{Curve, CurveShape};
+pub use crate::cylinder::Cylinder;
+pub use crate::disc::Disc;
+pub use crate::group::Group;
+pub use crate::heightfield::Heightfield;
+pub use crate::hittable::{HitRecord, Hittable, Interval};
+pub use crate::material::Material;
+pub use crate::matrix::Mat4;
+pub use crate::oriented_box::OrientedBox;
+pub use crate::plane::Plane;
+pub use crate::ray::Ray;
+pub use crate::rectangle::Rectangle;
+pub use crate::scene::{RenderControl, RenderSettings, Scene, SceneBuilder};
+pub use crate::scene_export::SceneWriter;
+pub use crate::sdf::{Sdf, SdfNode};
+pub use crate::sphere::Sphere;
+pub use crate::stats::{RayCounts, RenderStats};
+pub use crate::texture::{
+    Checker, HeightGradient, ImageTexture, MarbleTexture, NoiseTexture, SolidColor, Texture,
+    TextureRef, Tinted, VertexColors, WrapMode,
+};
+pub use crate::torus::Torus;
+pub use crate::transform::{Transform, Trs};
+pub use crate::triangle::Triangle;
+pub use crate::vec3::Vec3;
+pub use crate::water::Water;
+
+// Sampling draws from a seeded generator per thread rather than `thread_rng`, which needs an OS
+// source of randomness that wasm32 lacks. Each thread starts from the next seed in sequence.
+static NEXT_SEED: AtomicU64 = AtomicU64::new(0x853c_49e6_748f_ea9b);
+
+thread_local! {
+    static RNG: RefCell<StdRng> =
+        RefCell::new(StdRng::seed_from_u64(NEXT_SEED.fetch_add(1, Ordering::Relaxed)));
+}
+
+/// Restarts the sampling sequence of the calling thread, so single threaded renders such as
+/// `Scene::render_tile` are reproducible.
+pub fn seed_random(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
 
 fn random() -> f64 {
-    let mut rng = rand::thread_rng();
-    rng.gen::<f64>()
+    RNG.with(|rng| rng.borrow_mut().gen::<f64>())
 }
 fn random_f64(min: f64, max: f64) -> f64 {
     min + (max - min) * random()
@@ -160,7 +264,47 @@ fn create_world(settings: &RaytracerSettings) -> (Hittables, Camera) {
     (world, camera)
 }
 */
-fn ray_color(ray: ray::Ray, world: &hittables::Hittables, depth: i32) -> vec3::Vec3 {
+// Beer-Lambert falloff over `distance` travelled inside a medium with the given absorption.
+fn transmittance(medium: Option<Vec3>, distance: f64) -> Vec3 {
+    let channel = |absorption: f64| {
+        if absorption > 0.0 {
+            (-absorption * distance).exp()
+        } else {
+            1.0
+        }
+    };
+    match medium {
+        Some(a) => Vec3::new(channel(a.x()), channel(a.y()), channel(a.z())),
+        None => Vec3::new(1.0, 1.0, 1.0),
+    }
+}
+
+// Rays transmitted into an absorbing surface travel through its medium until they are
+// transmitted back out, reflections stay in the medium they arrived through.
+fn scattered_medium(
+    rec: &hittable::HitRecord,
+    scattered: ray::Ray,
+    medium: Option<Vec3>,
+) -> Option<Vec3> {
+    let transmitted = scattered.direction().dot(rec.normal.unwrap()) < 0.0;
+    match rec.material.as_ref().and_then(|m| m.absorption()) {
+        Some(absorption) if transmitted => {
+            if rec.get_front_face().unwrap() {
+                Some(absorption)
+            } else {
+                None
+            }
+        }
+        _ => medium,
+    }
+}
+
+fn ray_color(
+    ray: ray::Ray,
+    world: &hittables::Hittables,
+    depth: i32,
+    medium: Option<Vec3>,
+) -> vec3::Vec3 {
     let mut hit_rec = hittable::HitRecord::new();
     let bias = 0.01;
 
@@ -170,16 +314,17 @@ fn ray_color(ray: ray::Ray, world: &hittables::Hittables, depth: i32) -> vec3::V
 
     if world.hit(ray, 0.001, f64::INFINITY, &mut hit_rec) {
         let color = &mut vec3::Vec3::new(0.0, 0.0, 0.0);
-        let res = material::scatter(ray, hit_rec, color, hit_rec.material.unwrap());
+        let res = material::scatter(ray, &hit_rec, color, hit_rec.material.as_ref().unwrap());
         match res {
             Some(result) => {
                 let mut in_shadow = vec3::Vec3::new(1.0, 1.0, 1.0);
                 for i in 0..world.lights.len() {
-                    let light_direction = (*world.lights[i] - hit_rec.p.unwrap()).unit_vector();
+                    let light_direction = (world.lights[i] - hit_rec.p.unwrap()).unit_vector();
                     let point_of_intersection = hit_rec.p.unwrap() + (light_direction * bias);
-                    let max_dist = (point_of_intersection - *world.lights[i]).length();
+                    let max_dist = (point_of_intersection - world.lights[i]).length();
+                    stats::count(|counters| counters.rays.shadow += 1);
                     if world.hit(
-                        ray::Ray::new(point_of_intersection, light_direction),
+                        ray::Ray::new(point_of_intersection, light_direction, ray.time()),
                         0.01,
                         max_dist / 2.0,
                         &mut hittable::HitRecord::new(),
@@ -188,7 +333,13 @@ fn ray_color(ray: ray::Ray, world: &hittables::Hittables, depth: i32) -> vec3::V
                     }
                 }
 
-                *color * ray_color(result, world, depth - 1) * in_shadow
+                let distance = hit_rec.t.unwrap() * ray.direction().length();
+                let absorbed = transmittance(medium, distance);
+                let next_medium = scattered_medium(&hit_rec, result, medium);
+                if depth > 1 {
+                    stats::count(|counters| counters.rays.bounce += 1);
+                }
+                *color * ray_color(result, world, depth - 1, next_medium) * in_shadow * absorbed
             }
             None => vec3::Vec3::new(0.0, 0.0, 0.0),
         }
@@ -197,7 +348,7 @@ fn ray_color(ray: ray::Ray, world: &hittables::Hittables, depth: i32) -> vec3::V
         let t = 0.5 * (unit_dir.y() + 1.0);
         let one = vec3::Vec3::new(1.0, 1.0, 1.0) * (1.0 - t);
         let two = vec3::Vec3::new(0.5, 0.7, 1.0) * t;
-        one + two
+        (one + two) * transmittance(medium, f64::INFINITY)
     }
 }
 
@@ -218,7 +369,7 @@ fn create_work_list(image_width: i32, image_height: i32, num_cpu: usize) -> Vec<
                 if y < image_height as usize && x < image_width {
                     work_for_cpu.push(Work {
                         x: x as usize,
-                        y: y as usize,
+                        y,
                         colour: None,
                     });
                 }
@@ -230,121 +381,1094 @@ fn create_work_list(image_width: i32, image_height: i32, num_cpu: usize) -> Vec<
 }
 
 fn sample_pixel(
-    samples_per_pixel: usize,
+    settings: &RenderSettings,
     x: f64,
     y: i32,
-    image_width: i32,
-    image_height: i32,
-    max_depth: i32,
-    camera: &Camera,
+    camera: &dyn Camera,
     world: &Hittables,
 ) -> Vec3 {
     let mut pixel_color = vec3::Vec3::new(0.0, 0.0, 0.0);
 
-    for _k in 0..samples_per_pixel {
+    for _k in 0..settings.samples_per_pixel {
         let r = {
-            let u = (x + random()) / (image_width - 1) as f64;
-            let v = ((image_height - (y + 1)) as f64 + random()) / (image_height - 1) as f64;
+            let u = (x + random()) / (settings.image_width - 1) as f64;
+            let v = ((settings.image_height - (y + 1)) as f64 + random())
+                / (settings.image_height - 1) as f64;
             camera.get_ray(u, v)
         };
-        pixel_color = pixel_color + ray_color(r, world, max_depth);
+        stats::count(|counters| counters.rays.camera += 1);
+        pixel_color = pixel_color + ray_color(r, world, settings.max_depth, None);
     }
 
-    pixel_color
+    pixel_color * camera.exposure()
 }
 
 fn conv_py_vec(vector: Vec<f64>) -> Vec3 {
     vec3::Vec3::new(vector[0], vector[1], vector[2])
 }
 
-fn parse_ron_material(mat: Vec<String>) -> Material {
-    let material_type = &mat[0];
-    match &material_type[..] {
-        "Lambertian" => material::Material::Lambertian(vec3::Vec3::new(
-            mat[1].parse::<f64>().unwrap(),
-            mat[2].parse::<f64>().unwrap(),
-            mat[3].parse::<f64>().unwrap(),
+fn parse_ron_texture(tex: &RonTexture, textures: &HashMap<String, TextureRef>) -> TextureRef {
+    match &tex.textype[..] {
+        "Solid" => Arc::new(SolidColor::new(conv_py_vec(tex.vectors[0].clone()))),
+        "Checker" => {
+            let slot = |i: usize| -> TextureRef {
+                match tex.children.get(i) {
+                    Some(name) => lookup_texture(name, textures),
+                    None => Arc::new(SolidColor::new(conv_py_vec(tex.vectors[i].clone()))),
+                }
+            };
+            Arc::new(Checker::new(slot(0), slot(1), tex.scalars[0]))
+        }
+        "Image" => {
+            let wrap = match &tex.wrap {
+                Some(name) => WrapMode::from_name(name),
+                None => WrapMode::Repeat,
+            };
+            Arc::new(ImageTexture::load(
+                tex.path.as_ref().expect("image texture requires a path"),
+                wrap,
+                tex.linear,
+            ))
+        }
+        "Noise" => Arc::new(NoiseTexture::new(
+            tex.scalars[0] as u32,
+            conv_py_vec(tex.vectors[0].clone()),
+            tex.scalars[1],
+            tex.scalars[2] as usize,
+            tex.scalars[3],
+            tex.scalars[4],
+        )),
+        "Marble" => Arc::new(MarbleTexture::new(
+            tex.scalars[0] as u32,
+            conv_py_vec(tex.vectors[0].clone()),
+            tex.scalars[1],
+            tex.scalars[2] as usize,
+        )),
+        // Another texture scaled by the color in vectors[0].
+        "Tinted" => Arc::new(Tinted::new(
+            lookup_texture(&tex.children[0], textures),
+            conv_py_vec(tex.vectors[0].clone()),
+        )),
+        "HeightGradient" => Arc::new(HeightGradient::new(
+            tex.scalars
+                .iter()
+                .zip(tex.vectors.iter())
+                .map(|(height, color)| (*height, conv_py_vec(color.clone())))
+                .collect(),
         )),
+        &_ => {
+            panic!("Unknown texture type {}", tex.textype)
+        }
+    }
+}
+
+fn parse_ron_textures(ron_textures: &[RonTexture]) -> HashMap<String, TextureRef> {
+    let mut textures = HashMap::new();
+    for tex in ron_textures {
+        let texture = parse_ron_texture(tex, &textures);
+        textures.insert(tex.name.clone(), texture);
+    }
+    textures
+}
+
+fn lookup_texture(name: &str, textures: &HashMap<String, TextureRef>) -> TextureRef {
+    match textures.get(name) {
+        Some(texture) => texture.clone(),
+        None => panic!("Unknown texture {}", name),
+    }
+}
+
+// A color slot is either three numbers starting at `index` or the name of a texture.
+fn parse_color_slot(
+    mat: &[String],
+    index: usize,
+    textures: &HashMap<String, TextureRef>,
+) -> TextureRef {
+    match mat[index].parse::<f64>() {
+        Ok(r) => Arc::new(SolidColor::new(vec3::Vec3::new(
+            r,
+            mat[index + 1].parse::<f64>().unwrap(),
+            mat[index + 2].parse::<f64>().unwrap(),
+        ))),
+        Err(_) => lookup_texture(&mat[index], textures),
+    }
+}
+
+// A scalar slot is either a number or the name of a texture whose red channel is used.
+fn parse_scalar_slot(
+    mat: &[String],
+    index: usize,
+    textures: &HashMap<String, TextureRef>,
+) -> TextureRef {
+    match mat[index].parse::<f64>() {
+        Ok(value) => Arc::new(SolidColor::new(vec3::Vec3::new(value, value, value))),
+        Err(_) => lookup_texture(&mat[index], textures),
+    }
+}
+
+fn parse_ron_material(mat: Vec<String>, textures: &HashMap<String, TextureRef>) -> Material {
+    let material_type = &mat[0];
+    let base = match &material_type[..] {
+        "Lambertian" => material::Material::Lambertian(parse_color_slot(&mat, 1, textures)),
         "Metal" => material::Material::Metal(
-            vec3::Vec3::new(
-                mat[1].parse::<f64>().unwrap(),
-                mat[2].parse::<f64>().unwrap(),
-                mat[3].parse::<f64>().unwrap(),
-            ),
-            mat[4].parse::<f64>().unwrap(),
+            parse_color_slot(&mat, 1, textures),
+            parse_scalar_slot(&mat, 4, textures),
         ),
         "Mirror" => material::Material::Mirror,
+        "Hair" => material::Material::Hair(
+            parse_color_slot(&mat, 1, textures),
+            parse_scalar_slot(&mat, 4, textures),
+        ),
         "Dielectric" => material::Material::Dielectric(mat[4].parse::<f64>().unwrap()),
         &_ => {
             panic!("Unknown material found")
         }
+    };
+    apply_material_modifiers(base, &mat, textures)
+}
+
+// Entries of the form `key=value` wrap the base material, e.g. `normal_map=bricks_n`.
+fn apply_material_modifiers(
+    base: Material,
+    mat: &[String],
+    textures: &HashMap<String, TextureRef>,
+) -> Material {
+    let options: HashMap<&str, &str> = mat.iter().filter_map(|m| m.split_once('=')).collect();
+    let scalar = |key: &str, default: f64| match options.get(key) {
+        Some(value) => value.parse::<f64>().unwrap(),
+        None => default,
+    };
+
+    let mut material = base;
+    if let Some(name) = options.get("bump") {
+        material = Material::Bumped(
+            Box::new(material),
+            lookup_texture(name, textures),
+            scalar("bump_scale", 1.0),
+        );
     }
+    if let Some(name) = options.get("opacity") {
+        material = Material::Masked(
+            Box::new(material),
+            lookup_texture(name, textures),
+            scalar("alpha_threshold", 0.5),
+            options.get("alpha_mode") == Some(&"stochastic"),
+        );
+    }
+    if let Some(absorption) = options.get("absorption") {
+        let channels: Vec<f64> = absorption
+            .split(',')
+            .map(|c| c.parse::<f64>().unwrap())
+            .collect();
+        material = Material::Absorbing(
+            Box::new(material),
+            Vec3::new(channels[0], channels[1], channels[2]),
+        );
+    }
+    if let Some(name) = options.get("normal_map") {
+        material = Material::NormalMapped(
+            Box::new(material),
+            lookup_texture(name, textures),
+            scalar("normal_strength", 1.0),
+        );
+    }
+    material
 }
 
-fn parse_ron_object(obj: RonObject) -> Box<dyn Hittable + Send + Sync + 'static> {
-    if obj.objtype == "Sphere" {
-        return Box::new(Sphere::new(
-            conv_py_vec(obj.vectors[0].clone()),
-            obj.scalars[0],
-            parse_ron_material(obj.material),
-        ));
-    } else if obj.objtype == "Triangle" {
-        let cull_back = if obj.scalars[0] == 0.0 { false } else { true };
-        return Box::new(Triangle::new(
-            conv_py_vec(obj.vectors[0].clone()),
-            conv_py_vec(obj.vectors[1].clone()),
-            conv_py_vec(obj.vectors[2].clone()),
-            parse_ron_material(obj.material),
-            cull_back,
-        ));
+// Textures and shared geometry that objects in the scene refer to by name. The triangles of
+// each geometry are kept as surfaces to scatter objects over, and imported files by path.
+struct SceneAssets {
+    textures: HashMap<String, TextureRef>,
+    geometries: HashMap<String, Arc<dyn Hittable + Send + Sync>>,
+    surfaces: HashMap<String, Vec<[Vec3; 3]>>,
+    imports: HashMap<String, GltfScene>,
+}
+
+impl SceneAssets {
+    fn new(settings: &RaytracerScene) -> SceneAssets {
+        let mut assets = SceneAssets {
+            textures: parse_ron_textures(&settings.textures),
+            geometries: HashMap::new(),
+            surfaces: HashMap::new(),
+            imports: HashMap::new(),
+        };
+        let mut paths = vec![];
+        collect_gltf_paths(&settings.objects, &mut paths);
+        for geometry in &settings.geometries {
+            collect_gltf_paths(&geometry.objects, &mut paths);
+        }
+        for path in paths {
+            assets
+                .imports
+                .entry(path)
+                .or_insert_with_key(|path| GltfScene::load(path));
+        }
+        assets.build_geometries(&settings.geometries);
+        assets
+    }
+
+    // Textures are kept, so animated geometry can be rebuilt without reloading images.
+    fn build_geometries(&mut self, geometries: &[RonGeometry]) {
+        self.geometries.clear();
+        self.surfaces.clear();
+        for geometry in geometries {
+            let mut triangles = vec![];
+            collect_triangles(&geometry.objects, Mat4::identity(), &mut triangles);
+            self.surfaces.insert(geometry.name.clone(), triangles);
+            let objects = geometry
+                .objects
+                .iter()
+                .map(|obj| parse_ron_object(obj.clone(), self))
+                .collect();
+            self.geometries
+                .insert(geometry.name.clone(), Arc::new(Group::new(objects)));
+        }
     }
-    panic!("unknown ron object type.");
 }
 
-pub fn create_image(ron_string: String) -> Vec<Vec<u8>> {
-    let settings = configuration::RaytracerScene::from_ron(ron_string);
+fn collect_gltf_paths(objects: &[RonObject], paths: &mut Vec<String>) {
+    for obj in objects {
+        if obj.objtype == "Gltf" {
+            paths.push(obj.path.clone().expect("Gltf object requires a path"));
+        }
+        collect_gltf_paths(&obj.children, paths);
+    }
+}
 
-    let camera = camera::Camera::new(
-        conv_py_vec(settings.camera_pos.clone()),
-        conv_py_vec(settings.camera_dir.clone()),
-        conv_py_vec(settings.camera_up.clone()),
-        settings.v_fov,
-        settings.aspect_ratio,
-        settings.aperture,
-        settings.focal_distance,
+// Imported glTF objects with their placement in the world, following nodes and their transforms.
+fn collect_gltf_objects<'a>(
+    objects: &'a [RonObject],
+    parent: Mat4,
+    found: &mut Vec<(&'a RonObject, Mat4)>,
+) {
+    for obj in objects {
+        let matrix = parent * ron_matrix(&obj.transform);
+        if obj.objtype == "Gltf" {
+            found.push((obj, matrix));
+        }
+        collect_gltf_objects(&obj.children, matrix, found);
+    }
+}
+
+// World space triangles of the objects, following nodes and their transforms.
+fn collect_triangles(objects: &[RonObject], parent: Mat4, triangles: &mut Vec<[Vec3; 3]>) {
+    for obj in objects {
+        let matrix = parent * ron_matrix(&obj.transform);
+        match &obj.objtype[..] {
+            "Triangle" => triangles.push([0, 1, 2].map(|i| {
+                matrix.transform_point(conv_py_vec(obj.vectors[i].clone()))
+            })),
+            "Heightfield" => {
+                let (width, depth, heights) = ron_heightfield_grid(obj);
+                let grid = grid_triangles(
+                    conv_py_vec(obj.vectors[0].clone()),
+                    conv_py_vec(obj.vectors[1].clone()),
+                    width,
+                    depth,
+                    &heights,
+                );
+                triangles.extend(
+                    grid.into_iter()
+                        .map(|triangle| triangle.map(|p| matrix.transform_point(p))),
+                );
+            }
+            "Mesh" => match (&obj.path, &obj.mesh) {
+                (Some(path), _) => {
+                    let mesh = MeshData::load(path);
+                    triangles.extend(
+                        mesh.faces
+                            .iter()
+                            .map(|face| face.map(|i| matrix.transform_point(mesh.positions[i]))),
+                    );
+                }
+                (None, Some(mesh)) => triangles.extend(mesh.positions.chunks(3).map(|corners| {
+                    [0, 1, 2].map(|k| matrix.transform_point(conv_py_vec(corners[k].clone())))
+                })),
+                (None, None) => panic!("Mesh object requires a path or a mesh"),
+            },
+            "Node" => collect_triangles(&obj.children, matrix, triangles),
+            _ => {}
+        }
+    }
+}
+
+fn parse_ron_trs(transform: &Option<RonTransform>) -> Trs {
+    match transform {
+        Some(t) => Trs {
+            translate: conv_py_vec(t.translate.clone()),
+            rotate: conv_py_vec(t.rotate.clone()),
+            scale: conv_py_vec(t.scale.clone()),
+        },
+        None => Trs {
+            translate: Vec3::new(0.0, 0.0, 0.0),
+            rotate: Vec3::new(0.0, 0.0, 0.0),
+            scale: Vec3::new(1.0, 1.0, 1.0),
+        },
+    }
+}
+
+// Object to parent placement, the explicit matrix followed by translate, rotate and scale.
+fn ron_matrix(transform: &Option<RonTransform>) -> Mat4 {
+    let trs = parse_ron_trs(transform).matrix();
+    match transform.as_ref().and_then(|t| t.matrix.as_ref()) {
+        Some(rows) => trs * ron_explicit_matrix(rows),
+        None => trs,
+    }
+}
+
+fn ron_explicit_matrix(rows: &[Vec<f64>]) -> Mat4 {
+    let mut m = [[0.0; 4]; 4];
+    for (row, values) in m.iter_mut().zip(rows) {
+        row.copy_from_slice(&values[..4]);
+    }
+    Mat4 { m }
+}
+
+fn parse_ron_object(
+    obj: RonObject,
+    assets: &SceneAssets,
+) -> Box<dyn Hittable + Send + Sync + 'static> {
+    let shared: Arc<dyn Hittable + Send + Sync> = match &obj.objtype[..] {
+        "Node" => Arc::new(Group::new(
+            obj.children
+                .iter()
+                .map(|child| parse_ron_object(child.clone(), assets))
+                .collect(),
+        )),
+        "Union" | "Intersection" | "Difference" => {
+            // Operands are combined left to right, so a difference subtracts every later child
+            // from the first.
+            let op = CsgOp::from_name(&obj.objtype);
+            let mut operands = obj.children.iter().map(|child| {
+                let operand: Arc<dyn Hittable + Send + Sync> =
+                    Arc::from(parse_ron_object(child.clone(), assets));
+                if !is_closed(operand.as_ref()) {
+                    match &child.name {
+                        Some(name) => panic!(
+                            "{} operand {} \"{}\" is not a closed object",
+                            obj.objtype, child.objtype, name
+                        ),
+                        None => panic!(
+                            "{} operand {} is not a closed object",
+                            obj.objtype, child.objtype
+                        ),
+                    }
+                }
+                operand
+            });
+            let first = operands.next().expect("CSG requires at least one child");
+            operands.fold(first, |combined, operand| {
+                Arc::new(Csg::new(op, combined, operand))
+            })
+        }
+        "Scatter" => {
+            let settings = obj.scatter.as_ref().expect("Scatter object requires a scatter");
+            let name = obj.geometry.as_ref().expect("scatter requires a geometry name");
+            let geometry = match assets.geometries.get(name) {
+                Some(geometry) => geometry,
+                None => panic!("Unknown geometry {}", name),
+            };
+            let surface = match assets.surfaces.get(&settings.surface) {
+                Some(surface) => surface,
+                None => panic!("Unknown geometry {}", settings.surface),
+            };
+            let (min_scale, max_scale) = settings.scale;
+            if min_scale > max_scale {
+                panic!(
+                    "Scatter of {} over {} has its scale range backwards: ({}, {})",
+                    name, settings.surface, min_scale, max_scale
+                );
+            }
+            let scatter = InstanceScatter {
+                density: settings.density,
+                seed: settings.seed,
+                spacing: settings.spacing,
+                slope: settings.slope,
+                altitude: settings.altitude,
+                rotation_jitter: settings.rotation_jitter,
+                scale: settings.scale,
+                align_to_normal: settings.align_to_normal,
+            };
+            Arc::new(Bvh::new(scatter.scatter(surface, geometry)))
+        }
+        "Gltf" => assets.imports[obj.path.as_ref().unwrap()].objects.clone(),
+        "Instance" => {
+            let name = obj
+                .geometry
+                .as_ref()
+                .expect("instance requires a geometry name");
+            match assets.geometries.get(name) {
+                Some(geometry) => geometry.clone(),
+                None => panic!("Unknown geometry {}", name),
+            }
+        }
+        // scalars [density, seed, length, root_width, tip_width, bend, cylindrical], grown
+        // from the triangles of the named geometry.
+        "CurveScatter" => {
+            let name = obj
+                .geometry
+                .as_ref()
+                .expect("curve scatter requires a surface geometry");
+            let surface = match assets.surfaces.get(name) {
+                Some(surface) => surface,
+                None => panic!("Unknown geometry {}", name),
+            };
+            let scatter = CurveScatter {
+                density: obj.scalars[0],
+                seed: obj.scalars[1] as u32,
+                length: obj.scalars[2],
+                root_width: obj.scalars[3],
+                tip_width: obj.scalars[4],
+                bend: obj.scalars[5],
+                shape: curve_shape(obj.scalars.get(6)),
+            };
+            let material = parse_ron_material(obj.material.clone(), &assets.textures);
+            Arc::new(Bvh::new(scatter.scatter(surface, &material)))
+        }
+        _ => {
+            let primitive = parse_ron_primitive(&obj, &assets.textures);
+            if obj.transform.is_none() && obj.transform_end.is_none() {
+                return primitive;
+            }
+            Arc::from(primitive)
+        }
+    };
+
+    let mut transform = match &obj.transform_end {
+        Some(_) => {
+            // Motion interpolates translate, rotate and scale, the matrix stays fixed inside.
+            let shared = match obj.transform.as_ref().and_then(|t| t.matrix.as_ref()) {
+                Some(rows) => Arc::new(Transform::new(shared, ron_explicit_matrix(rows))),
+                None => shared,
+            };
+            let start = parse_ron_trs(&obj.transform);
+            Transform::animated(shared, start, parse_ron_trs(&obj.transform_end))
+        }
+        None => Transform::new(shared, ron_matrix(&obj.transform)),
+    };
+    let is_primitive = !matches!(
+        &obj.objtype[..],
+        "Node" | "Instance" | "Scatter" | "Gltf" | "Union" | "Intersection" | "Difference"
     );
+    if !is_primitive && !obj.material.is_empty() {
+        transform = transform.with_material(parse_ron_material(obj.material, &assets.textures));
+    }
+    Box::new(transform)
+}
+
+fn parse_ron_primitive(
+    obj: &RonObject,
+    textures: &HashMap<String, TextureRef>,
+) -> Box<dyn Hittable + Send + Sync + 'static> {
+    let vector = |i: usize| conv_py_vec(obj.vectors[i].clone());
+    let material = parse_ron_material(obj.material.clone(), textures);
+    match &obj.objtype[..] {
+        "Sphere" => Box::new(Sphere::new(vector(0), obj.scalars[0], material)),
+        "MovingSphere" => Box::new(Sphere::moving(
+            vector(0),
+            vector(1),
+            obj.scalars[0],
+            material,
+        )),
+        "Triangle" => {
+            let cull_back = obj.scalars[0] != 0.0;
+            let mut triangle = Triangle::new(vector(0), vector(1), vector(2), material, cull_back);
+            // Optional per-vertex uvs follow the three positions, then optional normals.
+            if obj.vectors.len() >= 6 {
+                let uv = |i: usize| (obj.vectors[i][0], obj.vectors[i][1]);
+                triangle = triangle.with_uvs([uv(3), uv(4), uv(5)]);
+            }
+            if obj.vectors.len() >= 9 {
+                triangle = triangle.with_normals([vector(6), vector(7), vector(8)]);
+            }
+            Box::new(triangle)
+        }
+        // Four control points, scalars [root_width, tip_width, cylindrical].
+        "Curve" => Box::new(Curve::new(
+            [vector(0), vector(1), vector(2), vector(3)],
+            obj.scalars[0],
+            obj.scalars[1],
+            curve_shape(obj.scalars.get(2)),
+            material,
+        )),
+        // PLY or STL file at `path`, or the triangles of `mesh`.
+        "Mesh" => match (&obj.path, &obj.mesh) {
+            (Some(path), _) => Box::new(Bvh::new(MeshData::load(path).triangles(&material))),
+            (None, Some(mesh)) => Box::new(Bvh::new(ron_mesh_triangles(mesh, &material))),
+            (None, None) => panic!("Mesh object requires a path or a mesh"),
+        },
+        "Plane" => Box::new(Plane::new(vector(0), vector(1), material)),
+        "Disc" => Box::new(Disc::new(vector(0), vector(1), obj.scalars[0], material)),
+        "Box" => Box::new(Cube::new(vector(0), vector(1), material)),
+        "OrientedBox" => Box::new(OrientedBox::new(vector(0), vector(1), vector(2), material)),
+        "Rectangle" => {
+            let cull_back = obj.scalars.first().is_some_and(|cull| *cull != 0.0);
+            Box::new(Rectangle::new(
+                vector(0),
+                vector(1),
+                vector(2),
+                vector(3),
+                material,
+                cull_back,
+            ))
+        }
+        "Cylinder" => Box::new(Cylinder::new(
+            vector(0),
+            vector(1),
+            obj.scalars[0],
+            obj.scalars[1],
+            material,
+        )),
+        "Cone" => Box::new(Cone::new(
+            vector(0),
+            vector(1),
+            obj.scalars[0],
+            obj.scalars[1],
+            material,
+        )),
+        "Heightfield" => Box::new(parse_ron_heightfield(obj, material)),
+        "Sdf" => Box::new(Sdf::new(
+            parse_ron_sdf(obj.sdf.as_ref().expect("Sdf object requires an sdf")),
+            material,
+        )),
+        // scalars [sea_level, seed, wave_count, amplitude, wavelength, spread, time], with the
+        // wind direction in vectors[0].
+        "Water" => Box::new(Water::new(
+            obj.scalars[0],
+            vector(0),
+            obj.scalars[1] as u32,
+            obj.scalars[2] as usize,
+            obj.scalars[3],
+            obj.scalars[4],
+            obj.scalars[5],
+            obj.scalars.get(6).copied().unwrap_or(0.0),
+            material,
+        )),
+        "Torus" => Box::new(Torus::new(
+            vector(0),
+            vector(1),
+            obj.scalars[0],
+            obj.scalars[1],
+            material,
+        )),
+        &_ => panic!("unknown ron object type {}.", obj.objtype),
+    }
+}
+
+fn curve_shape(cylindrical: Option<&f64>) -> CurveShape {
+    match cylindrical {
+        Some(flag) if *flag != 0.0 => CurveShape::Cylinder,
+        _ => CurveShape::Ribbon,
+    }
+}
+
+// Distance along the view direction to the surface under the image centre.
+fn autofocus(settings: &RaytracerScene, world: &Hittables) -> f64 {
+    let look_from = conv_py_vec(settings.camera_pos.clone());
+    let direction = (conv_py_vec(settings.camera_dir.clone()) - look_from).unit_vector();
+    let mut rec = hittable::HitRecord::new();
+    let ray = ray::Ray::new(look_from, direction, settings.shutter_open);
+    if world.hit(ray, 0.001, f64::INFINITY, &mut rec) {
+        rec.get_t().unwrap()
+    } else {
+        settings.focal_distance
+    }
+}
 
+fn parse_ron_aperture(bokeh: &Option<RonBokeh>, assets: &SceneAssets) -> Aperture {
+    match bokeh {
+        Some(RonBokeh {
+            texture: Some(name),
+            ..
+        }) => Aperture::Image(lookup_texture(name, &assets.textures)),
+        Some(bokeh) if bokeh.blades >= 3 => Aperture::Polygon(bokeh.blades, bokeh.rotation),
+        _ => Aperture::Circle,
+    }
+}
+
+fn build_camera(
+    settings: &RaytracerScene,
+    assets: &SceneAssets,
+    world: &Hittables,
+) -> Box<dyn Camera> {
+    let look_from = conv_py_vec(settings.camera_pos.clone());
+    let look_at = conv_py_vec(settings.camera_dir.clone());
+    let v_up = conv_py_vec(settings.camera_up.clone());
+    let shutter = Shutter {
+        open: settings.shutter_open,
+        close: settings.shutter_close,
+    };
+    if settings.camera_type == "Gltf" {
+        return build_camera(&imported_camera(settings, assets), assets, world);
+    }
+    let lens = settings
+        .physical_camera
+        .as_ref()
+        .map(|physical| PhysicalLens {
+            focal_length: physical.focal_length,
+            sensor_width: physical.sensor_width,
+            sensor_height: physical.sensor_height,
+            f_stop: physical.f_stop,
+            iso: physical.iso,
+            shutter_speed: physical.shutter_speed,
+        });
+    // Every projection takes the exposure of a physical camera. The others are pinholes with
+    // their own view size, so the lens only sets the field of view and defocus of a perspective.
+    let exposure = settings.exposure * lens.as_ref().map_or(1.0, |lens| lens.exposure());
+    match &settings.camera_type[..] {
+        "Perspective" => {
+            let (v_fov, aperture) = match &lens {
+                Some(lens) => (lens.v_fov(settings.aspect_ratio), lens.aperture()),
+                None => (settings.v_fov, settings.aperture),
+            };
+            let focus_dist = if settings.autofocus {
+                autofocus(settings, world)
+            } else {
+                settings.focal_distance
+            };
+            Box::new(
+                PerspectiveCamera::new(
+                    look_from,
+                    look_at,
+                    v_up,
+                    v_fov,
+                    settings.aspect_ratio,
+                    aperture,
+                    focus_dist,
+                )
+                .with_aperture(parse_ron_aperture(&settings.bokeh, assets))
+                .with_exposure(exposure)
+                .with_shutter(shutter),
+            )
+        }
+        "Orthographic" => {
+            let view_height = if settings.ortho_height > 0.0 {
+                settings.ortho_height
+            } else {
+                2.0 * settings.focal_distance * (settings.v_fov.to_radians() / 2.0).tan()
+            };
+            Box::new(
+                OrthographicCamera::new(
+                    look_from,
+                    look_at,
+                    v_up,
+                    view_height,
+                    settings.aspect_ratio,
+                )
+                .with_exposure(exposure)
+                .with_shutter(shutter),
+            )
+        }
+        "Fisheye" => Box::new(
+            FisheyeCamera::new(
+                look_from,
+                look_at,
+                v_up,
+                settings.v_fov,
+                settings.aspect_ratio,
+            )
+            .with_exposure(exposure)
+            .with_shutter(shutter),
+        ),
+        "Equirectangular" => Box::new(
+            EquirectangularCamera::new(look_from, look_at, v_up)
+                .with_exposure(exposure)
+                .with_shutter(shutter),
+        ),
+        &_ => panic!("Unknown camera type {}", settings.camera_type),
+    }
+}
+
+// The scene with its camera replaced by the first camera of its first imported glTF file,
+// placed by the importing object's transform and those of the nodes above it.
+fn imported_camera(settings: &RaytracerScene, assets: &SceneAssets) -> RaytracerScene {
+    let mut imports = vec![];
+    collect_gltf_objects(&settings.objects, Mat4::identity(), &mut imports);
+    let (matrix, camera) = imports
+        .into_iter()
+        .find_map(|(obj, matrix)| {
+            let cameras = &assets.imports[obj.path.as_ref().unwrap()].cameras;
+            cameras.first().map(|camera| (matrix, *camera))
+        })
+        .expect("Gltf camera type requires an imported glTF file with a camera");
+    let point = |p: Vec3| {
+        let p = matrix.transform_point(p);
+        vec![p.x(), p.y(), p.z()]
+    };
+    let up = matrix.transform_vector(camera.v_up);
+
+    let mut imported = settings.clone();
+    imported.camera_pos = point(camera.look_from);
+    imported.camera_dir = point(camera.look_at);
+    imported.camera_up = vec![up.x(), up.y(), up.z()];
+    match camera.projection {
+        GltfProjection::Perspective(v_fov) => {
+            imported.camera_type = "Perspective".to_string();
+            imported.v_fov = v_fov;
+        }
+        GltfProjection::Orthographic(view_height) => {
+            imported.camera_type = "Orthographic".to_string();
+            imported.ortho_height = view_height;
+        }
+    }
+    imported
+}
+
+// Scene lights followed by the punctual lights of imported glTF files.
+fn build_lights(settings: &RaytracerScene, assets: &SceneAssets) -> Vec<Vec3> {
     let mut light_objects = vec![];
     for light in settings.lights.clone() {
-        light_objects.push(Box::new(conv_py_vec(light.clone())));
+        light_objects.push(conv_py_vec(light.clone()));
+    }
+    let mut imports = vec![];
+    collect_gltf_objects(&settings.objects, Mat4::identity(), &mut imports);
+    for (obj, matrix) in imports {
+        let lights = &assets.imports[obj.path.as_ref().unwrap()].lights;
+        light_objects.extend(lights.iter().map(|light| matrix.transform_point(*light)));
+    }
+    light_objects
+}
+
+// Spans vectors[1] from the corner at vectors[0], with the y size as the height scale. Heights come
+// from the rows of `heights`, a 16-bit PNG or raw DEM at `path` (scalars[0] sets the raw width),
+// or from noise with scalars [terrain_resolution, seed, octaves, frequency, lacunarity].
+fn parse_ron_heightfield(obj: &RonObject, material: Material) -> Heightfield {
+    let (width, depth, heights) = ron_heightfield_grid(obj);
+    Heightfield::new(
+        conv_py_vec(obj.vectors[0].clone()),
+        conv_py_vec(obj.vectors[1].clone()),
+        width,
+        depth,
+        heights,
+        material,
+    )
+}
+
+// Corners of an inline mesh, see `RonMesh`.
+fn ron_mesh_triangles(mesh: &RonMesh, material: &Material) -> Vec<Box<dyn Hittable + Send + Sync>> {
+    // Vertex colors are blended by the default uvs of a triangle, custom uvs would scramble them.
+    if !mesh.colors.is_empty() && !mesh.uvs.is_empty() {
+        panic!("Mesh cannot have both colors and uvs");
     }
+    let corners =
+        |values: &[Vec<f64>], i: usize| [0, 1, 2].map(|k| conv_py_vec(values[3 * i + k].clone()));
+    (0..mesh.positions.len() / 3)
+        .map(|i| {
+            let material = if mesh.colors.is_empty() {
+                material.clone()
+            } else {
+                material.with_albedo(Arc::new(VertexColors::new(corners(&mesh.colors, i))))
+            };
+            let [a, b, c] = corners(&mesh.positions, i);
+            let mut triangle = Triangle::new(a, b, c, material, mesh.cull_back);
+            if !mesh.uvs.is_empty() {
+                let uv = |k: usize| (mesh.uvs[3 * i + k][0], mesh.uvs[3 * i + k][1]);
+                triangle = triangle.with_uvs([uv(0), uv(1), uv(2)]);
+            }
+            if !mesh.normals.is_empty() {
+                triangle = triangle.with_normals(corners(&mesh.normals, i));
+            }
+            Box::new(triangle) as Box<dyn Hittable + Send + Sync>
+        })
+        .collect()
+}
+
+fn ron_heightfield_grid(obj: &RonObject) -> (usize, usize, Vec<f32>) {
+    if !obj.heights.is_empty() {
+        let heights = obj.heights.iter().flatten().map(|h| *h as f32).collect();
+        return (obj.heights[0].len(), obj.heights.len(), heights);
+    }
+    match &obj.path {
+        Some(path) if path.to_lowercase().ends_with(".raw") => {
+            load_raw_heights(path, obj.scalars.first().map(|w| *w as usize))
+        }
+        Some(path) => load_image_heights(path),
+        None => {
+            let resolution = obj.scalars[0] as usize;
+            let heights = noise_heights(
+                conv_py_vec(obj.vectors[0].clone()),
+                conv_py_vec(obj.vectors[1].clone()),
+                resolution,
+                obj.scalars[1] as u32,
+                obj.scalars[2] as usize,
+                obj.scalars[3],
+                obj.scalars[4],
+            );
+            (resolution, resolution, heights)
+        }
+    }
+}
+
+fn parse_ron_sdf(sdf: &RonSdf) -> SdfNode {
+    let vector = |i: usize| conv_py_vec(sdf.vectors[i].clone());
+    let child = || Box::new(parse_ron_sdf(&sdf.children[0]));
+    // Combinators fold their children left to right, an optional scalar sets the smoothing.
+    let combine = |op: fn(Box<SdfNode>, Box<SdfNode>, f64) -> SdfNode, smooth: f64| {
+        let mut children = sdf.children.iter().map(parse_ron_sdf);
+        let first = children.next().expect("SDF combinator requires children");
+        children.fold(first, |combined, next| {
+            op(Box::new(combined), Box::new(next), smooth)
+        })
+    };
+    let smooth = sdf.scalars.first().copied().unwrap_or(0.0);
+    match &sdf.shape[..] {
+        "Sphere" => SdfNode::Sphere(sdf.scalars[0]),
+        "Box" => SdfNode::Box(vector(0)),
+        "RoundBox" => SdfNode::RoundBox(vector(0), sdf.scalars[0]),
+        "Torus" => SdfNode::Torus(sdf.scalars[0], sdf.scalars[1]),
+        "Cylinder" => SdfNode::Cylinder(sdf.scalars[0], sdf.scalars[1]),
+        "Capsule" => SdfNode::Capsule(vector(0), vector(1), sdf.scalars[0]),
+        "Mandelbulb" => SdfNode::Mandelbulb(sdf.scalars[0], sdf.scalars[1] as usize),
+        "Union" | "SmoothUnion" => combine(SdfNode::Union, smooth),
+        "Intersection" | "SmoothIntersection" => combine(SdfNode::Intersection, smooth),
+        "Subtraction" | "SmoothSubtraction" => combine(SdfNode::Subtraction, smooth),
+        "Translate" => SdfNode::Translate(child(), vector(0)),
+        "Rotate" => SdfNode::rotate(*child(), vector(0)),
+        "Scale" => {
+            // A factor of zero or below would divide by zero or turn the bounds inside out.
+            let factor = sdf.scalars[0];
+            if factor <= 0.0 {
+                panic!("SDF Scale factor must be positive, got {}", factor);
+            }
+            SdfNode::Scale(child(), factor)
+        }
+        "Displace" => SdfNode::Displace(
+            child(),
+            Perlin::new(sdf.scalars[0] as u32),
+            sdf.scalars[1],
+            sdf.scalars[2],
+            sdf.scalars[3] as usize,
+        ),
+        &_ => panic!("Unknown SDF shape {}", sdf.shape),
+    }
+}
+
+/// Renders a RON scene, see `Scene::from_ron`, as one RGB pixel per entry in row-major order.
+pub fn create_image(ron_string: String) -> Vec<Vec<u8>> {
+    create_image_with(ron_string, &RenderControl::default())
+}
+
+/// `create_image` reporting its progress to, and stopping early when cancelled through, `control`.
+pub fn create_image_with(ron_string: String, control: &RenderControl) -> Vec<Vec<u8>> {
+    Scene::from_ron(ron_string).render_with(control)
+}
+
+/// `create_image_with` also returning what the render did, see `RenderStats::to_json`.
+pub fn create_image_with_stats(
+    ron_string: String,
+    control: &RenderControl,
+) -> (Vec<Vec<u8>>, RenderStats) {
+    Scene::from_ron(ron_string).render_with_stats(control)
+}
+
+// Camera and world of a RON scene.
+fn build_scene(settings: &RaytracerScene) -> (Box<dyn Camera>, Hittables) {
+    let assets = SceneAssets::new(settings);
 
     let mut world_objects: Vec<Box<dyn Hittable + Send + Sync + 'static>> = vec![];
     for obj in settings.objects.clone() {
-        world_objects.push(parse_ron_object(obj.clone()));
+        world_objects.push(parse_ron_object(obj.clone(), &assets));
     }
 
     let world = Hittables {
-        lights: light_objects,
-        hittables: world_objects,
+        lights: build_lights(settings, &assets),
+        hittables: vec![Box::new(Bvh::new(world_objects))],
     };
+    let camera = build_camera(settings, &assets, &world);
+    (camera, world)
+}
 
-    let now = Instant::now();
-    let image = if settings.multithreading {
-        let image_ = Arc::new(Mutex::new({
-            let mut x =
-                Vec::with_capacity(settings.image_width as usize * settings.image_height as usize);
-            x.resize(
-                settings.image_width as usize * settings.image_height as usize,
-                vec![0 as u8, 0 as u8, 0 as u8],
-            );
-            x
-        }));
+fn parse_ron_animation(animation: &Option<RonAnimation>) -> Animation {
+    let animation = match animation {
+        Some(animation) => animation,
+        None => return Animation::still(),
+    };
+    let tracks = animation
+        .tracks
+        .iter()
+        .map(|track| {
+            Track::new(
+                Target::from_name(&track.target),
+                Interpolation::from_name(&track.interpolation),
+                track
+                    .keys
+                    .iter()
+                    .map(|key| Keyframe {
+                        frame: key.frame,
+                        value: key.value.clone(),
+                        handle_in: key.handle_in.clone(),
+                        handle_out: key.handle_out.clone(),
+                    })
+                    .collect(),
+            )
+        })
+        .collect();
+    Animation {
+        frame_start: animation.frame_start,
+        frame_end: animation.frame_end,
+        turntable: animation.turntable,
+        tracks,
+    }
+}
+
+/// Renders every frame of the scene's animation. Objects no track touches are built into one
+/// BVH that is shared by all frames, only the animated ones are rebuilt per frame.
+fn render_frames(settings: RaytracerScene, mut on_frame: impl FnMut(i32, Vec<u8>)) {
+    let animation = parse_ron_animation(&settings.animation);
+    let animated = animation.animated_objects();
+    // Instances and scatters of shared geometry pick up its changes only when they are rebuilt
+    // along with it, so they count as animated too.
+    let geometries = animated_geometries(&settings.geometries, &animated);
+    let geometry_animated = !geometries.is_empty();
+    let is_animated =
+        |obj: &RonObject| contains_named(obj, &animated) || references_geometry(obj, &geometries);
+
+    let mut assets = SceneAssets::new(&settings);
+    let static_objects: Arc<Bvh> = Arc::new(Bvh::new(
+        settings
+            .objects
+            .iter()
+            .filter(|obj| !is_animated(obj))
+            .map(|obj| parse_ron_object(obj.clone(), &assets))
+            .collect(),
+    ));
+
+    for frame in animation.frame_start..=animation.frame_end {
+        let frame_settings = animation.scene_at(&settings, frame);
+        if geometry_animated {
+            assets.build_geometries(&frame_settings.geometries);
+        }
+        let animated_objects = frame_settings
+            .objects
+            .iter()
+            .filter(|obj| is_animated(obj))
+            .map(|obj| parse_ron_object(obj.clone(), &assets))
+            .collect();
+
+        let world = Hittables {
+            lights: build_lights(&frame_settings, &assets),
+            hittables: vec![
+                Box::new(static_objects.clone()),
+                Box::new(Bvh::new(animated_objects)),
+            ],
+        };
+        let camera = build_camera(&frame_settings, &assets, &world);
+        eprintln!("Rendering frame {}.", frame);
+        on_frame(
+            frame,
+            render(
+                RenderSettings::from(&frame_settings),
+                camera,
+                world,
+                &RenderControl::default(),
+            )
+            .0,
+        );
+    }
+}
+
+/// Reads a scene file with its includes, variables and material library resolved, as RON that
+/// the other functions accept.
+pub fn load_scene(path: &str) -> String {
+    configuration::RaytracerScene::from_file(path).to_ron()
+}
+
+/// Builds the scene and writes it back out as RON, see `Scene::to_ron`.
+pub fn export_ron(ron_string: String, output_path: &str) {
+    std::fs::write(output_path, Scene::from_ron(ron_string).to_ron())
+        .unwrap_or_else(|e| panic!("failed to write scene {}: {}", output_path, e));
+}
+
+/// Builds the scene and writes it as a .gltf file, see `Scene::to_gltf`.
+pub fn export_gltf(ron_string: String, output_path: &str) {
+    std::fs::write(output_path, Scene::from_ron(ron_string).to_gltf())
+        .unwrap_or_else(|e| panic!("failed to write scene {}: {}", output_path, e));
+}
+
+/// Every frame of the scene's animation, in order.
+pub fn create_sequence(ron_string: String) -> Vec<Vec<Vec<u8>>> {
+    let settings = configuration::RaytracerScene::from_ron(ron_string);
+    let mut frames = vec![];
+    render_frames(settings, |_, image| frames.push(rgb_pixels(image)));
+    frames
+}
+
+// Path of a numbered frame, a run of `#` in the pattern is replaced by the zero padded frame
+// number, otherwise it is appended to the file stem, e.g. `out_0001.png`.
+fn frame_path(pattern: &str, frame: i32) -> String {
+    match pattern.find('#') {
+        Some(start) => {
+            let width = pattern[start..].chars().take_while(|c| *c == '#').count();
+            format!(
+                "{}{:0width$}{}",
+                &pattern[..start],
+                frame,
+                &pattern[start + width..],
+                width = width
+            )
+        }
+        None => match pattern.rfind('.') {
+            Some(dot) => format!("{}_{:04}{}", &pattern[..dot], frame, &pattern[dot..]),
+            None => format!("{}_{:04}", pattern, frame),
+        },
+    }
+}
+
+/// Renders the animation to numbered image files and returns their paths.
+pub fn render_sequence(ron_string: String, output_pattern: &str) -> Vec<String> {
+    let settings = configuration::RaytracerScene::from_ron(ron_string);
+    let (width, height) = (settings.image_width as u32, settings.image_height as u32);
+    let mut paths = vec![];
+    render_frames(settings, |frame, image| {
+        let path = frame_path(output_pattern, frame);
+        image::save_buffer(&path, &image, width, height, image::ColorType::Rgb8)
+            .unwrap_or_else(|e| panic!("failed to write frame {}: {}", path, e));
+        paths.push(path);
+    });
+    paths
+}
+
+// wasm32 renders on the calling thread, it has no threads to spawn.
+#[cfg(not(target_arch = "wasm32"))]
+fn cpu_count() -> usize {
+    num_cpus::get()
+}
+
+#[cfg(target_arch = "wasm32")]
+fn cpu_count() -> usize {
+    1
+}
+
+// Splits a contiguous RGB image into one entry per pixel, the layout of `create_image`.
+fn rgb_pixels(rgb: Vec<u8>) -> Vec<Vec<u8>> {
+    rgb.chunks(3).map(<[u8]>::to_vec).collect()
+}
+
+// RGB rows top to bottom, 3 bytes per pixel. Pixels not reached before the render is cancelled
+// stay black.
+fn render(
+    settings: RenderSettings,
+    camera: Box<dyn Camera>,
+    world: Hittables,
+    control: &RenderControl,
+) -> (Vec<u8>, RenderStats) {
+    let now = Stopwatch::start();
+    let acceleration_bytes = world.acceleration_bytes();
+    let mut counters = Counters::default();
+    let mut thread_seconds = vec![];
+    let image = if settings.multithreading && cfg!(not(target_arch = "wasm32")) {
+        let image_ = Arc::new(Mutex::new(vec![
+            0_u8;
+            settings.image_width as usize
+                * settings.image_height as usize
+                * 3
+        ]));
         let world_ = Arc::new(world);
-        let camera_ = Arc::new(camera);
+        let camera_: Arc<dyn Camera> = Arc::from(camera);
         let settings_ = Arc::new(settings);
 
-        let cpu_count = num_cpus::get();
+        let cpu_count = cpu_count();
+        let rows_done = Arc::new(AtomicUsize::new(0));
         let mut task_list = vec![];
         let work_list = Arc::new(create_work_list(
             settings_.image_width,
@@ -358,20 +1482,23 @@ pub fn create_image(ron_string: String) -> Vec<Vec<u8>> {
             let scoped_camera = camera_.clone();
             let scoped_work_list = work_list.clone();
             let scoped_settings = settings_.clone();
+            let scoped_rows_done = rows_done.clone();
+            let scoped_control = control.clone();
 
             task_list.push(thread::spawn(move || {
+                let busy = Stopwatch::start();
                 let work_list_for_cpu = scoped_work_list.get(cpu).unwrap();
                 let mut inner_work_vec = vec![];
 
                 for work in work_list_for_cpu {
+                    if scoped_control.is_cancelled() {
+                        break;
+                    }
                     let pixel_color = sample_pixel(
-                        scoped_settings.samples_per_pixel,
+                        &scoped_settings,
                         work.x as f64,
                         work.y as i32,
-                        scoped_settings.image_width,
-                        scoped_settings.image_height,
-                        scoped_settings.max_depth,
-                        &scoped_camera,
+                        scoped_camera.as_ref(),
                         &scoped_world,
                     );
 
@@ -380,38 +1507,58 @@ pub fn create_image(ron_string: String) -> Vec<Vec<u8>> {
                         y: work.y,
                         colour: Some(pixel_color.to_rgb(scoped_settings.samples_per_pixel)),
                     });
+                    if work.x + 1 == scoped_settings.image_width as usize {
+                        let rows = scoped_rows_done.fetch_add(1, Ordering::Relaxed) + 1;
+                        scoped_control.report(rows as f64 / scoped_settings.image_height as f64);
+                    }
                 }
 
                 let mut image_data = scoped_image.lock().unwrap();
                 for final_work in inner_work_vec {
-                    let colour = final_work.colour.unwrap().clone();
-                    image_data[(final_work.x as u32
+                    let colour = final_work.colour.unwrap();
+                    let at = (final_work.x as u32
                         + (final_work.y as u32 * scoped_settings.image_width as u32))
-                        as usize] = colour;
-                    //println!("{}:{}:{:#?}\n", final_work.x, final_work.y, final_work.colour.unwrap())
+                        as usize
+                        * 3;
+                    image_data[at..at + 3].copy_from_slice(&colour[..3]);
                 }
-
-                println!("Cpu {} done out of {}.", cpu + 1, cpu_count);
+                (stats::take_counters(), busy.seconds())
             }));
         }
 
+        // A panic on a rendering thread is raised again here once every thread has stopped, so
+        // callers such as the C interface see it instead of a partly black image.
+        let mut panic = None;
         for task in task_list {
-            let _ = task.join();
+            match task.join() {
+                Ok((thread_counters, seconds)) => {
+                    counters.add(thread_counters);
+                    thread_seconds.push(seconds);
+                }
+                Err(payload) => panic = panic.or(Some(payload)),
+            }
+        }
+        if let Some(payload) = panic {
+            std::panic::resume_unwind(payload);
         }
 
         let final_val = match image_.lock() {
             Ok(x) => x.clone(),
-            Err(_) => {
-                Vec::with_capacity(settings_.image_width as usize * settings_.image_height as usize)
-            }
+            Err(_) => Vec::with_capacity(
+                settings_.image_width as usize * settings_.image_height as usize * 3,
+            ),
         };
         final_val
     } else {
         // Single Thread
+        stats::take_counters();
         let mut image_ =
-            Vec::with_capacity(settings.image_width as usize * settings.image_height as usize);
+            vec![0_u8; settings.image_width as usize * settings.image_height as usize * 3];
         let progress_prints = settings.image_width as f64 / 16.0;
         for j in 0..settings.image_height {
+            if control.is_cancelled() {
+                break;
+            }
             // progress check
             if j % ((settings.image_height as f64 / progress_prints) as i32) == 0 {
                 eprintln!(
@@ -421,32 +1568,175 @@ pub fn create_image(ron_string: String) -> Vec<Vec<u8>> {
             }
 
             for i in 0..settings.image_width {
-                let pixel_color = sample_pixel(
-                    settings.samples_per_pixel,
-                    i as f64,
-                    j as i32,
-                    settings.image_width,
-                    settings.image_height,
-                    settings.max_depth,
-                    &camera,
-                    &world,
-                );
-                image_[i as usize * (j as usize * settings.image_width as usize)] =
-                    pixel_color.to_rgb(settings.samples_per_pixel);
+                let pixel_color = sample_pixel(&settings, i as f64, j, camera.as_ref(), &world);
+                let at = (i as usize + j as usize * settings.image_width as usize) * 3;
+                image_[at..at + 3]
+                    .copy_from_slice(&pixel_color.to_rgb(settings.samples_per_pixel)[..3]);
             }
+            control.report((j + 1) as f64 / settings.image_height as f64);
         }
+        counters = stats::take_counters();
+        thread_seconds.push(now.seconds());
         image_
     };
 
-    let mut seconds = now.elapsed().as_secs();
-    let mut minutes = seconds / 60;
-    seconds = seconds % 60;
-    let hours = minutes / 60;
-    minutes = minutes % 60;
-    eprintln!(
-        "100.00% Done\n\nTime taken: {}h : {}m : {}s\n\n",
-        hours, minutes, seconds
-    );
+    let stats = RenderStats::new(counters, now.seconds(), thread_seconds, acceleration_bytes);
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let mut seconds = stats.seconds as u64;
+        let mut minutes = seconds / 60;
+        seconds %= 60;
+        let hours = minutes / 60;
+        minutes %= 60;
+        eprintln!(
+            "100.00% Done\n\nTime taken: {}h : {}m : {}s\n\n",
+            hours, minutes, seconds
+        );
+    }
+
+    (image, stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_paths_are_numbered_from_the_pattern() {
+        assert_eq!(frame_path("out_###.png", 7), "out_007.png");
+        assert_eq!(frame_path("shot#/f.png", 12), "shot12/f.png");
+        assert_eq!(frame_path("out.png", 3), "out_0003.png");
+        assert_eq!(frame_path("frames/out", 42), "frames/out_0042");
+    }
 
-    image
+    #[test]
+    fn autofocus_picks_the_distance_to_the_hit_under_the_centre_pixel() {
+        let scene = |objects: &str| {
+            RaytracerScene::from_ron(format!(
+                r#"(multithreading: false, aspect_ratio: 1.0, image_width: 9, image_height: 9,
+                    samples_per_pixel: 1, max_depth: 1, v_fov: 90.0, aperture: 2.0,
+                    focal_distance: 10.0, autofocus: true, camera_pos: [0.0, 0.0, 5.0],
+                    camera_dir: [0.0, 0.0, 0.0], camera_up: [0.0, 1.0, 0.0], lights: [],
+                    objects: [{}])"#,
+                objects
+            ))
+        };
+        let build = |settings: &RaytracerScene, assets: &SceneAssets| Hittables {
+            lights: vec![],
+            hittables: settings
+                .objects
+                .iter()
+                .map(|obj| parse_ron_object(obj.clone(), assets))
+                .collect(),
+        };
+
+        let settings = scene(
+            r#"(objtype: "Sphere", vectors: [[0.0, 0.0, 0.0]], scalars: [1.0],
+                material: ["Mirror"])"#,
+        );
+        let assets = SceneAssets::new(&settings);
+        let world = build(&settings, &assets);
+        assert!((autofocus(&settings, &world) - 4.0).abs() < 1e-9);
+        // Rays through the centre from anywhere on the lens meet on the sphere.
+        let camera = build_camera(&settings, &assets, &world);
+        for _ in 0..100 {
+            let ray = camera.get_ray(0.5, 0.5);
+            assert!((ray.at(1.0) - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-9);
+        }
+
+        // Nothing under the centre keeps the focal distance.
+        let settings = scene("");
+        let assets = SceneAssets::new(&settings);
+        assert_eq!(autofocus(&settings, &build(&settings, &assets)), 10.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "SDF Scale factor must be positive, got -2")]
+    fn sdf_scales_must_be_positive() {
+        parse_ron_sdf(&RonSdf {
+            shape: "Scale".to_string(),
+            vectors: vec![],
+            scalars: vec![-2.0],
+            children: vec![RonSdf {
+                shape: "Sphere".to_string(),
+                vectors: vec![],
+                scalars: vec![1.0],
+                children: vec![],
+            }],
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Mesh cannot have both colors and uvs")]
+    fn inline_meshes_cannot_mix_colors_and_uvs() {
+        let corners = vec![
+            vec![0.0, 0.0, 0.0],
+            vec![1.0, 0.0, 0.0],
+            vec![0.0, 1.0, 0.0],
+        ];
+        let mesh = RonMesh {
+            positions: corners.clone(),
+            normals: vec![],
+            uvs: vec![vec![0.0, 0.0], vec![1.0, 0.0], vec![0.0, 1.0]],
+            colors: corners,
+            cull_back: false,
+        };
+        ron_mesh_triangles(&mesh, &Material::Mirror);
+    }
+
+    #[test]
+    #[should_panic(expected = "Scatter of rock over ground has its scale range backwards: (2, 1)")]
+    fn scatter_scale_ranges_must_be_ordered() {
+        create_image(
+            r#"(multithreading: false, aspect_ratio: 1.0, image_width: 4, image_height: 4,
+                samples_per_pixel: 1, max_depth: 1, v_fov: 90.0, aperture: 0.0,
+                focal_distance: 1.0, camera_pos: [0.0, 0.0, 5.0], camera_dir: [0.0, 0.0, 0.0],
+                camera_up: [0.0, 1.0, 0.0], lights: [],
+                geometries: [
+                    (name: "rock", objects: [(objtype: "Sphere", vectors: [[0.0, 0.0, 0.0]],
+                        scalars: [0.1], material: ["Mirror"])]),
+                    (name: "ground", objects: [(objtype: "Rectangle",
+                        vectors: [[0.0, 0.0, 0.0], [0.0, 0.0, 1.0], [1.0, 0.0, 1.0], [1.0, 0.0, 0.0]],
+                        material: ["Mirror"])]),
+                ],
+                objects: [(objtype: "Scatter", geometry: Some("rock"),
+                    scatter: Some((surface: "ground", density: 10.0, scale: (2.0, 1.0))))])"#
+                .to_string(),
+        );
+    }
+
+    #[test]
+    fn animated_objects_are_rebuilt_next_to_the_shared_static_ones() {
+        // A single bounce renders a silhouette, black where a ray hits and sky elsewhere. The
+        // static sphere stays in the centre while the animated one moves from behind the camera
+        // to enclose it.
+        let settings = RaytracerScene::from_ron(
+            r#"(multithreading: false, aspect_ratio: 1.0, image_width: 9, image_height: 9,
+                samples_per_pixel: 1, max_depth: 1, v_fov: 90.0, aperture: 0.0,
+                focal_distance: 1.0, camera_pos: [0.0, 0.0, 5.0], camera_dir: [0.0, 0.0, 0.0],
+                camera_up: [0.0, 1.0, 0.0], lights: [],
+                objects: [
+                    (objtype: "Sphere", vectors: [[0.0, 0.0, 0.0]], scalars: [2.0],
+                        material: ["Mirror"]),
+                    (objtype: "Node", name: Some("mover"),
+                        transform: Some((translate: [0.0, 0.0, 100.0])),
+                        children: [(objtype: "Sphere", vectors: [[0.0, 0.0, 0.0]],
+                            scalars: [10.0], material: ["Mirror"])]),
+                ],
+                animation: Some((frame_end: 1, tracks: [(target: "mover.translate", keys: [
+                    (frame: 0.0, value: [0.0, 0.0, 100.0]),
+                    (frame: 1.0, value: [0.0, 0.0, 0.0]),
+                ])])))"#
+                .to_string(),
+        );
+        let mut frames = vec![];
+        render_frames(settings, |frame, image| frames.push((frame, rgb_pixels(image))));
+
+        let black = |image: &Vec<Vec<u8>>, pixel: usize| image[pixel][..3] == [0, 0, 0];
+        let (centre, corner) = (4 * 9 + 4, 0);
+        assert_eq!(frames.len(), 2);
+        assert_eq!((frames[0].0, frames[1].0), (0, 1));
+        assert!(black(&frames[0].1, centre) && !black(&frames[0].1, corner));
+        assert!(black(&frames[1].1, centre) && black(&frames[1].1, corner));
+    }
 }
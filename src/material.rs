@@ -1,25 +1,149 @@
 use crate::ray::Ray;
+use crate::texture::TextureRef;
 use crate::vec3::Vec3;
-use crate::{hittable::HitRecord, random_unit_vec3, random_f64};
+use crate::{hittable::HitRecord, random, random_unit_vec3, random_f64};
 
-#[derive(Debug, Copy, Clone)]
-#[allow(dead_code)]
+/// How a surface scatters the rays hitting it.
+///
+/// Color and roughness slots are textures; constant values use `SolidColor`.
+#[derive(Debug, Clone)]
 pub enum Material {
-    Lambertian(Vec3),
-    Metal(Vec3, f64),
+    /// Diffuse surface with the given color.
+    Lambertian(TextureRef),
+    /// Reflective surface with a color and a fuzz, the roughness of the reflection.
+    Metal(TextureRef, TextureRef),
+    /// Clear glass with the given refractive index.
     Dielectric(f64),
+    /// Perfect white reflector.
     Mirror,
+    /// Fiber color and roughness, scattering around the hit record's tangent like a strand of hair.
+    Hair(TextureRef, TextureRef),
+    /// Tangent-space normal map and strength applied before scattering off the inner material.
+    NormalMapped(Box<Material>, TextureRef, f64),
+    /// Height texture and scale, the normal follows the finite-difference gradient.
+    Bumped(Box<Material>, TextureRef, f64),
+    /// Opacity texture, alpha threshold and whether alpha is used as a hit probability instead.
+    Masked(Box<Material>, TextureRef, f64, bool),
+    /// Per-channel absorption coefficient of the medium behind the surface, e.g. tinted water.
+    Absorbing(Box<Material>, Vec3),
 }
 
-pub fn scatter(ray: Ray, rec: HitRecord, color: &mut Vec3, material: Material) -> Option<Ray> {
+impl Material {
+    /// Alpha test for cutout materials, intersections that fail it are skipped entirely.
+    pub fn is_opaque_at(&self, rec: &HitRecord) -> bool {
+        match self {
+            Material::Masked(inner, opacity, threshold, stochastic) => {
+                let (u, v) = rec.uv.unwrap_or((0.0, 0.0));
+                let alpha = opacity.alpha(u, v, rec.p.unwrap());
+                let opaque = if *stochastic {
+                    random() < alpha
+                } else {
+                    alpha >= *threshold
+                };
+                opaque && inner.is_opaque_at(rec)
+            }
+            Material::NormalMapped(inner, _, _)
+            | Material::Bumped(inner, _, _)
+            | Material::Absorbing(inner, _) => inner.is_opaque_at(rec),
+            _ => true,
+        }
+    }
+
+    /// Same material with its color slot replaced, e.g. by the vertex colors of a mesh. Materials
+    /// without a color keep theirs.
+    pub fn with_albedo(&self, albedo: TextureRef) -> Material {
+        match self {
+            Material::Lambertian(_) => Material::Lambertian(albedo),
+            Material::Metal(_, fuzz) => Material::Metal(albedo, fuzz.clone()),
+            Material::Hair(_, roughness) => Material::Hair(albedo, roughness.clone()),
+            Material::NormalMapped(inner, map, strength) => {
+                Material::NormalMapped(Box::new(inner.with_albedo(albedo)), map.clone(), *strength)
+            }
+            Material::Bumped(inner, height, scale) => {
+                Material::Bumped(Box::new(inner.with_albedo(albedo)), height.clone(), *scale)
+            }
+            Material::Masked(inner, opacity, threshold, stochastic) => Material::Masked(
+                Box::new(inner.with_albedo(albedo)),
+                opacity.clone(),
+                *threshold,
+                *stochastic,
+            ),
+            Material::Absorbing(inner, absorption) => {
+                Material::Absorbing(Box::new(inner.with_albedo(albedo)), *absorption)
+            }
+            _ => self.clone(),
+        }
+    }
+
+    /// Absorption of the medium rays enter when they are transmitted through the surface.
+    pub fn absorption(&self) -> Option<Vec3> {
+        match self {
+            Material::Absorbing(_, absorption) => Some(*absorption),
+            Material::NormalMapped(inner, _, _)
+            | Material::Bumped(inner, _, _)
+            | Material::Masked(inner, _, _, _) => inner.absorption(),
+            _ => None,
+        }
+    }
+}
+
+/// Bounces `ray` off the hit and sets `color` to the attenuation of the bounce. `None` means the
+/// ray was absorbed.
+pub fn scatter(ray: Ray, rec: &HitRecord, color: &mut Vec3, material: &Material) -> Option<Ray> {
     match material {
-        Material::Lambertian(col) => lambertian_scatter(ray, rec, color, col),
-        Material::Metal(col, fuzz) => metal_scatter(ray, rec, color, col, fuzz),
-        Material::Dielectric(refractive_index) => dielectric_scatter(ray, rec, color, refractive_index),
+        Material::Lambertian(albedo) => lambertian_scatter(ray, rec, color, sample(albedo, rec)),
+        Material::Metal(albedo, fuzz) => {
+            metal_scatter(ray, rec, color, sample(albedo, rec), sample(fuzz, rec).x())
+        }
+        Material::Dielectric(refractive_index) => dielectric_scatter(ray, rec, color, *refractive_index),
         Material::Mirror => mirror_scatter(ray, rec, color),
+        Material::Hair(albedo, roughness) => {
+            hair_scatter(ray, rec, color, sample(albedo, rec), sample(roughness, rec).x())
+        }
+        Material::NormalMapped(inner, normal_map, strength) => {
+            let texel = sample(normal_map, rec);
+            let local_normal = Vec3::new(
+                (texel.x() * 2.0 - 1.0) * strength,
+                (texel.y() * 2.0 - 1.0) * strength,
+                texel.z() * 2.0 - 1.0,
+            );
+            let mut shaded = rec.clone();
+            shaded.set_shading_normal(ray, local_normal);
+            scatter(ray, &shaded, color, inner)
+        }
+        Material::Bumped(inner, height, scale) => {
+            let mut shaded = rec.clone();
+            shaded.set_shading_normal(ray, bump_normal(height, rec, *scale));
+            scatter(ray, &shaded, color, inner)
+        }
+        Material::Masked(inner, _, _, _) | Material::Absorbing(inner, _) => {
+            scatter(ray, rec, color, inner)
+        }
     }
 }
 
+// Finite differences along the tangent frame, stepping both the uv and the world position so
+// image and procedural height textures both work.
+fn bump_normal(height: &TextureRef, rec: &HitRecord, scale: f64) -> Vec3 {
+    let eps = 1e-3;
+    let (u, v) = rec.uv.unwrap_or((0.0, 0.0));
+    let p = rec.p.unwrap();
+    let (tangent, bitangent) = match (rec.tangent, rec.bitangent) {
+        (Some(tangent), Some(bitangent)) => (tangent, bitangent),
+        _ => return Vec3::new(0.0, 0.0, 1.0),
+    };
+
+    let h = height.value(u, v, p).x();
+    let dh_du = (height.value(u + eps, v, p + tangent * eps).x() - h) / eps;
+    let dh_dv = (height.value(u, v + eps, p + bitangent * eps).x() - h) / eps;
+    Vec3::new(-scale * dh_du, -scale * dh_dv, 1.0)
+}
+
+fn sample(texture: &TextureRef, rec: &HitRecord) -> Vec3 {
+    let (u, v) = rec.uv.unwrap_or((0.0, 0.0));
+    texture.value(u, v, rec.p.unwrap())
+}
+
 fn reflect(v: Vec3, n: Vec3) -> Vec3 {
     v - n * (2.0 * v.dot(n))
 }
@@ -44,25 +168,29 @@ fn schlick(cosine: f64, ref_idx: f64) -> f64 {
 }
 
 fn lambertian_scatter(
-    _ray: Ray,
-    rec: HitRecord,
+    ray: Ray,
+    rec: &HitRecord,
     color: &mut Vec3,
     material_color: Vec3,
 ) -> Option<Ray> {
     let scatter_direction = rec.normal.unwrap() + random_unit_vec3();
     color.clone_from(&material_color);
-    Some(Ray::new(rec.p.unwrap(), scatter_direction))
+    Some(Ray::new(rec.p.unwrap(), scatter_direction, ray.time()))
 }
 
 fn metal_scatter(
     ray: Ray,
-    rec: HitRecord,
+    rec: &HitRecord,
     color: &mut Vec3,
     material_color: Vec3,
     fuzz: f64,
 ) -> Option<Ray> {
     let reflected = reflect(ray.direction().unit_vector(), rec.normal.unwrap());
-    let scattered = Ray::new(rec.p.unwrap(), reflected + random_unit_vec3() * fuzz);
+    let scattered = Ray::new(
+        rec.p.unwrap(),
+        reflected + random_unit_vec3() * fuzz,
+        ray.time(),
+    );
     color.clone_from(&material_color);
     if scattered.direction().dot(rec.normal.unwrap()) > 0.0 {
         Some(scattered)
@@ -73,10 +201,14 @@ fn metal_scatter(
 
 fn mirror_scatter(
     ray: Ray,
-    rec: HitRecord,
+    rec: &HitRecord,
     color: &mut Vec3,
 ) -> Option<Ray> {
-    let reflected = Ray::new(rec.p.unwrap(), reflect(ray.direction().unit_vector(), rec.normal.unwrap()));
+    let reflected = Ray::new(
+        rec.p.unwrap(),
+        reflect(ray.direction().unit_vector(), rec.normal.unwrap()),
+        ray.time(),
+    );
     color.clone_from(&Vec3::new(1.0, 1.0, 1.0));
     if reflected.direction().dot(rec.normal.unwrap()) > 0.0 {
         Some(reflected)
@@ -85,9 +217,43 @@ fn mirror_scatter(
     }
 }
 
+// Picks one of the R, TT and TRT lobes of a fiber: a white reflection off the surface, light
+// passing straight through tinted once, and light reflected back inside tinted twice. Mirroring
+// the normal component keeps the angle to the fiber, so highlights spread along the strand's
+// tangent, and roughness widens each lobe.
+fn hair_scatter(
+    ray: Ray,
+    rec: &HitRecord,
+    color: &mut Vec3,
+    material_color: Vec3,
+    roughness: f64,
+) -> Option<Ray> {
+    let direction = ray.direction().unit_vector();
+    let normal = rec.normal.unwrap();
+    let lobe = random();
+    let (scattered, tint) = if lobe < 0.2 {
+        (reflect(direction, normal), Vec3::new(1.0, 1.0, 1.0))
+    } else if lobe < 0.5 {
+        (direction, material_color)
+    } else {
+        (reflect(direction, normal), material_color * material_color)
+    };
+    color.clone_from(&tint);
+
+    // Roughness should not tip a lobe to the other side of the fiber.
+    let jittered = scattered + random_unit_vec3() * roughness;
+    let side = jittered.dot(normal);
+    let flipped = if side * scattered.dot(normal) < 0.0 {
+        jittered - normal * (2.0 * side)
+    } else {
+        jittered
+    };
+    Some(Ray::new(rec.p.unwrap(), flipped, ray.time()))
+}
+
 fn dielectric_scatter(
     ray: Ray,
-    rec: HitRecord,
+    rec: &HitRecord,
     color: &mut Vec3,
     refractive_index: f64,
 ) -> Option<Ray> {
@@ -108,14 +274,86 @@ fn dielectric_scatter(
         cosine = -ray.direction().dot(rec.normal.unwrap()) / ray.direction().length();
     }
 
-    match refract(ray.direction(), outward_normal, ni_over_nt) {
-        Some(ray) => {
-            if random_f64(0.0, 1.0) > schlick(cosine, refractive_index) {
-                return Some(Ray::new(rec.p.unwrap().clone(), ray.clone()))
-            }
-        },
-        None => { },
+    if let Some(refracted) = refract(ray.direction(), outward_normal, ni_over_nt) {
+        if random_f64(0.0, 1.0) > schlick(cosine, refractive_index) {
+            return Some(Ray::new(rec.p.unwrap(), refracted, ray.time()));
+        }
+    }
+
+    Some(Ray::new(rec.p.unwrap(), reflected, ray.time()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::texture::{SolidColor, Texture};
+    use std::sync::Arc;
+
+    // Height rising along u.
+    #[derive(Debug)]
+    struct Ramp;
+
+    impl Texture for Ramp {
+        fn value(&self, u: f64, _v: f64, _p: Vec3) -> Vec3 {
+            Vec3::new(u, u, u)
+        }
+    }
+
+    // Hit at the origin of the z = 0 plane, with the tangent frame along x and y, for a ray
+    // coming straight down from above or straight up from below.
+    fn hit(from_above: bool) -> (Ray, HitRecord) {
+        let direction = if from_above { -1.0 } else { 1.0 };
+        let ray = Ray::new(
+            Vec3::new(0.0, 0.0, -direction),
+            Vec3::new(0.0, 0.0, direction),
+            0.0,
+        );
+        let mut rec = HitRecord::new();
+        rec.t = Some(1.0);
+        rec.p = Some(Vec3::new(0.0, 0.0, 0.0));
+        rec.set_face_normal(ray, Vec3::new(0.0, 0.0, 1.0));
+        rec.uv = Some((0.5, 0.5));
+        rec.tangent = Some(Vec3::new(1.0, 0.0, 0.0));
+        rec.bitangent = Some(Vec3::new(0.0, 1.0, 0.0));
+        (ray, rec)
+    }
+
+    fn reflect_off(material: &Material, from_above: bool) -> Vec3 {
+        let (ray, rec) = hit(from_above);
+        let mut color = Vec3::new(0.0, 0.0, 0.0);
+        scatter(ray, &rec, &mut color, material)
+            .unwrap()
+            .direction()
     }
 
-    Some(Ray::new(rec.p.unwrap().clone(), reflected))
+    #[test]
+    fn flat_normal_map_leaves_the_normal_unchanged() {
+        let flat = Arc::new(SolidColor::new(Vec3::new(0.5, 0.5, 1.0)));
+        let mapped = Material::NormalMapped(Box::new(Material::Mirror), flat, 1.0);
+        for from_above in [true, false] {
+            assert_eq!(
+                reflect_off(&mapped, from_above),
+                reflect_off(&Material::Mirror, from_above)
+            );
+        }
+    }
+
+    #[test]
+    fn bump_tilts_the_normal_down_the_slope() {
+        let (_, rec) = hit(true);
+        let local = bump_normal(&(Arc::new(Ramp) as TextureRef), &rec, 0.5);
+        assert!((local.x() + 0.5).abs() < 1e-9, "{:?}", local);
+        assert_eq!(local.y(), 0.0);
+
+        // Height rises towards +x, so the outward normal leans towards -x and a ray coming
+        // straight down is reflected that way.
+        let bumped = Material::Bumped(Box::new(Material::Mirror), Arc::new(Ramp), 0.5);
+        let above = reflect_off(&bumped, true);
+        assert!(above.x() < 0.0 && above.z() > 0.0, "{:?}", above);
+
+        // Seen from below the same bump leans the other way, mirroring the front face.
+        let below = reflect_off(&bumped, false);
+        assert!(below.x() > 0.0 && below.z() < 0.0, "{:?}", below);
+        assert!((below + above).length() < 1e-9);
+    }
 }
@@ -0,0 +1,168 @@
+use crate::vec3::Vec3;
+
+/// Row-major affine transform acting on column vectors.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Mat4 {
+    pub m: [[f64; 4]; 4],
+}
+
+impl Mat4 {
+    pub fn identity() -> Mat4 {
+        Mat4::scale(Vec3::new(1.0, 1.0, 1.0))
+    }
+
+    pub fn translation(offset: Vec3) -> Mat4 {
+        Mat4 {
+            m: [
+                [1.0, 0.0, 0.0, offset.x()],
+                [0.0, 1.0, 0.0, offset.y()],
+                [0.0, 0.0, 1.0, offset.z()],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    pub fn scale(factors: Vec3) -> Mat4 {
+        Mat4 {
+            m: [
+                [factors.x(), 0.0, 0.0, 0.0],
+                [0.0, factors.y(), 0.0, 0.0],
+                [0.0, 0.0, factors.z(), 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    pub fn rotation_x(degrees: f64) -> Mat4 {
+        let (s, c) = degrees.to_radians().sin_cos();
+        Mat4 {
+            m: [
+                [1.0, 0.0, 0.0, 0.0],
+                [0.0, c, -s, 0.0],
+                [0.0, s, c, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    pub fn rotation_y(degrees: f64) -> Mat4 {
+        let (s, c) = degrees.to_radians().sin_cos();
+        Mat4 {
+            m: [
+                [c, 0.0, s, 0.0],
+                [0.0, 1.0, 0.0, 0.0],
+                [-s, 0.0, c, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    pub fn rotation_z(degrees: f64) -> Mat4 {
+        let (s, c) = degrees.to_radians().sin_cos();
+        Mat4 {
+            m: [
+                [c, -s, 0.0, 0.0],
+                [s, c, 0.0, 0.0],
+                [0.0, 0.0, 1.0, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    /// Euler angles in degrees, applied about x, then y, then z.
+    pub fn rotation(degrees: Vec3) -> Mat4 {
+        Mat4::rotation_z(degrees.z())
+            * Mat4::rotation_y(degrees.y())
+            * Mat4::rotation_x(degrees.x())
+    }
+
+    /// Rotation taking the x, y and z axes onto the given orthonormal vectors.
+    pub fn from_basis(x: Vec3, y: Vec3, z: Vec3) -> Mat4 {
+        Mat4 {
+            m: [
+                [x.x(), y.x(), z.x(), 0.0],
+                [x.y(), y.y(), z.y(), 0.0],
+                [x.z(), y.z(), z.z(), 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    /// Scale, then rotate, then translate.
+    pub fn from_trs(translate: Vec3, rotate: Vec3, scale: Vec3) -> Mat4 {
+        Mat4::translation(translate) * Mat4::rotation(rotate) * Mat4::scale(scale)
+    }
+
+    pub fn transpose(&self) -> Mat4 {
+        let mut out = [[0.0; 4]; 4];
+        for (i, row) in out.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.m[j][i];
+            }
+        }
+        Mat4 { m: out }
+    }
+
+    /// Gauss-Jordan elimination with partial pivoting, None for singular matrices.
+    pub fn inverse(&self) -> Option<Mat4> {
+        let mut a = self.m;
+        let mut inv = Mat4::identity().m;
+        for col in 0..4 {
+            let pivot = (col..4)
+                .max_by(|&i, &j| a[i][col].abs().partial_cmp(&a[j][col].abs()).unwrap())
+                .unwrap();
+            if a[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let scale = 1.0 / a[col][col];
+            for j in 0..4 {
+                a[col][j] *= scale;
+                inv[col][j] *= scale;
+            }
+            for row in 0..4 {
+                if row != col {
+                    let factor = a[row][col];
+                    for j in 0..4 {
+                        a[row][j] -= factor * a[col][j];
+                        inv[row][j] -= factor * inv[col][j];
+                    }
+                }
+            }
+        }
+        Some(Mat4 { m: inv })
+    }
+
+    pub fn transform_point(&self, p: Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * p.x() + m[0][1] * p.y() + m[0][2] * p.z() + m[0][3],
+            m[1][0] * p.x() + m[1][1] * p.y() + m[1][2] * p.z() + m[1][3],
+            m[2][0] * p.x() + m[2][1] * p.y() + m[2][2] * p.z() + m[2][3],
+        )
+    }
+
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * v.x() + m[0][1] * v.y() + m[0][2] * v.z(),
+            m[1][0] * v.x() + m[1][1] * v.y() + m[1][2] * v.z(),
+            m[2][0] * v.x() + m[2][1] * v.y() + m[2][2] * v.z(),
+        )
+    }
+}
+
+impl std::ops::Mul for Mat4 {
+    type Output = Mat4;
+    fn mul(self, rhs: Mat4) -> Mat4 {
+        let mut out = [[0.0; 4]; 4];
+        for (i, row) in out.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.m[i][k] * rhs.m[k][j]).sum();
+            }
+        }
+        Mat4 { m: out }
+    }
+}
@@ -0,0 +1,544 @@
+use crate::hittable::Hittable;
+use crate::material::Material;
+use crate::texture::VertexColors;
+use crate::triangle::Triangle;
+use crate::vec3::Vec3;
+use std::fmt;
+use std::sync::Arc;
+
+// Why a mesh file could not be loaded: it could not be read, or its contents are not a mesh.
+#[derive(Debug)]
+pub enum MeshError {
+    Io(std::io::Error),
+    Format(String),
+}
+
+impl fmt::Display for MeshError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MeshError::Io(e) => write!(f, "{}", e),
+            MeshError::Format(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+// Indexed triangles read from a PLY or STL file, with the per-vertex normals and colors of PLY
+// files that have them. Colors are linearised with gamma 2 like color images.
+#[derive(Debug, Clone, Default)]
+pub struct MeshData {
+    pub positions: Vec<Vec3>,
+    pub normals: Option<Vec<Vec3>>,
+    pub colors: Option<Vec<Vec3>>,
+    pub faces: Vec<[usize; 3]>,
+}
+
+impl MeshData {
+    // Picks the format from the file extension, ASCII and binary encodings are both detected.
+    pub fn load(path: &str) -> MeshData {
+        MeshData::read(path).unwrap_or_else(|e| panic!("failed to load mesh {}: {}", path, e))
+    }
+
+    // `load` returning the error instead of panicking, for callers that report it themselves.
+    pub fn read(path: &str) -> Result<MeshData, MeshError> {
+        let bytes = std::fs::read(path).map_err(MeshError::Io)?;
+        let result = match path.to_lowercase().rsplit('.').next() {
+            Some("ply") => parse_ply(&bytes),
+            Some("stl") => parse_stl(&bytes),
+            _ => Err("unknown mesh format".to_string()),
+        };
+        result.map_err(MeshError::Format)
+    }
+
+    // Vertex colors replace the color slot of the material.
+    pub fn triangles(&self, mat: &Material) -> Vec<Box<dyn Hittable + Send + Sync>> {
+        self.faces
+            .iter()
+            .map(|face| {
+                let corner = |values: &Vec<Vec3>| face.map(|i| values[i]);
+                let material = match &self.colors {
+                    Some(colors) => mat.with_albedo(Arc::new(VertexColors::new(corner(colors)))),
+                    None => mat.clone(),
+                };
+                let [a, b, c] = corner(&self.positions);
+                let triangle = Triangle::new(a, b, c, material, false);
+                let triangle = match &self.normals {
+                    Some(normals) => triangle.with_normals(corner(normals)),
+                    None => triangle,
+                };
+                Box::new(triangle) as Box<dyn Hittable + Send + Sync>
+            })
+            .collect()
+    }
+}
+
+// Binary files are 84 header bytes plus 50 per facet, and ASCII files start with "solid". The
+// header of a binary file may itself start with "solid", so a file of exactly the binary size is
+// binary, and one that is not text is read as binary with trailing bytes ignored.
+fn parse_stl(bytes: &[u8]) -> Result<MeshData, String> {
+    let binary_count = bytes
+        .get(80..84)
+        .map(|count| u32::from_le_bytes([count[0], count[1], count[2], count[3]]) as usize);
+    let binary_size = binary_count.and_then(|count| count.checked_mul(50)?.checked_add(84));
+    let ascii =
+        bytes.starts_with(b"solid") && (contains(bytes, b"facet") || contains(bytes, b"endsolid"));
+    let corners = match (binary_count, binary_size) {
+        (Some(count), Some(size)) if bytes.len() == size => stl_binary_corners(bytes, count),
+        _ if ascii => stl_ascii_corners(bytes)?,
+        (Some(count), Some(size)) if bytes.len() > size => stl_binary_corners(bytes, count),
+        _ => return Err("neither an ASCII nor a complete binary STL file".to_string()),
+    };
+
+    let mut mesh = MeshData::default();
+    for facet in corners.chunks_exact(3) {
+        let first = mesh.positions.len();
+        mesh.positions.extend_from_slice(facet);
+        mesh.faces.push([first, first + 1, first + 2]);
+    }
+    Ok(mesh)
+}
+
+fn contains(bytes: &[u8], word: &[u8]) -> bool {
+    bytes.windows(word.len()).any(|window| window == word)
+}
+
+fn stl_binary_corners(bytes: &[u8], count: usize) -> Vec<Vec3> {
+    (0..count)
+        .flat_map(|facet| {
+            let start = 84 + 50 * facet + 12;
+            (0..3).map(move |corner| {
+                let float = |i: usize| {
+                    let at = start + 12 * corner + 4 * i;
+                    f32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+                        as f64
+                };
+                Vec3::new(float(0), float(1), float(2))
+            })
+        })
+        .collect()
+}
+
+fn stl_ascii_corners(bytes: &[u8]) -> Result<Vec<Vec3>, String> {
+    let mut corners = vec![];
+    for (number, line) in String::from_utf8_lossy(bytes).lines().enumerate() {
+        let mut words = line.split_whitespace();
+        if words.next() != Some("vertex") {
+            continue;
+        }
+        let values: Vec<f64> = words.map_while(|w| w.parse().ok()).collect();
+        if values.len() < 3 {
+            return Err(format!(
+                "stl vertex on line {} needs three coordinates",
+                number + 1
+            ));
+        }
+        corners.push(Vec3::new(values[0], values[1], values[2]));
+    }
+    if corners.len() % 3 != 0 {
+        return Err("stl facet without three vertices".to_string());
+    }
+    Ok(corners)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum PlyFormat {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+#[derive(Debug, Clone)]
+enum PlyProperty {
+    Scalar(String, String),
+    // Name, count type and item type.
+    List(String, String, String),
+}
+
+#[derive(Debug, Clone)]
+struct PlyElement {
+    name: String,
+    count: usize,
+    properties: Vec<PlyProperty>,
+}
+
+// Reads numbers one at a time from the body, as text tokens or packed binary values.
+struct PlyReader<'a> {
+    format: PlyFormat,
+    body: &'a [u8],
+    offset: usize,
+    tokens: std::str::SplitAsciiWhitespace<'a>,
+}
+
+impl<'a> PlyReader<'a> {
+    fn read(&mut self, kind: &str) -> Result<f64, String> {
+        if self.format == PlyFormat::Ascii {
+            return self
+                .tokens
+                .next()
+                .and_then(|token| token.parse::<f64>().ok())
+                .ok_or_else(|| "truncated ply body".to_string());
+        }
+        let size = match kind {
+            "char" | "uchar" | "int8" | "uint8" => 1,
+            "short" | "ushort" | "int16" | "uint16" => 2,
+            "int" | "uint" | "float" | "int32" | "uint32" | "float32" => 4,
+            "double" | "float64" => 8,
+            _ => return Err(format!("unknown ply type {}", kind)),
+        };
+        let raw = self
+            .body
+            .get(self.offset..self.offset + size)
+            .ok_or_else(|| "truncated ply body".to_string())?;
+        self.offset += size;
+        let mut buffer = [0u8; 8];
+        buffer[..size].copy_from_slice(raw);
+        if self.format == PlyFormat::BigEndian {
+            buffer[..size].reverse();
+        }
+        let value = match kind {
+            "char" | "int8" => buffer[0] as i8 as f64,
+            "uchar" | "uint8" => buffer[0] as f64,
+            "short" | "int16" => i16::from_le_bytes([buffer[0], buffer[1]]) as f64,
+            "ushort" | "uint16" => u16::from_le_bytes([buffer[0], buffer[1]]) as f64,
+            "int" | "int32" => {
+                i32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as f64
+            }
+            "uint" | "uint32" => {
+                u32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as f64
+            }
+            "float" | "float32" => {
+                f32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as f64
+            }
+            _ => f64::from_le_bytes(buffer),
+        };
+        Ok(value)
+    }
+}
+
+// Polygons with more than three corners are split into fans.
+fn parse_ply(bytes: &[u8]) -> Result<MeshData, String> {
+    let header_end = bytes
+        .windows(10)
+        .position(|window| window == b"end_header")
+        .ok_or_else(|| "missing ply header".to_string())?;
+    let body_start = bytes[header_end..]
+        .iter()
+        .position(|b| *b == b'\n')
+        .map_or(bytes.len(), |newline| header_end + newline + 1);
+    let header = String::from_utf8_lossy(&bytes[..header_end]);
+
+    let mut format = None;
+    let mut elements: Vec<PlyElement> = vec![];
+    for line in header.lines() {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words[..] {
+            ["format", "ascii", _] => format = Some(PlyFormat::Ascii),
+            ["format", "binary_little_endian", _] => format = Some(PlyFormat::LittleEndian),
+            ["format", "binary_big_endian", _] => format = Some(PlyFormat::BigEndian),
+            ["element", name, count] => elements.push(PlyElement {
+                name: name.to_string(),
+                count: count.parse().map_err(|_| "bad ply element count")?,
+                properties: vec![],
+            }),
+            ["property", "list", count, item, name] => elements
+                .last_mut()
+                .ok_or("ply property outside an element")?
+                .properties
+                .push(PlyProperty::List(
+                    name.to_string(),
+                    count.to_string(),
+                    item.to_string(),
+                )),
+            ["property", kind, name] => elements
+                .last_mut()
+                .ok_or("ply property outside an element")?
+                .properties
+                .push(PlyProperty::Scalar(name.to_string(), kind.to_string())),
+            _ => {}
+        }
+    }
+
+    let format = format.ok_or_else(|| "missing ply format".to_string())?;
+    let body = &bytes[body_start..];
+    let text = match format {
+        PlyFormat::Ascii => std::str::from_utf8(body).map_err(|_| "ply body is not text")?,
+        _ => "",
+    };
+    let mut reader = PlyReader {
+        format,
+        body,
+        offset: 0,
+        tokens: text.split_ascii_whitespace(),
+    };
+
+    let mut mesh = MeshData::default();
+    let mut normals = vec![];
+    let mut colors = vec![];
+    for element in &elements {
+        for _ in 0..element.count {
+            let mut scalars = std::collections::HashMap::new();
+            let mut indices = vec![];
+            for property in &element.properties {
+                match property {
+                    PlyProperty::Scalar(name, kind) => {
+                        let value = reader.read(kind)?;
+                        // 8-bit colors are stored as 0-255.
+                        let value = if kind == "uchar" || kind == "uint8" {
+                            value / 255.0
+                        } else {
+                            value
+                        };
+                        scalars.insert(name.as_str(), value);
+                    }
+                    PlyProperty::List(name, count_kind, item_kind) => {
+                        let count = reader.read(count_kind)? as usize;
+                        for _ in 0..count {
+                            let index = reader.read(item_kind)? as usize;
+                            if name == "vertex_indices" || name == "vertex_index" {
+                                indices.push(index);
+                            }
+                        }
+                    }
+                }
+            }
+
+            match &element.name[..] {
+                "vertex" => {
+                    let value = |name: &str| scalars.get(name).copied();
+                    let coordinate =
+                        |name: &str| value(name).ok_or(format!("ply vertex lacks {}", name));
+                    mesh.positions.push(Vec3::new(
+                        coordinate("x")?,
+                        coordinate("y")?,
+                        coordinate("z")?,
+                    ));
+                    if let (Some(x), Some(y), Some(z)) = (value("nx"), value("ny"), value("nz")) {
+                        normals.push(Vec3::new(x, y, z));
+                    }
+                    if let (Some(r), Some(g), Some(b)) =
+                        (value("red"), value("green"), value("blue"))
+                    {
+                        colors.push(Vec3::new(r * r, g * g, b * b));
+                    }
+                }
+                "face" => {
+                    for i in 1..indices.len().saturating_sub(1) {
+                        mesh.faces.push([indices[0], indices[i], indices[i + 1]]);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    if let Some(face) = mesh
+        .faces
+        .iter()
+        .flatten()
+        .find(|i| **i >= mesh.positions.len())
+    {
+        return Err(format!("ply face refers to missing vertex {}", face));
+    }
+    if normals.len() == mesh.positions.len() && !normals.is_empty() {
+        mesh.normals = Some(normals);
+    }
+    if colors.len() == mesh.positions.len() && !colors.is_empty() {
+        mesh.colors = Some(colors);
+    }
+    Ok(mesh)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ASCII_STL: &str = "solid pyramid
+facet normal 0 0 -1
+  outer loop
+    vertex 0 0 0
+    vertex 1 0 0
+    vertex 0 1 0
+  endloop
+endfacet
+facet normal 0 -1 0
+  outer loop
+    vertex 0 0 0
+    vertex 1 0 0
+    vertex 0 0 1.5
+  endloop
+endfacet
+endsolid pyramid
+";
+
+    fn binary_stl(header: &[u8], facets: &[[[f32; 3]; 3]], trailing: usize) -> Vec<u8> {
+        let mut bytes = header.to_vec();
+        bytes.resize(80, b' ');
+        bytes.extend_from_slice(&(facets.len() as u32).to_le_bytes());
+        for facet in facets {
+            bytes.extend_from_slice(&[0; 12]);
+            for value in facet.iter().flatten() {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            bytes.extend_from_slice(&[0; 2]);
+        }
+        bytes.resize(bytes.len() + trailing, 0);
+        bytes
+    }
+
+    const FACET: [[f32; 3]; 3] = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 2.0, 0.5]];
+
+    #[test]
+    fn ascii_stl_facets() {
+        let mesh = parse_stl(ASCII_STL.as_bytes()).unwrap();
+        assert_eq!(mesh.faces, vec![[0, 1, 2], [3, 4, 5]]);
+        assert_eq!(mesh.positions[5].z(), 1.5);
+        assert!(mesh.normals.is_none() && mesh.colors.is_none());
+    }
+
+    #[test]
+    fn ascii_stl_vertex_without_three_coordinates_is_an_error() {
+        let broken = ASCII_STL.replace("vertex 0 1 0", "vertex 0 1");
+        let error = parse_stl(broken.as_bytes()).unwrap_err();
+        assert!(error.contains("line 6"), "{}", error);
+    }
+
+    #[test]
+    fn binary_stl_facets() {
+        for header in [&b"binary"[..], &b"solid header written by a CAD tool"[..]] {
+            let mesh = parse_stl(&binary_stl(header, &[FACET, FACET], 0)).unwrap();
+            assert_eq!(mesh.faces.len(), 2);
+            assert_eq!(mesh.positions[2].y(), 2.0);
+            assert_eq!(mesh.positions[2].z(), 0.5);
+        }
+    }
+
+    #[test]
+    fn binary_stl_with_trailing_bytes() {
+        let mesh = parse_stl(&binary_stl(b"solid", &[FACET], 7)).unwrap();
+        assert_eq!(mesh.faces.len(), 1);
+        assert_eq!(mesh.positions[1].x(), 1.0);
+    }
+
+    #[test]
+    fn truncated_binary_stl_is_an_error() {
+        let mut bytes = binary_stl(b"binary", &[FACET, FACET], 0);
+        bytes.truncate(bytes.len() - 10);
+        assert!(parse_stl(&bytes).is_err());
+    }
+
+    const PLY_HEADER: &str = "element vertex 4
+property float x
+property float y
+property float z
+property float nx
+property float ny
+property float nz
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+end_header
+";
+
+    // A unit quad facing +z, corners colored black, white, red and grey.
+    const QUAD: [([f32; 3], [u8; 3]); 4] = [
+        ([0.0, 0.0, 0.0], [0, 0, 0]),
+        ([1.0, 0.0, 0.0], [255, 255, 255]),
+        ([1.0, 1.0, 0.0], [255, 0, 0]),
+        ([0.0, 1.0, 0.0], [51, 51, 51]),
+    ];
+
+    fn assert_quad(mesh: &MeshData) {
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(mesh.positions[2].y(), 1.0);
+        assert_eq!(mesh.faces, vec![[0, 1, 2], [0, 2, 3]]);
+        let normals = mesh.normals.as_ref().unwrap();
+        assert!(normals.iter().all(|n| n.z() == 1.0));
+        let colors = mesh.colors.as_ref().unwrap();
+        assert_eq!(colors[1].x(), 1.0);
+        assert_eq!(colors[2].y(), 0.0);
+        assert!((colors[3].x() - 0.04).abs() < 1e-9);
+    }
+
+    #[test]
+    fn ascii_ply_with_normals_colors_and_quads() {
+        let mut text = format!("ply\nformat ascii 1.0\ncomment quad\n{}", PLY_HEADER);
+        for (p, c) in QUAD {
+            text += &format!(
+                "{} {} {} 0 0 1 {} {} {}\n",
+                p[0], p[1], p[2], c[0], c[1], c[2]
+            );
+        }
+        text += "4 0 1 2 3\n";
+        assert_quad(&parse_ply(text.as_bytes()).unwrap());
+    }
+
+    fn binary_ply(format: &str, big_endian: bool) -> Vec<u8> {
+        let mut bytes = format!("ply\nformat {} 1.0\n{}", format, PLY_HEADER).into_bytes();
+        let float = |value: f32| {
+            if big_endian {
+                value.to_be_bytes()
+            } else {
+                value.to_le_bytes()
+            }
+        };
+        for (p, c) in QUAD {
+            for value in p.iter().chain(&[0.0, 0.0, 1.0]) {
+                bytes.extend_from_slice(&float(*value));
+            }
+            bytes.extend_from_slice(&c);
+        }
+        bytes.push(4);
+        for index in 0..4_i32 {
+            if big_endian {
+                bytes.extend_from_slice(&index.to_be_bytes());
+            } else {
+                bytes.extend_from_slice(&index.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    #[test]
+    fn little_endian_ply() {
+        assert_quad(&parse_ply(&binary_ply("binary_little_endian", false)).unwrap());
+    }
+
+    #[test]
+    fn big_endian_ply() {
+        assert_quad(&parse_ply(&binary_ply("binary_big_endian", true)).unwrap());
+    }
+
+    #[test]
+    fn ply_errors() {
+        let truncated = binary_ply("binary_little_endian", false);
+        assert!(parse_ply(&truncated[..truncated.len() - 3]).is_err());
+        let text = format!("ply\nformat ascii 1.0\n{}0 0 0 0 0 1 0 0 0\n", PLY_HEADER);
+        assert!(parse_ply(text.as_bytes()).is_err());
+        assert!(parse_ply(b"ply\nformat ascii 1.0\nelement face 1\nproperty list uchar int vertex_indices\nend_header\n3 0 1 2\n").is_err());
+    }
+
+    #[test]
+    fn read_tells_unreadable_files_from_bad_contents() {
+        let dir = std::env::temp_dir();
+        let missing = dir.join("mesh_file_missing.stl");
+        assert!(matches!(
+            MeshData::read(missing.to_str().unwrap()),
+            Err(MeshError::Io(_))
+        ));
+
+        let unknown = dir.join("mesh_file_unknown.obj");
+        std::fs::write(&unknown, ASCII_STL).unwrap();
+        assert!(matches!(
+            MeshData::read(unknown.to_str().unwrap()),
+            Err(MeshError::Format(_))
+        ));
+
+        let stl = dir.join("mesh_file_pyramid.stl");
+        std::fs::write(&stl, ASCII_STL).unwrap();
+        assert_eq!(
+            MeshData::read(stl.to_str().unwrap()).unwrap().faces.len(),
+            2
+        );
+    }
+}
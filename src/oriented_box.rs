@@ -0,0 +1,131 @@
+use crate::aabb::Aabb;
+use crate::cube::{face_uv, slab_intersect};
+use crate::frame::Frame;
+use crate::hittable;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::scene_export::SceneWriter;
+use crate::vec3::Vec3;
+
+/// Box with its own axes, intersected as an axis aligned box in its local frame.
+#[derive(Debug, Clone)]
+pub struct OrientedBox {
+    frame: Frame,
+    half_size: Vec3,
+    rotation: Vec3,
+    material: Material,
+}
+
+impl OrientedBox {
+    /// `rotation` holds Euler angles in degrees, applied about x, then y, then z.
+    pub fn new(center: Vec3, size: Vec3, rotation: Vec3, mat: Material) -> OrientedBox {
+        let rotate = |v: Vec3| {
+            let (sx, cx) = rotation.x().to_radians().sin_cos();
+            let (sy, cy) = rotation.y().to_radians().sin_cos();
+            let (sz, cz) = rotation.z().to_radians().sin_cos();
+            let v = Vec3::new(v.x(), cx * v.y() - sx * v.z(), sx * v.y() + cx * v.z());
+            let v = Vec3::new(cy * v.x() + sy * v.z(), v.y(), -sy * v.x() + cy * v.z());
+            Vec3::new(cz * v.x() - sz * v.y(), sz * v.x() + cz * v.y(), v.z())
+        };
+        OrientedBox {
+            frame: Frame::from_axes(
+                center,
+                rotate(Vec3::new(1.0, 0.0, 0.0)),
+                rotate(Vec3::new(0.0, 1.0, 0.0)),
+                rotate(Vec3::new(0.0, 0.0, 1.0)),
+            ),
+            half_size: size * 0.5,
+            rotation,
+            material: mat,
+        }
+    }
+}
+
+impl hittable::Hittable for OrientedBox {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, rec: &mut hittable::HitRecord) -> bool {
+        let local_ray = self.frame.ray_to_local(ray);
+        let ((t_near, near_normal), (t_far, far_normal)) =
+            match slab_intersect(-self.half_size, self.half_size, local_ray) {
+                Some(hits) => hits,
+                None => return false,
+            };
+        let (t, local_normal) = if t_near > t_min && t_near < t_max {
+            (t_near, near_normal)
+        } else if t_far > t_min && t_far < t_max {
+            (t_far, far_normal)
+        } else {
+            return false;
+        };
+
+        let local = (local_ray.at(t) + self.half_size) / (self.half_size * 2.0);
+        let (uv, tangent, bitangent) = face_uv(local, local_normal);
+        rec.t = Some(t);
+        rec.p = Some(ray.at(t));
+        rec.set_face_normal(ray, self.frame.dir_to_world(local_normal));
+        rec.material = Some(self.material.clone());
+        rec.uv = Some(uv);
+        rec.tangent = Some(self.frame.dir_to_world(tangent));
+        rec.bitangent = Some(self.frame.dir_to_world(bitangent));
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.frame.bounds(-self.half_size, self.half_size))
+    }
+
+    fn intervals(&self, ray: Ray) -> Option<Vec<hittable::Interval>> {
+        let local_ray = self.frame.ray_to_local(ray);
+        Some(
+            match slab_intersect(-self.half_size, self.half_size, local_ray) {
+                Some(((t_near, _), (t_far, _))) => vec![(t_near, t_far)],
+                None => vec![],
+            },
+        )
+    }
+
+    fn export(&self, writer: &mut SceneWriter) {
+        let vectors = [self.frame.origin, self.half_size * 2.0, self.rotation];
+        writer.primitive("OrientedBox", &vectors, &[], &self.material);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::{HitRecord, Hittable};
+
+    // 2 x 2 x 2 box turned 45 degrees about z, so its corners point along the x and y axes.
+    fn turned() -> OrientedBox {
+        OrientedBox::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(2.0, 2.0, 2.0),
+            Vec3::new(0.0, 0.0, 45.0),
+            Material::Mirror,
+        )
+    }
+
+    fn cast(origin: Vec3, direction: Vec3) -> Option<HitRecord> {
+        let mut rec = HitRecord::new();
+        let ray = Ray::new(origin, direction, 0.0);
+        turned()
+            .hit(ray, 0.001, f64::INFINITY, &mut rec)
+            .then_some(rec)
+    }
+
+    #[test]
+    fn faces_follow_the_rotation() {
+        let diagonal = Vec3::new(1.0, 1.0, 0.0).unit_vector();
+        let rec = cast(diagonal * 5.0, -diagonal).unwrap();
+        assert!((rec.t.unwrap() - 4.0).abs() < 1e-9);
+        assert!((rec.normal.unwrap() - diagonal).length() < 1e-9);
+        let (u, v) = rec.uv.unwrap();
+        assert!((u - 0.5).abs() < 1e-9 && (v - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn corners_reach_past_the_unrotated_size() {
+        let left = Vec3::new(-1.0, 0.0, 0.0);
+        assert!(cast(Vec3::new(5.0, 1.3, 0.0), left).is_some());
+        assert!(cast(Vec3::new(5.0, 1.5, 0.0), left).is_none());
+    }
+}
@@ -0,0 +1,123 @@
+use crate::vec3::Vec3;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+const POINT_COUNT: usize = 256;
+
+// Gradient noise from Ray Tracing: The Next Week, seeded so textures are reproducible.
+#[derive(Debug, Clone)]
+pub struct Perlin {
+    seed: u32,
+    gradients: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    pub fn new(seed: u32) -> Perlin {
+        let mut rng = StdRng::seed_from_u64(seed as u64);
+        let gradients = (0..POINT_COUNT)
+            .map(|_| {
+                Vec3::new(
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                )
+                .unit_vector()
+            })
+            .collect();
+
+        Perlin {
+            seed,
+            gradients,
+            perm_x: Perlin::generate_perm(&mut rng),
+            perm_y: Perlin::generate_perm(&mut rng),
+            perm_z: Perlin::generate_perm(&mut rng),
+        }
+    }
+
+    pub fn seed(&self) -> u32 {
+        self.seed
+    }
+
+    fn generate_perm(rng: &mut StdRng) -> Vec<usize> {
+        let mut perm: Vec<usize> = (0..POINT_COUNT).collect();
+        perm.shuffle(rng);
+        perm
+    }
+
+    // Noise in the range [-1, 1].
+    pub fn noise(&self, p: Vec3) -> f64 {
+        let u = p.x() - p.x().floor();
+        let v = p.y() - p.y().floor();
+        let w = p.z() - p.z().floor();
+
+        let i = p.x().floor() as i64;
+        let j = p.y().floor() as i64;
+        let k = p.z().floor() as i64;
+
+        let mut c = [[[Vec3::new(0.0, 0.0, 0.0); 2]; 2]; 2];
+        for (di, plane) in c.iter_mut().enumerate() {
+            for (dj, row) in plane.iter_mut().enumerate() {
+                for (dk, cell) in row.iter_mut().enumerate() {
+                    *cell = self.gradients[self.perm_x[((i + di as i64) & 255) as usize]
+                        ^ self.perm_y[((j + dj as i64) & 255) as usize]
+                        ^ self.perm_z[((k + dk as i64) & 255) as usize]];
+                }
+            }
+        }
+
+        Perlin::trilinear_interp(&c, u, v, w)
+    }
+
+    fn trilinear_interp(c: &[[[Vec3; 2]; 2]; 2], u: f64, v: f64, w: f64) -> f64 {
+        let uu = u * u * (3.0 - 2.0 * u);
+        let vv = v * v * (3.0 - 2.0 * v);
+        let ww = w * w * (3.0 - 2.0 * w);
+
+        let mut accum = 0.0;
+        for (i, plane) in c.iter().enumerate() {
+            for (j, row) in plane.iter().enumerate() {
+                for (k, gradient) in row.iter().enumerate() {
+                    let (fi, fj, fk) = (i as f64, j as f64, k as f64);
+                    let weight = Vec3::new(u - fi, v - fj, w - fk);
+                    accum += (fi * uu + (1.0 - fi) * (1.0 - uu))
+                        * (fj * vv + (1.0 - fj) * (1.0 - vv))
+                        * (fk * ww + (1.0 - fk) * (1.0 - ww))
+                        * gradient.dot(weight);
+                }
+            }
+        }
+        accum
+    }
+
+    // Fractal Brownian motion, roughly in the range [-1, 1].
+    pub fn fbm(&self, p: Vec3, octaves: usize, lacunarity: f64, gain: f64) -> f64 {
+        let mut accum = 0.0;
+        let mut norm = 0.0;
+        let mut amplitude = 1.0;
+        let mut point = p;
+        for _ in 0..octaves.max(1) {
+            accum += amplitude * self.noise(point);
+            norm += amplitude;
+            amplitude *= gain;
+            point = point * lacunarity;
+        }
+        accum / norm
+    }
+
+    // Sum of absolute octaves, used for marble veins.
+    pub fn turbulence(&self, p: Vec3, octaves: usize) -> f64 {
+        let mut accum = 0.0;
+        let mut weight = 1.0;
+        let mut point = p;
+        for _ in 0..octaves.max(1) {
+            accum += weight * self.noise(point);
+            weight *= 0.5;
+            point = point * 2.0;
+        }
+        accum.abs()
+    }
+}
@@ -0,0 +1,115 @@
+use crate::aabb::Aabb;
+use crate::frame::Frame;
+use crate::hittable;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::scene_export::SceneWriter;
+use crate::vec3::Vec3;
+
+/// Infinite plane, uv are world-space distances along the plane's tangent frame.
+#[derive(Debug, Clone)]
+pub struct Plane {
+    frame: Frame,
+    material: Material,
+}
+
+impl Plane {
+    pub fn new(point: Vec3, normal: Vec3, mat: Material) -> Plane {
+        Plane {
+            frame: Frame::new(point, normal),
+            material: mat,
+        }
+    }
+}
+
+impl hittable::Hittable for Plane {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, rec: &mut hittable::HitRecord) -> bool {
+        let normal = self.frame.axis;
+        let denom = ray.direction().dot(normal);
+        if denom.abs() < 1e-12 {
+            return false;
+        }
+        let t = (self.frame.origin - ray.origin()).dot(normal) / denom;
+        if t < t_min || t > t_max {
+            return false;
+        }
+
+        let p = ray.at(t);
+        let local = self.frame.point_to_local(p);
+        rec.t = Some(t);
+        rec.p = Some(p);
+        rec.set_face_normal(ray, normal);
+        rec.material = Some(self.material.clone());
+        rec.uv = Some((local.x(), -local.z()));
+        rec.tangent = Some(self.frame.tangent);
+        rec.bitangent = Some(normal.cross(self.frame.tangent));
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+
+    fn export(&self, writer: &mut SceneWriter) {
+        writer.primitive(
+            "Plane",
+            &[self.frame.origin, self.frame.axis],
+            &[],
+            &self.material,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::{HitRecord, Hittable};
+
+    fn cast(origin: Vec3, direction: Vec3, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let plane = Plane::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Material::Mirror,
+        );
+        let mut rec = HitRecord::new();
+        let ray = Ray::new(origin, direction, 0.0);
+        plane.hit(ray, t_min, t_max, &mut rec).then_some(rec)
+    }
+
+    #[test]
+    fn hits_from_both_sides_within_the_ray_bounds() {
+        let down = Vec3::new(0.0, -1.0, 0.0);
+        let rec = cast(Vec3::new(1.0, 2.0, 3.0), down, 0.001, f64::INFINITY).unwrap();
+        assert_eq!(rec.t, Some(2.0));
+        assert_eq!(rec.p, Some(Vec3::new(1.0, 0.0, 3.0)));
+        assert_eq!(rec.normal, Some(Vec3::new(0.0, 1.0, 0.0)));
+        assert_eq!(rec.get_front_face(), Some(true));
+
+        let rec = cast(Vec3::new(0.0, -2.0, 0.0), -down, 0.001, f64::INFINITY).unwrap();
+        assert_eq!(rec.normal, Some(Vec3::new(0.0, -1.0, 0.0)));
+        assert_eq!(rec.get_front_face(), Some(false));
+
+        assert!(cast(Vec3::new(0.0, 2.0, 0.0), down, 0.001, 1.5).is_none());
+        assert!(cast(Vec3::new(0.0, 2.0, 0.0), down, 2.5, f64::INFINITY).is_none());
+        let along = Vec3::new(1.0, 0.0, 0.0);
+        assert!(cast(Vec3::new(0.0, 2.0, 0.0), along, 0.001, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn uvs_are_distances_along_the_plane() {
+        let down = Vec3::new(0.0, -1.0, 0.0);
+        let uv = |x, z| {
+            cast(Vec3::new(x, 1.0, z), down, 0.001, f64::INFINITY)
+                .unwrap()
+                .uv
+                .unwrap()
+        };
+        let (u0, v0) = uv(0.0, 0.0);
+        for (x, z) in [(2.0, 0.0), (0.0, 3.0), (1.0, -1.0)] {
+            let (u, v) = uv(x, z);
+            let moved = ((u - u0).powi(2) + (v - v0).powi(2)).sqrt();
+            let distance = (x * x + z * z).sqrt();
+            assert!((moved - distance).abs() < 1e-9);
+        }
+    }
+}
@@ -0,0 +1,440 @@
+use crate::bvh::Bvh;
+use crate::camera::{Camera, PerspectiveCamera};
+use crate::cone::Cone;
+use crate::cube::Cube;
+use crate::cylinder::Cylinder;
+use crate::disc::Disc;
+use crate::hittable::Hittable;
+use crate::material::Material;
+use crate::mesh_file::{MeshData, MeshError};
+use crate::plane::Plane;
+use crate::rectangle::Rectangle;
+use crate::scene::{RenderControl, RenderSettings, Scene};
+use crate::scene_file;
+use crate::sphere::Sphere;
+use crate::texture::SolidColor;
+use crate::torus::Torus;
+use crate::triangle::Triangle;
+use crate::vec3::Vec3;
+use numpy::{PyArray1, PyArray3, PyArrayMethods};
+use pyo3::exceptions::{PyOSError, PyValueError};
+use pyo3::prelude::*;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::Arc;
+
+// Points, directions and colors are passed from Python as any sequence of three numbers.
+type PyVec3 = [f64; 3];
+
+fn vec3(v: PyVec3) -> Vec3 {
+    Vec3::new(v[0], v[1], v[2])
+}
+
+// Contiguous RGB rows as a (height, width, 3) uint8 array, taking over the buffer.
+fn image_array(
+    py: Python<'_>,
+    rgb: Vec<u8>,
+    settings: RenderSettings,
+) -> PyResult<Bound<'_, PyArray3<u8>>> {
+    let shape = [
+        settings.image_height as usize,
+        settings.image_width as usize,
+        3,
+    ];
+    PyArray1::from_vec(py, rgb).reshape(shape)
+}
+
+#[pyclass(name = "Material", module = "raytracing", frozen)]
+#[derive(Clone)]
+struct PyMaterial {
+    inner: Material,
+}
+
+#[pymethods]
+impl PyMaterial {
+    #[staticmethod]
+    fn lambertian(color: PyVec3) -> PyMaterial {
+        PyMaterial {
+            inner: Material::Lambertian(Arc::new(SolidColor::new(vec3(color)))),
+        }
+    }
+
+    #[staticmethod]
+    #[pyo3(signature = (color, fuzz = 0.0))]
+    fn metal(color: PyVec3, fuzz: f64) -> PyMaterial {
+        PyMaterial {
+            inner: Material::Metal(
+                Arc::new(SolidColor::new(vec3(color))),
+                Arc::new(SolidColor::new(Vec3::new(fuzz, fuzz, fuzz))),
+            ),
+        }
+    }
+
+    #[staticmethod]
+    fn dielectric(ior: f64) -> PyMaterial {
+        PyMaterial {
+            inner: Material::Dielectric(ior),
+        }
+    }
+
+    #[staticmethod]
+    fn mirror() -> PyMaterial {
+        PyMaterial {
+            inner: Material::Mirror,
+        }
+    }
+
+    #[staticmethod]
+    fn hair(color: PyVec3, roughness: f64) -> PyMaterial {
+        PyMaterial {
+            inner: Material::Hair(
+                Arc::new(SolidColor::new(vec3(color))),
+                Arc::new(SolidColor::new(Vec3::new(roughness, roughness, roughness))),
+            ),
+        }
+    }
+
+    // Same material tinting light that travels through it, like `absorption=` in RON scenes.
+    fn with_absorption(&self, absorption: PyVec3) -> PyMaterial {
+        PyMaterial {
+            inner: Material::Absorbing(Box::new(self.inner.clone()), vec3(absorption)),
+        }
+    }
+}
+
+// Base class of every primitive, so `Scene.add` accepts any of them.
+#[pyclass(name = "Primitive", module = "raytracing", subclass, frozen)]
+struct PyPrimitive {
+    inner: Arc<dyn Hittable + Send + Sync>,
+}
+
+impl PyPrimitive {
+    fn new(object: impl Hittable + Send + Sync + 'static) -> PyPrimitive {
+        PyPrimitive {
+            inner: Arc::new(object),
+        }
+    }
+}
+
+#[pyclass(name = "Sphere", module = "raytracing", extends = PyPrimitive, frozen)]
+struct PySphere;
+
+#[pymethods]
+impl PySphere {
+    #[new]
+    fn new(center: PyVec3, radius: f64, material: PyMaterial) -> (PySphere, PyPrimitive) {
+        let sphere = Sphere::new(vec3(center), radius, material.inner);
+        (PySphere, PyPrimitive::new(sphere))
+    }
+}
+
+#[pyclass(name = "Plane", module = "raytracing", extends = PyPrimitive, frozen)]
+struct PyPlane;
+
+#[pymethods]
+impl PyPlane {
+    #[new]
+    fn new(point: PyVec3, normal: PyVec3, material: PyMaterial) -> (PyPlane, PyPrimitive) {
+        let plane = Plane::new(vec3(point), vec3(normal), material.inner);
+        (PyPlane, PyPrimitive::new(plane))
+    }
+}
+
+#[pyclass(name = "Triangle", module = "raytracing", extends = PyPrimitive, frozen)]
+struct PyTriangle;
+
+#[pymethods]
+impl PyTriangle {
+    #[new]
+    #[pyo3(signature = (a, b, c, material, cull_back_face = false))]
+    fn new(
+        a: PyVec3,
+        b: PyVec3,
+        c: PyVec3,
+        material: PyMaterial,
+        cull_back_face: bool,
+    ) -> (PyTriangle, PyPrimitive) {
+        let triangle = Triangle::new(vec3(a), vec3(b), vec3(c), material.inner, cull_back_face);
+        (PyTriangle, PyPrimitive::new(triangle))
+    }
+}
+
+// The fourth corner is opposite the first, as in RON scenes.
+#[pyclass(name = "Rectangle", module = "raytracing", extends = PyPrimitive, frozen)]
+struct PyRectangle;
+
+#[pymethods]
+impl PyRectangle {
+    #[new]
+    #[pyo3(signature = (a, b, c, d, material, cull_back_face = false))]
+    fn new(
+        a: PyVec3,
+        b: PyVec3,
+        c: PyVec3,
+        d: PyVec3,
+        material: PyMaterial,
+        cull_back_face: bool,
+    ) -> (PyRectangle, PyPrimitive) {
+        let rectangle = Rectangle::new(
+            vec3(a),
+            vec3(b),
+            vec3(c),
+            vec3(d),
+            material.inner,
+            cull_back_face,
+        );
+        (PyRectangle, PyPrimitive::new(rectangle))
+    }
+}
+
+#[pyclass(name = "Cube", module = "raytracing", extends = PyPrimitive, frozen)]
+struct PyCube;
+
+#[pymethods]
+impl PyCube {
+    #[new]
+    fn new(corner1: PyVec3, corner2: PyVec3, material: PyMaterial) -> (PyCube, PyPrimitive) {
+        let cube = Cube::new(vec3(corner1), vec3(corner2), material.inner);
+        (PyCube, PyPrimitive::new(cube))
+    }
+}
+
+#[pyclass(name = "Cylinder", module = "raytracing", extends = PyPrimitive, frozen)]
+struct PyCylinder;
+
+#[pymethods]
+impl PyCylinder {
+    #[new]
+    fn new(
+        base: PyVec3,
+        axis: PyVec3,
+        radius: f64,
+        height: f64,
+        material: PyMaterial,
+    ) -> (PyCylinder, PyPrimitive) {
+        let cylinder = Cylinder::new(vec3(base), vec3(axis), radius, height, material.inner);
+        (PyCylinder, PyPrimitive::new(cylinder))
+    }
+}
+
+#[pyclass(name = "Cone", module = "raytracing", extends = PyPrimitive, frozen)]
+struct PyCone;
+
+#[pymethods]
+impl PyCone {
+    #[new]
+    fn new(
+        base: PyVec3,
+        axis: PyVec3,
+        radius: f64,
+        height: f64,
+        material: PyMaterial,
+    ) -> (PyCone, PyPrimitive) {
+        let cone = Cone::new(vec3(base), vec3(axis), radius, height, material.inner);
+        (PyCone, PyPrimitive::new(cone))
+    }
+}
+
+#[pyclass(name = "Disc", module = "raytracing", extends = PyPrimitive, frozen)]
+struct PyDisc;
+
+#[pymethods]
+impl PyDisc {
+    #[new]
+    fn new(
+        center: PyVec3,
+        normal: PyVec3,
+        radius: f64,
+        material: PyMaterial,
+    ) -> (PyDisc, PyPrimitive) {
+        let disc = Disc::new(vec3(center), vec3(normal), radius, material.inner);
+        (PyDisc, PyPrimitive::new(disc))
+    }
+}
+
+#[pyclass(name = "Torus", module = "raytracing", extends = PyPrimitive, frozen)]
+struct PyTorus;
+
+#[pymethods]
+impl PyTorus {
+    #[new]
+    fn new(
+        center: PyVec3,
+        axis: PyVec3,
+        major_radius: f64,
+        minor_radius: f64,
+        material: PyMaterial,
+    ) -> (PyTorus, PyPrimitive) {
+        let torus = Torus::new(
+            vec3(center),
+            vec3(axis),
+            major_radius,
+            minor_radius,
+            material.inner,
+        );
+        (PyTorus, PyPrimitive::new(torus))
+    }
+}
+
+// PLY or STL file, vertex colors replace the material color like the RON `Mesh` object.
+#[pyclass(name = "Mesh", module = "raytracing", extends = PyPrimitive, frozen)]
+struct PyMesh;
+
+#[pymethods]
+impl PyMesh {
+    // Raises OSError when the file cannot be read and ValueError when it is not a mesh.
+    #[new]
+    fn new(path: &str, material: PyMaterial) -> PyResult<(PyMesh, PyPrimitive)> {
+        let mesh = MeshData::read(path).map_err(|e| {
+            let message = format!("failed to load mesh {}: {}", path, e);
+            match e {
+                MeshError::Io(_) => PyOSError::new_err(message),
+                MeshError::Format(_) => PyValueError::new_err(message),
+            }
+        })?;
+        Ok((
+            PyMesh,
+            PyPrimitive::new(Bvh::new(mesh.triangles(&material.inner))),
+        ))
+    }
+}
+
+// Mutable counterpart of `Scene`, primitives are shared so it can be rendered repeatedly.
+#[pyclass(name = "Scene", module = "raytracing")]
+struct PyScene {
+    settings: RenderSettings,
+    camera: Option<Arc<dyn Camera>>,
+    objects: Vec<Arc<dyn Hittable + Send + Sync>>,
+    lights: Vec<Vec3>,
+}
+
+#[pymethods]
+impl PyScene {
+    // Raises ValueError unless the image has a positive width and height.
+    #[new]
+    #[pyo3(signature = (width = 400, height = 225, samples_per_pixel = 100, max_depth = 50, multithreading = true))]
+    fn new(
+        width: i32,
+        height: i32,
+        samples_per_pixel: usize,
+        max_depth: i32,
+        multithreading: bool,
+    ) -> PyResult<PyScene> {
+        if width <= 0 || height <= 0 {
+            return Err(PyValueError::new_err(format!(
+                "image size must be positive, got {}x{}",
+                width, height
+            )));
+        }
+        Ok(PyScene {
+            settings: RenderSettings {
+                image_width: width,
+                image_height: height,
+                samples_per_pixel,
+                max_depth,
+                multithreading,
+            },
+            camera: None,
+            objects: vec![],
+            lights: vec![],
+        })
+    }
+
+    #[pyo3(signature = (look_from, look_at, v_up = [0.0, 1.0, 0.0], v_fov = 90.0, aperture = 0.0, focus_distance = 1.0))]
+    fn camera(
+        &mut self,
+        look_from: PyVec3,
+        look_at: PyVec3,
+        v_up: PyVec3,
+        v_fov: f64,
+        aperture: f64,
+        focus_distance: f64,
+    ) {
+        self.camera = Some(Arc::new(PerspectiveCamera::new(
+            vec3(look_from),
+            vec3(look_at),
+            vec3(v_up),
+            v_fov,
+            self.settings.aspect_ratio(),
+            aperture,
+            focus_distance,
+        )));
+    }
+
+    fn add(&mut self, primitive: PyRef<'_, PyPrimitive>) {
+        self.objects.push(primitive.inner.clone());
+    }
+
+    fn light(&mut self, position: PyVec3) {
+        self.lights.push(vec3(position));
+    }
+
+    // The GIL is released while rendering.
+    fn render<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyArray3<u8>>> {
+        let mut builder = Scene::builder().settings(self.settings);
+        if let Some(camera) = &self.camera {
+            builder = builder.camera(camera.clone());
+        }
+        for object in &self.objects {
+            builder = builder.add(object.clone());
+        }
+        for light in &self.lights {
+            builder = builder.light(*light);
+        }
+        let scene = builder.build();
+        let rgb = py.detach(|| scene.render_rgb(&RenderControl::default()));
+        image_array(py, rgb, self.settings)
+    }
+}
+
+// RON scene string rendered like `create_image`. Scenes are built by code that panics on bad
+// input, so those panics are raised as ValueError with their message.
+#[pyfunction]
+fn render_ron(py: Python<'_>, ron_string: String) -> PyResult<Bound<'_, PyArray3<u8>>> {
+    let scene = py
+        .detach(|| catch_unwind(AssertUnwindSafe(|| Scene::from_ron(ron_string))))
+        .map_err(|payload| {
+            let message = payload
+                .downcast_ref::<String>()
+                .map(String::as_str)
+                .or_else(|| payload.downcast_ref::<&str>().copied())
+                .unwrap_or("invalid scene");
+            PyValueError::new_err(message.to_string())
+        })?;
+    let settings = scene.settings();
+    let rgb = py.detach(|| scene.render_rgb(&RenderControl::default()));
+    image_array(py, rgb, settings)
+}
+
+// Raises OSError when the scene file cannot be read and ValueError for mistakes inside it,
+// including includes that cannot be read, at the file and line they were made.
+#[pyfunction]
+fn load_scene(path: &str) -> PyResult<String> {
+    let scene = scene_file::load_scene(path).map_err(|e| {
+        if e.line == 0 {
+            PyOSError::new_err(e.to_string())
+        } else {
+            PyValueError::new_err(e.to_string())
+        }
+    })?;
+    Ok(scene.to_ron())
+}
+
+#[pymodule]
+fn raytracing(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyMaterial>()?;
+    m.add_class::<PyPrimitive>()?;
+    m.add_class::<PySphere>()?;
+    m.add_class::<PyPlane>()?;
+    m.add_class::<PyTriangle>()?;
+    m.add_class::<PyRectangle>()?;
+    m.add_class::<PyCube>()?;
+    m.add_class::<PyCylinder>()?;
+    m.add_class::<PyCone>()?;
+    m.add_class::<PyDisc>()?;
+    m.add_class::<PyTorus>()?;
+    m.add_class::<PyMesh>()?;
+    m.add_class::<PyScene>()?;
+    m.add_function(wrap_pyfunction!(render_ron, m)?)?;
+    m.add_function(wrap_pyfunction!(load_scene, m)?)?;
+    Ok(())
+}
@@ -1,24 +1,32 @@
 use crate::vec3::Vec3;
 
+/// Half line from `origin` along `direction`, which is not necessarily a unit vector.
 #[derive(Debug, Copy, Clone)]
 pub struct Ray {
     origin: Vec3,
     direction: Vec3,
+    time: f64,
 }
 
 impl Ray {
-    pub fn new(orig: Vec3, dir: Vec3) -> Ray {
+    pub fn new(orig: Vec3, dir: Vec3, time: f64) -> Ray {
         Ray {
             origin: orig,
             direction: dir,
+            time,
         }
     }
     pub fn origin(&self) -> Vec3 {
-        self.origin.clone()
+        self.origin
     }
     pub fn direction(&self) -> Vec3 {
-        self.direction.clone()
+        self.direction
     }
+    /// Moment within the shutter interval the ray samples, used by moving objects.
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+    /// Point `t` directions along the ray.
     pub fn at(&self, t: f64) -> Vec3 {
         self.origin + self.direction * t
     }
@@ -0,0 +1,117 @@
+use crate::aabb::Aabb;
+use crate::hittable;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::scene_export::SceneWriter;
+use crate::triangle::Triangle;
+use crate::vec3::Vec3;
+
+/// Quad given by its corners in the order bottom-left, bottom-right, top-left, top-right,
+/// split into two triangles with uvs spanning [0, 1].
+pub struct Rectangle {
+    triangles: [Triangle; 2],
+}
+
+impl Rectangle {
+    pub fn new(
+        point1: Vec3,
+        point2: Vec3,
+        point3: Vec3,
+        point4: Vec3,
+        mat: Material,
+        cull_back_face: bool,
+    ) -> Rectangle {
+        Rectangle {
+            triangles: [
+                Triangle::new(point1, point2, point4, mat.clone(), cull_back_face).with_uvs([
+                    (0.0, 0.0),
+                    (1.0, 0.0),
+                    (1.0, 1.0),
+                ]),
+                Triangle::new(point1, point4, point3, mat, cull_back_face).with_uvs([
+                    (0.0, 0.0),
+                    (1.0, 1.0),
+                    (0.0, 1.0),
+                ]),
+            ],
+        }
+    }
+}
+
+impl hittable::Hittable for Rectangle {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, rec: &mut hittable::HitRecord) -> bool {
+        let mut closest = t_max;
+        let mut hit_anything = false;
+        for triangle in &self.triangles {
+            if triangle.hit(ray, t_min, closest, rec) {
+                closest = rec.t.unwrap();
+                hit_anything = true;
+            }
+        }
+        hit_anything
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let first = self.triangles[0].bounding_box()?;
+        Some(first.surrounding(&self.triangles[1].bounding_box()?))
+    }
+
+    // Written as the two triangles of an inline mesh.
+    fn export(&self, writer: &mut SceneWriter) {
+        for triangle in &self.triangles {
+            hittable::Hittable::export(triangle, writer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::{HitRecord, Hittable};
+
+    fn square(cull_back_face: bool) -> Rectangle {
+        Rectangle::new(
+            Vec3::new(-1.0, -1.0, 0.0),
+            Vec3::new(1.0, -1.0, 0.0),
+            Vec3::new(-1.0, 1.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            Material::Mirror,
+            cull_back_face,
+        )
+    }
+
+    fn cast(rectangle: &Rectangle, x: f64, y: f64, from: f64) -> Option<HitRecord> {
+        let mut rec = HitRecord::new();
+        let ray = Ray::new(
+            Vec3::new(x, y, from),
+            Vec3::new(0.0, 0.0, -from.signum()),
+            0.0,
+        );
+        rectangle
+            .hit(ray, 0.001, f64::INFINITY, &mut rec)
+            .then_some(rec)
+    }
+
+    #[test]
+    fn uvs_span_both_triangles() {
+        let square = square(false);
+        for (x, y, uv) in [
+            (0.0, 0.0, (0.5, 0.5)),
+            (0.5, -0.5, (0.75, 0.25)),
+            (-0.5, 0.5, (0.25, 0.75)),
+        ] {
+            let rec = cast(&square, x, y, 2.0).unwrap();
+            let (u, v) = rec.uv.unwrap();
+            assert!((u - uv.0).abs() < 1e-9 && (v - uv.1).abs() < 1e-9);
+            assert_eq!(rec.normal, Some(Vec3::new(0.0, 0.0, 1.0)));
+        }
+        assert!(cast(&square, 1.5, 0.0, 2.0).is_none());
+    }
+
+    #[test]
+    fn back_faces_are_culled_on_request() {
+        assert!(cast(&square(false), 0.3, 0.2, -2.0).is_some());
+        assert!(cast(&square(true), 0.3, 0.2, -2.0).is_none());
+        assert!(cast(&square(true), 0.3, 0.2, 2.0).is_some());
+    }
+}
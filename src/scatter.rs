@@ -0,0 +1,373 @@
+use crate::curve::{Curve, CurveShape};
+use crate::hittable::Hittable;
+use crate::material::Material;
+use crate::matrix::Mat4;
+use crate::transform::Transform;
+use crate::vec3::Vec3;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+// Point sampled on a surface with the surface normal there, which follows the triangle winding.
+#[derive(Debug, Copy, Clone)]
+pub struct SurfacePoint {
+    pub p: Vec3,
+    pub normal: Vec3,
+}
+
+// Uniformly distributed points, `density` per unit of area on average.
+pub fn sample_surface(
+    triangles: &[[Vec3; 3]],
+    density: f64,
+    rng: &mut StdRng,
+) -> Vec<SurfacePoint> {
+    let mut points = vec![];
+    for [a, b, c] in triangles {
+        let cross = (*b - *a).cross(*c - *a);
+        let expected = 0.5 * cross.length() * density;
+        // The fractional part is rounded randomly so small triangles still get their share.
+        let mut count = expected.floor() as usize;
+        if rng.gen::<f64>() < expected.fract() {
+            count += 1;
+        }
+        let normal = cross.unit_vector();
+        for _ in 0..count {
+            let (mut u, mut v) = (rng.gen::<f64>(), rng.gen::<f64>());
+            if u + v > 1.0 {
+                u = 1.0 - u;
+                v = 1.0 - v;
+            }
+            points.push(SurfacePoint {
+                p: *a + (*b - *a) * u + (*c - *a) * v,
+                normal,
+            });
+        }
+    }
+    points
+}
+
+// Blades or strands grown from a surface. Lengths vary by up to a third either way and each
+// curve leans `bend` of its length in a random direction.
+#[derive(Debug, Clone)]
+pub struct CurveScatter {
+    pub density: f64,
+    pub seed: u32,
+    pub length: f64,
+    pub root_width: f64,
+    pub tip_width: f64,
+    pub bend: f64,
+    pub shape: CurveShape,
+}
+
+impl CurveScatter {
+    pub fn scatter(
+        &self,
+        triangles: &[[Vec3; 3]],
+        mat: &Material,
+    ) -> Vec<Box<dyn Hittable + Send + Sync>> {
+        let mut rng = StdRng::seed_from_u64(self.seed as u64);
+        let roots = sample_surface(triangles, self.density, &mut rng);
+        roots
+            .into_iter()
+            .map(|root| {
+                let length = self.length * rng.gen_range(0.67..1.33);
+                let lean = random_perpendicular(root.normal, &mut rng) * (self.bend * length);
+                let up = root.normal * length;
+                let control = [
+                    root.p,
+                    root.p + up * (1.0 / 3.0),
+                    root.p + up * (2.0 / 3.0) + lean * (1.0 / 3.0),
+                    root.p + up * (1.0 - 0.3 * self.bend) + lean,
+                ];
+                Box::new(Curve::new(
+                    control,
+                    self.root_width,
+                    self.tip_width,
+                    self.shape,
+                    mat.clone(),
+                )) as Box<dyn Hittable + Send + Sync>
+            })
+            .collect()
+    }
+}
+
+// Copies of a shared geometry spread over a surface. Candidates are drawn at `density` per unit
+// of area, dropped outside the slope (degrees from +y) and altitude ranges, then thinned by dart
+// throwing so no two are closer than `spacing`.
+#[derive(Debug, Clone)]
+pub struct InstanceScatter {
+    pub density: f64,
+    pub seed: u32,
+    pub spacing: f64,
+    pub slope: (f64, f64),
+    pub altitude: (f64, f64),
+    // Largest random turn about the up axis in degrees, and the range of uniform scales.
+    pub rotation_jitter: f64,
+    pub scale: (f64, f64),
+    // Tilt instances onto the surface normal instead of keeping them upright.
+    pub align_to_normal: bool,
+}
+
+impl InstanceScatter {
+    pub fn scatter(
+        &self,
+        triangles: &[[Vec3; 3]],
+        geometry: &Arc<dyn Hittable + Send + Sync>,
+    ) -> Vec<Box<dyn Hittable + Send + Sync>> {
+        let mut rng = StdRng::seed_from_u64(self.seed as u64);
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let mut candidates: Vec<SurfacePoint> = sample_surface(triangles, self.density, &mut rng)
+            .into_iter()
+            .filter(|point| {
+                let slope = point.normal.dot(up).clamp(-1.0, 1.0).acos().to_degrees();
+                let altitude = point.p.y();
+                slope >= self.slope.0
+                    && slope <= self.slope.1
+                    && altitude >= self.altitude.0
+                    && altitude <= self.altitude.1
+            })
+            .collect();
+        // Dart throwing in surface order would favour the first triangles.
+        candidates.shuffle(&mut rng);
+
+        let mut accepted = PoissonGrid::new(self.spacing);
+        candidates
+            .into_iter()
+            .filter(|point| accepted.insert(point.p))
+            .map(|point| {
+                let axis = if self.align_to_normal {
+                    point.normal
+                } else {
+                    up
+                };
+                let turn = rng.gen_range(-1.0..=1.0) * self.rotation_jitter;
+                let scale = rng.gen_range(self.scale.0..=self.scale.1);
+                let matrix = Mat4::translation(point.p)
+                    * align_up(axis)
+                    * Mat4::rotation_y(turn)
+                    * Mat4::scale(Vec3::new(scale, scale, scale));
+                Box::new(Transform::new(geometry.clone(), matrix))
+                    as Box<dyn Hittable + Send + Sync>
+            })
+            .collect()
+    }
+}
+
+// Accepted points hashed into cells of the minimum spacing, so each insertion only checks the
+// neighbouring cells.
+struct PoissonGrid {
+    spacing: f64,
+    cells: HashMap<(i64, i64, i64), Vec<Vec3>>,
+}
+
+impl PoissonGrid {
+    fn new(spacing: f64) -> PoissonGrid {
+        PoissonGrid {
+            spacing,
+            cells: HashMap::new(),
+        }
+    }
+
+    fn cell(&self, p: Vec3) -> (i64, i64, i64) {
+        (
+            (p.x() / self.spacing).floor() as i64,
+            (p.y() / self.spacing).floor() as i64,
+            (p.z() / self.spacing).floor() as i64,
+        )
+    }
+
+    // Keeps the point unless it is within the spacing of one kept before.
+    fn insert(&mut self, p: Vec3) -> bool {
+        if self.spacing <= 0.0 {
+            return true;
+        }
+        let (x, y, z) = self.cell(p);
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    if let Some(points) = self.cells.get(&(x + dx, y + dy, z + dz)) {
+                        if points.iter().any(|q| (*q - p).length() < self.spacing) {
+                            return false;
+                        }
+                    }
+                }
+            }
+        }
+        self.cells.entry((x, y, z)).or_default().push(p);
+        true
+    }
+}
+
+// Rotation taking +y onto `axis`.
+fn align_up(axis: Vec3) -> Mat4 {
+    let helper = if axis.x().abs() > 0.9 {
+        Vec3::new(0.0, 0.0, 1.0)
+    } else {
+        Vec3::new(1.0, 0.0, 0.0)
+    };
+    let z = helper.cross(axis).unit_vector();
+    let x = axis.cross(z);
+    Mat4::from_basis(x, axis, z)
+}
+
+// Unit vector at a random angle around `normal`.
+fn random_perpendicular(normal: Vec3, rng: &mut StdRng) -> Vec3 {
+    let helper = if normal.x().abs() > 0.9 {
+        Vec3::new(0.0, 1.0, 0.0)
+    } else {
+        Vec3::new(1.0, 0.0, 0.0)
+    };
+    let tangent = normal.cross(helper).unit_vector();
+    let bitangent = normal.cross(tangent);
+    let angle = rng.gen_range(0.0..std::f64::consts::TAU);
+    tangent * angle.cos() + bitangent * angle.sin()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Unit square in the xz plane facing +y.
+    fn square() -> Vec<[Vec3; 3]> {
+        let corner = |x: f64, z: f64| Vec3::new(x, 0.0, z);
+        vec![
+            [corner(0.0, 0.0), corner(0.0, 1.0), corner(1.0, 0.0)],
+            [corner(1.0, 0.0), corner(0.0, 1.0), corner(1.0, 1.0)],
+        ]
+    }
+
+    #[test]
+    fn samples_cover_the_surface_at_the_density() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let points = sample_surface(&square(), 1000.0, &mut rng);
+        assert_eq!(points.len(), 1000);
+        for point in &points {
+            assert!(point.p.y() == 0.0);
+            assert!((0.0..=1.0).contains(&point.p.x()) && (0.0..=1.0).contains(&point.p.z()));
+            assert!((point.normal - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-12);
+        }
+        // Both halves get their share.
+        let below_diagonal = points.iter().filter(|s| s.p.x() + s.p.z() < 1.0).count();
+        assert!((400..600).contains(&below_diagonal), "{}", below_diagonal);
+
+        // Fractional counts are rounded up as often as they ask for.
+        let sparse: usize = (0..200)
+            .map(|_| sample_surface(&square(), 0.5, &mut rng).len())
+            .sum();
+        assert!((70..130).contains(&sparse), "{}", sparse);
+    }
+
+    #[test]
+    fn curves_grow_from_the_surface_along_its_normal() {
+        let scatter = CurveScatter {
+            density: 50.0,
+            seed: 4,
+            length: 1.0,
+            root_width: 0.1,
+            tip_width: 0.0,
+            bend: 0.3,
+            shape: CurveShape::Ribbon,
+        };
+        let curves = scatter.scatter(&square(), &Material::Mirror);
+        assert_eq!(curves.len(), 50);
+        for curve in &curves {
+            let bounds = curve.bounding_box().unwrap();
+            // Roots sit on the square and tips reach up to a third past the length.
+            assert!(bounds.min.y() >= -0.05 - 1e-9 && bounds.min.y() <= 1e-9);
+            assert!(bounds.max.y() > 0.67 * 0.7 && bounds.max.y() <= 1.33 + 0.05 + 1e-9);
+            assert!(bounds.min.x() > -0.5 && bounds.max.x() < 1.5);
+        }
+
+        // The same seed grows the same curves.
+        let again = scatter.scatter(&square(), &Material::Mirror);
+        for (a, b) in curves.iter().zip(&again) {
+            let (a, b) = (a.bounding_box().unwrap(), b.bounding_box().unwrap());
+            assert!((a.min - b.min).length() == 0.0 && (a.max - b.max).length() == 0.0);
+        }
+    }
+
+    // Flat ground at y = 0, a 45 degree ramp up to y = 1 and a flat plateau at y = 2.
+    fn terrain() -> Vec<[Vec3; 3]> {
+        let quad = |a: Vec3, b: Vec3, c: Vec3, d: Vec3| vec![[a, b, c], [c, b, d]];
+        let p = Vec3::new;
+        let mut triangles = square();
+        triangles.extend(quad(
+            p(2.0, 0.0, 0.0),
+            p(2.0, 0.0, 1.0),
+            p(3.0, 1.0, 0.0),
+            p(3.0, 1.0, 1.0),
+        ));
+        triangles.extend(quad(
+            p(5.0, 2.0, 0.0),
+            p(5.0, 2.0, 1.0),
+            p(6.0, 2.0, 0.0),
+            p(6.0, 2.0, 1.0),
+        ));
+        triangles
+    }
+
+    fn placed(slope: (f64, f64), altitude: (f64, f64), spacing: f64) -> Vec<Vec3> {
+        let scatter = InstanceScatter {
+            density: 400.0,
+            seed: 9,
+            spacing,
+            slope,
+            altitude,
+            rotation_jitter: 180.0,
+            scale: (0.5, 2.0),
+            align_to_normal: false,
+        };
+        let pebble: Arc<dyn Hittable + Send + Sync> = Arc::new(crate::sphere::Sphere::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            0.01,
+            Material::Mirror,
+        ));
+        scatter
+            .scatter(&terrain(), &pebble)
+            .iter()
+            .map(|instance| instance.bounding_box().unwrap().centroid())
+            .collect()
+    }
+
+    const ANY_SLOPE: (f64, f64) = (0.0, 180.0);
+    const ANY_ALTITUDE: (f64, f64) = (f64::NEG_INFINITY, f64::INFINITY);
+
+    #[test]
+    fn instances_outside_the_slope_and_altitude_ranges_are_dropped() {
+        // Two flat squares and a ramp of area sqrt(2).
+        let everywhere = placed(ANY_SLOPE, ANY_ALTITUDE, 0.0);
+        assert!((1364..=1366).contains(&everywhere.len()));
+
+        let flat = placed((0.0, 30.0), ANY_ALTITUDE, 0.0);
+        assert_eq!(flat.len(), 800);
+        assert!(flat
+            .iter()
+            .all(|p| p.y().abs() < 1e-9 || (p.y() - 2.0).abs() < 1e-9));
+
+        let steep = placed((30.0, 60.0), ANY_ALTITUDE, 0.0);
+        assert!(steep.len() > 500);
+        assert!(steep.iter().all(|p| (p.x() - 2.0 - p.y()).abs() < 1e-9));
+
+        let high = placed(ANY_SLOPE, (1.5, f64::INFINITY), 0.0);
+        assert_eq!(high.len(), 400);
+        assert!(high.iter().all(|p| (p.y() - 2.0).abs() < 1e-9));
+
+        let low = placed(ANY_SLOPE, (f64::NEG_INFINITY, 0.5), 0.0);
+        assert!(low.iter().all(|p| p.y() <= 0.5 && p.x() < 2.5));
+    }
+
+    #[test]
+    fn instances_keep_the_poisson_spacing() {
+        let points = placed(ANY_SLOPE, ANY_ALTITUDE, 0.15);
+        for (i, a) in points.iter().enumerate() {
+            for b in &points[i + 1..] {
+                assert!((*a - *b).length() >= 0.15);
+            }
+        }
+        // Dense candidates leave few gaps a disk of that size would fit in.
+        assert!(points.len() > 60, "{}", points.len());
+        assert!(points.len() < 1364);
+    }
+}
@@ -0,0 +1,287 @@
+use crate::bvh::Bvh;
+use crate::camera::{Camera, PerspectiveCamera};
+use crate::configuration::RaytracerScene;
+use crate::hittable::Hittable;
+use crate::hittables::Hittables;
+use crate::scene_export::{describe_scene, scene_to_gltf};
+use crate::stats::RenderStats;
+use crate::vec3::Vec3;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Image size and sampling of a render. Defaults to 400x225 with 100 samples per pixel.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RenderSettings {
+    pub image_width: i32,
+    pub image_height: i32,
+    pub samples_per_pixel: usize,
+    pub max_depth: i32,
+    pub multithreading: bool,
+}
+
+impl RenderSettings {
+    pub fn aspect_ratio(&self) -> f64 {
+        self.image_width as f64 / self.image_height as f64
+    }
+}
+
+impl Default for RenderSettings {
+    fn default() -> RenderSettings {
+        RenderSettings {
+            image_width: 400,
+            image_height: 225,
+            samples_per_pixel: 100,
+            max_depth: 50,
+            multithreading: true,
+        }
+    }
+}
+
+impl From<&RaytracerScene> for RenderSettings {
+    fn from(settings: &RaytracerScene) -> RenderSettings {
+        RenderSettings {
+            image_width: settings.image_width,
+            image_height: settings.image_height,
+            samples_per_pixel: settings.samples_per_pixel,
+            max_depth: settings.max_depth,
+            multithreading: settings.multithreading,
+        }
+    }
+}
+
+type ProgressFn = dyn FnMut(f64) + Send;
+
+/// Progress reporting and cancellation of a render. Clones share the same state, so a render can
+/// be cancelled from another thread. Progress is the finished fraction of rows, reported from the
+/// rendering threads one call at a time.
+#[derive(Clone, Default)]
+pub struct RenderControl {
+    cancelled: Arc<AtomicBool>,
+    progress: Option<Arc<Mutex<ProgressFn>>>,
+}
+
+impl RenderControl {
+    pub fn new() -> RenderControl {
+        RenderControl::default()
+    }
+
+    /// Calls `progress` with the finished fraction of the image as rows complete.
+    pub fn on_progress(mut self, progress: impl FnMut(f64) + Send + 'static) -> RenderControl {
+        self.progress = Some(Arc::new(Mutex::new(progress)));
+        self
+    }
+
+    /// Stops the render soon after, leaving the pixels not yet rendered black.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn report(&self, fraction: f64) {
+        if let Some(progress) = &self.progress {
+            (progress.lock().unwrap())(fraction);
+        }
+    }
+}
+
+/// Objects, lights and camera ready to render, built in Rust instead of from RON:
+///
+/// ```
+/// use raytracing::{Material, PerspectiveCamera, Scene, SolidColor, Sphere, Vec3};
+/// use std::sync::Arc;
+///
+/// let material = Material::Lambertian(Arc::new(SolidColor::new(Vec3::new(0.8, 0.1, 0.1))));
+/// let from = Vec3::new(0.0, 0.0, 1.0);
+/// let at = Vec3::new(0.0, 0.0, -1.0);
+/// let up = Vec3::new(0.0, 1.0, 0.0);
+/// let image = Scene::builder()
+///     .image_size(32, 18)
+///     .samples_per_pixel(4)
+///     .camera(PerspectiveCamera::new(from, at, up, 40.0, 16.0 / 9.0, 0.0, 1.0))
+///     .add(Sphere::new(Vec3::new(0.0, 0.0, -1.0), 0.5, material))
+///     .light(Vec3::new(0.0, 5.0, 0.0))
+///     .build()
+///     .render();
+/// assert_eq!(image.len(), 32 * 18);
+/// ```
+pub struct Scene {
+    settings: RenderSettings,
+    camera: Box<dyn Camera>,
+    world: Hittables,
+}
+
+impl Scene {
+    pub fn builder() -> SceneBuilder {
+        SceneBuilder::default()
+    }
+
+    /// Builds the scene described by a RON string, as `create_image` does.
+    pub fn from_ron(ron_string: String) -> Scene {
+        let settings = RaytracerScene::from_ron(ron_string);
+        let (camera, world) = crate::build_scene(&settings);
+        Scene {
+            settings: RenderSettings::from(&settings),
+            camera,
+            world,
+        }
+    }
+
+    pub fn settings(&self) -> RenderSettings {
+        self.settings
+    }
+
+    /// The scene as RON that `from_ron` loads back: settings, camera, lights and every object,
+    /// with meshes and heightfields written inline. Objects without a RON description, such as
+    /// custom `Hittable`s, are reported on stderr and left out.
+    pub fn to_ron(&self) -> String {
+        self.describe().to_ron()
+    }
+
+    /// glTF 2.0 document of the scene with every surface tessellated into triangles.
+    pub fn to_gltf(&self) -> String {
+        scene_to_gltf(&self.describe())
+    }
+
+    fn describe(&self) -> RaytracerScene {
+        describe_scene(self.settings, self.camera.as_ref(), &self.world)
+    }
+
+    /// One RGB row-major pixel per entry, like `create_image`.
+    pub fn render(self) -> Vec<Vec<u8>> {
+        self.render_with(&RenderControl::default())
+    }
+
+    pub fn render_with(self, control: &RenderControl) -> Vec<Vec<u8>> {
+        self.render_with_stats(control).0
+    }
+
+    /// The image along with ray counts, timings and acceleration structure memory of the render.
+    pub fn render_with_stats(self, control: &RenderControl) -> (Vec<Vec<u8>>, RenderStats) {
+        let (rgb, stats) = self.render_rgb_with_stats(control);
+        (crate::rgb_pixels(rgb), stats)
+    }
+
+    /// The image as one contiguous buffer of RGB rows top to bottom, 3 bytes per pixel, ready to
+    /// hand to image libraries or arrays without copying each pixel.
+    pub fn render_rgb(self, control: &RenderControl) -> Vec<u8> {
+        self.render_rgb_with_stats(control).0
+    }
+
+    pub fn render_rgb_with_stats(self, control: &RenderControl) -> (Vec<u8>, RenderStats) {
+        crate::render(self.settings, self.camera, self.world, control)
+    }
+
+    /// Renders the pixels from (x, y) to (x + width, y + height), clipped to the image, on the
+    /// calling thread into `rgba`, which holds the whole image at 4 bytes per pixel like a canvas
+    /// `ImageData`. Alpha is always opaque.
+    pub fn render_tile(&self, x: i32, y: i32, width: i32, height: i32, rgba: &mut [u8]) {
+        let image_width = self.settings.image_width;
+        assert!(
+            rgba.len() >= image_width as usize * self.settings.image_height as usize * 4,
+            "RGBA buffer smaller than the image"
+        );
+        for j in y.max(0)..(y + height).min(self.settings.image_height) {
+            for i in x.max(0)..(x + width).min(image_width) {
+                let color = crate::sample_pixel(
+                    &self.settings,
+                    i as f64,
+                    j,
+                    self.camera.as_ref(),
+                    &self.world,
+                );
+                let rgb = color.to_rgb(self.settings.samples_per_pixel);
+                let at = (j * image_width + i) as usize * 4;
+                rgba[at..at + 3].copy_from_slice(&rgb[..3]);
+                rgba[at + 3] = 255;
+            }
+        }
+    }
+}
+
+/// Collects the settings, camera, objects and lights of a [`Scene`], see [`Scene::builder`].
+#[derive(Default)]
+pub struct SceneBuilder {
+    settings: RenderSettings,
+    camera: Option<Box<dyn Camera>>,
+    objects: Vec<Box<dyn Hittable + Send + Sync>>,
+    lights: Vec<Vec3>,
+}
+
+impl SceneBuilder {
+    pub fn settings(mut self, settings: RenderSettings) -> SceneBuilder {
+        self.settings = settings;
+        self
+    }
+
+    pub fn image_size(mut self, width: i32, height: i32) -> SceneBuilder {
+        self.settings.image_width = width;
+        self.settings.image_height = height;
+        self
+    }
+
+    pub fn samples_per_pixel(mut self, samples: usize) -> SceneBuilder {
+        self.settings.samples_per_pixel = samples;
+        self
+    }
+
+    pub fn max_depth(mut self, depth: i32) -> SceneBuilder {
+        self.settings.max_depth = depth;
+        self
+    }
+
+    pub fn multithreading(mut self, enabled: bool) -> SceneBuilder {
+        self.settings.multithreading = enabled;
+        self
+    }
+
+    pub fn camera(mut self, camera: impl Camera + 'static) -> SceneBuilder {
+        self.camera = Some(Box::new(camera));
+        self
+    }
+
+    /// Any primitive, or a custom type implementing `Hittable`.
+    #[allow(clippy::should_implement_trait)]
+    pub fn add(mut self, object: impl Hittable + Send + Sync + 'static) -> SceneBuilder {
+        self.objects.push(Box::new(object));
+        self
+    }
+
+    pub fn add_boxed(mut self, object: Box<dyn Hittable + Send + Sync>) -> SceneBuilder {
+        self.objects.push(object);
+        self
+    }
+
+    /// Point light casting shadows onto the scene.
+    pub fn light(mut self, position: Vec3) -> SceneBuilder {
+        self.lights.push(position);
+        self
+    }
+
+    /// Objects are put in one BVH. Without a camera the view looks down -z from the origin with
+    /// a 90 degree field of view.
+    pub fn build(self) -> Scene {
+        let settings = self.settings;
+        let camera = self.camera.unwrap_or_else(|| {
+            Box::new(PerspectiveCamera::new(
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, -1.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                90.0,
+                settings.aspect_ratio(),
+                0.0,
+                1.0,
+            ))
+        });
+        Scene {
+            settings,
+            camera,
+            world: Hittables {
+                lights: self.lights,
+                hittables: vec![Box::new(Bvh::new(self.objects))],
+            },
+        }
+    }
+}
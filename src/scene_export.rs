@@ -0,0 +1,1436 @@
+use crate::camera::Camera;
+use crate::configuration::{
+    RaytracerScene, RonGeometry, RonMesh, RonObject, RonTexture, RonTransform,
+};
+use crate::curve::bezier;
+use crate::frame::Frame;
+use crate::heightfield::grid_triangles;
+use crate::hittables::Hittables;
+use crate::material::Material;
+use crate::matrix::Mat4;
+use crate::mesh_file::MeshData;
+use crate::scene::RenderSettings;
+use crate::sdf::SdfNode;
+use crate::texture::TextureRef;
+use crate::transform::Trs;
+use crate::vec3::Vec3;
+use crate::{conv_py_vec, parse_ron_sdf, parse_ron_textures, ron_heightfield_grid, ron_matrix};
+use base64::Engine;
+use serde_json::{json, Value};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+
+const BARYCENTRIC_UVS: [(f64, f64); 3] = [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)];
+
+pub fn ron_vector(v: Vec3) -> Vec<f64> {
+    vec![v.x(), v.y(), v.z()]
+}
+
+pub(crate) fn ron_trs(trs: &Trs) -> RonTransform {
+    RonTransform {
+        translate: ron_vector(trs.translate),
+        rotate: ron_vector(trs.rotate),
+        scale: ron_vector(trs.scale),
+        matrix: None,
+    }
+}
+
+pub(crate) fn ron_placement(matrix: &Mat4) -> RonTransform {
+    RonTransform {
+        matrix: Some(matrix.m.iter().map(|row| row.to_vec()).collect()),
+        ..RonTransform::default()
+    }
+}
+
+fn ron_number(value: f64) -> String {
+    format!("{}", value)
+}
+
+// Built scene being written back out as RON, see `Scene::to_ron`. Objects, textures and cameras
+// describe themselves through their `export` methods. Triangles are gathered into inline meshes
+// per node, geometry held by several owners becomes a shared geometry, and whatever cannot be
+// described is reported when the scene is finished instead of being dropped silently.
+pub struct SceneWriter {
+    pub(crate) scene: RaytracerScene,
+    objects: Vec<RonObject>,
+    // Positions in `objects` of the inline meshes of the node being written.
+    meshes: Vec<usize>,
+    textures: HashMap<usize, String>,
+    geometries: HashMap<usize, String>,
+    vertex_colors: Option<[Vec3; 3]>,
+    skipped: BTreeSet<String>,
+}
+
+impl SceneWriter {
+    fn new(settings: RenderSettings) -> SceneWriter {
+        SceneWriter {
+            scene: RaytracerScene {
+                multithreading: settings.multithreading,
+                aspect_ratio: settings.aspect_ratio(),
+                image_width: settings.image_width,
+                image_height: settings.image_height,
+                samples_per_pixel: settings.samples_per_pixel,
+                max_depth: settings.max_depth,
+                v_fov: 90.0,
+                aperture: 0.0,
+                focal_distance: 1.0,
+                camera_pos: vec![0.0, 0.0, 0.0],
+                camera_dir: vec![0.0, 0.0, -1.0],
+                camera_up: vec![0.0, 1.0, 0.0],
+                camera_type: "Perspective".to_string(),
+                ortho_height: 0.0,
+                physical_camera: None,
+                autofocus: false,
+                bokeh: None,
+                shutter_open: 0.0,
+                shutter_close: 0.0,
+                exposure: 1.0,
+                objects: vec![],
+                lights: vec![],
+                textures: vec![],
+                geometries: vec![],
+                materials: vec![],
+                animation: None,
+            },
+            objects: vec![],
+            meshes: vec![],
+            textures: HashMap::new(),
+            geometries: HashMap::new(),
+            vertex_colors: None,
+            skipped: BTreeSet::new(),
+        }
+    }
+
+    // Notes something the RON format has no description for, e.g. a custom `Hittable`.
+    pub fn skip(&mut self, what: &str) {
+        self.skipped.insert(what.to_string());
+    }
+
+    pub(crate) fn object(&mut self, object: RonObject) {
+        self.objects.push(object);
+    }
+
+    // Object given by its RON type, vectors, scalars and material, like `Sphere`. Returns it for
+    // any further fields.
+    pub fn primitive(
+        &mut self,
+        objtype: &str,
+        vectors: &[Vec3],
+        scalars: &[f64],
+        material: &Material,
+    ) -> &mut RonObject {
+        let material = self.material(material);
+        self.object(RonObject {
+            vectors: vectors.iter().map(|v| ron_vector(*v)).collect(),
+            scalars: scalars.to_vec(),
+            material,
+            ..RonObject::new(objtype)
+        });
+        self.objects.last_mut().unwrap()
+    }
+
+    // Adds a triangle to the inline mesh of the current node that shares its material and
+    // attributes. Vertex colors in the material's color slot become colors of the mesh.
+    pub fn triangle(
+        &mut self,
+        points: [Vec3; 3],
+        uvs: [(f64, f64); 3],
+        normals: Option<[Vec3; 3]>,
+        material: &Material,
+        cull_back: bool,
+    ) {
+        let material = self.material(material);
+        let colors = self.vertex_colors.take();
+        // A mesh holds colors or uvs but not both, see `RonMesh`, so the uvs of vertex colored
+        // triangles are reported and left out.
+        let custom_uvs = uvs != BARYCENTRIC_UVS && colors.is_none();
+        if uvs != BARYCENTRIC_UVS && colors.is_some() {
+            self.skip("uvs of vertex colored triangles");
+        }
+        let matches = |object: &RonObject| {
+            let mesh = object.mesh.as_ref().unwrap();
+            object.material == material
+                && mesh.cull_back == cull_back
+                && mesh.normals.is_empty() == normals.is_none()
+                && mesh.uvs.is_empty() != custom_uvs
+                && mesh.colors.is_empty() == colors.is_none()
+        };
+        let found = self
+            .meshes
+            .iter()
+            .copied()
+            .find(|&index| matches(&self.objects[index]));
+        let index = match found {
+            Some(index) => index,
+            None => {
+                self.objects.push(RonObject {
+                    material: material.clone(),
+                    mesh: Some(RonMesh {
+                        positions: vec![],
+                        normals: vec![],
+                        uvs: vec![],
+                        colors: vec![],
+                        cull_back,
+                    }),
+                    ..RonObject::new("Mesh")
+                });
+                self.meshes.push(self.objects.len() - 1);
+                self.objects.len() - 1
+            }
+        };
+        let mesh = self.objects[index].mesh.as_mut().unwrap();
+        mesh.positions.extend(points.iter().map(|p| ron_vector(*p)));
+        if let Some(normals) = normals {
+            mesh.normals.extend(normals.iter().map(|n| ron_vector(*n)));
+        }
+        if custom_uvs {
+            mesh.uvs.extend(uvs.iter().map(|uv| vec![uv.0, uv.1]));
+        }
+        if let Some(colors) = colors {
+            mesh.colors.extend(colors.iter().map(|c| ron_vector(*c)));
+        }
+    }
+
+    // Objects written by `export`, with its triangles gathered into meshes placed where their
+    // first triangle was written.
+    pub fn export_children(&mut self, export: impl FnOnce(&mut SceneWriter)) -> Vec<RonObject> {
+        let objects = std::mem::take(&mut self.objects);
+        let meshes = std::mem::take(&mut self.meshes);
+        export(self);
+        self.meshes = meshes;
+        std::mem::replace(&mut self.objects, objects)
+    }
+
+    // Several objects written as one, a `Node` unless there is only one.
+    pub(crate) fn single(&self, mut children: Vec<RonObject>) -> RonObject {
+        if children.len() == 1 {
+            children.remove(0)
+        } else {
+            RonObject {
+                children,
+                ..RonObject::new("Node")
+            }
+        }
+    }
+
+    pub(crate) fn group(&mut self, children: Vec<RonObject>) {
+        if !children.is_empty() {
+            let object = self.single(children);
+            self.object(object);
+        }
+    }
+
+    // Objects placed by a transform, with an optional material override. A single child takes
+    // the placement itself when it has none of its own.
+    pub(crate) fn placed(
+        &mut self,
+        mut children: Vec<RonObject>,
+        transform: RonTransform,
+        transform_end: Option<RonTransform>,
+        material: Vec<String>,
+    ) {
+        let takes_placement = |child: &RonObject| {
+            child.transform.is_none()
+                && child.transform_end.is_none()
+                && (material.is_empty() || !is_primitive(child) && child.material.is_empty())
+        };
+        if children.len() == 1 && takes_placement(&children[0]) {
+            let mut child = children.remove(0);
+            child.transform = Some(transform);
+            child.transform_end = transform_end;
+            if !material.is_empty() {
+                child.material = material;
+            }
+            self.object(child);
+        } else {
+            self.object(RonObject {
+                children,
+                transform: Some(transform),
+                transform_end,
+                material,
+                ..RonObject::new("Node")
+            });
+        }
+    }
+
+    // Geometry with several owners is written once and placed by instances.
+    pub(crate) fn shared(&mut self, key: usize, export: impl FnOnce(&mut SceneWriter)) {
+        let name = match self.geometries.get(&key) {
+            Some(name) => name.clone(),
+            None => {
+                let objects = self.export_children(export);
+                let name = format!("geometry{}", self.scene.geometries.len());
+                self.scene.geometries.push(RonGeometry {
+                    name: name.clone(),
+                    objects,
+                });
+                self.geometries.insert(key, name.clone());
+                name
+            }
+        };
+        self.object(RonObject {
+            geometry: Some(name),
+            ..RonObject::new("Instance")
+        });
+    }
+
+    // Name of the texture in the written scene, textures without a description become white.
+    pub fn texture(&mut self, texture: &TextureRef) -> String {
+        let key = Arc::as_ptr(texture) as *const () as usize;
+        if let Some(name) = self.textures.get(&key) {
+            return name.clone();
+        }
+        let description = texture.export(self);
+        self.add_texture(key, texture, description)
+    }
+
+    fn add_texture(
+        &mut self,
+        key: usize,
+        texture: &TextureRef,
+        description: Option<RonTexture>,
+    ) -> String {
+        let name = format!("texture{}", self.scene.textures.len());
+        let mut description = description.unwrap_or_else(|| {
+            self.skip(&format!("texture {:?}", texture));
+            RonTexture {
+                vectors: vec![vec![1.0, 1.0, 1.0]],
+                ..RonTexture::new("Solid")
+            }
+        });
+        description.name = name.clone();
+        self.scene.textures.push(description);
+        self.textures.insert(key, name.clone());
+        name
+    }
+
+    // Three numbers for a constant color, otherwise a texture name padded to three entries.
+    fn color_slot(&mut self, texture: &TextureRef) -> Vec<String> {
+        let key = Arc::as_ptr(texture) as *const () as usize;
+        if let Some(name) = self.textures.get(&key) {
+            return vec![name.clone(), String::new(), String::new()];
+        }
+        let description = texture.export(self);
+        match &description {
+            Some(solid) if solid.textype == "Solid" => {
+                return solid.vectors[0].iter().map(|c| ron_number(*c)).collect();
+            }
+            Some(colors) if colors.textype == "VertexColors" => {
+                let corner = |i: usize| conv_py_vec(colors.vectors[i].clone());
+                self.vertex_colors = Some([corner(0), corner(1), corner(2)]);
+                return vec!["1".to_string(); 3];
+            }
+            _ => {}
+        }
+        let name = self.add_texture(key, texture, description);
+        vec![name, String::new(), String::new()]
+    }
+
+    fn scalar_slot(&mut self, texture: &TextureRef) -> String {
+        match texture.export(self) {
+            Some(solid) if solid.textype == "Solid" => ron_number(solid.vectors[0][0]),
+            _ => self.texture(texture),
+        }
+    }
+
+    // Material entries as parsed by `parse_ron_material`, wrappers become `key=value` modifiers.
+    pub(crate) fn material(&mut self, material: &Material) -> Vec<String> {
+        self.vertex_colors = None;
+        let mut modifiers = vec![];
+        let mut base = material;
+        loop {
+            base = match base {
+                Material::NormalMapped(inner, map, strength) => {
+                    modifiers.push(format!("normal_map={}", self.texture(map)));
+                    modifiers.push(format!("normal_strength={}", strength));
+                    inner
+                }
+                Material::Bumped(inner, height, scale) => {
+                    modifiers.push(format!("bump={}", self.texture(height)));
+                    modifiers.push(format!("bump_scale={}", scale));
+                    inner
+                }
+                Material::Masked(inner, opacity, threshold, stochastic) => {
+                    modifiers.push(format!("opacity={}", self.texture(opacity)));
+                    modifiers.push(format!("alpha_threshold={}", threshold));
+                    if *stochastic {
+                        modifiers.push("alpha_mode=stochastic".to_string());
+                    }
+                    inner
+                }
+                Material::Absorbing(inner, absorption) => {
+                    modifiers.push(format!(
+                        "absorption={},{},{}",
+                        absorption.x(),
+                        absorption.y(),
+                        absorption.z()
+                    ));
+                    inner
+                }
+                _ => break,
+            };
+        }
+        let mut entries = match base {
+            Material::Lambertian(color) => {
+                let mut entries = vec!["Lambertian".to_string()];
+                entries.extend(self.color_slot(color));
+                entries
+            }
+            Material::Metal(color, fuzz) | Material::Hair(color, fuzz) => {
+                let kind = match base {
+                    Material::Metal(..) => "Metal",
+                    _ => "Hair",
+                };
+                let mut entries = vec![kind.to_string()];
+                entries.extend(self.color_slot(color));
+                let colors = self.vertex_colors.take();
+                entries.push(self.scalar_slot(fuzz));
+                self.vertex_colors = colors;
+                entries
+            }
+            Material::Mirror => vec!["Mirror".to_string()],
+            Material::Dielectric(ior) => vec![
+                "Dielectric".to_string(),
+                "0".to_string(),
+                "0".to_string(),
+                "0".to_string(),
+                ron_number(*ior),
+            ],
+            _ => unreachable!("material wrappers are peeled off above"),
+        };
+        entries.extend(modifiers);
+        entries
+    }
+
+    fn finish(mut self, objects: Vec<RonObject>) -> RaytracerScene {
+        self.scene.objects = match objects.len() {
+            1 if objects[0].objtype == "Node"
+                && objects[0].transform.is_none()
+                && objects[0].material.is_empty() =>
+            {
+                objects.into_iter().next().unwrap().children
+            }
+            _ => objects,
+        };
+        if !self.skipped.is_empty() {
+            let skipped: Vec<String> = self.skipped.into_iter().collect();
+            eprintln!("Scene export left out {}", skipped.join(", "));
+        }
+        self.scene
+    }
+}
+
+// Types whose material is their own rather than an override of their children's.
+fn is_primitive(object: &RonObject) -> bool {
+    !matches!(
+        &object.objtype[..],
+        "Node" | "Instance" | "Scatter" | "Gltf" | "Union" | "Intersection" | "Difference"
+    )
+}
+
+// RON description of a built scene: settings, camera, lights and every object of the world.
+pub fn describe_scene(
+    settings: RenderSettings,
+    camera: &dyn Camera,
+    world: &Hittables,
+) -> RaytracerScene {
+    let mut writer = SceneWriter::new(settings);
+    camera.export(&mut writer);
+    writer.scene.lights = world
+        .lights
+        .iter()
+        .map(|light| ron_vector(*light))
+        .collect();
+    let objects = writer.export_children(|writer| {
+        for object in &world.hittables {
+            object.export(writer);
+        }
+    });
+    writer.finish(objects)
+}
+
+// Subdivisions around tessellated round shapes.
+const SIDES: usize = 32;
+// Grid cells along each side of an SDF's bounds when its surface is extracted.
+const SDF_CELLS: usize = 48;
+
+// Triangle corner written to the glTF buffer.
+#[derive(Debug, Clone, Copy)]
+struct Corner {
+    position: Vec3,
+    normal: Vec3,
+    uv: (f64, f64),
+    color: Option<Vec3>,
+}
+
+impl Corner {
+    fn new(position: Vec3, normal: Vec3, uv: (f64, f64)) -> Corner {
+        Corner {
+            position,
+            normal,
+            uv,
+            color: None,
+        }
+    }
+}
+
+// World space triangles sharing one material, with vertex colors when they have any.
+struct Batch {
+    material: Vec<String>,
+    colored: bool,
+    corners: Vec<Corner>,
+}
+
+// Triangles of the scene grouped by material. Materials set on nodes and instances override
+// those of their children, like `Transform::with_material`.
+struct Collector<'a> {
+    settings: &'a RaytracerScene,
+    textures: HashMap<String, TextureRef>,
+    batches: Vec<Batch>,
+    left_out: BTreeSet<String>,
+}
+
+impl<'a> Collector<'a> {
+    // Procedural textures in the color slot are baked into vertex colors.
+    fn push(&mut self, material: &[String], matrix: Mat4, corners: Vec<Corner>) {
+        let normal_matrix = matrix.inverse().unwrap_or_else(Mat4::identity).transpose();
+        let baked = material
+            .get(1)
+            .and_then(|slot| self.settings.textures.iter().find(|tex| &tex.name == slot))
+            .filter(|tex| tex.textype != "Image" && tex.textype != "Solid")
+            .map(|tex| self.textures[&tex.name].clone());
+        let corners = corners.into_iter().map(|corner| {
+            let position = matrix.transform_point(corner.position);
+            let (u, v) = corner.uv;
+            Corner {
+                position,
+                normal: unit_or_up(normal_matrix.transform_vector(corner.normal)),
+                uv: corner.uv,
+                color: match &baked {
+                    Some(texture) => Some(texture.value(u, v, position)),
+                    None => corner.color,
+                },
+            }
+        });
+        let corners: Vec<Corner> = corners.collect();
+        let colored = corners.iter().any(|corner| corner.color.is_some());
+        let found = self
+            .batches
+            .iter()
+            .position(|batch| batch.material == material && batch.colored == colored);
+        let batch = match found {
+            Some(batch) => batch,
+            None => {
+                self.batches.push(Batch {
+                    material: material.to_vec(),
+                    colored,
+                    corners: vec![],
+                });
+                self.batches.len() - 1
+            }
+        };
+        self.batches[batch].corners.extend(corners);
+    }
+
+    fn collect(&mut self, objects: &[RonObject], parent: Mat4, inherited: Option<&[String]>) {
+        for obj in objects {
+            let matrix = parent * ron_matrix(&obj.transform);
+            let own = if obj.material.is_empty() {
+                None
+            } else {
+                Some(&obj.material[..])
+            };
+            let material = inherited.or(own).unwrap_or(&[]);
+            match &obj.objtype[..] {
+                "Node" => self.collect(&obj.children, matrix, inherited.or(own)),
+                "Instance" => {
+                    let name = obj
+                        .geometry
+                        .as_ref()
+                        .expect("instance requires a geometry name");
+                    let geometry = self
+                        .settings
+                        .geometries
+                        .iter()
+                        .find(|geometry| &geometry.name == name)
+                        .unwrap_or_else(|| panic!("Unknown geometry {}", name));
+                    self.collect(&geometry.objects, matrix, inherited.or(own));
+                }
+                _ => match surface(obj) {
+                    Some(corners) => self.push(material, matrix, corners),
+                    None => {
+                        self.left_out.insert(format!("{} objects", obj.objtype));
+                    }
+                },
+            }
+        }
+    }
+}
+
+fn unit_or_up(v: Vec3) -> Vec3 {
+    if v.length_squared() > 0.0 {
+        v.unit_vector()
+    } else {
+        Vec3::new(0.0, 1.0, 0.0)
+    }
+}
+
+// Flat shaded triangle.
+fn face(points: [Vec3; 3], uvs: [(f64, f64); 3]) -> [Corner; 3] {
+    let normal = unit_or_up((points[1] - points[0]).cross(points[2] - points[0]));
+    [0, 1, 2].map(|k| Corner::new(points[k], normal, uvs[k]))
+}
+
+// Quads over the (u, v) unit square mapped onto a surface by `point`, which returns the position
+// and normal there.
+fn grid(columns: usize, rows: usize, point: impl Fn(f64, f64) -> (Vec3, Vec3)) -> Vec<Corner> {
+    let corner = |i: usize, j: usize| {
+        let uv = (i as f64 / columns as f64, j as f64 / rows as f64);
+        let (position, normal) = point(uv.0, uv.1);
+        Corner::new(position, normal, uv)
+    };
+    let mut corners = vec![];
+    for j in 0..rows {
+        for i in 0..columns {
+            let (a, b) = (corner(i, j), corner(i + 1, j));
+            let (c, d) = (corner(i + 1, j + 1), corner(i, j + 1));
+            corners.extend([a, b, c, a, c, d]);
+        }
+    }
+    corners
+}
+
+// Direction a fraction `u` of a turn around the frame's axis.
+fn radial(frame: &Frame, u: f64) -> Vec3 {
+    let (sin, cos) = (u * 2.0 * std::f64::consts::PI).sin_cos();
+    frame.dir_to_world(Vec3::new(cos, 0.0, sin))
+}
+
+// Disc of `radius` at `height` along the frame's axis, facing `normal`.
+fn cap(frame: &Frame, radius: f64, height: f64, normal: Vec3) -> Vec<Corner> {
+    grid(SIDES, 1, |u, v| {
+        let center = frame.origin + frame.axis * height;
+        (center + radial(frame, u) * (radius * v), normal)
+    })
+}
+
+// Unit cube around the origin placed by `matrix`.
+fn cube(matrix: Mat4) -> Vec<Corner> {
+    let axes = [
+        Vec3::new(1.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        Vec3::new(0.0, 0.0, 1.0),
+    ];
+    let normal_matrix = matrix.inverse().unwrap_or_else(Mat4::identity).transpose();
+    let mut corners = vec![];
+    for axis in 0..3 {
+        let (u_axis, v_axis) = (axes[(axis + 1) % 3], axes[(axis + 2) % 3]);
+        for sign in [-1.0, 1.0] {
+            let normal = axes[axis] * sign;
+            corners.extend(grid(1, 1, |u, v| {
+                let p = normal * 0.5 + u_axis * (u - 0.5) + v_axis * (v - 0.5);
+                let n = unit_or_up(normal_matrix.transform_vector(normal));
+                (matrix.transform_point(p), n)
+            }));
+        }
+    }
+    corners
+}
+
+// Triangles approximating an object in its own space, None for types without a finite surface
+// such as planes, water and CSG.
+fn surface(obj: &RonObject) -> Option<Vec<Corner>> {
+    let vector = |i: usize| conv_py_vec(obj.vectors[i].clone());
+    let scalar = |i: usize| obj.scalars[i];
+    let corners = match &obj.objtype[..] {
+        "Triangle" => {
+            let uvs = if obj.vectors.len() >= 6 {
+                [3, 4, 5].map(|i| (obj.vectors[i][0], obj.vectors[i][1]))
+            } else {
+                BARYCENTRIC_UVS
+            };
+            let mut corners = face([vector(0), vector(1), vector(2)], uvs);
+            if obj.vectors.len() >= 9 {
+                for (k, corner) in corners.iter_mut().enumerate() {
+                    corner.normal = vector(6 + k).unit_vector();
+                }
+            }
+            corners.to_vec()
+        }
+        "Rectangle" => {
+            let (a, b, c, d) = (vector(0), vector(1), vector(2), vector(3));
+            let mut corners = face([a, b, d], [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0)]).to_vec();
+            corners.extend(face([a, d, c], [(0.0, 0.0), (1.0, 1.0), (0.0, 1.0)]));
+            corners
+        }
+        "Mesh" => match (&obj.path, &obj.mesh) {
+            (Some(path), _) => mesh_file_surface(&MeshData::load(path)),
+            (None, Some(mesh)) => inline_mesh_surface(mesh),
+            (None, None) => panic!("Mesh object requires a path or a mesh"),
+        },
+        "Heightfield" => {
+            let (origin, size) = (vector(0), vector(1));
+            let (width, depth, heights) = ron_heightfield_grid(obj);
+            let uv = |p: Vec3| {
+                (
+                    (p.x() - origin.x()) / size.x(),
+                    1.0 - (p.z() - origin.z()) / size.z(),
+                )
+            };
+            grid_triangles(origin, size, width, depth, &heights)
+                .into_iter()
+                .flat_map(|triangle| face(triangle, triangle.map(uv)))
+                .collect()
+        }
+        // Latitude and longitude grid with the uv mapping of `Sphere`.
+        "Sphere" | "MovingSphere" => {
+            let (center, radius) = (vector(0), scalar(0).abs());
+            grid(SIDES, SIDES / 2, |u, v| {
+                let (sin_theta, cos_theta) = (v * std::f64::consts::PI).sin_cos();
+                let (sin_phi, cos_phi) = (u * 2.0 * std::f64::consts::PI).sin_cos();
+                let normal = Vec3::new(-cos_phi * sin_theta, -cos_theta, sin_phi * sin_theta);
+                (center + normal * radius, normal)
+            })
+        }
+        "Box" => {
+            let (min, max) = (vector(0), vector(1));
+            cube(Mat4::from_trs(
+                (min + max) * 0.5,
+                Vec3::new(0.0, 0.0, 0.0),
+                max - min,
+            ))
+        }
+        "OrientedBox" => cube(Mat4::from_trs(vector(0), vector(2), vector(1))),
+        "Disc" => {
+            let frame = Frame::new(vector(0), vector(1));
+            cap(&frame, scalar(0), 0.0, frame.axis)
+        }
+        "Cylinder" => {
+            let frame = Frame::new(vector(0), vector(1));
+            let (radius, height) = (scalar(0), scalar(1));
+            let mut corners = grid(SIDES, 1, |u, v| {
+                let out = radial(&frame, u);
+                (frame.origin + frame.axis * (height * v) + out * radius, out)
+            });
+            corners.extend(cap(&frame, radius, 0.0, -frame.axis));
+            corners.extend(cap(&frame, radius, height, frame.axis));
+            corners
+        }
+        "Cone" => {
+            let frame = Frame::new(vector(0), vector(1));
+            let (radius, height) = (scalar(0), scalar(1));
+            let mut corners = grid(SIDES, 1, |u, v| {
+                let out = radial(&frame, u);
+                let p = frame.origin + frame.axis * (height * v) + out * (radius * (1.0 - v));
+                (p, (out * height + frame.axis * radius).unit_vector())
+            });
+            corners.extend(cap(&frame, radius, 0.0, -frame.axis));
+            corners
+        }
+        "Torus" => {
+            let frame = Frame::new(vector(0), vector(1));
+            let (major, minor) = (scalar(0), scalar(1));
+            grid(SIDES, SIDES / 2, |u, v| {
+                let out = radial(&frame, u);
+                let (sin, cos) = (v * 2.0 * std::f64::consts::PI).sin_cos();
+                let normal = out * cos + frame.axis * sin;
+                (frame.origin + out * major + normal * minor, normal)
+            })
+        }
+        // Ribbons turn to face the viewer, which a mesh cannot, so both shapes become tubes.
+        "Curve" => {
+            let control = [vector(0), vector(1), vector(2), vector(3)];
+            let (root, tip) = (scalar(0), scalar(1));
+            grid(6, SIDES, |u, v| {
+                let (before, after) = ((v - 0.01).max(0.0), (v + 0.01).min(1.0));
+                let tangent = bezier(&control, after) - bezier(&control, before);
+                let frame = Frame::new(bezier(&control, v), tangent);
+                let out = radial(&frame, u);
+                (
+                    frame.origin + out * ((root * (1.0 - v) + tip * v) / 2.0),
+                    out,
+                )
+            })
+        }
+        "Sdf" => sdf_surface(&parse_ron_sdf(
+            obj.sdf.as_ref().expect("Sdf object requires an sdf"),
+        )),
+        _ => return None,
+    };
+    Some(corners)
+}
+
+// Mesh file triangles with their normals and vertex colors when the file has them.
+fn mesh_file_surface(mesh: &MeshData) -> Vec<Corner> {
+    let mut corners = vec![];
+    for face_indices in &mesh.faces {
+        let mut triangle = face(face_indices.map(|i| mesh.positions[i]), BARYCENTRIC_UVS);
+        for (corner, i) in triangle.iter_mut().zip(face_indices) {
+            if let Some(normals) = &mesh.normals {
+                corner.normal = normals[*i];
+            }
+            corner.color = mesh.colors.as_ref().map(|colors| colors[*i]);
+        }
+        corners.extend(triangle);
+    }
+    corners
+}
+
+fn inline_mesh_surface(mesh: &RonMesh) -> Vec<Corner> {
+    let point = |values: &[Vec<f64>], i: usize| conv_py_vec(values[i].clone());
+    let mut corners = vec![];
+    for first in (0..mesh.positions.len()).step_by(3) {
+        let uv = |k: usize| match mesh.uvs.get(first + k) {
+            Some(uv) => (uv[0], uv[1]),
+            None => BARYCENTRIC_UVS[k],
+        };
+        let positions = [0, 1, 2].map(|k| point(&mesh.positions, first + k));
+        let mut triangle = face(positions, [uv(0), uv(1), uv(2)]);
+        for (k, corner) in triangle.iter_mut().enumerate() {
+            if !mesh.normals.is_empty() {
+                corner.normal = point(&mesh.normals, first + k);
+            }
+            if !mesh.colors.is_empty() {
+                corner.color = Some(point(&mesh.colors, first + k));
+            }
+        }
+        corners.extend(triangle);
+    }
+    corners
+}
+
+// Zero crossing of the distance field, found by marching tetrahedra through a grid over its
+// bounds. Normals are the gradient of the field.
+fn sdf_surface(root: &SdfNode) -> Vec<Corner> {
+    // Corners of a grid cell, and the six tetrahedra around its diagonal from corner 0 to 6.
+    const CELL: [(usize, usize, usize); 8] = [
+        (0, 0, 0),
+        (1, 0, 0),
+        (1, 1, 0),
+        (0, 1, 0),
+        (0, 0, 1),
+        (1, 0, 1),
+        (1, 1, 1),
+        (0, 1, 1),
+    ];
+    const TETRAHEDRA: [[usize; 4]; 6] = [
+        [0, 5, 1, 6],
+        [0, 1, 2, 6],
+        [0, 2, 3, 6],
+        [0, 3, 7, 6],
+        [0, 7, 4, 6],
+        [0, 4, 5, 6],
+    ];
+    let bounds = root.bounds();
+    let step = (bounds.max - bounds.min) * (1.0 / SDF_CELLS as f64);
+    let n = SDF_CELLS + 1;
+    let point =
+        |i: usize, j: usize, k: usize| bounds.min + step * Vec3::new(i as f64, j as f64, k as f64);
+    let mut distances = vec![0.0; n * n * n];
+    for i in 0..n {
+        for j in 0..n {
+            for k in 0..n {
+                distances[(i * n + j) * n + k] = root.distance(point(i, j, k));
+            }
+        }
+    }
+    let epsilon = step.length() * 1e-3;
+    let normal = |p: Vec3| {
+        let axis = |offset: Vec3| root.distance(p + offset) - root.distance(p - offset);
+        unit_or_up(Vec3::new(
+            axis(Vec3::new(epsilon, 0.0, 0.0)),
+            axis(Vec3::new(0.0, epsilon, 0.0)),
+            axis(Vec3::new(0.0, 0.0, epsilon)),
+        ))
+    };
+    let crossing = |(a, da): (Vec3, f64), (b, db): (Vec3, f64)| a + (b - a) * (da / (da - db));
+
+    let mut corners = vec![];
+    for i in 0..SDF_CELLS {
+        for j in 0..SDF_CELLS {
+            for k in 0..SDF_CELLS {
+                let cell = CELL.map(|(x, y, z)| {
+                    let (x, y, z) = (i + x, j + y, k + z);
+                    (point(x, y, z), distances[(x * n + y) * n + z])
+                });
+                for tetrahedron in TETRAHEDRA {
+                    let (inside, outside): (Vec<_>, Vec<_>) = tetrahedron
+                        .iter()
+                        .map(|c| cell[*c])
+                        .partition(|(_, distance)| *distance < 0.0);
+                    let points = match (&inside[..], &outside[..]) {
+                        ([lone], [a, b, c]) | ([a, b, c], [lone]) => vec![
+                            crossing(*lone, *a),
+                            crossing(*lone, *b),
+                            crossing(*lone, *c),
+                        ],
+                        ([a, b], [c, d]) => {
+                            let (ac, ad) = (crossing(*a, *c), crossing(*a, *d));
+                            let (bc, bd) = (crossing(*b, *c), crossing(*b, *d));
+                            vec![ac, ad, bd, ac, bd, bc]
+                        }
+                        _ => vec![],
+                    };
+                    corners.extend(
+                        points
+                            .into_iter()
+                            .map(|p| Corner::new(p, normal(p), (0.0, 0.0))),
+                    );
+                }
+            }
+        }
+    }
+    corners
+}
+
+// Binary buffer of the document with its views and accessors, plus the images, samplers and
+// textures referenced by materials.
+#[derive(Default)]
+struct GltfData {
+    buffer: Vec<u8>,
+    buffer_views: Vec<Value>,
+    accessors: Vec<Value>,
+    images: Vec<Value>,
+    samplers: Vec<Value>,
+    textures: Vec<Value>,
+    texture_index: HashMap<String, usize>,
+}
+
+impl GltfData {
+    // Float accessor over `values`, `components` to an element.
+    fn accessor(&mut self, values: &[f32], components: usize, bounds: bool) -> usize {
+        let offset = self.buffer.len();
+        for value in values {
+            self.buffer.extend_from_slice(&value.to_le_bytes());
+        }
+        self.buffer_views.push(json!({
+            "buffer": 0,
+            "byteOffset": offset,
+            "byteLength": self.buffer.len() - offset,
+        }));
+        let kind = ["SCALAR", "VEC2", "VEC3", "VEC4"][components - 1];
+        let mut accessor = json!({
+            "bufferView": self.buffer_views.len() - 1,
+            "componentType": 5126,
+            "count": values.len() / components,
+            "type": kind,
+        });
+        if bounds {
+            let component = |i: usize| values.iter().skip(i).step_by(components);
+            let min: Vec<f32> = (0..components)
+                .map(|i| component(i).fold(f32::INFINITY, |m, v| m.min(*v)))
+                .collect();
+            let max: Vec<f32> = (0..components)
+                .map(|i| component(i).fold(f32::NEG_INFINITY, |m, v| m.max(*v)))
+                .collect();
+            accessor["min"] = json!(min);
+            accessor["max"] = json!(max);
+        }
+        self.accessors.push(accessor);
+        self.accessors.len() - 1
+    }
+
+    // glTF texture for an image texture of the scene, with the file or data URI stored in the
+    // buffer.
+    fn image(&mut self, texture: &RonTexture) -> usize {
+        if let Some(index) = self.texture_index.get(&texture.name) {
+            return *index;
+        }
+        let path = texture
+            .path
+            .as_ref()
+            .expect("image texture requires a path");
+        let (mime, bytes) = match path.strip_prefix("data:") {
+            Some(uri) => {
+                let (mime, encoded) = uri.split_once(";base64,").expect("invalid data URI");
+                let bytes = base64::engine::general_purpose::STANDARD
+                    .decode(encoded)
+                    .unwrap_or_else(|e| panic!("invalid data URI: {}", e));
+                (mime.to_string(), bytes)
+            }
+            None => {
+                let bytes = std::fs::read(path)
+                    .unwrap_or_else(|e| panic!("failed to read texture {}: {}", path, e));
+                let mime = match path.to_lowercase().rsplit('.').next() {
+                    Some("jpg") | Some("jpeg") => "image/jpeg",
+                    _ => "image/png",
+                };
+                (mime.to_string(), bytes)
+            }
+        };
+        let offset = self.buffer.len();
+        self.buffer.extend_from_slice(&bytes);
+        self.buffer_views.push(json!({
+            "buffer": 0,
+            "byteOffset": offset,
+            "byteLength": bytes.len(),
+        }));
+        // Accessors that follow must start on a four byte boundary.
+        self.buffer.resize(self.buffer.len().div_ceil(4) * 4, 0);
+        let wrap = match texture.wrap.as_deref() {
+            Some("Clamp") => 33071,
+            Some("Mirror") => 33648,
+            _ => 10497,
+        };
+        self.images.push(json!({
+            "bufferView": self.buffer_views.len() - 1,
+            "mimeType": mime,
+        }));
+        self.samplers.push(json!({ "wrapS": wrap, "wrapT": wrap }));
+        self.textures.push(json!({
+            "source": self.images.len() - 1,
+            "sampler": self.samplers.len() - 1,
+        }));
+        let index = self.textures.len() - 1;
+        self.texture_index.insert(texture.name.clone(), index);
+        index
+    }
+}
+
+// glTF 2.0 document with every surface of the scene tessellated into one embedded buffer, one
+// primitive per material, plus the camera and point lights. Image textures are embedded and
+// procedural ones baked into vertex colors. Objects without a finite surface, like planes, water
+// and CSG, are reported on stderr.
+pub fn scene_to_gltf(settings: &RaytracerScene) -> String {
+    let mut collector = Collector {
+        settings,
+        textures: parse_ron_textures(&settings.textures),
+        batches: vec![],
+        left_out: BTreeSet::new(),
+    };
+    collector.collect(&settings.objects, Mat4::identity(), None);
+
+    let mut data = GltfData::default();
+    let mut primitives = vec![];
+    let mut materials = vec![];
+    for (i, batch) in collector.batches.iter().enumerate() {
+        let flat = |values: &dyn Fn(&Corner) -> Vec<f64>| -> Vec<f32> {
+            batch
+                .corners
+                .iter()
+                .flat_map(values)
+                .map(|v| v as f32)
+                .collect()
+        };
+        let positions = flat(&|c| ron_vector(c.position));
+        let normals = flat(&|c| ron_vector(c.normal));
+        let uvs = flat(&|c| vec![c.uv.0, 1.0 - c.uv.1]);
+        let mut attributes = json!({
+            "POSITION": data.accessor(&positions, 3, true),
+            "NORMAL": data.accessor(&normals, 3, false),
+            "TEXCOORD_0": data.accessor(&uvs, 2, false),
+        });
+        if batch.colored {
+            let white = Vec3::new(1.0, 1.0, 1.0);
+            let colors = flat(&|c| ron_vector(c.color.unwrap_or(white)));
+            attributes["COLOR_0"] = json!(data.accessor(&colors, 3, false));
+        }
+        primitives.push(json!({ "attributes": attributes, "material": i }));
+        materials.push(gltf_material(
+            &batch.material,
+            settings,
+            &mut data,
+            &mut collector.left_out,
+        ));
+    }
+
+    let mut nodes = vec![];
+    let mut meshes = vec![];
+    if !primitives.is_empty() {
+        meshes.push(json!({ "primitives": primitives }));
+        nodes.push(json!({ "mesh": 0 }));
+    }
+    nodes.push(camera_node(settings));
+    let lights: Vec<Value> = settings
+        .lights
+        .iter()
+        .map(|_| json!({ "type": "point" }))
+        .collect();
+    for (i, light) in settings.lights.iter().enumerate() {
+        nodes.push(json!({
+            "translation": [light[0], light[1], light[2]],
+            "extensions": { "KHR_lights_punctual": { "light": i } },
+        }));
+    }
+    if !collector.left_out.is_empty() {
+        let left_out: Vec<String> = collector.left_out.into_iter().collect();
+        eprintln!("glTF export left out {}", left_out.join(", "));
+    }
+
+    let uri = format!(
+        "data:application/octet-stream;base64,{}",
+        base64::engine::general_purpose::STANDARD.encode(&data.buffer)
+    );
+    let mut document = json!({
+        "asset": { "version": "2.0", "generator": "raytracing" },
+        "extensionsUsed": ["KHR_lights_punctual", "KHR_materials_transmission", "KHR_materials_ior"],
+        "extensions": { "KHR_lights_punctual": { "lights": lights } },
+        "scene": 0,
+        "scenes": [{ "nodes": (0..nodes.len()).collect::<Vec<_>>() }],
+        "nodes": nodes,
+        "meshes": meshes,
+        "materials": materials,
+        "cameras": [camera(settings)],
+        "accessors": data.accessors,
+        "bufferViews": data.buffer_views,
+        "buffers": [{ "byteLength": data.buffer.len(), "uri": uri }],
+    });
+    if !data.textures.is_empty() {
+        document["images"] = json!(data.images);
+        document["samplers"] = json!(data.samplers);
+        document["textures"] = json!(data.textures);
+    }
+    serde_json::to_string_pretty(&document).expect("serialization failed")
+}
+
+// Metallic-roughness material for RON material entries. Constant colors become the base color,
+// image textures the base color or normal texture, and procedural colors are already in the
+// vertex colors. Modifiers glTF has no equivalent for are noted in `left_out`.
+fn gltf_material(
+    mat: &[String],
+    settings: &RaytracerScene,
+    data: &mut GltfData,
+    left_out: &mut BTreeSet<String>,
+) -> Value {
+    let texture = |name: &str| settings.textures.iter().find(|tex| tex.name == name);
+    let number = |i: usize| mat.get(i).and_then(|value| value.parse::<f64>().ok());
+    let kind = mat.first().map_or("Lambertian", |kind| kind.as_str());
+    let mut color = match (number(1), number(2), number(3)) {
+        (Some(r), Some(g), Some(b)) => json!([r, g, b, 1.0]),
+        _ => json!([1.0, 1.0, 1.0, 1.0]),
+    };
+    let mut color_texture = None;
+    if let Some(tex) = mat.get(1).and_then(|name| texture(name)) {
+        match &tex.textype[..] {
+            "Image" => color_texture = Some(data.image(tex)),
+            "Solid" => {
+                color = json!([tex.vectors[0][0], tex.vectors[0][1], tex.vectors[0][2], 1.0])
+            }
+            _ => {}
+        }
+    }
+    let roughness = match kind {
+        "Metal" | "Hair" => match (number(4), mat.get(4)) {
+            (Some(fuzz), _) => fuzz,
+            (None, Some(_)) => {
+                left_out.insert("roughness textures".to_string());
+                0.5
+            }
+            (None, None) => 0.5,
+        },
+        "Mirror" | "Dielectric" => 0.0,
+        _ => 1.0,
+    };
+    let metallic = match kind {
+        "Metal" | "Mirror" => 1.0,
+        _ => 0.0,
+    };
+    if kind == "Mirror" || kind == "Dielectric" {
+        color = json!([1.0, 1.0, 1.0, 1.0]);
+    }
+    let mut material = json!({
+        "doubleSided": true,
+        "pbrMetallicRoughness": {
+            "baseColorFactor": color,
+            "metallicFactor": metallic,
+            "roughnessFactor": roughness,
+        },
+    });
+    if let Some(index) = color_texture {
+        material["pbrMetallicRoughness"]["baseColorTexture"] = json!({ "index": index });
+    }
+    if kind == "Dielectric" {
+        material["extensions"] = json!({
+            "KHR_materials_transmission": { "transmissionFactor": 1.0 },
+            "KHR_materials_ior": { "ior": number(4).unwrap_or(1.5) },
+        });
+    }
+
+    let modifier = |key: &str| {
+        mat.iter()
+            .find_map(|entry| entry.strip_prefix(key)?.strip_prefix('='))
+    };
+    match modifier("normal_map").and_then(texture) {
+        Some(tex) if tex.textype == "Image" => {
+            let scale = modifier("normal_strength").map_or(1.0, |s| s.parse().unwrap_or(1.0));
+            material["normalTexture"] = json!({ "index": data.image(tex), "scale": scale });
+        }
+        Some(_) => {
+            left_out.insert("procedural normal maps".to_string());
+        }
+        None => {}
+    }
+    for (key, what) in [
+        ("bump", "bump maps"),
+        ("opacity", "opacity masks"),
+        ("absorption", "absorption"),
+    ] {
+        if modifier(key).is_some() {
+            left_out.insert(what.to_string());
+        }
+    }
+    material
+}
+
+fn camera(settings: &RaytracerScene) -> Value {
+    if settings.camera_type == "Orthographic" {
+        let view_height = if settings.ortho_height > 0.0 {
+            settings.ortho_height
+        } else {
+            2.0 * settings.focal_distance * (settings.v_fov.to_radians() / 2.0).tan()
+        };
+        return json!({
+            "type": "orthographic",
+            "orthographic": {
+                "xmag": view_height / 2.0 * settings.aspect_ratio,
+                "ymag": view_height / 2.0,
+                "znear": 0.001,
+                "zfar": 1.0e6,
+            },
+        });
+    }
+    json!({
+        "type": "perspective",
+        "perspective": {
+            "yfov": settings.v_fov.to_radians(),
+            "aspectRatio": settings.aspect_ratio,
+            "znear": 0.001,
+        },
+    })
+}
+
+// The camera looks down its local -z, so z points from the look-at point back to the eye.
+fn camera_node(settings: &RaytracerScene) -> Value {
+    let look_from = conv_py_vec(settings.camera_pos.clone());
+    let look_at = conv_py_vec(settings.camera_dir.clone());
+    let v_up = conv_py_vec(settings.camera_up.clone());
+    let z = (look_from - look_at).unit_vector();
+    let x = v_up.cross(z).unit_vector();
+    let y = z.cross(x);
+    json!({
+        "camera": 0,
+        "matrix": [
+            x.x(), x.y(), x.z(), 0.0,
+            y.x(), y.y(), y.z(), 0.0,
+            z.x(), z.y(), z.z(), 0.0,
+            look_from.x(), look_from.y(), look_from.z(), 1.0,
+        ],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::Scene;
+    use crate::texture::VertexColors;
+    use crate::triangle::Triangle;
+
+    const SCENE: &str = r#"(
+        multithreading: true,
+        aspect_ratio: 1.5,
+        image_width: 30,
+        image_height: 20,
+        samples_per_pixel: 1,
+        max_depth: 4,
+        v_fov: 60.0,
+        aperture: 0.0,
+        focal_distance: 5.0,
+        camera_pos: [0.0, 2.0, 5.0],
+        camera_dir: [0.0, 0.0, 0.0],
+        camera_up: [0.0, 1.0, 0.0],
+        textures: [(name: "check", textype: "Checker", vectors: [[1.0, 1.0, 1.0], [0.0, 0.0, 0.0]], scalars: [2.0])],
+        geometries: [(name: "tri", objects: [
+            (objtype: "Triangle", vectors: [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]], scalars: [0.0], material: ["Lambertian", "0.8", "0.2", "0.2"]),
+        ])],
+        objects: [
+            (objtype: "Sphere", vectors: [[0.0, 1.0, 0.0]], scalars: [1.0], material: ["Metal", "0.7", "0.6", "0.2", "0.3"]),
+            (objtype: "Rectangle", vectors: [[-2.0, 0.0, 2.0], [2.0, 0.0, 2.0], [-2.0, 0.0, -2.0], [2.0, 0.0, -2.0]], material: ["Lambertian", "check", "", "", "normal_map=check"]),
+            (objtype: "Instance", geometry: Some("tri"), transform: Some((translate: [1.0, 0.0, 0.0]))),
+            (objtype: "Instance", geometry: Some("tri"), material: ["Mirror"]),
+        ],
+        lights: [[0.0, 4.0, 0.0], [2.0, 4.0, 1.0]],
+    )"#;
+
+    // Everything the RON format can describe, with a mesh file and an image texture written to
+    // the temporary directory. The shutter stays open for the moving sphere and the animated box.
+    fn full_scene(name: &str) -> String {
+        let dir = std::env::temp_dir();
+        let mesh = dir.join(format!("{}.ply", name));
+        let image = dir.join(format!("{}.png", name));
+        std::fs::write(
+            &mesh,
+            "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\nproperty float y\n\
+             property float z\nproperty uchar red\nproperty uchar green\nproperty uchar blue\n\
+             element face 1\nproperty list uchar int vertex_indices\nend_header\n\
+             -1 0 -1 255 0 0\n1 0 -1 0 255 0\n0 1.5 -1 0 0 255\n3 0 1 2\n",
+        )
+        .unwrap();
+        image::RgbImage::from_fn(4, 4, |x, y| image::Rgb([x as u8 * 60, y as u8 * 60, 90]))
+            .save(&image)
+            .unwrap();
+        format!(
+            r#"(
+            multithreading: false,
+            aspect_ratio: 1.5,
+            image_width: 24,
+            image_height: 16,
+            samples_per_pixel: 2,
+            max_depth: 4,
+            v_fov: 60.0,
+            aperture: 0.0,
+            focal_distance: 5.0,
+            camera_pos: [0.0, 2.0, 6.0],
+            camera_dir: [0.0, 0.5, 0.0],
+            camera_up: [0.0, 1.0, 0.0],
+            shutter_open: 0.0,
+            shutter_close: 1.0,
+            textures: [
+                (name: "check", textype: "Checker", vectors: [[1.0, 1.0, 1.0], [0.1, 0.1, 0.1]], scalars: [2.0]),
+                (name: "noise", textype: "Noise", vectors: [[0.9, 0.8, 0.7]], scalars: [3.0, 2.0, 2.0, 2.0, 0.5]),
+                (name: "photo", textype: "Image", path: Some("{image}"), wrap: Some("Clamp")),
+            ],
+            geometries: [(name: "tri", objects: [
+                (objtype: "Triangle", vectors: [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]], scalars: [0.0], material: ["Lambertian", "0.8", "0.2", "0.2"]),
+            ])],
+            objects: [
+                (objtype: "Sphere", vectors: [[0.0, 1.0, 0.0]], scalars: [1.0], material: ["Metal", "0.7", "0.6", "0.2", "0.3"]),
+                (objtype: "Plane", vectors: [[0.0, -0.5, 0.0], [0.0, 1.0, 0.0]], material: ["Lambertian", "check"]),
+                (objtype: "Rectangle", vectors: [[-3.0, 0.0, -2.0], [3.0, 0.0, -2.0], [-3.0, 3.0, -2.0], [3.0, 3.0, -2.0]], material: ["Lambertian", "photo", "", "", "normal_map=check"]),
+                (objtype: "Mesh", path: Some("{mesh}"), transform: Some((translate: [-2.0, 0.0, 0.0])), material: ["Lambertian", "1", "1", "1"]),
+                (objtype: "Instance", geometry: Some("tri"), transform: Some((translate: [1.5, 0.0, 0.0], rotate: [0.0, 30.0, 0.0]))),
+                (objtype: "Instance", geometry: Some("tri"), material: ["Mirror"]),
+                (objtype: "Box", vectors: [[1.5, 0.0, 0.5], [2.0, 0.5, 1.0]], material: ["Lambertian", "noise"]),
+                (objtype: "OrientedBox", vectors: [[-1.5, 0.3, 1.0], [0.4, 0.6, 0.4], [0.0, 45.0, 10.0]], material: ["Dielectric", "0", "0", "0", "1.5"]),
+                (objtype: "Cylinder", vectors: [[2.5, 0.0, -1.0], [0.0, 1.0, 0.2]], scalars: [0.3, 1.0], material: ["Lambertian", "0.2", "0.5", "0.9"]),
+                (objtype: "Torus", vectors: [[-2.5, 1.5, 0.0], [1.0, 1.0, 0.0]], scalars: [0.5, 0.15], material: ["Metal", "0.9", "0.9", "0.9", "noise"]),
+                (objtype: "Curve", vectors: [[0.5, 0.0, 1.5], [0.6, 0.5, 1.5], [0.4, 1.0, 1.6], [0.7, 1.5, 1.5]], scalars: [0.1, 0.02, 1.0], material: ["Hair", "0.6", "0.4", "0.2", "0.3"]),
+                (objtype: "Sdf", sdf: Some((shape: "Translate", vectors: [[-0.8, 0.3, 1.8]], children: [(shape: "SmoothUnion", scalars: [0.1], children: [(shape: "Sphere", scalars: [0.3]), (shape: "Rotate", vectors: [[0.0, 0.0, 30.0]], children: [(shape: "Box", vectors: [[0.4, 0.1, 0.1]])])])])), material: ["Lambertian", "0.3", "0.8", "0.3"]),
+                (objtype: "Difference", children: [
+                    (objtype: "Sphere", vectors: [[1.0, 2.5, 0.0]], scalars: [0.4], material: ["Lambertian", "0.9", "0.9", "0.2"]),
+                    (objtype: "Cone", vectors: [[1.0, 2.3, 0.0], [0.0, 1.0, 0.0]], scalars: [0.3, 0.6], material: ["Lambertian", "0.9", "0.9", "0.2"]),
+                ]),
+                (objtype: "Heightfield", vectors: [[-3.0, -0.4, 2.0], [2.0, 0.3, 1.0]], scalars: [8.0, 5.0, 2.0, 2.0, 0.5], material: ["Lambertian", "0.5", "0.4", "0.3"]),
+                (objtype: "MovingSphere", vectors: [[2.0, 2.0, 0.0], [2.3, 2.1, 0.0]], scalars: [0.25], material: ["Lambertian", "0.8", "0.8", "0.8", "bump=noise", "bump_scale=0.5"]),
+                (objtype: "Box", vectors: [[-0.2, 0.0, -0.2], [0.2, 0.4, 0.2]], transform: Some((translate: [-1.0, 2.0, 0.0])), transform_end: Some((translate: [-0.8, 2.0, 0.0], rotate: [0.0, 20.0, 0.0])), material: ["Lambertian", "0.4", "0.4", "0.9", "opacity=check", "alpha_threshold=0.5"]),
+                (objtype: "Disc", vectors: [[0.0, 2.8, 0.0], [0.0, 1.0, 0.2]], scalars: [0.4], material: ["Lambertian", "0.9", "0.4", "0.4", "opacity=noise", "alpha_mode=stochastic"]),
+                (objtype: "Water", vectors: [[1.0, 0.0, 0.3]], scalars: [-0.45, 3.0, 4.0, 0.02, 0.5, 30.0, 0.25], material: ["Dielectric", "0", "0", "0", "1.33", "absorption=0.2,0.05,0.02"]),
+            ],
+            lights: [[0.0, 4.0, 0.0], [2.0, 4.0, 1.0]],
+        )"#,
+            image = image.display(),
+            mesh = mesh.display(),
+        )
+    }
+
+    fn render(scene: &Scene) -> Vec<u8> {
+        let settings = scene.settings();
+        let mut rgba = vec![0; settings.image_width as usize * settings.image_height as usize * 4];
+        crate::seed_random(7);
+        scene.render_tile(0, 0, settings.image_width, settings.image_height, &mut rgba);
+        rgba
+    }
+
+    fn nested(objects: &[RonObject]) -> Vec<&RonObject> {
+        objects
+            .iter()
+            .flat_map(|object| std::iter::once(object).chain(nested(&object.children)))
+            .collect()
+    }
+
+    #[test]
+    fn written_scene_renders_like_the_loaded_one() {
+        let loaded = Scene::from_ron(full_scene("ron_export"));
+        let written = loaded.to_ron();
+        let reloaded = Scene::from_ron(written.clone());
+        assert_eq!(render(&reloaded), render(&loaded));
+        // Writing is a fixed point: the reloaded scene writes out the same RON.
+        assert_eq!(reloaded.to_ron(), written);
+
+        let description = RaytracerScene::from_ron(written);
+        let mut all = nested(&description.objects);
+        for geometry in &description.geometries {
+            all.extend(nested(&geometry.objects));
+        }
+        let meshes: Vec<_> = all.iter().filter(|obj| obj.objtype == "Mesh").collect();
+        assert!(!meshes.is_empty());
+        assert!(meshes
+            .iter()
+            .all(|mesh| mesh.path.is_none() && mesh.mesh.is_some()));
+        // The triangle instanced twice stays one shared geometry.
+        assert_eq!(description.geometries.len(), 1);
+    }
+    #[test]
+    fn vertex_colored_triangles_are_written_without_uvs() {
+        let colors = VertexColors::new([
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        ]);
+        let triangle = Triangle::new(
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(1.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, -1.0),
+            Material::Lambertian(Arc::new(colors)),
+            false,
+        )
+        .with_uvs([(0.5, 0.5), (1.0, 0.5), (0.5, 1.0)]);
+        let written = Scene::builder().add(triangle).build().to_ron();
+
+        let description = RaytracerScene::from_ron(written.clone());
+        let mesh = description.objects[0].mesh.as_ref().unwrap();
+        assert_eq!(mesh.colors.len(), 3);
+        assert!(mesh.uvs.is_empty());
+        // Loading rejects meshes with both, so the written scene has to load.
+        Scene::from_ron(written);
+    }
+
+    #[test]
+    fn gltf_export_loads_back() {
+        let exported = Scene::from_ron(SCENE.to_string()).to_gltf();
+        let (document, buffers, _) = gltf::import_slice(exported.as_bytes()).unwrap();
+
+        let mesh = document.meshes().next().unwrap();
+        let mut triangles = 0;
+        let mut colored = 0;
+        for primitive in mesh.primitives() {
+            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+            triangles += reader.read_positions().unwrap().count() / 3;
+            assert!(reader.read_normals().is_some() && reader.read_tex_coords(0).is_some());
+            colored += reader.read_colors(0).is_some() as usize;
+        }
+        // The tessellated sphere, two for the rectangle and one per triangle instance.
+        assert_eq!(triangles, SIDES * SIDES + 4);
+        // The overriding mirror material gets a primitive of its own.
+        assert_eq!(mesh.primitives().count(), 4);
+        // The checker texture is baked into vertex colors.
+        assert_eq!(colored, 1);
+        assert_eq!(document.cameras().count(), 1);
+        assert_eq!(document.lights().unwrap().count(), 2);
+    }
+
+    #[test]
+    fn gltf_export_embeds_images_and_tessellates_shapes() {
+        let exported = Scene::from_ron(full_scene("gltf_export")).to_gltf();
+        let (document, buffers, images) = gltf::import_slice(exported.as_bytes()).unwrap();
+        assert_eq!(images.len(), 1);
+        assert_eq!((images[0].width, images[0].height), (4, 4));
+
+        let mesh = document.meshes().next().unwrap();
+        let textured = mesh.primitives().filter(|primitive| {
+            let pbr = primitive.material().pbr_metallic_roughness();
+            pbr.base_color_texture().is_some()
+        });
+        assert_eq!(textured.count(), 1);
+        // Every primitive is tessellated, only the plane, the water and the CSG difference are
+        // left out.
+        let triangles: usize = mesh
+            .primitives()
+            .map(|primitive| {
+                let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+                reader.read_positions().unwrap().count() / 3
+            })
+            .sum();
+        assert!(triangles > SIDES * SIDES * 2);
+        assert_eq!(mesh.primitives().count(), 15);
+    }
+}
@@ -0,0 +1,474 @@
+use crate::configuration::{RaytracerScene, RonObject};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+// Material types understood by `parse_ron_material`, anything else must be a library name.
+const BUILTIN_MATERIALS: [&str; 5] = ["Lambertian", "Metal", "Mirror", "Hair", "Dielectric"];
+
+// Problem found while reading a scene, at the file and line it was written. Line 0 means the
+// file itself could not be read.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SceneFileError {
+    pub file: String,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for SceneFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.file, self.line, self.message)
+    }
+}
+
+// Parses scene text after expanding its directives, then replaces material library names on
+// objects with their definitions. Lines of the form
+//   #include "materials.ron"   splice in another file, relative to the including one
+//   #let radius = 2 * 0.5       define a variable for the rest of the scene
+// and `${expr}` anywhere else is replaced with the value of the arithmetic expression.
+pub fn parse_scene(text: &str, file: Option<&Path>) -> Result<RaytracerScene, SceneFileError> {
+    let mut expander = Expander {
+        lines: vec![],
+        origins: vec![],
+        variables: HashMap::new(),
+        stack: vec![],
+    };
+    let (name, dir) = match file {
+        Some(path) => (
+            path.display().to_string(),
+            path.parent().map(Path::to_path_buf).unwrap_or_default(),
+        ),
+        None => ("<scene>".to_string(), PathBuf::new()),
+    };
+    // The top file counts as being expanded, so including it again is reported where it happens.
+    if let Some(path) = file.and_then(|path| path.canonicalize().ok()) {
+        expander.stack.push(path);
+    }
+    expander.expand(text, &name, &dir)?;
+
+    let source = expander.lines.join("\n");
+    let mut scene: RaytracerScene = ron::from_str(&source)
+        .map_err(|e| expander.error_at(e.position.line.saturating_sub(1), e.code.to_string()))?;
+
+    let mut library: HashMap<String, Vec<String>> = HashMap::new();
+    for entry in &scene.materials {
+        let definition = resolve_material(&entry.material, &library)
+            .map_err(|name| expander.error_at_text(&name, unknown_material(&name)))?;
+        library.insert(entry.name.clone(), definition);
+    }
+    let mut objects = std::mem::take(&mut scene.objects);
+    resolve_objects(&mut objects, &library)
+        .map_err(|name| expander.error_at_text(&name, unknown_material(&name)))?;
+    scene.objects = objects;
+    for geometry in &mut scene.geometries {
+        resolve_objects(&mut geometry.objects, &library)
+            .map_err(|name| expander.error_at_text(&name, unknown_material(&name)))?;
+    }
+    Ok(scene)
+}
+
+pub fn load_scene(path: &str) -> Result<RaytracerScene, SceneFileError> {
+    let text = std::fs::read_to_string(path).map_err(|e| SceneFileError {
+        file: path.to_string(),
+        line: 0,
+        message: e.to_string(),
+    })?;
+    parse_scene(&text, Some(Path::new(path)))
+}
+
+fn unknown_material(name: &str) -> String {
+    format!("unknown material {}", name)
+}
+
+// Expanded scene text with the file and line each output line came from.
+struct Expander {
+    lines: Vec<String>,
+    origins: Vec<(String, usize)>,
+    variables: HashMap<String, f64>,
+    // Files being expanded, to catch include cycles.
+    stack: Vec<PathBuf>,
+}
+
+impl Expander {
+    fn expand(&mut self, text: &str, name: &str, dir: &Path) -> Result<(), SceneFileError> {
+        for (index, line) in text.lines().enumerate() {
+            let error = |message: String| SceneFileError {
+                file: name.to_string(),
+                line: index + 1,
+                message,
+            };
+            let directive = line.trim_start();
+            if let Some(rest) = directive.strip_prefix("#include ") {
+                let included = rest.trim().trim_matches('"');
+                let path = dir.join(included);
+                let canonical = path
+                    .canonicalize()
+                    .map_err(|e| error(format!("cannot include {}: {}", included, e)))?;
+                if self.stack.contains(&canonical) {
+                    return Err(error(format!("{} includes itself", included)));
+                }
+                let contents = std::fs::read_to_string(&path)
+                    .map_err(|e| error(format!("cannot include {}: {}", included, e)))?;
+                self.stack.push(canonical);
+                let parent = path.parent().map(Path::to_path_buf).unwrap_or_default();
+                self.expand(&contents, &path.display().to_string(), &parent)?;
+                self.stack.pop();
+            } else if let Some(rest) = directive.strip_prefix("#let ") {
+                let (variable, expression) = rest
+                    .split_once('=')
+                    .ok_or_else(|| error("expected #let name = expression".to_string()))?;
+                let variable = variable.trim();
+                if !is_identifier(variable) {
+                    return Err(error(format!("invalid variable name {}", variable)));
+                }
+                let value = evaluate(expression, &self.variables).map_err(error)?;
+                self.variables.insert(variable.to_string(), value);
+                // Keep the line so RON errors still map to the right place.
+                self.push(String::new(), name, index + 1);
+            } else {
+                let substituted = self.substitute(line).map_err(error)?;
+                self.push(substituted, name, index + 1);
+            }
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, line: String, file: &str, number: usize) {
+        self.lines.push(line);
+        self.origins.push((file.to_string(), number));
+    }
+
+    fn substitute(&self, line: &str) -> Result<String, String> {
+        let mut out = String::new();
+        let mut rest = line;
+        while let Some(start) = rest.find("${") {
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| "unclosed ${".to_string())?;
+            let value = evaluate(&rest[start + 2..start + end], &self.variables)?;
+            out.push_str(&rest[..start]);
+            out.push_str(&value.to_string());
+            rest = &rest[start + end + 1..];
+        }
+        out.push_str(rest);
+        Ok(out)
+    }
+
+    fn error_at(&self, index: usize, message: String) -> SceneFileError {
+        let (file, line) = self
+            .origins
+            .get(index)
+            .or_else(|| self.origins.last())
+            .cloned()
+            .unwrap_or_else(|| ("<scene>".to_string(), 0));
+        SceneFileError {
+            file,
+            line,
+            message,
+        }
+    }
+
+    // Points at the first line mentioning the quoted name, once the scene is already parsed.
+    fn error_at_text(&self, name: &str, message: String) -> SceneFileError {
+        let quoted = format!("\"{}\"", name);
+        let index = self
+            .lines
+            .iter()
+            .position(|line| line.contains(&quoted))
+            .unwrap_or(0);
+        self.error_at(index, message)
+    }
+}
+
+// Library names expand to their definition, followed by any further entries such as modifiers.
+// The error holds the name that is neither a material type nor in the library.
+fn resolve_material(
+    material: &[String],
+    library: &HashMap<String, Vec<String>>,
+) -> Result<Vec<String>, String> {
+    let first = match material.first() {
+        Some(first) => first,
+        None => return Ok(vec![]),
+    };
+    if BUILTIN_MATERIALS.contains(&first.as_str()) {
+        return Ok(material.to_vec());
+    }
+    match library.get(first) {
+        Some(definition) => Ok(definition.iter().chain(&material[1..]).cloned().collect()),
+        None => Err(first.clone()),
+    }
+}
+
+fn resolve_objects(
+    objects: &mut [RonObject],
+    library: &HashMap<String, Vec<String>>,
+) -> Result<(), String> {
+    for obj in objects {
+        obj.material = resolve_material(&obj.material, library)?;
+        resolve_objects(&mut obj.children, library)?;
+    }
+    Ok(())
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Name(String),
+    Symbol(char),
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let chars: Vec<char> = expression.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            let number = text
+                .parse()
+                .map_err(|_| format!("invalid number {}", text))?;
+            tokens.push(Token::Number(number));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Name(chars[start..i].iter().collect()));
+        } else if "+-*/^()".contains(c) {
+            tokens.push(Token::Symbol(c));
+            i += 1;
+        } else {
+            return Err(format!("unexpected {} in expression", c));
+        }
+    }
+    Ok(tokens)
+}
+
+// Arithmetic with + - * / ^, parentheses, variables, `pi` and the functions sqrt, abs, sin,
+// cos and tan (in degrees).
+fn evaluate(expression: &str, variables: &HashMap<String, f64>) -> Result<f64, String> {
+    let mut parser = Parser {
+        tokens: tokenize(expression)?,
+        position: 0,
+        variables,
+    };
+    let value = parser.sum()?;
+    if let Some(token) = parser.tokens.get(parser.position) {
+        return Err(format!("unexpected {:?} in expression", token));
+    }
+    if !value.is_finite() {
+        return Err(format!("{} is not a finite number", expression.trim()));
+    }
+    Ok(value)
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    position: usize,
+    variables: &'a HashMap<String, f64>,
+}
+
+impl<'a> Parser<'a> {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn accept(&mut self, symbol: char) -> bool {
+        if self.tokens.get(self.position) == Some(&Token::Symbol(symbol)) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn sum(&mut self) -> Result<f64, String> {
+        let mut value = self.product()?;
+        loop {
+            if self.accept('+') {
+                value += self.product()?;
+            } else if self.accept('-') {
+                value -= self.product()?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn product(&mut self) -> Result<f64, String> {
+        let mut value = self.unary()?;
+        loop {
+            if self.accept('*') {
+                value *= self.unary()?;
+            } else if self.accept('/') {
+                value /= self.unary()?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<f64, String> {
+        if self.accept('-') {
+            return Ok(-self.unary()?);
+        }
+        let base = self.atom()?;
+        // Right associative, so 2^3^2 is 2^9.
+        if self.accept('^') {
+            return Ok(base.powf(self.unary()?));
+        }
+        Ok(base)
+    }
+
+    fn atom(&mut self) -> Result<f64, String> {
+        match self.next() {
+            Some(Token::Number(value)) => Ok(value),
+            Some(Token::Symbol('(')) => {
+                let value = self.sum()?;
+                if !self.accept(')') {
+                    return Err("missing )".to_string());
+                }
+                Ok(value)
+            }
+            Some(Token::Name(name)) => {
+                if self.accept('(') {
+                    let argument = self.sum()?;
+                    if !self.accept(')') {
+                        return Err("missing )".to_string());
+                    }
+                    return match &name[..] {
+                        "sqrt" => Ok(argument.sqrt()),
+                        "abs" => Ok(argument.abs()),
+                        "sin" => Ok(argument.to_radians().sin()),
+                        "cos" => Ok(argument.to_radians().cos()),
+                        "tan" => Ok(argument.to_radians().tan()),
+                        _ => Err(format!("unknown function {}", name)),
+                    };
+                }
+                match (&name[..], self.variables.get(&name)) {
+                    (_, Some(value)) => Ok(*value),
+                    ("pi", None) => Ok(std::f64::consts::PI),
+                    _ => Err(format!("undefined variable {}", name)),
+                }
+            }
+            Some(token) => Err(format!("unexpected {:?} in expression", token)),
+            None => Err("incomplete expression".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(expression: &str) -> f64 {
+        evaluate(expression, &HashMap::new()).unwrap()
+    }
+
+    // Scene files in a directory of their own under the temporary directory.
+    fn write_files(dir: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, contents) in files {
+            std::fs::write(dir.join(name), contents).unwrap();
+        }
+        dir
+    }
+
+    const SETTINGS: &str = "(
+    multithreading: false,
+    aspect_ratio: 1.0,
+    image_width: 8,
+    image_height: 8,
+    samples_per_pixel: 1,
+    max_depth: 2,
+    v_fov: 60.0,
+    aperture: 0.0,
+    focal_distance: 1.0,
+    camera_pos: [0.0, 0.0, 1.0],
+    camera_dir: [0.0, 0.0, 0.0],
+    camera_up: [0.0, 1.0, 0.0],
+    lights: [],";
+
+    #[test]
+    fn precedence() {
+        assert_eq!(value("1 + 2 * 3"), 7.0);
+        assert_eq!(value("(1 + 2) * 3"), 9.0);
+        assert_eq!(value("10 - 4 - 3"), 3.0);
+        assert_eq!(value("12 / 3 / 2"), 2.0);
+        assert_eq!(value("2 * 3 ^ 2"), 18.0);
+        assert_eq!(value("-2 ^ 2"), -4.0);
+        assert_eq!(value("sqrt(8 * 2) + cos(60)"), 4.5);
+    }
+
+    #[test]
+    fn power_is_right_associative() {
+        assert_eq!(value("2 ^ 3 ^ 2"), 512.0);
+        assert_eq!(value("(2 ^ 3) ^ 2"), 64.0);
+    }
+
+    #[test]
+    fn unknown_variable() {
+        let mut variables = HashMap::new();
+        let error = evaluate("2 * radius", &variables).unwrap_err();
+        assert_eq!(error, "undefined variable radius");
+        variables.insert("radius".to_string(), 1.5);
+        assert_eq!(evaluate("2 * radius", &variables), Ok(3.0));
+        assert_eq!(value("pi"), std::f64::consts::PI);
+    }
+
+    #[test]
+    fn include_cycle_is_an_error() {
+        let dir = write_files(
+            "scene_file_cycle",
+            &[
+                ("a.ron", "#include \"b.ron\"\n"),
+                ("b.ron", "// shared\n#include \"a.ron\"\n"),
+            ],
+        );
+        let error = load_scene(dir.join("a.ron").to_str().unwrap()).unwrap_err();
+        assert!(error.file.ends_with("b.ron"), "{}", error);
+        assert_eq!(error.line, 2);
+        assert_eq!(error.message, "a.ron includes itself");
+    }
+
+    #[test]
+    fn error_in_included_file_reports_its_line() {
+        let objects = "#let radius = 0.5\n    objects: [\n        (objtype: \"Sphere\", \
+                       vectors: [[0.0, 0.0, 0.0]], scalars: [${2 * radus}]),\n    ],\n";
+        let dir = write_files(
+            "scene_file_include",
+            &[
+                ("objects.ron", objects),
+                (
+                    "scene.ron",
+                    &format!("{}\n#include \"objects.ron\"\n)", SETTINGS),
+                ),
+            ],
+        );
+        let error = load_scene(dir.join("scene.ron").to_str().unwrap()).unwrap_err();
+        assert!(error.file.ends_with("objects.ron"), "{}", error);
+        assert_eq!(error.line, 3);
+        assert_eq!(error.message, "undefined variable radus");
+
+        // The same scene without the typo loads, with the included objects.
+        std::fs::write(dir.join("objects.ron"), objects.replace("radus", "radius")).unwrap();
+        let scene = load_scene(dir.join("scene.ron").to_str().unwrap()).unwrap();
+        assert_eq!(scene.objects[0].scalars, vec![1.0]);
+    }
+}
@@ -0,0 +1,448 @@
+use crate::aabb::Aabb;
+use crate::configuration::RonSdf;
+use crate::cube::slab_intersect;
+use crate::frame::Frame;
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::matrix::Mat4;
+use crate::perlin::Perlin;
+use crate::ray::Ray;
+use crate::scene_export::{ron_vector, SceneWriter};
+use crate::vec3::Vec3;
+use std::f64::consts::PI;
+
+const MAX_STEPS: usize = 512;
+
+fn abs(v: Vec3) -> Vec3 {
+    Vec3::new(v.x().abs(), v.y().abs(), v.z().abs())
+}
+
+fn max0(v: Vec3) -> Vec3 {
+    Vec3::new(v.x().max(0.0), v.y().max(0.0), v.z().max(0.0))
+}
+
+// Polynomial smooth minimum, blending over a distance of about `k`.
+fn smooth_min(a: f64, b: f64, k: f64) -> f64 {
+    if k <= 0.0 {
+        return a.min(b);
+    }
+    let h = (0.5 + 0.5 * (b - a) / k).clamp(0.0, 1.0);
+    b * (1.0 - h) + a * h - k * h * (1.0 - h)
+}
+
+fn smooth_max(a: f64, b: f64, k: f64) -> f64 {
+    -smooth_min(-a, -b, k)
+}
+
+/// Signed distance function tree, negative inside. Combinators take a smoothing radius, 0 gives
+/// the hard operation.
+#[derive(Debug, Clone)]
+pub enum SdfNode {
+    Sphere(f64),
+    /// Half extents.
+    Box(Vec3),
+    /// Half extents and edge radius.
+    RoundBox(Vec3, f64),
+    /// Major and minor radius, around the y axis.
+    Torus(f64, f64),
+    /// Radius and half height, along the y axis.
+    Cylinder(f64, f64),
+    /// Segment end points and radius.
+    Capsule(Vec3, Vec3, f64),
+    /// Mandelbulb with power and iteration count, inside a radius of about 1.2.
+    Mandelbulb(f64, usize),
+    Union(Box<SdfNode>, Box<SdfNode>, f64),
+    Intersection(Box<SdfNode>, Box<SdfNode>, f64),
+    Subtraction(Box<SdfNode>, Box<SdfNode>, f64),
+    Translate(Box<SdfNode>, Vec3),
+    /// Rotation from the Euler angles in degrees, also stored as the inverse to map points back.
+    Rotate(Box<SdfNode>, Vec3, Mat4),
+    Scale(Box<SdfNode>, f64),
+    /// Surface pushed out by fBm noise with amplitude, frequency and octaves, e.g. rocks.
+    Displace(Box<SdfNode>, Perlin, f64, f64, usize),
+}
+
+impl SdfNode {
+    pub fn rotate(child: SdfNode, degrees: Vec3) -> SdfNode {
+        SdfNode::Rotate(
+            Box::new(child),
+            degrees,
+            Mat4::rotation(degrees).transpose(),
+        )
+    }
+
+    pub fn distance(&self, p: Vec3) -> f64 {
+        match self {
+            SdfNode::Sphere(radius) => p.length() - radius,
+            SdfNode::Box(half) => {
+                let q = abs(p) - *half;
+                max0(q).length() + q.x().max(q.y()).max(q.z()).min(0.0)
+            }
+            SdfNode::RoundBox(half, radius) => {
+                let q = abs(p) - *half + Vec3::new(*radius, *radius, *radius);
+                max0(q).length() + q.x().max(q.y()).max(q.z()).min(0.0) - radius
+            }
+            SdfNode::Torus(major, minor) => {
+                let ring = (p.x() * p.x() + p.z() * p.z()).sqrt() - major;
+                (ring * ring + p.y() * p.y()).sqrt() - minor
+            }
+            SdfNode::Cylinder(radius, half_height) => {
+                let dx = (p.x() * p.x() + p.z() * p.z()).sqrt() - radius;
+                let dy = p.y().abs() - half_height;
+                dx.max(dy).min(0.0) + (dx.max(0.0).powi(2) + dy.max(0.0).powi(2)).sqrt()
+            }
+            SdfNode::Capsule(a, b, radius) => {
+                let pa = p - *a;
+                let ba = *b - *a;
+                let h = (pa.dot(ba) / ba.length_squared()).clamp(0.0, 1.0);
+                (pa - ba * h).length() - radius
+            }
+            SdfNode::Mandelbulb(power, iterations) => {
+                let mut z = p;
+                let mut dr = 1.0;
+                let mut r = z.length();
+                for _ in 0..*iterations {
+                    r = z.length();
+                    if !(1e-12..=2.0).contains(&r) {
+                        break;
+                    }
+                    let theta = (z.z() / r).acos() * power;
+                    let phi = z.y().atan2(z.x()) * power;
+                    dr = r.powf(power - 1.0) * power * dr + 1.0;
+                    z = Vec3::new(
+                        theta.sin() * phi.cos(),
+                        theta.sin() * phi.sin(),
+                        theta.cos(),
+                    ) * r.powf(*power)
+                        + p;
+                }
+                0.5 * r.max(1e-12).ln() * r / dr
+            }
+            SdfNode::Union(a, b, k) => smooth_min(a.distance(p), b.distance(p), *k),
+            SdfNode::Intersection(a, b, k) => smooth_max(a.distance(p), b.distance(p), *k),
+            SdfNode::Subtraction(a, b, k) => smooth_max(a.distance(p), -b.distance(p), *k),
+            SdfNode::Translate(child, offset) => child.distance(p - *offset),
+            SdfNode::Rotate(child, _, inverse) => child.distance(inverse.transform_vector(p)),
+            SdfNode::Scale(child, factor) => child.distance(p * (1.0 / factor)) * factor,
+            SdfNode::Displace(child, perlin, amplitude, frequency, octaves) => {
+                child.distance(p) + amplitude * perlin.fbm(p * *frequency, *octaves, 2.0, 0.5)
+            }
+        }
+    }
+
+    /// Conservative bounds of the negative region.
+    pub fn bounds(&self) -> Aabb {
+        let cube =
+            |half: f64| Aabb::new(Vec3::new(-half, -half, -half), Vec3::new(half, half, half));
+        let pad = |b: Aabb, amount: f64| {
+            let amount = Vec3::new(amount, amount, amount);
+            Aabb::new(b.min - amount, b.max + amount)
+        };
+        match self {
+            SdfNode::Sphere(radius) => cube(*radius),
+            SdfNode::Box(half) | SdfNode::RoundBox(half, _) => Aabb::new(-*half, *half),
+            SdfNode::Torus(major, minor) => Aabb::new(
+                Vec3::new(-(major + minor), -minor, -(major + minor)),
+                Vec3::new(major + minor, *minor, major + minor),
+            ),
+            SdfNode::Cylinder(radius, half_height) => Aabb::new(
+                Vec3::new(-radius, -half_height, -radius),
+                Vec3::new(*radius, *half_height, *radius),
+            ),
+            SdfNode::Capsule(a, b, radius) => pad(Aabb::from_points(&[*a, *b]), *radius),
+            SdfNode::Mandelbulb(_, _) => cube(1.2),
+            SdfNode::Union(a, b, k) => pad(a.bounds().surrounding(&b.bounds()), *k),
+            SdfNode::Intersection(a, _, k) | SdfNode::Subtraction(a, _, k) => pad(a.bounds(), *k),
+            SdfNode::Translate(child, offset) => {
+                let b = child.bounds();
+                Aabb::new(b.min + *offset, b.max + *offset)
+            }
+            SdfNode::Rotate(child, _, inverse) => child.bounds().transformed(&inverse.transpose()),
+            SdfNode::Scale(child, factor) => {
+                let b = child.bounds();
+                Aabb::new(b.min * *factor, b.max * *factor)
+            }
+            SdfNode::Displace(child, _, amplitude, _, _) => pad(child.bounds(), amplitude.abs()),
+        }
+    }
+
+    // Upper bound on how fast the function changes, steps are shortened by it so displaced
+    // and smoothed fields that are not exact distances are not overshot.
+    fn lipschitz(&self) -> f64 {
+        match self {
+            SdfNode::Union(a, b, _)
+            | SdfNode::Intersection(a, b, _)
+            | SdfNode::Subtraction(a, b, _) => a.lipschitz().max(b.lipschitz()),
+            SdfNode::Translate(child, _)
+            | SdfNode::Rotate(child, _, _)
+            | SdfNode::Scale(child, _) => child.lipschitz(),
+            SdfNode::Displace(child, _, amplitude, frequency, _) => {
+                child.lipschitz() + 2.0 * amplitude.abs() * frequency
+            }
+            _ => 1.0,
+        }
+    }
+
+    /// The node as parsed by `parse_ron_sdf`.
+    pub(crate) fn to_ron(&self) -> RonSdf {
+        let node = |shape: &str, vectors: &[Vec3], scalars: &[f64], children: &[&SdfNode]| RonSdf {
+            shape: shape.to_string(),
+            vectors: vectors.iter().map(|v| ron_vector(*v)).collect(),
+            scalars: scalars.to_vec(),
+            children: children.iter().map(|child| child.to_ron()).collect(),
+        };
+        match self {
+            SdfNode::Sphere(radius) => node("Sphere", &[], &[*radius], &[]),
+            SdfNode::Box(half) => node("Box", &[*half], &[], &[]),
+            SdfNode::RoundBox(half, radius) => node("RoundBox", &[*half], &[*radius], &[]),
+            SdfNode::Torus(major, minor) => node("Torus", &[], &[*major, *minor], &[]),
+            SdfNode::Cylinder(radius, half_height) => {
+                node("Cylinder", &[], &[*radius, *half_height], &[])
+            }
+            SdfNode::Capsule(a, b, radius) => node("Capsule", &[*a, *b], &[*radius], &[]),
+            SdfNode::Mandelbulb(power, iterations) => {
+                node("Mandelbulb", &[], &[*power, *iterations as f64], &[])
+            }
+            SdfNode::Union(a, b, smooth) => node("Union", &[], &[*smooth], &[a, b]),
+            SdfNode::Intersection(a, b, smooth) => node("Intersection", &[], &[*smooth], &[a, b]),
+            SdfNode::Subtraction(a, b, smooth) => node("Subtraction", &[], &[*smooth], &[a, b]),
+            SdfNode::Translate(child, offset) => node("Translate", &[*offset], &[], &[child]),
+            SdfNode::Rotate(child, degrees, _) => node("Rotate", &[*degrees], &[], &[child]),
+            SdfNode::Scale(child, factor) => node("Scale", &[], &[*factor], &[child]),
+            SdfNode::Displace(child, perlin, amplitude, frequency, octaves) => node(
+                "Displace",
+                &[],
+                &[
+                    perlin.seed() as f64,
+                    *amplitude,
+                    *frequency,
+                    *octaves as f64,
+                ],
+                &[child],
+            ),
+        }
+    }
+}
+
+/// Implicit surface found by sphere tracing the distance field inside its bounds.
+pub struct Sdf {
+    root: SdfNode,
+    bounds: Aabb,
+    step_scale: f64,
+    material: Material,
+}
+
+impl Sdf {
+    pub fn new(root: SdfNode, mat: Material) -> Sdf {
+        Sdf {
+            bounds: root.bounds(),
+            step_scale: 0.9 / root.lipschitz(),
+            root,
+            material: mat,
+        }
+    }
+
+    // Central differences of the distance field.
+    fn normal(&self, p: Vec3) -> Vec3 {
+        let h = 1e-5 * (1.0 + p.length());
+        let d = |offset: Vec3| self.root.distance(p + offset) - self.root.distance(p - offset);
+        Vec3::new(
+            d(Vec3::new(h, 0.0, 0.0)),
+            d(Vec3::new(0.0, h, 0.0)),
+            d(Vec3::new(0.0, 0.0, h)),
+        )
+        .unit_vector()
+    }
+}
+
+impl Hittable for Sdf {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let ((t_near, _), (t_far, _)) = match slab_intersect(self.bounds.min, self.bounds.max, ray)
+        {
+            Some(hits) => hits,
+            None => return false,
+        };
+        let speed = ray.direction().length();
+        let end = t_far.min(t_max);
+        let mut t = t_near.max(t_min);
+        if t >= end {
+            return false;
+        }
+
+        // Rays starting inside march on the absolute distance until they leave the surface.
+        let side = self.root.distance(ray.at(t)).signum();
+        let mut hit_t = None;
+        for _ in 0..MAX_STEPS {
+            let distance = side * self.root.distance(ray.at(t));
+            let epsilon = 1e-5 * (1.0 + t * speed);
+            if distance < epsilon {
+                hit_t = Some(t);
+                break;
+            }
+            t += distance.max(epsilon) * self.step_scale / speed;
+            if t >= end {
+                return false;
+            }
+        }
+        let t = match hit_t {
+            Some(t) if t > t_min => t,
+            _ => return false,
+        };
+
+        let p = ray.at(t);
+        let outward_normal = self.normal(p);
+        let frame = Frame::new(p, outward_normal);
+        let dir = (p - self.bounds.centroid()).unit_vector();
+        rec.t = Some(t);
+        rec.p = Some(p);
+        rec.set_face_normal(ray, outward_normal);
+        rec.material = Some(self.material.clone());
+        // Spherical mapping around the centre of the bounds.
+        rec.uv = Some((
+            ((-dir.z()).atan2(dir.x()) + PI) / (2.0 * PI),
+            (-dir.y()).acos() / PI,
+        ));
+        rec.tangent = Some(frame.tangent);
+        rec.bitangent = Some(outward_normal.cross(frame.tangent));
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+
+    fn export(&self, writer: &mut SceneWriter) {
+        writer.primitive("Sdf", &[], &[], &self.material).sdf = Some(self.root.to_ron());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sphere(radius: f64) -> Box<SdfNode> {
+        Box::new(SdfNode::Sphere(radius))
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    fn cast(sdf: &Sdf, origin: Vec3, direction: Vec3) -> Option<HitRecord> {
+        let mut rec = HitRecord::new();
+        let ray = Ray::new(origin, direction, 0.0);
+        sdf.hit(ray, 0.001, f64::INFINITY, &mut rec).then_some(rec)
+    }
+
+    #[test]
+    fn primitives_are_exact_distances() {
+        let p = |x: f64, y: f64, z: f64| Vec3::new(x, y, z);
+        let cube = SdfNode::Box(p(1.0, 1.0, 1.0));
+        assert!(close(cube.distance(p(2.0, 0.0, 0.0)), 1.0));
+        assert!(close(cube.distance(p(2.0, 2.0, 0.0)), 2.0_f64.sqrt()));
+        assert!(close(cube.distance(p(0.0, 0.5, 0.0)), -0.5));
+        let rounded = SdfNode::RoundBox(p(1.0, 1.0, 1.0), 0.25);
+        assert!(close(rounded.distance(p(2.0, 0.0, 0.0)), 1.0));
+        // Corners are rounded about a point inset by the edge radius.
+        assert!(close(
+            rounded.distance(p(2.0, 2.0, 0.0)),
+            1.25 * 2.0_f64.sqrt() - 0.25
+        ));
+        let torus = SdfNode::Torus(2.0, 0.5);
+        assert!(close(torus.distance(p(0.0, 0.0, 2.0)), -0.5));
+        assert!(close(torus.distance(p(0.0, 0.0, 0.0)), 1.5));
+        let cylinder = SdfNode::Cylinder(1.0, 1.0);
+        assert!(close(cylinder.distance(p(0.0, 3.0, 0.0)), 2.0));
+        assert!(close(cylinder.distance(p(3.0, 0.0, 0.0)), 2.0));
+        assert!(close(cylinder.distance(p(4.0, 5.0, 0.0)), 5.0));
+        let capsule = SdfNode::Capsule(p(0.0, 0.0, 0.0), p(0.0, 2.0, 0.0), 0.5);
+        assert!(close(capsule.distance(p(0.0, 3.0, 0.0)), 0.5));
+        assert!(close(capsule.distance(p(1.0, 1.0, 0.0)), 0.5));
+    }
+
+    #[test]
+    fn transforms_move_the_field() {
+        let moved = SdfNode::Translate(sphere(1.0), Vec3::new(3.0, 0.0, 0.0));
+        assert!(close(moved.distance(Vec3::new(3.0, 2.0, 0.0)), 1.0));
+        let bounds = moved.bounds();
+        assert!(close(bounds.min.x(), 2.0) && close(bounds.max.x(), 4.0));
+
+        let turned = SdfNode::rotate(
+            SdfNode::Box(Vec3::new(2.0, 1.0, 1.0)),
+            Vec3::new(0.0, 0.0, 90.0),
+        );
+        assert!(close(turned.distance(Vec3::new(0.0, 3.0, 0.0)), 1.0));
+        assert!(close(turned.distance(Vec3::new(3.0, 0.0, 0.0)), 2.0));
+
+        // Scaling keeps distances exact, so the step length does not change.
+        let scaled = SdfNode::Scale(sphere(1.0), 2.0);
+        assert!(close(scaled.distance(Vec3::new(3.0, 0.0, 0.0)), 1.0));
+        assert!(close(scaled.bounds().max.y(), 2.0));
+        assert!(close(scaled.lipschitz(), 1.0));
+    }
+
+    #[test]
+    fn smooth_combinators_blend_only_near_the_seam() {
+        let (a, b) = (
+            sphere(1.0),
+            Box::new(SdfNode::Translate(sphere(1.0), Vec3::new(1.5, 0.0, 0.0))),
+        );
+        let seam = Vec3::new(0.75, 0.8, 0.0);
+        let far = Vec3::new(-3.0, 0.0, 0.0);
+        let hard = |node: SdfNode| node.distance(seam);
+
+        let union = SdfNode::Union(a.clone(), b.clone(), 0.5);
+        assert!(union.distance(seam) < hard(SdfNode::Union(a.clone(), b.clone(), 0.0)) - 1e-3);
+        assert!(close(union.distance(far), 2.0));
+
+        let intersection = SdfNode::Intersection(a.clone(), b.clone(), 0.5);
+        assert!(
+            intersection.distance(seam)
+                > hard(SdfNode::Intersection(a.clone(), b.clone(), 0.0)) + 1e-3
+        );
+        assert!(close(intersection.distance(far), 3.5));
+
+        let subtraction = SdfNode::Subtraction(a.clone(), b.clone(), 0.0);
+        assert!(close(subtraction.distance(Vec3::new(-0.5, 0.0, 0.0)), -0.5));
+        assert!(close(subtraction.distance(Vec3::new(0.75, 0.0, 0.0)), 0.25));
+    }
+
+    #[test]
+    fn steps_are_shortened_by_the_lipschitz_bound() {
+        let rough = SdfNode::Displace(sphere(1.0), Perlin::new(1), 0.1, 5.0, 3);
+        assert!(close(rough.lipschitz(), 2.0));
+        let both = SdfNode::Union(Box::new(rough), sphere(1.0), 0.0);
+        assert!(close(both.lipschitz(), 2.0));
+        let sdf = Sdf::new(both, Material::Mirror);
+        assert!(close(sdf.step_scale, 0.45));
+        assert!(close(
+            Sdf::new(SdfNode::Sphere(1.0), Material::Mirror).step_scale,
+            0.9
+        ));
+    }
+
+    #[test]
+    fn rays_hit_the_surface_from_outside_and_inside() {
+        let ball = Sdf::new(SdfNode::Sphere(1.0), Material::Mirror);
+        let rec = cast(&ball, Vec3::new(5.0, 0.0, 0.0), Vec3::new(-2.0, 0.0, 0.0)).unwrap();
+        assert!((rec.t.unwrap() - 2.0).abs() < 1e-4);
+        assert!((rec.normal.unwrap() - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-4);
+        assert_eq!(rec.get_front_face(), Some(true));
+
+        let rec = cast(&ball, Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0)).unwrap();
+        assert!((rec.t.unwrap() - 1.0).abs() < 1e-4);
+        assert!((rec.normal.unwrap() - Vec3::new(-1.0, 0.0, 0.0)).length() < 1e-4);
+        assert_eq!(rec.get_front_face(), Some(false));
+
+        // Inside the hollow of a shell the ray is outside the shape and meets its inner wall.
+        let shell = Sdf::new(
+            SdfNode::Subtraction(sphere(2.0), sphere(1.0), 0.0),
+            Material::Mirror,
+        );
+        let rec = cast(&shell, Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0)).unwrap();
+        assert!((rec.t.unwrap() - 1.0).abs() < 1e-4);
+        assert_eq!(rec.get_front_face(), Some(true));
+
+        assert!(cast(&ball, Vec3::new(5.0, 2.0, 0.0), Vec3::new(-1.0, 0.0, 0.0)).is_none());
+    }
+}
@@ -1,11 +1,16 @@
+use crate::aabb::Aabb;
 use crate::hittable;
 use crate::material;
 use crate::ray::Ray;
+use crate::scene_export::SceneWriter;
 use crate::vec3::Vec3;
 
-#[derive(Debug, Copy, Clone)]
+/// Sphere, optionally moving in a straight line while the shutter is open.
+#[derive(Debug, Clone)]
 pub struct Sphere {
     center: Vec3,
+    // Offset travelled by the center between times 0 and 1.
+    motion: Vec3,
     radius: f64,
     material: material::Material,
 }
@@ -15,16 +20,53 @@ impl Sphere {
     pub fn new(cen: Vec3, rad: f64, mat: material::Material) -> Sphere {
         Sphere {
             center: cen,
+            motion: Vec3::new(0.0, 0.0, 0.0),
             radius: rad,
             material: mat,
         }
     }
+
+    /// Sphere centred on `cen0` at time 0 and on `cen1` at time 1.
+    pub fn moving(cen0: Vec3, cen1: Vec3, rad: f64, mat: material::Material) -> Sphere {
+        Sphere {
+            center: cen0,
+            motion: cen1 - cen0,
+            radius: rad,
+            material: mat,
+        }
+    }
+
+    pub fn center(&self, time: f64) -> Vec3 {
+        self.center + self.motion * time
+    }
+}
+
+impl Sphere {
+    // Spherical mapping: u wraps around the y axis starting at -x, v runs from the bottom pole.
+    fn set_surface_frame(&self, outward_normal: Vec3, rec: &mut hittable::HitRecord) {
+        let theta = (-outward_normal.y()).acos();
+        let phi = (-outward_normal.z()).atan2(outward_normal.x()) + std::f64::consts::PI;
+        rec.uv = Some((
+            phi / (2.0 * std::f64::consts::PI),
+            theta / std::f64::consts::PI,
+        ));
+
+        let ring = Vec3::new(outward_normal.z(), 0.0, -outward_normal.x());
+        let tangent = if ring.length_squared() > 1e-12 {
+            ring.unit_vector()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        rec.tangent = Some(tangent);
+        rec.bitangent = Some(outward_normal.cross(tangent));
+    }
 }
 
 impl hittable::Hittable for Sphere {
     fn hit(&self, ray: Ray, t_min: f64, t_max: f64, rec: &mut hittable::HitRecord) -> bool {
-        let r = ray.clone();
-        let oc: Vec3 = r.origin() - self.center;
+        let r = ray;
+        let center = self.center(r.time());
+        let oc: Vec3 = r.origin() - center;
         let a = r.direction().length_squared();
         let half_b = oc.dot(r.direction());
         let c = oc.length_squared() - self.radius * self.radius;
@@ -35,25 +77,133 @@ impl hittable::Hittable for Sphere {
             let temp1 = (-half_b - root) / a;
             let temp2 = (-half_b + root) / a;
 
-            if temp1 < t_max && temp1 > t_min {
-                rec.t = Some(temp1);
-                rec.p = Some(r.at(rec.t.unwrap()));
-                let outward_normal = (rec.p.unwrap() - self.center) * (1.0 / self.radius);
-                rec.set_face_normal(r, outward_normal);
-                rec.material = Some(self.material.clone());
-                true
+            let t = if temp1 < t_max && temp1 > t_min {
+                temp1
             } else if temp2 < t_max && temp2 > t_min {
-                rec.t = Some(temp1);
-                rec.p = Some(r.at(rec.t.unwrap()));
-                let outward_normal = (rec.p.unwrap() - self.center) * (1.0 / self.radius);
-                rec.set_face_normal(r, outward_normal);
-                rec.material = Some(self.material.clone());
-                true
+                temp2
             } else {
-                false
-            }
+                return false;
+            };
+
+            rec.t = Some(t);
+            rec.p = Some(r.at(t));
+            let outward_normal = (rec.p.unwrap() - center) * (1.0 / self.radius);
+            rec.set_face_normal(r, outward_normal);
+            rec.material = Some(self.material.clone());
+            self.set_surface_frame(outward_normal, rec);
+            true
         } else {
             false
         }
     }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let extent = Vec3::new(self.radius, self.radius, self.radius);
+        let start = Aabb::new(self.center(0.0) - extent, self.center(0.0) + extent);
+        let end = Aabb::new(self.center(1.0) - extent, self.center(1.0) + extent);
+        Some(start.surrounding(&end))
+    }
+
+    fn intervals(&self, ray: Ray) -> Option<Vec<hittable::Interval>> {
+        let oc = ray.origin() - self.center(ray.time());
+        let a = ray.direction().length_squared();
+        let half_b = oc.dot(ray.direction());
+        let c = oc.length_squared() - self.radius * self.radius;
+        let discriminant = half_b * half_b - a * c;
+        if discriminant <= 0.0 {
+            return Some(vec![]);
+        }
+        let root = discriminant.sqrt();
+        Some(vec![((-half_b - root) / a, (-half_b + root) / a)])
+    }
+
+    fn export(&self, writer: &mut SceneWriter) {
+        if self.motion.length_squared() > 0.0 {
+            let end = self.center + self.motion;
+            writer.primitive(
+                "MovingSphere",
+                &[self.center, end],
+                &[self.radius],
+                &self.material,
+            );
+        } else {
+            writer.primitive("Sphere", &[self.center], &[self.radius], &self.material);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::Hittable;
+
+    // Hit on the unit sphere at the origin, for a ray aimed at `point` on its surface from outside.
+    fn hit_at(point: Vec3) -> hittable::HitRecord {
+        let sphere = Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, material::Material::Mirror);
+        let mut rec = hittable::HitRecord::new();
+        let ray = Ray::new(point * 3.0, -point, 0.0);
+        assert!(sphere.hit(ray, 0.001, f64::INFINITY, &mut rec));
+        rec
+    }
+
+    fn assert_close(actual: (f64, f64), expected: (f64, f64)) {
+        assert!(
+            (actual.0 - expected.0).abs() < 1e-6 && (actual.1 - expected.1).abs() < 1e-6,
+            "{:?} instead of {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn v_runs_from_the_bottom_pole_to_the_top() {
+        assert_eq!(hit_at(Vec3::new(0.0, -1.0, 0.0)).uv.unwrap().1, 0.0);
+        assert_close(hit_at(Vec3::new(1.0, 0.0, 0.0)).uv.unwrap(), (0.5, 0.5));
+        assert_eq!(hit_at(Vec3::new(0.0, 1.0, 0.0)).uv.unwrap().1, 1.0);
+
+        // The tangent ring degenerates at the poles, the frame falls back to a fixed axis.
+        let pole = hit_at(Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(pole.tangent, Some(Vec3::new(1.0, 0.0, 0.0)));
+        assert_eq!(pole.bitangent, Some(Vec3::new(0.0, 0.0, -1.0)));
+    }
+
+    #[test]
+    fn u_wraps_around_y_with_the_seam_at_minus_x() {
+        assert_close(hit_at(Vec3::new(0.0, 0.0, 1.0)).uv.unwrap(), (0.25, 0.5));
+        assert_close(hit_at(Vec3::new(0.0, 0.0, -1.0)).uv.unwrap(), (0.75, 0.5));
+
+        let side = |z: f64| hit_at(Vec3::new(-1.0, 0.0, z).unit_vector()).uv.unwrap().0;
+        assert!(side(0.01) < 0.01);
+        assert!(side(-0.01) > 0.99);
+    }
+
+    #[test]
+    fn moving_spheres_are_hit_where_they_are_at_the_ray_time() {
+        let sphere = Sphere::moving(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            0.5,
+            material::Material::Mirror,
+        );
+        assert_eq!(sphere.center(0.25), Vec3::new(0.5, 0.0, 0.0));
+        let down = |x: f64, time: f64| {
+            let mut rec = hittable::HitRecord::new();
+            let ray = Ray::new(Vec3::new(x, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0), time);
+            sphere
+                .hit(ray, 0.001, f64::INFINITY, &mut rec)
+                .then_some(rec)
+        };
+        let rec = down(1.0, 0.5).unwrap();
+        assert_eq!(rec.t, Some(4.5));
+        assert_eq!(rec.normal, Some(Vec3::new(0.0, 1.0, 0.0)));
+        assert!(down(1.0, 0.0).is_none());
+        assert!(down(1.0, 1.0).is_none());
+    }
+
+    #[test]
+    fn frame_follows_increasing_u_and_v() {
+        let rec = hit_at(Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(rec.tangent, Some(Vec3::new(1.0, 0.0, 0.0)));
+        assert_eq!(rec.bitangent, Some(Vec3::new(0.0, 1.0, 0.0)));
+    }
 }
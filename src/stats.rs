@@ -0,0 +1,135 @@
+use serde::Serialize;
+use std::cell::Cell;
+
+// Rays traced during a render, by what they were traced for.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Serialize)]
+pub struct RayCounts {
+    pub camera: u64,
+    pub bounce: u64,
+    pub shadow: u64,
+}
+
+impl RayCounts {
+    pub fn total(&self) -> u64 {
+        self.camera + self.bounce + self.shadow
+    }
+}
+
+// What a render did and how long it took, returned by `Scene::render_with_stats` and
+// `create_image_with_stats`. Times are zero on wasm32, which has no clock.
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct RenderStats {
+    pub rays: RayCounts,
+    // Object hit tests from the BVH leaves, including retries past masked out hits.
+    pub intersection_tests: u64,
+    pub bvh_node_visits: u64,
+    // Camera and bounce rays per camera ray, so 1.0 when no ray bounces.
+    pub average_path_length: f64,
+    pub samples_per_second: f64,
+    pub seconds: f64,
+    // Busy time of each rendering thread, one entry when rendering on a single thread.
+    pub thread_seconds: Vec<f64>,
+    // BVH nodes and heightfield min / max levels, shared geometry counted once.
+    pub acceleration_bytes: usize,
+}
+
+impl RenderStats {
+    pub(crate) fn new(
+        counters: Counters,
+        seconds: f64,
+        thread_seconds: Vec<f64>,
+        acceleration_bytes: usize,
+    ) -> RenderStats {
+        let rays = counters.rays;
+        RenderStats {
+            rays,
+            intersection_tests: counters.intersection_tests,
+            bvh_node_visits: counters.bvh_node_visits,
+            average_path_length: if rays.camera == 0 {
+                0.0
+            } else {
+                (rays.camera + rays.bounce) as f64 / rays.camera as f64
+            },
+            samples_per_second: if seconds > 0.0 {
+                rays.camera as f64 / seconds
+            } else {
+                0.0
+            },
+            seconds,
+            thread_seconds,
+            acceleration_bytes,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    pub fn write_json(&self, path: &str) {
+        std::fs::write(path, self.to_json())
+            .unwrap_or_else(|e| panic!("Could not write render stats {}: {}", path, e));
+    }
+}
+
+// Counts gathered per thread, so the hot paths never contend on shared atomics.
+#[derive(Debug, Default, Copy, Clone)]
+pub(crate) struct Counters {
+    pub rays: RayCounts,
+    pub intersection_tests: u64,
+    pub bvh_node_visits: u64,
+}
+
+impl Counters {
+    pub fn add(&mut self, other: Counters) {
+        self.rays.camera += other.rays.camera;
+        self.rays.bounce += other.rays.bounce;
+        self.rays.shadow += other.rays.shadow;
+        self.intersection_tests += other.intersection_tests;
+        self.bvh_node_visits += other.bvh_node_visits;
+    }
+}
+
+thread_local! {
+    static COUNTERS: Cell<Counters> = Cell::new(Counters::default());
+}
+
+pub(crate) fn count(update: impl FnOnce(&mut Counters)) {
+    COUNTERS.with(|counters| {
+        let mut current = counters.get();
+        update(&mut current);
+        counters.set(current);
+    });
+}
+
+// Counts of the calling thread since the last call.
+pub(crate) fn take_counters() -> Counters {
+    COUNTERS.with(|counters| counters.replace(Counters::default()))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) struct Stopwatch(std::time::Instant);
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Stopwatch {
+    pub fn start() -> Stopwatch {
+        Stopwatch(std::time::Instant::now())
+    }
+
+    pub fn seconds(&self) -> f64 {
+        self.0.elapsed().as_secs_f64()
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) struct Stopwatch;
+
+#[cfg(target_arch = "wasm32")]
+impl Stopwatch {
+    pub fn start() -> Stopwatch {
+        Stopwatch
+    }
+
+    pub fn seconds(&self) -> f64 {
+        0.0
+    }
+}
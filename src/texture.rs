@@ -0,0 +1,576 @@
+use crate::configuration::RonTexture;
+use crate::perlin::Perlin;
+use crate::scene_export::{ron_vector, SceneWriter};
+use crate::vec3::Vec3;
+use base64::Engine;
+use std::fmt;
+use std::io::Cursor;
+use std::sync::Arc;
+
+/// Color looked up from the uv and world position of a hit.
+pub trait Texture: fmt::Debug + Send + Sync {
+    /// Linear RGB color at `(u, v)` on the surface, or at `p` for solid textures.
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3;
+
+    /// Coverage used by opacity masks, grayscale textures use their red channel.
+    fn alpha(&self, u: f64, v: f64, p: Vec3) -> f64 {
+        self.value(u, v, p).x()
+    }
+
+    /// RON description for `Scene::to_ron`, textures without one are reported as left out.
+    fn export(&self, _writer: &mut SceneWriter) -> Option<RonTexture> {
+        None
+    }
+}
+
+/// Texture shared between materials.
+pub type TextureRef = Arc<dyn Texture>;
+
+/// The same color everywhere.
+#[derive(Debug, Copy, Clone)]
+pub struct SolidColor {
+    color: Vec3,
+}
+
+impl SolidColor {
+    pub fn new(color: Vec3) -> SolidColor {
+        SolidColor { color }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _p: Vec3) -> Vec3 {
+        self.color
+    }
+
+    fn export(&self, _writer: &mut SceneWriter) -> Option<RonTexture> {
+        Some(RonTexture {
+            vectors: vec![ron_vector(self.color)],
+            ..RonTexture::new("Solid")
+        })
+    }
+}
+
+/// 3D checker pattern, alternating between two textures every `1 / scale` units.
+#[derive(Debug, Clone)]
+pub struct Checker {
+    even: TextureRef,
+    odd: TextureRef,
+    scale: f64,
+}
+
+impl Checker {
+    pub fn new(even: TextureRef, odd: TextureRef, scale: f64) -> Checker {
+        Checker { even, odd, scale }
+    }
+}
+
+impl Texture for Checker {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        let sum = (p.x() * self.scale).floor() as i64
+            + (p.y() * self.scale).floor() as i64
+            + (p.z() * self.scale).floor() as i64;
+        if sum % 2 == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+
+    fn export(&self, writer: &mut SceneWriter) -> Option<RonTexture> {
+        Some(RonTexture {
+            scalars: vec![self.scale],
+            children: vec![writer.texture(&self.even), writer.texture(&self.odd)],
+            ..RonTexture::new("Checker")
+        })
+    }
+}
+
+/// How image lookups outside [0, 1] map back onto the image.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum WrapMode {
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+impl WrapMode {
+    pub fn from_name(name: &str) -> WrapMode {
+        match name {
+            "Repeat" => WrapMode::Repeat,
+            "Clamp" => WrapMode::Clamp,
+            "Mirror" => WrapMode::Mirror,
+            _ => panic!("Unknown wrap mode {}", name),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            WrapMode::Repeat => "Repeat",
+            WrapMode::Clamp => "Clamp",
+            WrapMode::Mirror => "Mirror",
+        }
+    }
+
+    fn wrap(&self, i: i64, size: usize) -> usize {
+        let n = size as i64;
+        let wrapped = match self {
+            WrapMode::Repeat => i.rem_euclid(n),
+            WrapMode::Clamp => i.clamp(0, n - 1),
+            WrapMode::Mirror => {
+                let period = i.rem_euclid(2 * n);
+                if period < n {
+                    period
+                } else {
+                    2 * n - 1 - period
+                }
+            }
+        };
+        wrapped as usize
+    }
+}
+
+/// Color images are linearised (gamma 2, matching `Vec3::to_rgb`) on load, data images such as
+/// normal maps are kept as stored. Alpha is never converted. The file an image was loaded from is
+/// kept for writing the scene back out.
+#[derive(Clone)]
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    texels: Vec<[f32; 4]>,
+    wrap: WrapMode,
+    has_alpha: bool,
+    source: Option<(String, bool)>,
+}
+
+impl ImageTexture {
+    pub fn new(width: usize, height: usize, texels: Vec<[f32; 4]>, wrap: WrapMode) -> ImageTexture {
+        assert_eq!(
+            width * height,
+            texels.len(),
+            "texel count does not match image size"
+        );
+        let has_alpha = texels.iter().any(|texel| texel[3] < 1.0);
+        ImageTexture {
+            width,
+            height,
+            texels,
+            wrap,
+            has_alpha,
+            source: None,
+        }
+    }
+
+    /// `path` may also be a base64 `data:` URI, as written by `to_data_uri`.
+    pub fn load(path: &str, wrap: WrapMode, linear: bool) -> ImageTexture {
+        let image = match path.split_once(";base64,") {
+            Some((_, data)) if path.starts_with("data:") => {
+                base64::engine::general_purpose::STANDARD
+                    .decode(data)
+                    .map_err(|e| e.to_string())
+                    .and_then(|bytes| image::load_from_memory(&bytes).map_err(|e| e.to_string()))
+            }
+            _ => image::open(path).map_err(|e| e.to_string()),
+        }
+        .unwrap_or_else(|e| panic!("failed to load texture {}: {}", path, e))
+        .to_rgba32f();
+        let (width, height) = image.dimensions();
+        let texels = image
+            .pixels()
+            .map(|px| {
+                if linear {
+                    [px[0], px[1], px[2], px[3]]
+                } else {
+                    [px[0] * px[0], px[1] * px[1], px[2] * px[2], px[3]]
+                }
+            })
+            .collect();
+        let mut texture = ImageTexture::new(width as usize, height as usize, texels, wrap);
+        texture.source = Some((path.to_string(), linear));
+        texture
+    }
+
+    /// The texels as a 16-bit PNG, stored linear so that loading it with `linear` set gives them
+    /// back.
+    pub fn to_data_uri(&self) -> String {
+        let channels: Vec<u16> = self
+            .texels
+            .iter()
+            .flatten()
+            .map(|value| (value.clamp(0.0, 1.0) * 65535.0).round() as u16)
+            .collect();
+        let image: image::ImageBuffer<image::Rgba<u16>, Vec<u16>> =
+            image::ImageBuffer::from_raw(self.width as u32, self.height as u32, channels).unwrap();
+        let mut png = Cursor::new(vec![]);
+        image
+            .write_to(&mut png, image::ImageFormat::Png)
+            .expect("PNG encoding failed");
+        format!(
+            "data:image/png;base64,{}",
+            base64::engine::general_purpose::STANDARD.encode(png.into_inner())
+        )
+    }
+
+    fn texel(&self, x: i64, y: i64) -> [f32; 4] {
+        let x = self.wrap.wrap(x, self.width);
+        let y = self.wrap.wrap(y, self.height);
+        self.texels[y * self.width + x]
+    }
+
+    /// Bilinearly filtered lookup, v = 0 is the bottom row of the image.
+    pub fn sample(&self, u: f64, v: f64) -> [f64; 4] {
+        let x = u * self.width as f64 - 0.5;
+        let y = (1.0 - v) * self.height as f64 - 0.5;
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let fx = x - x0;
+        let fy = y - y0;
+
+        let c00 = self.texel(x0 as i64, y0 as i64);
+        let c10 = self.texel(x0 as i64 + 1, y0 as i64);
+        let c01 = self.texel(x0 as i64, y0 as i64 + 1);
+        let c11 = self.texel(x0 as i64 + 1, y0 as i64 + 1);
+
+        let mut out = [0.0; 4];
+        for (i, channel) in out.iter_mut().enumerate() {
+            let top = c00[i] as f64 * (1.0 - fx) + c10[i] as f64 * fx;
+            let bottom = c01[i] as f64 * (1.0 - fx) + c11[i] as f64 * fx;
+            *channel = top * (1.0 - fy) + bottom * fy;
+        }
+        out
+    }
+}
+
+impl fmt::Debug for ImageTexture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ImageTexture")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("wrap", &self.wrap)
+            .finish()
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: Vec3) -> Vec3 {
+        let texel = self.sample(u, v);
+        Vec3::new(texel[0], texel[1], texel[2])
+    }
+
+    fn alpha(&self, u: f64, v: f64, _p: Vec3) -> f64 {
+        let texel = self.sample(u, v);
+        if self.has_alpha {
+            texel[3]
+        } else {
+            texel[0]
+        }
+    }
+
+    // Images built in memory, e.g. embedded in a glTF file, are written inline.
+    fn export(&self, _writer: &mut SceneWriter) -> Option<RonTexture> {
+        let (path, linear) = match &self.source {
+            Some((path, linear)) => (path.clone(), *linear),
+            None => (self.to_data_uri(), true),
+        };
+        Some(RonTexture {
+            path: Some(path),
+            wrap: Some(self.wrap.name().to_string()),
+            linear,
+            ..RonTexture::new("Image")
+        })
+    }
+}
+
+/// Colors at the corners of a triangle, blended by its default barycentric uvs.
+#[derive(Debug, Copy, Clone)]
+pub struct VertexColors {
+    colors: [Vec3; 3],
+}
+
+impl VertexColors {
+    pub fn new(colors: [Vec3; 3]) -> VertexColors {
+        VertexColors { colors }
+    }
+}
+
+impl Texture for VertexColors {
+    fn value(&self, u: f64, v: f64, _p: Vec3) -> Vec3 {
+        self.colors[0] * (1.0 - u - v) + self.colors[1] * u + self.colors[2] * v
+    }
+
+    // Only meaningful on a triangle, which writes the colors into its mesh, see
+    // `SceneWriter::triangle`.
+    fn export(&self, _writer: &mut SceneWriter) -> Option<RonTexture> {
+        Some(RonTexture {
+            vectors: self.colors.iter().map(|color| ron_vector(*color)).collect(),
+            ..RonTexture::new("VertexColors")
+        })
+    }
+}
+
+/// Another texture multiplied by a constant color, e.g. an image scaled by a material factor.
+#[derive(Debug, Clone)]
+pub struct Tinted {
+    texture: TextureRef,
+    tint: Vec3,
+}
+
+impl Tinted {
+    pub fn new(texture: TextureRef, tint: Vec3) -> Tinted {
+        Tinted { texture, tint }
+    }
+}
+
+impl Texture for Tinted {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        self.texture.value(u, v, p) * self.tint
+    }
+
+    fn alpha(&self, u: f64, v: f64, p: Vec3) -> f64 {
+        self.texture.alpha(u, v, p)
+    }
+
+    fn export(&self, writer: &mut SceneWriter) -> Option<RonTexture> {
+        Some(RonTexture {
+            vectors: vec![ron_vector(self.tint)],
+            children: vec![writer.texture(&self.texture)],
+            ..RonTexture::new("Tinted")
+        })
+    }
+}
+
+/// fBm noise remapped to [0, 1] and used to scale a base color.
+#[derive(Debug, Clone)]
+pub struct NoiseTexture {
+    perlin: Perlin,
+    color: Vec3,
+    scale: f64,
+    octaves: usize,
+    lacunarity: f64,
+    gain: f64,
+}
+
+impl NoiseTexture {
+    pub fn new(
+        seed: u32,
+        color: Vec3,
+        scale: f64,
+        octaves: usize,
+        lacunarity: f64,
+        gain: f64,
+    ) -> NoiseTexture {
+        NoiseTexture {
+            perlin: Perlin::new(seed),
+            color,
+            scale,
+            octaves,
+            lacunarity,
+            gain,
+        }
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f64, _v: f64, p: Vec3) -> Vec3 {
+        let n = self
+            .perlin
+            .fbm(p * self.scale, self.octaves, self.lacunarity, self.gain);
+        self.color * (0.5 * (1.0 + n))
+    }
+
+    fn export(&self, _writer: &mut SceneWriter) -> Option<RonTexture> {
+        Some(RonTexture {
+            vectors: vec![ron_vector(self.color)],
+            scalars: vec![
+                self.perlin.seed() as f64,
+                self.scale,
+                self.octaves as f64,
+                self.lacunarity,
+                self.gain,
+            ],
+            ..RonTexture::new("Noise")
+        })
+    }
+}
+
+/// Color banded by sine veins along z, distorted by Perlin turbulence.
+#[derive(Debug, Clone)]
+pub struct MarbleTexture {
+    perlin: Perlin,
+    color: Vec3,
+    scale: f64,
+    octaves: usize,
+}
+
+impl MarbleTexture {
+    pub fn new(seed: u32, color: Vec3, scale: f64, octaves: usize) -> MarbleTexture {
+        MarbleTexture {
+            perlin: Perlin::new(seed),
+            color,
+            scale,
+            octaves,
+        }
+    }
+}
+
+impl Texture for MarbleTexture {
+    fn value(&self, _u: f64, _v: f64, p: Vec3) -> Vec3 {
+        let veins = (self.scale * p.z() + 10.0 * self.perlin.turbulence(p, self.octaves)).sin();
+        self.color * (0.5 * (1.0 + veins))
+    }
+
+    fn export(&self, _writer: &mut SceneWriter) -> Option<RonTexture> {
+        Some(RonTexture {
+            vectors: vec![ron_vector(self.color)],
+            scalars: vec![self.perlin.seed() as f64, self.scale, self.octaves as f64],
+            ..RonTexture::new("Marble")
+        })
+    }
+}
+
+/// Color ramp over world height, e.g. sand -> grass -> rock -> snow on terrain.
+#[derive(Debug, Clone)]
+pub struct HeightGradient {
+    stops: Vec<(f64, Vec3)>,
+}
+
+impl HeightGradient {
+    pub fn new(mut stops: Vec<(f64, Vec3)>) -> HeightGradient {
+        assert!(!stops.is_empty(), "height gradient needs at least one stop");
+        stops.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        HeightGradient { stops }
+    }
+}
+
+impl Texture for HeightGradient {
+    fn value(&self, _u: f64, _v: f64, p: Vec3) -> Vec3 {
+        let height = p.y();
+        let first = self.stops[0];
+        let last = self.stops[self.stops.len() - 1];
+        if height <= first.0 {
+            return first.1;
+        }
+        if height >= last.0 {
+            return last.1;
+        }
+        for pair in self.stops.windows(2) {
+            let (h0, c0) = pair[0];
+            let (h1, c1) = pair[1];
+            if height <= h1 {
+                let t = if h1 > h0 {
+                    (height - h0) / (h1 - h0)
+                } else {
+                    1.0
+                };
+                return c0 * (1.0 - t) + c1 * t;
+            }
+        }
+        last.1
+    }
+
+    fn export(&self, _writer: &mut SceneWriter) -> Option<RonTexture> {
+        Some(RonTexture {
+            vectors: self.stops.iter().map(|stop| ron_vector(stop.1)).collect(),
+            scalars: self.stops.iter().map(|stop| stop.0).collect(),
+            ..RonTexture::new("HeightGradient")
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gray(value: f32) -> [f32; 4] {
+        [value, value, value, 1.0]
+    }
+
+    #[test]
+    fn checker_alternates_across_every_cell_boundary() {
+        let red = Vec3::new(1.0, 0.0, 0.0);
+        let blue = Vec3::new(0.0, 0.0, 1.0);
+        let checker = Checker::new(
+            Arc::new(SolidColor::new(red)),
+            Arc::new(SolidColor::new(blue)),
+            2.0,
+        );
+        let at = |x, y, z| checker.value(0.0, 0.0, Vec3::new(x, y, z));
+        assert_eq!(at(0.25, 0.25, 0.25), red);
+        assert_eq!(at(0.75, 0.25, 0.25), blue);
+        assert_eq!(at(0.75, 0.75, 0.25), red);
+        // Cells below zero keep alternating instead of repeating the cell above zero.
+        assert_eq!(at(-0.25, 0.25, 0.25), blue);
+        assert_eq!(at(-0.25, -0.25, 0.25), red);
+        assert_eq!(at(-0.25, -0.25, -0.25), blue);
+    }
+
+    #[test]
+    fn image_texture_filters_bilinearly() {
+        // Rows are stored top first, v = 0 samples the bottom row.
+        let texture = ImageTexture::new(
+            2,
+            2,
+            vec![gray(0.0), gray(1.0), gray(2.0), [3.0, 3.0, 3.0, 0.5]],
+            WrapMode::Clamp,
+        );
+        assert_eq!(texture.sample(0.25, 0.75), [0.0, 0.0, 0.0, 1.0]);
+        assert_eq!(texture.sample(0.75, 0.25), [3.0, 3.0, 3.0, 0.5]);
+        assert_eq!(texture.sample(0.5, 0.5), [1.5, 1.5, 1.5, 0.875]);
+        assert_eq!(texture.sample(0.5, 0.75)[0], 0.5);
+        assert_eq!(
+            texture.value(0.25, 0.25, Vec3::new(0.0, 0.0, 0.0)),
+            Vec3::new(2.0, 2.0, 2.0)
+        );
+    }
+
+    #[test]
+    fn wrap_modes_map_texels_outside_the_image() {
+        let wrapped = |mode: WrapMode| [-2, -1, 3, 4].map(|i| mode.wrap(i, 3));
+        assert_eq!(wrapped(WrapMode::Repeat), [1, 2, 0, 1]);
+        assert_eq!(wrapped(WrapMode::Clamp), [0, 0, 2, 2]);
+        assert_eq!(wrapped(WrapMode::Mirror), [1, 0, 2, 1]);
+
+        // Filtering across the left and right edges of a black and white image.
+        let sample =
+            |mode, u| ImageTexture::new(2, 1, vec![gray(0.0), gray(1.0)], mode).sample(u, 0.5)[0];
+        assert_eq!(sample(WrapMode::Repeat, 0.0), 0.5);
+        assert_eq!(sample(WrapMode::Clamp, 0.0), 0.0);
+        assert_eq!(sample(WrapMode::Mirror, 0.0), 0.0);
+        assert_eq!(sample(WrapMode::Repeat, 1.25), 0.0);
+        assert_eq!(sample(WrapMode::Clamp, 1.25), 1.0);
+        assert_eq!(sample(WrapMode::Mirror, 1.25), 1.0);
+    }
+
+    #[test]
+    fn images_load_back_from_their_data_uri() {
+        // Stored as 16-bit, so texels come back to within half a step of 1 / 65535.
+        let texels = vec![
+            [0.0, 0.25, 0.5, 1.0],
+            [1.0, 0.75, 0.125, 0.5],
+            gray(0.3),
+            gray(0.9),
+        ];
+        let uri = ImageTexture::new(2, 2, texels.clone(), WrapMode::Clamp).to_data_uri();
+        assert!(uri.starts_with("data:image/png;base64,"));
+
+        let loaded = ImageTexture::load(&uri, WrapMode::Clamp, true);
+        assert_eq!((loaded.width, loaded.height), (2, 2));
+        for (texel, expected) in loaded.texels.iter().zip(&texels) {
+            for (channel, expected) in texel.iter().zip(expected) {
+                assert!(
+                    (channel - expected).abs() < 1e-5,
+                    "{:?} instead of {:?}",
+                    texel,
+                    expected
+                );
+            }
+        }
+        assert_eq!(loaded.source, Some((uri, true)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown wrap mode clamp")]
+    fn wrap_mode_names_are_capitalised() {
+        WrapMode::from_name("clamp");
+    }
+}
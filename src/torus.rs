@@ -0,0 +1,274 @@
+use crate::aabb::Aabb;
+use crate::frame::{azimuth_u, azimuthal_frame, local_intervals, Frame, LocalHit};
+use crate::hittable;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::scene_export::SceneWriter;
+use crate::vec3::Vec3;
+use std::f64::consts::PI;
+
+/// Torus around `axis` through `center`, with the tube of `minor_radius` swept at `major_radius`.
+#[derive(Debug, Clone)]
+pub struct Torus {
+    frame: Frame,
+    major_radius: f64,
+    minor_radius: f64,
+    material: Material,
+}
+
+// Real roots of the polynomial with ascending coefficients inside [lo, hi], found by bisecting
+// between the roots of its derivative where the polynomial is monotonic.
+fn real_roots(coeffs: &[f64], lo: f64, hi: f64) -> Vec<f64> {
+    let eval = |t: f64| coeffs.iter().rev().fold(0.0, |acc, c| acc * t + c);
+    if coeffs.len() <= 1 {
+        return vec![];
+    }
+    if coeffs.len() == 2 {
+        if coeffs[1].abs() < 1e-300 {
+            return vec![];
+        }
+        let t = -coeffs[0] / coeffs[1];
+        return if t >= lo && t <= hi { vec![t] } else { vec![] };
+    }
+
+    let derivative: Vec<f64> = coeffs
+        .iter()
+        .enumerate()
+        .skip(1)
+        .map(|(i, c)| c * i as f64)
+        .collect();
+    let mut bounds = vec![lo];
+    bounds.extend(real_roots(&derivative, lo, hi));
+    bounds.push(hi);
+
+    let mut roots = vec![];
+    for pair in bounds.windows(2) {
+        let (mut a, mut b) = (pair[0], pair[1]);
+        let (mut fa, fb) = (eval(a), eval(b));
+        if fa == 0.0 {
+            roots.push(a);
+            continue;
+        }
+        if fa * fb > 0.0 {
+            continue;
+        }
+        for _ in 0..100 {
+            let mid = 0.5 * (a + b);
+            let fm = eval(mid);
+            if fa * fm <= 0.0 {
+                b = mid;
+            } else {
+                a = mid;
+                fa = fm;
+            }
+            if b - a < 1e-12 * (1.0 + a.abs()) {
+                break;
+            }
+        }
+        roots.push(0.5 * (a + b));
+    }
+    // Near a double root, where a ray grazes the surface, the polynomial vanishes in rounding
+    // over about the square root of the machine precision, so both sides land that far apart.
+    roots.dedup_by(|a, b| (*a - *b).abs() < 1e-6 * (1.0 + b.abs()));
+    roots
+}
+
+impl Torus {
+    pub fn new(
+        center: Vec3,
+        axis: Vec3,
+        major_radius: f64,
+        minor_radius: f64,
+        mat: Material,
+    ) -> Torus {
+        Torus {
+            frame: Frame::new(center, axis),
+            major_radius,
+            minor_radius,
+            material: mat,
+        }
+    }
+
+    /// Every intersection of the local ray with the torus, sorted by distance.
+    pub fn local_hits(&self, ray: Ray) -> Vec<LocalHit> {
+        let length = ray.direction().length();
+        let d = ray.direction() * (1.0 / length);
+
+        // Clip to the bounding sphere and solve from its entry point to keep the quartic
+        // well conditioned for distant rays.
+        let bound = self.major_radius + self.minor_radius;
+        let half_b = ray.origin().dot(d);
+        let c = ray.origin().length_squared() - bound * bound;
+        let discriminant = half_b * half_b - c;
+        if discriminant <= 0.0 {
+            return vec![];
+        }
+        let t_enter = -half_b - discriminant.sqrt();
+        let t_exit = -half_b + discriminant.sqrt();
+        let o = ray.origin() + d * t_enter;
+
+        // (|p|^2 + R^2 - r^2)^2 = 4 R^2 (x^2 + z^2)
+        let r2 = self.major_radius * self.major_radius;
+        let a1 = 2.0 * o.dot(d);
+        let a0 = o.length_squared() + r2 - self.minor_radius * self.minor_radius;
+        let coeffs = [
+            a0 * a0 - 4.0 * r2 * (o.x() * o.x() + o.z() * o.z()),
+            2.0 * a1 * a0 - 8.0 * r2 * (o.x() * d.x() + o.z() * d.z()),
+            a1 * a1 + 2.0 * a0 - 4.0 * r2 * (d.x() * d.x() + d.z() * d.z()),
+            2.0 * a1,
+            1.0,
+        ];
+
+        real_roots(&coeffs, 0.0, t_exit - t_enter)
+            .into_iter()
+            .map(|s| {
+                let p = o + d * s;
+                let radial = Vec3::new(p.x(), 0.0, p.z());
+                let radial = if radial.length_squared() > 1e-18 {
+                    radial.unit_vector()
+                } else {
+                    Vec3::new(1.0, 0.0, 0.0)
+                };
+                let tube = p - radial * self.major_radius;
+                let normal = tube.unit_vector();
+                let tube_angle = tube.y().atan2(tube.dot(radial));
+                LocalHit {
+                    t: (s + t_enter) / length,
+                    normal,
+                    uv: (azimuth_u(p), (tube_angle + PI) / (2.0 * PI)),
+                }
+            })
+            .collect()
+    }
+}
+
+impl hittable::Hittable for Torus {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, rec: &mut hittable::HitRecord) -> bool {
+        let local_ray = self.frame.ray_to_local(ray);
+        let hit = match self
+            .local_hits(local_ray)
+            .into_iter()
+            .find(|hit| hit.t > t_min && hit.t < t_max)
+        {
+            Some(hit) => hit,
+            None => return false,
+        };
+
+        let outward_normal = self.frame.dir_to_world(hit.normal);
+        let (tangent, bitangent) = azimuthal_frame(self.frame.axis, outward_normal);
+        rec.t = Some(hit.t);
+        rec.p = Some(ray.at(hit.t));
+        rec.set_face_normal(ray, outward_normal);
+        rec.material = Some(self.material.clone());
+        rec.uv = Some(hit.uv);
+        rec.tangent = Some(tangent);
+        rec.bitangent = Some(bitangent);
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let outer = self.major_radius + self.minor_radius;
+        Some(self.frame.bounds(
+            Vec3::new(-outer, -self.minor_radius, -outer),
+            Vec3::new(outer, self.minor_radius, outer),
+        ))
+    }
+
+    fn intervals(&self, ray: Ray) -> Option<Vec<hittable::Interval>> {
+        Some(local_intervals(
+            &self.local_hits(self.frame.ray_to_local(ray)),
+        ))
+    }
+
+    fn export(&self, writer: &mut SceneWriter) {
+        let vectors = [self.frame.origin, self.frame.axis];
+        let scalars = [self.major_radius, self.minor_radius];
+        writer.primitive("Torus", &vectors, &scalars, &self.material);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::{HitRecord, Hittable};
+
+    // Major radius 1 around the y axis, tube radius 0.25.
+    fn ring() -> Torus {
+        Torus::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            1.0,
+            0.25,
+            Material::Mirror,
+        )
+    }
+
+    fn cast(origin: Vec3, direction: Vec3, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let mut rec = HitRecord::new();
+        let ray = Ray::new(origin, direction, 0.0);
+        ring().hit(ray, t_min, t_max, &mut rec).then_some(rec)
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-6,
+            "{} instead of {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn finds_every_real_root_in_range() {
+        // (t - 1)(t - 2)(t - 3)(t - 4)
+        let roots = real_roots(&[24.0, -50.0, 35.0, -10.0, 1.0], 0.0, 10.0);
+        assert_eq!(roots.len(), 4, "{:?}", roots);
+        for (root, expected) in roots.iter().zip([1.0, 2.0, 3.0, 4.0]) {
+            assert_close(*root, expected);
+        }
+        assert_eq!(
+            real_roots(&[24.0, -50.0, 35.0, -10.0, 1.0], 1.5, 3.5).len(),
+            2
+        );
+
+        // A double root is reported once, a positive quadratic has none.
+        let double = real_roots(&[4.0, -4.0, 1.0], 0.0, 10.0);
+        assert_eq!(double.len(), 1, "{:?}", double);
+        assert_close(double[0], 2.0);
+        assert!(real_roots(&[1.0, 0.0, 1.0], -10.0, 10.0).is_empty());
+    }
+
+    #[test]
+    fn axial_rays_pass_through_the_hole() {
+        let down = Vec3::new(0.0, -1.0, 0.0);
+        assert!(cast(Vec3::new(0.0, 5.0, 0.0), down, 0.001, f64::INFINITY).is_none());
+        assert!(cast(Vec3::new(0.7, 5.0, 0.0), down, 0.001, f64::INFINITY).is_none());
+
+        let rec = cast(Vec3::new(1.0, 5.0, 0.0), down, 0.001, f64::INFINITY).unwrap();
+        assert_close(rec.t.unwrap(), 4.75);
+        assert!((rec.normal.unwrap() - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-6);
+        assert_close(rec.uv.unwrap().1, 0.75);
+    }
+
+    #[test]
+    fn rays_across_the_ring_hit_the_tube_four_times() {
+        let left = Vec3::new(-1.0, 0.0, 0.0);
+        let hits = ring().local_hits(Ray::new(Vec3::new(5.0, 0.0, 0.0), left, 0.0));
+        let ts: Vec<f64> = hits.iter().map(|hit| hit.t).collect();
+        assert_eq!(ts.len(), 4, "{:?}", ts);
+        for (t, expected) in ts.iter().zip([3.75, 4.25, 5.75, 6.25]) {
+            assert_close(*t, expected);
+        }
+        assert!((hits[0].normal - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-6);
+        assert!((hits[1].normal - Vec3::new(-1.0, 0.0, 0.0)).length() < 1e-6);
+        assert_close(hits[0].uv.0, 0.5);
+        assert_close(hits[0].uv.1, 0.5);
+
+        // The ray bounds pick the hit, here the inner wall of the near side of the tube.
+        let rec = cast(Vec3::new(5.0, 0.0, 0.0), left, 4.0, f64::INFINITY).unwrap();
+        assert_close(rec.t.unwrap(), 4.25);
+        assert!(cast(Vec3::new(5.0, 0.0, 0.0), left, 0.001, 3.5).is_none());
+        // Passing just over the tube.
+        assert!(cast(Vec3::new(5.0, 0.3, 0.0), left, 0.001, f64::INFINITY).is_none());
+    }
+}
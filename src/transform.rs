@@ -0,0 +1,369 @@
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable, Interval};
+use crate::material::Material;
+use crate::matrix::Mat4;
+use crate::ray::Ray;
+use crate::scene_export::{ron_placement, ron_trs, SceneWriter};
+use crate::vec3::Vec3;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// Translation, Euler rotation in degrees and scale, kept separate so motion can be
+/// interpolated per component.
+#[derive(Debug, Copy, Clone)]
+pub struct Trs {
+    pub translate: Vec3,
+    pub rotate: Vec3,
+    pub scale: Vec3,
+}
+
+impl Trs {
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::from_trs(self.translate, self.rotate, self.scale)
+    }
+
+    /// Undoes the translation, rotation and scale in reverse order, cheaper per ray than
+    /// inverting `matrix`.
+    pub fn inverse_matrix(&self) -> Mat4 {
+        let s = self.scale;
+        assert!(
+            s.x() != 0.0 && s.y() != 0.0 && s.z() != 0.0,
+            "transform matrix is not invertible"
+        );
+        Mat4::scale(Vec3::new(1.0 / s.x(), 1.0 / s.y(), 1.0 / s.z()))
+            * Mat4::rotation_x(-self.rotate.x())
+            * Mat4::rotation_y(-self.rotate.y())
+            * Mat4::rotation_z(-self.rotate.z())
+            * Mat4::translation(-self.translate)
+    }
+
+    pub fn lerp(&self, other: &Trs, t: f64) -> Trs {
+        let mix = |a: Vec3, b: Vec3| a * (1.0 - t) + b * t;
+        Trs {
+            translate: mix(self.translate, other.translate),
+            rotate: mix(self.rotate, other.rotate),
+            scale: mix(self.scale, other.scale),
+        }
+    }
+}
+
+/// Places shared object-space geometry in the world, optionally overriding its material.
+pub struct Transform {
+    object: Arc<dyn Hittable + Send + Sync>,
+    matrix: Mat4,
+    inverse: Mat4,
+    normal_matrix: Mat4,
+    material: Option<Material>,
+    // Start and end placement for objects moving between times 0 and 1.
+    motion: Option<(Trs, Trs)>,
+}
+
+impl Transform {
+    pub fn new(object: Arc<dyn Hittable + Send + Sync>, matrix: Mat4) -> Transform {
+        let inverse = matrix
+            .inverse()
+            .expect("transform matrix is not invertible");
+        Transform {
+            object,
+            matrix,
+            inverse,
+            normal_matrix: inverse.transpose(),
+            material: None,
+            motion: None,
+        }
+    }
+
+    pub fn animated(object: Arc<dyn Hittable + Send + Sync>, start: Trs, end: Trs) -> Transform {
+        let mut transform = Transform::new(object, start.matrix());
+        transform.motion = Some((start, end));
+        transform
+    }
+
+    // Object to world, world to object and normal matrices at the given time.
+    fn matrices_at(&self, time: f64) -> (Mat4, Mat4, Mat4) {
+        match &self.motion {
+            Some((start, end)) => {
+                let trs = start.lerp(end, time.clamp(0.0, 1.0));
+                let inverse = trs.inverse_matrix();
+                (trs.matrix(), inverse, inverse.transpose())
+            }
+            None => (self.matrix, self.inverse, self.normal_matrix),
+        }
+    }
+
+    // The direction is not renormalised so t is the same in both spaces.
+    fn local_ray(&self, inverse: &Mat4, ray: Ray) -> Ray {
+        Ray::new(
+            inverse.transform_point(ray.origin()),
+            inverse.transform_vector(ray.direction()),
+            ray.time(),
+        )
+    }
+
+    pub fn with_material(mut self, material: Material) -> Transform {
+        self.material = Some(material);
+        self
+    }
+}
+
+impl Hittable for Transform {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let (matrix, inverse, normal_matrix) = self.matrices_at(ray.time());
+        let local_ray = self.local_ray(&inverse, ray);
+        if !self.object.hit(local_ray, t_min, t_max, rec) {
+            return false;
+        }
+
+        rec.p = Some(matrix.transform_point(rec.p.unwrap()));
+        rec.normal = Some(
+            normal_matrix
+                .transform_vector(rec.normal.unwrap())
+                .unit_vector(),
+        );
+        rec.tangent = rec
+            .tangent
+            .map(|tangent| matrix.transform_vector(tangent).unit_vector());
+        rec.bitangent = rec
+            .bitangent
+            .map(|bitangent| matrix.transform_vector(bitangent).unit_vector());
+        // An override's own mask still has to be tested by the enclosing BVH leaf.
+        if let Some(material) = &self.material {
+            rec.material = Some(material.clone());
+            rec.opacity_resolved = false;
+        }
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let bounds = self.object.bounding_box()?;
+        match &self.motion {
+            Some((start, end)) => Some(swept_bounds(bounds, start, end)),
+            None => Some(bounds.transformed(&self.matrix)),
+        }
+    }
+
+    fn intervals(&self, ray: Ray) -> Option<Vec<Interval>> {
+        let (_, inverse, _) = self.matrices_at(ray.time());
+        self.object.intervals(self.local_ray(&inverse, ray))
+    }
+
+    fn acceleration_bytes(&self, seen: &mut HashSet<usize>) -> usize {
+        self.object.acceleration_bytes(seen)
+    }
+
+    fn export(&self, writer: &mut SceneWriter) {
+        let children = writer.export_children(|writer| self.object.export(writer));
+        let material = match &self.material {
+            Some(material) => writer.material(material),
+            None => vec![],
+        };
+        let (transform, transform_end) = match &self.motion {
+            Some((start, end)) => (ron_trs(start), Some(ron_trs(end))),
+            None => (ron_placement(&self.matrix), None),
+        };
+        writer.placed(children, transform, transform_end, material);
+    }
+}
+
+// Box around `bounds` over the whole motion. Translation and scale move each corner along a
+// straight line, but rotation bends its path into an arc that bulges past the boxes at either
+// end, so the placement is sampled every few degrees and padded by how far a point can travel
+// between two samples.
+fn swept_bounds(bounds: Aabb, start: &Trs, end: &Trs) -> Aabb {
+    let turn = end.rotate - start.rotate;
+    let turn = (turn.x().abs() + turn.y().abs() + turn.z().abs()).to_radians();
+    let steps = (turn / 5.0_f64.to_radians()).ceil().max(1.0);
+
+    let mut swept = bounds.transformed(&start.matrix());
+    for i in 1..=steps as usize {
+        let placement = start.lerp(end, i as f64 / steps);
+        swept = swept.surrounding(&bounds.transformed(&placement.matrix()));
+    }
+    if turn == 0.0 {
+        return swept;
+    }
+
+    let reach = bounds
+        .corners()
+        .iter()
+        .fold(0.0_f64, |reach, corner| reach.max(corner.length()));
+    let largest = |s: Vec3| s.x().abs().max(s.y().abs()).max(s.z().abs());
+    let grow = largest(start.scale).max(largest(end.scale));
+    let pad = (reach * grow * turn
+        + (end.translate - start.translate).length()
+        + reach * (end.scale - start.scale).length())
+        / steps;
+    let pad = Vec3::new(pad, pad, pad);
+    Aabb::new(swept.min - pad, swept.max + pad)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sphere::Sphere;
+    use crate::vec3::Vec3;
+
+    fn unit_sphere() -> Arc<dyn Hittable + Send + Sync> {
+        Arc::new(Sphere::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            1.0,
+            Material::Dielectric(1.5),
+        ))
+    }
+
+    fn cast(object: &dyn Hittable, origin: Vec3, direction: Vec3) -> Option<HitRecord> {
+        let mut rec = HitRecord::new();
+        let ray = Ray::new(origin, direction, 0.0);
+        object
+            .hit(ray, 0.001, f64::INFINITY, &mut rec)
+            .then_some(rec)
+    }
+
+    #[test]
+    fn places_shared_geometry_in_the_world() {
+        let forward = Vec3::new(0.0, 0.0, -1.0);
+        let placed = Transform::new(
+            unit_sphere(),
+            Mat4::from_trs(
+                Vec3::new(0.0, 0.0, -5.0),
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(2.0, 2.0, 2.0),
+            ),
+        );
+        let rec = cast(&placed, Vec3::new(0.0, 0.0, 0.0), forward).unwrap();
+        assert!((rec.t.unwrap() - 3.0).abs() < 1e-9);
+        assert!((rec.p.unwrap() - Vec3::new(0.0, 0.0, -3.0)).length() < 1e-9);
+        assert!((rec.normal.unwrap() - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-9);
+        assert!(cast(&placed, Vec3::new(2.5, 0.0, 0.0), forward).is_none());
+
+        // Nodes nest: the child scale applies inside the parent translation.
+        let child = Transform::new(unit_sphere(), Mat4::scale(Vec3::new(2.0, 2.0, 2.0)));
+        let parent = Transform::new(
+            Arc::new(child),
+            Mat4::translation(Vec3::new(0.0, 0.0, -5.0)),
+        );
+        let rec = cast(&parent, Vec3::new(0.0, 0.0, 0.0), forward).unwrap();
+        assert!((rec.t.unwrap() - 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn normals_follow_non_uniform_scale() {
+        // Ellipsoid x^2 / 4 + y^2 = 1, hit from above where x = sqrt(2).
+        let stretched = Transform::new(unit_sphere(), Mat4::scale(Vec3::new(2.0, 1.0, 1.0)));
+        let x = 2.0_f64.sqrt();
+        let rec = cast(
+            &stretched,
+            Vec3::new(x, 5.0, 0.0),
+            Vec3::new(0.0, -1.0, 0.0),
+        )
+        .unwrap();
+        assert!((rec.p.unwrap().y() - 0.5_f64.sqrt()).abs() < 1e-9);
+        let expected = Vec3::new(1.0, 2.0, 0.0).unit_vector();
+        assert!((rec.normal.unwrap() - expected).length() < 1e-9);
+    }
+
+    #[test]
+    fn trs_inverse_undoes_the_matrix() {
+        let trs = Trs {
+            translate: Vec3::new(1.0, -2.0, 3.0),
+            rotate: Vec3::new(30.0, -45.0, 60.0),
+            scale: Vec3::new(2.0, 0.5, 3.0),
+        };
+        let product = trs.matrix() * trs.inverse_matrix();
+        for (i, row) in product.m.iter().enumerate() {
+            for (j, value) in row.iter().enumerate() {
+                let expected = if i == j { 1.0 } else { 0.0 };
+                assert!((value - expected).abs() < 1e-12, "{:?}", product);
+            }
+        }
+    }
+
+    #[test]
+    fn animated_transforms_are_hit_where_they_are_at_the_ray_time() {
+        let still = Trs {
+            translate: Vec3::new(0.0, 0.0, 0.0),
+            rotate: Vec3::new(0.0, 0.0, 0.0),
+            scale: Vec3::new(0.5, 0.5, 0.5),
+        };
+        let moved = Trs {
+            translate: Vec3::new(2.0, 0.0, 0.0),
+            rotate: Vec3::new(0.0, 90.0, 0.0),
+            scale: Vec3::new(1.0, 1.0, 1.0),
+        };
+        let moving = Transform::animated(unit_sphere(), still, moved);
+        let down = |x: f64, time: f64| {
+            let mut rec = HitRecord::new();
+            let ray = Ray::new(Vec3::new(x, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0), time);
+            moving
+                .hit(ray, 0.001, f64::INFINITY, &mut rec)
+                .then_some(rec)
+        };
+
+        // Halfway the sphere is centred on x = 1 with radius 0.75.
+        let rec = down(1.0, 0.5).unwrap();
+        assert!((rec.t.unwrap() - 4.25).abs() < 1e-9);
+        assert!((rec.normal.unwrap() - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-9);
+        assert!(down(1.0, 0.0).is_none());
+        assert!(down(2.0, 1.0).is_some());
+        // Times outside the shutter interval hold the end placements.
+        assert!(down(2.0, 1.5).is_some());
+        assert!(down(2.0, -0.5).is_none());
+    }
+
+    #[test]
+    fn animated_transforms_are_bounded_over_their_whole_motion() {
+        let offset: Arc<dyn Hittable + Send + Sync> =
+            Arc::new(Sphere::new(Vec3::new(2.0, 0.0, 0.0), 1.0, Material::Mirror));
+        let start = Trs {
+            translate: Vec3::new(0.0, 0.0, 0.0),
+            rotate: Vec3::new(0.0, 0.0, 0.0),
+            scale: Vec3::new(1.0, 1.0, 1.0),
+        };
+        let end = Trs {
+            translate: Vec3::new(1.0, 0.0, 0.0),
+            rotate: Vec3::new(0.0, 180.0, 0.0),
+            scale: Vec3::new(2.0, 2.0, 2.0),
+        };
+        let swept = Transform::animated(offset.clone(), start, end)
+            .bounding_box()
+            .unwrap();
+        let inside = |b: Aabb| {
+            b.min.x() >= swept.min.x()
+                && b.min.y() >= swept.min.y()
+                && b.min.z() >= swept.min.z()
+                && b.max.x() <= swept.max.x()
+                && b.max.y() <= swept.max.y()
+                && b.max.z() <= swept.max.z()
+        };
+        let child = offset.bounding_box().unwrap();
+        for i in 0..=1000 {
+            let placement = start.lerp(&end, i as f64 / 1000.0);
+            assert!(inside(child.transformed(&placement.matrix())), "time {}", i);
+        }
+
+        // Without rotation the box is exactly the one around both ends.
+        let sliding = Trs {
+            rotate: Vec3::new(0.0, 0.0, 0.0),
+            ..end
+        };
+        let swept = Transform::animated(unit_sphere(), start, sliding)
+            .bounding_box()
+            .unwrap();
+        assert!((swept.min - Vec3::new(-1.0, -2.0, -2.0)).length() < 1e-9);
+        assert!((swept.max - Vec3::new(3.0, 2.0, 2.0)).length() < 1e-9);
+    }
+
+    #[test]
+    fn material_override_replaces_the_geometry_material() {
+        let origin = Vec3::new(0.0, 0.0, 5.0);
+        let forward = Vec3::new(0.0, 0.0, -1.0);
+        let plain = Transform::new(unit_sphere(), Mat4::identity());
+        let rec = cast(&plain, origin, forward).unwrap();
+        assert!(matches!(rec.material, Some(Material::Dielectric(_))));
+
+        let mirrored =
+            Transform::new(unit_sphere(), Mat4::identity()).with_material(Material::Mirror);
+        let rec = cast(&mirrored, origin, forward).unwrap();
+        assert!(matches!(rec.material, Some(Material::Mirror)));
+    }
+}
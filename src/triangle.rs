@@ -1,58 +1,97 @@
+use crate::aabb::Aabb;
 use crate::material::Material;
 use crate::vec3::Vec3;
 use crate::hittable;
 use crate::ray::Ray;
+use crate::scene_export::SceneWriter;
 
+/// Single triangle, shaded flat unless given vertex normals. With `cull_back_face` set rays
+/// only hit the side the counter-clockwise winding faces.
 pub struct Triangle {
     points: Vec<Vec3>,
     normal: Vec3,
+    uvs: [(f64, f64); 3],
+    // Per-vertex normals interpolated for smooth shading, the face normal is used without them.
+    normals: Option<[Vec3; 3]>,
+    tangent: Vec3,
+    bitangent: Vec3,
     material: Material,
     culling: bool,
 }
 
 impl Triangle {
     pub fn new(point1: Vec3, point2: Vec3, point3: Vec3, mat: Material, cull_back_face: bool) -> Triangle {
-        let points_ = {
-            let mut points = vec![];
-            points.push(point1);
-            points.push(point2);
-            points.push(point3);
-            points
-        };
+        let points_ = vec![point1, point2, point3];
         let normal_ = {
             let a = point2 - point1;
             let b = point3 - point1;
             a.cross(b).unit_vector()
         };
 
-        Triangle {
+        let mut triangle = Triangle {
             points: points_,
             normal: normal_,
+            uvs: [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)],
+            normals: None,
+            tangent: Vec3::new(0.0, 0.0, 0.0),
+            bitangent: Vec3::new(0.0, 0.0, 0.0),
             material: mat,
             culling: cull_back_face,
+        };
+        triangle.compute_tangents();
+        triangle
+    }
+
+    /// Per-vertex texture coordinates, defaults to the barycentric coordinates of the hit.
+    pub fn with_uvs(mut self, uvs: [(f64, f64); 3]) -> Triangle {
+        self.uvs = uvs;
+        self.compute_tangents();
+        self
+    }
+
+    pub fn with_normals(mut self, normals: [Vec3; 3]) -> Triangle {
+        self.normals = Some(normals);
+        self
+    }
+
+    fn compute_tangents(&mut self) {
+        let edge1 = self.points[1] - self.points[0];
+        let edge2 = self.points[2] - self.points[0];
+        let (du1, dv1) = (self.uvs[1].0 - self.uvs[0].0, self.uvs[1].1 - self.uvs[0].1);
+        let (du2, dv2) = (self.uvs[2].0 - self.uvs[0].0, self.uvs[2].1 - self.uvs[0].1);
+        let det = du1 * dv2 - du2 * dv1;
+
+        let tangent = if det.abs() > 1e-12 {
+            (edge1 * dv2 - edge2 * dv1) * (1.0 / det)
+        } else {
+            edge1
+        };
+        // Gram-Schmidt against the face normal so the frame stays orthonormal.
+        self.tangent = (tangent - self.normal * self.normal.dot(tangent)).unit_vector();
+        self.bitangent = self.normal.cross(self.tangent);
+        if det < 0.0 {
+            self.bitangent = -self.bitangent;
         }
     }
 }
 
 impl hittable::Hittable for Triangle {
     fn hit(&self, ray: Ray, t_min: f64, t_max: f64, rec: &mut hittable::HitRecord) -> bool {
-        let vertex0 = self.points.get(0).unwrap().clone();
-        let vertex1 = self.points.get(1).unwrap().clone();
-        let vertex2 = self.points.get(2).unwrap().clone();
+        let vertex0 = self.points[0];
+        let vertex1 = self.points[1];
+        let vertex2 = self.points[2];
 
         let edge1 = vertex1 - vertex0;
         let edge2 = vertex2 - vertex0;
 
         let h = ray.direction().cross(edge2);
         let a = edge1.dot(h);
-        if self.culling {
-            if a < t_min { return false; }
-        }
+        if self.culling && a < t_min { return false; }
 
         let f = 1.0 / a;
         let s = ray.origin() - vertex0;
         let u = f * (s.dot(h));
-        if u < 0.0 || u > 1.0 { return false; }
+        if !(0.0..=1.0).contains(&u) { return false; }
 
         let q = s.cross(edge1);
         let v = f * (ray.direction().dot(q));
@@ -63,10 +102,90 @@ impl hittable::Hittable for Triangle {
         let intersection_point = ray.origin() + ray.direction() * t;
 
         rec.t = Some(t);
-        rec.p = Some(intersection_point.clone());
-        rec.set_face_normal(ray, self.normal.clone());
+        rec.p = Some(intersection_point);
+        rec.set_face_normal(ray, self.normal);
         rec.material = Some(self.material.clone());
-        
+
+        let w = 1.0 - u - v;
+        rec.uv = Some((
+            self.uvs[0].0 * w + self.uvs[1].0 * u + self.uvs[2].0 * v,
+            self.uvs[0].1 * w + self.uvs[1].1 * u + self.uvs[2].1 * v,
+        ));
+        rec.tangent = Some(self.tangent);
+        rec.bitangent = Some(self.bitangent);
+
+        if let Some(normals) = self.normals {
+            // Keep the side chosen from the face normal so back faces stay consistent.
+            let mut shading = (normals[0] * w + normals[1] * u + normals[2] * v).unit_vector();
+            if shading.dot(self.normal) < 0.0 {
+                shading = -shading;
+            }
+            rec.normal = Some(if rec.get_front_face().unwrap() { shading } else { -shading });
+        }
+
         true
     }
-}
\ No newline at end of file
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::from_points(&self.points))
+    }
+
+    fn export(&self, writer: &mut SceneWriter) {
+        let points = [self.points[0], self.points[1], self.points[2]];
+        writer.triangle(points, self.uvs, self.normals, &self.material, self.culling);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::Hittable;
+
+    fn triangle() -> Triangle {
+        Triangle::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Material::Mirror,
+            false,
+        )
+    }
+
+    fn uv_at(triangle: &Triangle, x: f64, y: f64) -> (f64, f64) {
+        let mut rec = hittable::HitRecord::new();
+        let ray = Ray::new(Vec3::new(x, y, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        assert!(triangle.hit(ray, 0.001, f64::INFINITY, &mut rec));
+        rec.uv.unwrap()
+    }
+
+    #[test]
+    fn uvs_are_interpolated_barycentrically() {
+        assert_eq!(uv_at(&triangle(), 0.25, 0.5), (0.25, 0.5));
+
+        let mapped = triangle().with_uvs([(0.2, 0.1), (0.6, 0.1), (0.2, 0.9)]);
+        for ((x, y), expected) in [((0.25, 0.5), (0.3, 0.5)), ((0.0, 0.0), (0.2, 0.1))] {
+            let (u, v) = uv_at(&mapped, x, y);
+            assert!(
+                (u - expected.0).abs() < 1e-9 && (v - expected.1).abs() < 1e-9,
+                "{:?} instead of {:?}",
+                (u, v),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn tangents_keep_the_handedness_of_the_uv_layout() {
+        let handedness = |t: &Triangle| t.tangent.cross(t.bitangent).dot(t.normal);
+        let plain = triangle();
+        assert_eq!(plain.tangent, Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(plain.bitangent, Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(handedness(&plain), 1.0);
+
+        // Mirrored in u: the tangent flips, the bitangent still follows increasing v.
+        let mirrored = triangle().with_uvs([(1.0, 0.0), (0.0, 0.0), (1.0, 1.0)]);
+        assert_eq!(mirrored.tangent, Vec3::new(-1.0, 0.0, 0.0));
+        assert_eq!(mirrored.bitangent, Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(handedness(&mirrored), -1.0);
+    }
+}
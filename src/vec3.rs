@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
 
+/// Point, direction or linear RGB color, depending on where it is used.
 #[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Vec3 {
     pub x: f64,
@@ -41,6 +42,7 @@ impl Vec3 {
         self.x * v.x + self.y * v.y + self.z * v.z
     }
     #[allow(dead_code)]
+    /// Right-handed cross product.
     pub fn cross(&self, v: Vec3) -> Vec3 {
         Vec3 {
             x: self.y * v.z - self.z * v.y,
@@ -54,12 +56,14 @@ impl Vec3 {
     pub fn length(&self) -> f64 {
         self.length_squared().sqrt()
     }
+    /// Same direction with length 1.
     pub fn unit_vector(&self) -> Vec3 {
-        self.clone() * (1.0 / self.length())
+        *self * (1.0 / self.length())
     }
 
+    /// The color averaged over `samples_per_pixel` samples as a PPM pixel, see `to_rgb`.
     #[allow(dead_code)]
-    pub fn to_string(&self, samples_per_pixel: usize) -> String {
+    pub fn to_string(self, samples_per_pixel: usize) -> String {
         let scale = 1.0 / samples_per_pixel as f64;
         let r = (256.0 * clamp((self.x * scale).sqrt(), 0.0, 0.999)) as u8;
         let g = (256.0 * clamp((self.y * scale).sqrt(), 0.0, 0.999)) as u8;
@@ -67,7 +71,9 @@ impl Vec3 {
         format!("{} {} {}", r, g, b)
     }
 
-    pub fn to_rgb(&self, samples_per_pixel: usize) -> Vec<u8> {
+    /// The color averaged over `samples_per_pixel` samples, gamma corrected with a square root
+    /// and quantised to 8 bits per channel.
+    pub fn to_rgb(self, samples_per_pixel: usize) -> Vec<u8> {
         let scale = 1.0 / samples_per_pixel as f64;
         let r = (256.0 * clamp((self.x * scale).sqrt(), 0.0, 0.999)) as u8;
         let g = (256.0 * clamp((self.y * scale).sqrt(), 0.0, 0.999)) as u8;
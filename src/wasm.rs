@@ -0,0 +1,70 @@
+// Exports for a WebAssembly module driven from JavaScript without generated bindings. The page
+// copies the RON scene into memory from `wasm_alloc`, then fills an RGBA buffer, also from
+// `wasm_alloc`, one tile per animation frame and draws it to a canvas. See web/index.html.
+#![allow(clippy::missing_safety_doc)]
+
+use crate::scene::Scene;
+
+// Buffer of `len` bytes owned by the caller until passed to `wasm_free`.
+#[no_mangle]
+pub extern "C" fn wasm_alloc(len: usize) -> *mut u8 {
+    let mut buffer = vec![0_u8; len];
+    let ptr = buffer.as_mut_ptr();
+    std::mem::forget(buffer);
+    ptr
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_free(ptr: *mut u8, len: usize) {
+    drop(Vec::from_raw_parts(ptr, len, len));
+}
+
+// Scene from RON text of `len` bytes, null when it is not UTF-8.
+#[no_mangle]
+pub unsafe extern "C" fn wasm_scene_from_ron(ron: *const u8, len: usize) -> *mut Scene {
+    match std::str::from_utf8(std::slice::from_raw_parts(ron, len)) {
+        Ok(ron) => Box::into_raw(Box::new(Scene::from_ron(ron.to_string()))),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_scene_width(scene: *const Scene) -> i32 {
+    (*scene).settings().image_width
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_scene_height(scene: *const Scene) -> i32 {
+    (*scene).settings().image_height
+}
+
+// `rgba` holds the whole image, width * height * 4 bytes.
+#[no_mangle]
+pub unsafe extern "C" fn wasm_scene_render_tile(
+    scene: *const Scene,
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+    rgba: *mut u8,
+) {
+    let settings = (*scene).settings();
+    let len = settings.image_width as usize * settings.image_height as usize * 4;
+    (*scene).render_tile(
+        x,
+        y,
+        width,
+        height,
+        std::slice::from_raw_parts_mut(rgba, len),
+    );
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_scene_free(scene: *mut Scene) {
+    drop(Box::from_raw(scene));
+}
+
+#[no_mangle]
+pub extern "C" fn wasm_seed(seed: u64) {
+    crate::seed_random(seed);
+}
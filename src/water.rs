@@ -0,0 +1,222 @@
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::scene_export::SceneWriter;
+use crate::vec3::Vec3;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::f64::consts::PI;
+
+const GRAVITY: f64 = 9.81;
+
+// Single Gerstner wave travelling along `direction` in the xz plane.
+#[derive(Debug, Clone)]
+struct Wave {
+    direction: (f64, f64),
+    wavenumber: f64,
+    amplitude: f64,
+    steepness: f64,
+    speed: f64,
+    phase: f64,
+}
+
+/// Horizontal water surface at `sea_level`. The surface itself stays flat, the sum of seeded
+/// Gerstner waves only bends the shading normal so refraction and reflections ripple.
+#[derive(Debug, Clone)]
+pub struct Water {
+    sea_level: f64,
+    waves: Vec<Wave>,
+    time: f64,
+    material: Material,
+    // Wind, seed, wave count, amplitude, wavelength and spread the waves were generated from.
+    spectrum: (Vec3, u32, usize, f64, f64, f64),
+}
+
+impl Water {
+    /// Wavelengths are spread around `wavelength` with amplitudes following a Phillips-like
+    /// spectrum, and directions scattered up to `spread` radians either side of the wind.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        sea_level: f64,
+        wind: Vec3,
+        seed: u32,
+        wave_count: usize,
+        amplitude: f64,
+        wavelength: f64,
+        spread: f64,
+        time: f64,
+        mat: Material,
+    ) -> Water {
+        let mut rng = StdRng::seed_from_u64(seed as u64);
+        // Only the width of the fan matters, so a negative spread scatters like a positive one.
+        let spread = spread.abs();
+        let wind_angle = wind.z().atan2(wind.x());
+        let waves = (0..wave_count)
+            .map(|_| {
+                let length = wavelength * rng.gen_range(0.25..2.0);
+                let angle = wind_angle + rng.gen_range(-spread..=spread);
+                let wavenumber = 2.0 * PI / length;
+                let wave_amplitude = amplitude * length / wavelength / wave_count as f64;
+                Wave {
+                    direction: (angle.cos(), angle.sin()),
+                    wavenumber,
+                    amplitude: wave_amplitude,
+                    // Keeps the summed crests from looping over themselves.
+                    steepness: 1.0 / (wavenumber * wave_amplitude * wave_count as f64).max(1.0),
+                    speed: (GRAVITY * wavenumber).sqrt(),
+                    phase: rng.gen_range(0.0..2.0 * PI),
+                }
+            })
+            .collect();
+
+        Water {
+            sea_level,
+            waves,
+            time,
+            material: mat,
+            spectrum: (wind, seed, wave_count, amplitude, wavelength, spread),
+        }
+    }
+
+    // Normal of the summed Gerstner surface above the point (x, z).
+    fn wave_normal(&self, x: f64, z: f64) -> Vec3 {
+        let mut normal = Vec3::new(0.0, 1.0, 0.0);
+        for wave in &self.waves {
+            let (dx, dz) = wave.direction;
+            let theta = wave.wavenumber * (dx * x + dz * z) - wave.speed * self.time + wave.phase;
+            let ka = wave.wavenumber * wave.amplitude;
+            normal = normal
+                - Vec3::new(
+                    dx * ka * theta.cos(),
+                    wave.steepness * ka * theta.sin(),
+                    dz * ka * theta.cos(),
+                );
+        }
+        normal.unit_vector()
+    }
+}
+
+impl Hittable for Water {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let denom = ray.direction().y();
+        if denom.abs() < 1e-12 {
+            return false;
+        }
+        let t = (self.sea_level - ray.origin().y()) / denom;
+        if t < t_min || t > t_max {
+            return false;
+        }
+
+        let p = ray.at(t);
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let tangent = Vec3::new(1.0, 0.0, 0.0);
+        let bitangent = up.cross(tangent);
+        rec.t = Some(t);
+        rec.p = Some(p);
+        rec.set_face_normal(ray, up);
+        rec.material = Some(self.material.clone());
+        rec.uv = Some((p.x(), -p.z()));
+        rec.tangent = Some(tangent);
+        rec.bitangent = Some(bitangent);
+
+        let normal = self.wave_normal(p.x(), p.z());
+        rec.set_shading_normal(
+            ray,
+            Vec3::new(normal.dot(tangent), normal.dot(bitangent), normal.y()),
+        );
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+
+    fn export(&self, writer: &mut SceneWriter) {
+        let (wind, seed, wave_count, amplitude, wavelength, spread) = self.spectrum;
+        let scalars = [
+            self.sea_level,
+            seed as f64,
+            wave_count as f64,
+            amplitude,
+            wavelength,
+            spread,
+            self.time,
+        ];
+        writer.primitive("Water", &[wind], &scalars, &self.material);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sea(amplitude: f64, spread: f64) -> Water {
+        Water::new(
+            2.0,
+            Vec3::new(1.0, 0.0, 0.5),
+            7,
+            8,
+            amplitude,
+            3.0,
+            spread,
+            0.0,
+            Material::Dielectric(1.33),
+        )
+    }
+
+    fn cast(water: &Water, origin: Vec3, direction: Vec3) -> Option<HitRecord> {
+        let mut rec = HitRecord::new();
+        let ray = Ray::new(origin, direction, 0.0);
+        water
+            .hit(ray, 0.001, f64::INFINITY, &mut rec)
+            .then_some(rec)
+    }
+
+    #[test]
+    fn waves_shade_a_flat_surface_at_sea_level() {
+        let water = sea(0.5, 0.4);
+        for i in 0..50 {
+            let x = i as f64 * 0.37;
+            let from_above = cast(&water, Vec3::new(x, 6.0, -x), Vec3::new(0.3, -1.0, 0.2));
+            let from_below = cast(&water, Vec3::new(-x, -3.0, x), Vec3::new(-0.1, 1.0, 0.4));
+            for rec in [from_above.unwrap(), from_below.unwrap()] {
+                assert!((rec.p.unwrap().y() - 2.0).abs() < 1e-9);
+                // The waves bend the shading normal but never past the horizon.
+                assert!(rec.normal.unwrap().y().abs() > 0.1);
+            }
+        }
+        assert!(cast(&water, Vec3::new(0.0, 6.0, 0.0), Vec3::new(1.0, 0.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn calm_water_faces_straight_up() {
+        let water = sea(0.0, 0.4);
+        let rec = cast(&water, Vec3::new(1.0, 5.0, 2.0), Vec3::new(0.5, -1.0, 0.0)).unwrap();
+        assert!((rec.t.unwrap() - 3.0).abs() < 1e-12);
+        assert!((rec.normal.unwrap() - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-12);
+        let rec = cast(&water, Vec3::new(1.0, 0.0, 2.0), Vec3::new(0.0, 1.0, 0.5)).unwrap();
+        assert!((rec.normal.unwrap() - Vec3::new(0.0, -1.0, 0.0)).length() < 1e-12);
+    }
+
+    #[test]
+    fn waves_ripple_the_normal() {
+        let water = sea(0.5, 0.4);
+        let normals: Vec<Vec3> = (0..20)
+            .map(|i| water.wave_normal(i as f64 * 0.5, 0.0))
+            .collect();
+        assert!(normals.iter().all(|n| n.y() > 0.0));
+        assert!(normals
+            .windows(2)
+            .any(|pair| (pair[0] - pair[1]).length() > 0.01));
+    }
+
+    #[test]
+    fn negative_spread_scatters_like_a_positive_one() {
+        let (left, right) = (sea(0.5, -0.4), sea(0.5, 0.4));
+        for i in 0..10 {
+            let (x, z) = (i as f64 * 1.3, i as f64 * -0.7);
+            assert!((left.wave_normal(x, z) - right.wave_normal(x, z)).length() < 1e-12);
+        }
+    }
+}
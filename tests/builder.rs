@@ -0,0 +1,211 @@
+use raytracing::{
+    seed_random, Aabb, HitRecord, Hittable, Material, PerspectiveCamera, Ray, RenderControl,
+    RenderSettings, Scene, SolidColor, Sphere, Vec3,
+};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+// Horizontal floor written outside the crate, counting the rays that hit it.
+#[derive(Debug)]
+struct Floor {
+    height: f64,
+    material: Material,
+    hits: Arc<AtomicUsize>,
+}
+
+impl Hittable for Floor {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let t = (self.height - ray.origin().y()) / ray.direction().y();
+        if !(t > t_min && t < t_max) {
+            return false;
+        }
+        let p = ray.at(t);
+        rec.t = Some(t);
+        rec.p = Some(p);
+        rec.set_face_normal(ray, Vec3::new(0.0, 1.0, 0.0));
+        rec.material = Some(self.material.clone());
+        rec.uv = Some((p.x(), p.z()));
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        true
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+}
+
+fn scene(floor: Option<Floor>) -> Scene {
+    let mut builder = Scene::builder()
+        .settings(RenderSettings {
+            image_width: 16,
+            image_height: 12,
+            samples_per_pixel: 2,
+            max_depth: 4,
+            multithreading: false,
+        })
+        .camera(PerspectiveCamera::new(
+            Vec3::new(0.0, 1.0, 2.0),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            60.0,
+            16.0 / 12.0,
+            0.0,
+            1.0,
+        ))
+        .light(Vec3::new(0.0, 5.0, 0.0));
+    if let Some(floor) = floor {
+        builder = builder.add(floor);
+    }
+    builder.build()
+}
+
+#[test]
+fn renders_a_custom_hittable() {
+    let hits = Arc::new(AtomicUsize::new(0));
+    let floor = Floor {
+        height: 0.0,
+        material: Material::Lambertian(Arc::new(SolidColor::new(Vec3::new(0.9, 0.1, 0.1)))),
+        hits: hits.clone(),
+    };
+    let with_floor = scene(Some(floor)).render();
+    let without = scene(None).render();
+    assert!(hits.load(Ordering::Relaxed) > 0);
+
+    // The bottom row looks at the red floor instead of the sky.
+    let bottom = with_floor.len() - 1;
+    assert_ne!(with_floor[bottom], without[bottom]);
+    assert!(with_floor[bottom][0] > with_floor[bottom][2]);
+    // The top row still sees the sky.
+    assert!(with_floor[0][2] > with_floor[0][0]);
+}
+
+// Custom types have no RON description, the rest of the scene is still written.
+#[test]
+fn custom_hittables_are_left_out_of_written_scenes() {
+    let floor = Floor {
+        height: 0.0,
+        material: Material::Mirror,
+        hits: Arc::new(AtomicUsize::new(0)),
+    };
+    let written = scene(Some(floor)).to_ron();
+    assert!(written.contains("objects: [],"));
+    // Camera and light are written just as without the floor and survive loading.
+    assert_eq!(written, scene(None).to_ron());
+    let reloaded = Scene::from_ron(written.clone());
+    assert_eq!(reloaded.settings().image_width, 16);
+    assert_eq!(reloaded.to_ron(), written);
+}
+
+// The flat buffer handed to numpy as a (height, width, 3) array holds the same pixels as the
+// per-pixel image, row by row.
+#[test]
+fn rgb_render_is_rows_of_pixels() {
+    seed_random(5);
+    let pixels = scene(None).render();
+    seed_random(5);
+    let rgb = scene(None).render_rgb(&RenderControl::default());
+    assert_eq!(rgb.len(), 12 * 16 * 3);
+    assert_eq!(rgb, pixels.concat());
+    // Row 3, column 5 of the array.
+    let at = (3 * 16 + 5) * 3;
+    assert_eq!(&rgb[at..at + 3], &pixels[3 * 16 + 5][..]);
+}
+
+// Every sample starts with one camera ray, and rays into the grid of spheres walk the BVH built
+// over them, on one thread or many.
+#[test]
+fn render_stats_count_rays_and_bvh_work() {
+    for multithreading in [false, true] {
+        let mut settings = scene(None).settings();
+        settings.multithreading = multithreading;
+        let mut builder = Scene::builder()
+            .settings(settings)
+            .camera(PerspectiveCamera::new(
+                Vec3::new(0.0, 0.0, 4.0),
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                60.0,
+                16.0 / 12.0,
+                0.0,
+                1.0,
+            ));
+        for x in -2..=2 {
+            for y in -1..=1 {
+                let material =
+                    Material::Lambertian(Arc::new(SolidColor::new(Vec3::new(0.5, 0.5, 0.5))));
+                builder = builder.add(Sphere::new(
+                    Vec3::new(x as f64, y as f64, 0.0),
+                    0.4,
+                    material,
+                ));
+            }
+        }
+        let (_, stats) = builder
+            .build()
+            .render_rgb_with_stats(&RenderControl::default());
+        assert_eq!(stats.rays.camera, 16 * 12 * 2);
+        assert!(stats.average_path_length >= 1.0);
+        assert!(stats.bvh_node_visits > 0);
+        assert!(stats.intersection_tests > 0);
+    }
+}
+
+// Every field of the stats is written to JSON under its own name and reads back as written.
+#[test]
+fn render_stats_json_reads_back() {
+    let mut settings = scene(None).settings();
+    settings.multithreading = true;
+    let (_, stats) = Scene::builder()
+        .settings(settings)
+        .camera(PerspectiveCamera::new(
+            Vec3::new(0.0, 0.0, 4.0),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            60.0,
+            16.0 / 12.0,
+            0.0,
+            1.0,
+        ))
+        .add(Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, Material::Mirror))
+        .build()
+        .render_rgb_with_stats(&RenderControl::default());
+
+    let path = std::env::temp_dir().join("raytracing-builder-stats.json");
+    stats.write_json(path.to_str().unwrap());
+    let written = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(written, stats.to_json());
+
+    let json: serde_json::Value = serde_json::from_str(&written).unwrap();
+    let object = json.as_object().unwrap();
+    assert_eq!(object.len(), 8);
+    let rays = &json["rays"];
+    assert_eq!(rays["camera"].as_u64(), Some(stats.rays.camera));
+    assert_eq!(rays["bounce"].as_u64(), Some(stats.rays.bounce));
+    assert_eq!(rays["shadow"].as_u64(), Some(stats.rays.shadow));
+    assert_eq!(
+        json["intersection_tests"].as_u64(),
+        Some(stats.intersection_tests)
+    );
+    assert_eq!(
+        json["bvh_node_visits"].as_u64(),
+        Some(stats.bvh_node_visits)
+    );
+    assert_eq!(
+        json["acceleration_bytes"].as_u64(),
+        Some(stats.acceleration_bytes as u64)
+    );
+    let close = |value: &serde_json::Value, expected: f64| {
+        (value.as_f64().unwrap() - expected).abs() <= 1e-12 * expected.abs().max(1.0)
+    };
+    assert!(close(
+        &json["average_path_length"],
+        stats.average_path_length
+    ));
+    assert!(close(&json["samples_per_second"], stats.samples_per_second));
+    assert!(close(&json["seconds"], stats.seconds));
+    let threads = json["thread_seconds"].as_array().unwrap();
+    assert_eq!(threads.len(), stats.thread_seconds.len());
+    for (value, &seconds) in threads.iter().zip(&stats.thread_seconds) {
+        assert!(close(value, seconds));
+    }
+}
@@ -0,0 +1,48 @@
+// Also run under WebAssembly with `cargo test --target wasm32-wasip1 --test tiles`.
+use raytracing::{seed_random, Scene};
+
+const SCENE: &str = r#"(
+    multithreading: false,
+    aspect_ratio: 2.0,
+    image_width: 24,
+    image_height: 12,
+    samples_per_pixel: 2,
+    max_depth: 4,
+    v_fov: 60.0,
+    aperture: 0.0,
+    focal_distance: 1.0,
+    camera_pos: [0.0, 0.0, 1.0],
+    camera_dir: [0.0, 0.0, -1.0],
+    camera_up: [0.0, 1.0, 0.0],
+    objects: [
+        (objtype: "Sphere", vectors: [[0.0, 0.0, -1.0]], scalars: [0.5], material: ["Lambertian", "0.8", "0.1", "0.1"]),
+    ],
+    lights: [[0.0, 5.0, 0.0]],
+)"#;
+
+fn render_in_tiles(scene: &Scene, tile: i32) -> Vec<u8> {
+    let settings = scene.settings();
+    let mut rgba = vec![0; settings.image_width as usize * settings.image_height as usize * 4];
+    for y in (0..settings.image_height).step_by(tile as usize) {
+        for x in (0..settings.image_width).step_by(tile as usize) {
+            scene.render_tile(x, y, tile, tile, &mut rgba);
+        }
+    }
+    rgba
+}
+
+#[test]
+fn tiles_fill_the_image_reproducibly() {
+    let scene = Scene::from_ron(SCENE.to_string());
+    seed_random(7);
+    let first = render_in_tiles(&scene, 5);
+    seed_random(7);
+    let second = render_in_tiles(&scene, 5);
+
+    assert_eq!(first, second);
+    assert!(first.chunks_exact(4).all(|pixel| pixel[3] == 255));
+    // The sphere in the middle is red against the blue sky in the corner.
+    let pixel = |x: usize, y: usize| &first[(y * 24 + x) * 4..(y * 24 + x) * 4 + 3];
+    assert!(pixel(12, 6)[0] > pixel(12, 6)[2]);
+    assert!(pixel(0, 0)[2] > pixel(0, 0)[0]);
+}